@@ -0,0 +1,14 @@
+// Feeds arbitrary bytes to the AWS config parser: `cargo +nightly fuzz run config_parser`.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+#[path = "../../src/config_parser.rs"]
+#[allow(dead_code)]
+mod config_parser;
+
+fuzz_target!(|data: &[u8]| {
+    let parsed = config_parser::parse_bytes(data);
+    let lines = data.split(|b| *b == b'\n').count();
+    assert!(parsed.diagnostics.iter().all(|d| d.line >= 1 && d.line <= lines));
+});
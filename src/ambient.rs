@@ -0,0 +1,108 @@
+// Ambient credentials as the base of a role profile (`credential_source`).
+//
+// Inside ECS tasks and EKS pods with Pod Identity, credentials come from the container
+// credentials endpoint; on EC2 from the instance metadata service. A role profile with
+// `credential_source = EcsContainer`, `Ec2InstanceMetadata` or `Environment` instead of
+// a source_profile assumes its role with those, so the same ~/.aws/config works on a
+// workstation and in a cluster job. The endpoints are read through the AWS CLI with its
+// shared files and the other providers out of the way, so the CLI cannot pick something
+// else; `Environment` takes the keys from the environment directly.
+use anyhow::{anyhow, Context, Result};
+
+use crate::{aws_env, backend, ratelimit, StsCredentials};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Environment,
+    EcsContainer,
+    Ec2InstanceMetadata,
+}
+
+impl Source {
+    /// The `credential_source` value, as the AWS CLI spells it.
+    pub fn parse(value: &str) -> Result<Source> {
+        match value {
+            "Environment" => Ok(Source::Environment),
+            "EcsContainer" => Ok(Source::EcsContainer),
+            "Ec2InstanceMetadata" => Ok(Source::Ec2InstanceMetadata),
+            other => Err(anyhow!(
+                "Unknown credential_source '{}'; expected Environment, EcsContainer or Ec2InstanceMetadata",
+                other
+            )),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Source::Environment => "Environment",
+            Source::EcsContainer => "EcsContainer",
+            Source::Ec2InstanceMetadata => "Ec2InstanceMetadata",
+        }
+    }
+
+    /// Whether the credentials are those of a role, which caps the sessions assumed
+    /// with them at an hour (role chaining).
+    pub fn is_role(self) -> bool {
+        self != Source::Environment
+    }
+}
+
+/// Variables that would let another provider answer before the one asked for.
+const OTHER_PROVIDERS: &[&str] = &[
+    "AWS_PROFILE",
+    "AWS_DEFAULT_PROFILE",
+    "AWS_ACCESS_KEY_ID",
+    "AWS_SECRET_ACCESS_KEY",
+    "AWS_SESSION_TOKEN",
+    "AWS_WEB_IDENTITY_TOKEN_FILE",
+    "AWS_ROLE_ARN",
+];
+
+const CONTAINER_ENDPOINT: &[&str] = &["AWS_CONTAINER_CREDENTIALS_RELATIVE_URI", "AWS_CONTAINER_CREDENTIALS_FULL_URI"];
+
+/// Read the credentials `source` provides on this host.
+pub async fn credentials(source: Source) -> Result<StsCredentials> {
+    let var = |name: &str| aws_env::get(&aws_env::current_lookup, name);
+    if source == Source::Environment {
+        return match (var("AWS_ACCESS_KEY_ID"), var("AWS_SECRET_ACCESS_KEY")) {
+            (Some(access_key_id), Some(secret_access_key)) => Ok(StsCredentials {
+                access_key_id,
+                secret_access_key,
+                session_token: var("AWS_SESSION_TOKEN").unwrap_or_default(),
+                expiration: var("AWS_CREDENTIAL_EXPIRATION").unwrap_or_default(),
+            }),
+            _ => Err(anyhow!(
+                "credential_source = Environment, but AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY are not set"
+            )),
+        };
+    }
+    let endpoint_set = CONTAINER_ENDPOINT.iter().any(|name| var(name).is_some());
+    if source == Source::EcsContainer && !endpoint_set {
+        return Err(anyhow!(
+            "credential_source = EcsContainer, but no container credentials endpoint is set \
+             (AWS_CONTAINER_CREDENTIALS_RELATIVE_URI or AWS_CONTAINER_CREDENTIALS_FULL_URI); \
+             is this an ECS task or an EKS pod with Pod Identity?"
+        ));
+    }
+    crate::progress::advance(&format!("read {} credentials", source.name()));
+    let mut cmd = backend::command("aws");
+    cmd.args(["configure", "export-credentials", "--format", "process"])
+        .env("AWS_CONFIG_FILE", "/dev/null")
+        .env("AWS_SHARED_CREDENTIALS_FILE", "/dev/null");
+    for name in OTHER_PROVIDERS {
+        cmd.env_remove(name);
+    }
+    if source == Source::Ec2InstanceMetadata {
+        for name in CONTAINER_ENDPOINT {
+            cmd.env_remove(name);
+        }
+    }
+    let output = ratelimit::output(&mut cmd)
+        .await
+        .context("failed to run aws configure export-credentials")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("Reading {} credentials failed: {}", source.name(), stderr.trim()));
+    }
+    serde_json::from_slice(&output.stdout).context("Parsing export-credentials JSON response failed")
+}
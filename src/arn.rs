@@ -0,0 +1,189 @@
+// Amazon Resource Names.
+//
+// `arn:partition:service:region:account-id:resource`. The resource is `type/id`,
+// `type:id` or a bare name depending on the service; it may itself contain colons, so
+// only the first five separators split the ARN. Region and account are empty for global
+// services (IAM has no region, S3 neither region nor account). Roles may also be written
+// as `123456789012/Name` or `Name` (see `RoleSpec`) and are expanded before use.
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Error, Result};
+
+/// Partitions an ARN can name.
+pub const PARTITIONS: &[&str] = &[
+    "aws",
+    "aws-cn",
+    "aws-us-gov",
+    "aws-iso",
+    "aws-iso-b",
+    "aws-iso-e",
+    "aws-iso-f",
+    "aws-eusc",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Arn {
+    pub partition: String,
+    pub service: String,
+    pub region: String,
+    pub account: String,
+    /// Everything after the account, e.g. `role/path/Name`.
+    pub resource: String,
+}
+
+impl Arn {
+    /// The account id, when the ARN names one.
+    pub fn account_id(&self) -> Option<&str> {
+        (!self.account.is_empty()).then_some(self.account.as_str())
+    }
+
+    /// `role` in `role/Name`, `function` in `function:name`; `None` for bare names.
+    pub fn resource_type(&self) -> Option<&str> {
+        // S3 resources are `bucket/key`, which only looks like a typed resource.
+        if self.service == "s3" {
+            return None;
+        }
+        self.split_resource().map(|(kind, _)| kind)
+    }
+
+    pub fn is_role(&self) -> bool {
+        self.service == "iam" && self.resource_type() == Some("role")
+    }
+
+    fn split_resource(&self) -> Option<(&str, &str)> {
+        let idx = self.resource.find(['/', ':'])?;
+        Some((&self.resource[..idx], &self.resource[idx + 1..]))
+    }
+}
+
+impl FromStr for Arn {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Arn> {
+        let invalid = |reason: String| anyhow!("invalid ARN '{}': {}", s, reason);
+        if s.chars().any(char::is_whitespace) {
+            return Err(invalid("contains whitespace".to_string()));
+        }
+        let parts: Vec<&str> = s.splitn(6, ':').collect();
+        let [prefix, partition, service, region, account, resource] = parts[..] else {
+            return Err(invalid("expected arn:partition:service:region:account:resource".to_string()));
+        };
+        if prefix != "arn" {
+            return Err(invalid("must start with 'arn:'".to_string()));
+        }
+        if !PARTITIONS.contains(&partition) {
+            return Err(invalid(format!("unknown partition '{}'", partition)));
+        }
+        let is_name = |v: &str| v.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+        if service.is_empty() || !is_name(service) {
+            return Err(invalid(format!("bad service '{}'", service)));
+        }
+        if !is_name(region) {
+            return Err(invalid(format!("bad region '{}'", region)));
+        }
+        // `aws` owns AWS managed resources such as arn:aws:iam::aws:policy/ReadOnlyAccess.
+        if !(account.is_empty() || is_account_id(account) || account == "aws") {
+            return Err(invalid(format!("account '{}' is not a 12-digit id", account)));
+        }
+        if resource.is_empty() {
+            return Err(invalid("missing resource".to_string()));
+        }
+        Ok(Arn {
+            partition: partition.to_string(),
+            service: service.to_string(),
+            region: region.to_string(),
+            account: account.to_string(),
+            resource: resource.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Arn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "arn:{}:{}:{}:{}:{}",
+            self.partition, self.service, self.region, self.account, self.resource
+        )
+    }
+}
+
+/// The partition `region` belongs to.
+pub fn partition_for_region(region: &str) -> &'static str {
+    const PREFIXES: &[(&str, &str)] = &[
+        ("cn-", "aws-cn"),
+        ("us-gov-", "aws-us-gov"),
+        ("us-isob-", "aws-iso-b"),
+        ("us-isof-", "aws-iso-f"),
+        ("us-iso-", "aws-iso"),
+        ("eu-isoe-", "aws-iso-e"),
+        ("eusc-", "aws-eusc"),
+    ];
+    PREFIXES
+        .iter()
+        .find(|(prefix, _)| region.starts_with(prefix))
+        .map(|(_, partition)| *partition)
+        .unwrap_or("aws")
+}
+
+/// The ARN of role `name` (which may include a path) in `account`.
+pub fn role(partition: &str, account: &str, name: &str) -> Arn {
+    Arn {
+        partition: partition.to_string(),
+        service: "iam".to_string(),
+        region: String::new(),
+        account: account.to_string(),
+        resource: format!("role/{}", name),
+    }
+}
+
+/// A role as written by the user: a full ARN, `123456789012/Name` or just `Name`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RoleSpec {
+    Arn(Arn),
+    InAccount { account: String, name: String },
+    /// A role in the account of the credentials it is assumed with.
+    Name(String),
+}
+
+impl RoleSpec {
+    pub fn parse(s: &str) -> Result<RoleSpec> {
+        if s.starts_with("arn:") {
+            return parse_role(s).map(RoleSpec::Arn);
+        }
+        let valid_name = |name: &str| {
+            !name.is_empty()
+                && !name.starts_with('/')
+                && !name.ends_with('/')
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || "+=,.@_-/".contains(c))
+        };
+        let (account, name) = match s.split_once('/') {
+            Some((account, name)) if is_account_id(account) => (Some(account), name),
+            _ => (None, s),
+        };
+        if !valid_name(name) {
+            return Err(anyhow!("invalid role '{}': expected an ARN, ACCOUNT_ID/RoleName or RoleName", s));
+        }
+        Ok(match account {
+            Some(account) => RoleSpec::InAccount {
+                account: account.to_string(),
+                name: name.to_string(),
+            },
+            None => RoleSpec::Name(name.to_string()),
+        })
+    }
+}
+
+pub fn is_account_id(s: &str) -> bool {
+    s.len() == 12 && s.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Parse `s` as an IAM role ARN.
+pub fn parse_role(s: &str) -> Result<Arn> {
+    let arn: Arn = s.parse()?;
+    if !arn.is_role() {
+        return Err(anyhow!("'{}' is not an IAM role ARN", s));
+    }
+    Ok(arn)
+}
@@ -0,0 +1,228 @@
+// Interactive prompts.
+//
+// Every question awx asks goes through here. By default these are dialoguer's
+// widgets: an arrow-key list, a y/n toggle and a hidden password field, redrawn in
+// place with colors. `--plain-prompts` (also AWX_PLAIN_PROMPTS=1, and implied by
+// TERM=dumb) replaces them with numbered, line-based questions on stderr that are
+// read back as typed lines, which screen readers and dumb terminals follow. Secrets
+// are still read without echo.
+//
+// `--answers file.toml` decides the questions ahead of time, for scripted runs that
+// still take the interactive code paths. Each question has a key (`profile`,
+// `duration`, `mfa_device`, `save_mfa_serial`, ...); questions about one profile can be
+// answered for that profile alone in a table, e.g. `[duration] deployer = "15m"`, next
+// to a plain `duration = "1h"` for the rest. A list is answered with an item's label
+// (for profiles, the name) or its 1-based number, a yes/no question with a boolean and
+// a secret with a string. Each answer used is echoed to stderr. A question the file does
+// not answer is an error rather than a prompt, so a script never hangs on one.
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use anyhow::{anyhow, Context, Result};
+use dialoguer::{Confirm, Input, Password, Select};
+
+static ANSWERS: OnceLock<(PathBuf, toml::Table)> = OnceLock::new();
+
+/// Which question is asked, and about which profile when it is about one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Key<'a>(pub &'static str, pub Option<&'a str>);
+
+/// Answer the questions of this process from the TOML file at `path`.
+pub fn load_answers(path: &Path) -> Result<()> {
+    let content = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let table: toml::Table = toml::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))?;
+    let _ = ANSWERS.set((path.to_path_buf(), table));
+    Ok(())
+}
+
+/// The answer `answers` holds for `key`: the entry for its profile in a table, else a
+/// plain value.
+pub fn lookup<'a>(answers: &'a toml::Table, key: Key) -> Option<&'a toml::Value> {
+    match (answers.get(key.0)?, key.1) {
+        (toml::Value::Table(by_profile), Some(profile)) => by_profile.get(profile),
+        (toml::Value::Table(_), None) => None,
+        (value, _) => Some(value),
+    }
+}
+
+/// The item an answer names: by label (ignoring case, and for decorated labels the
+/// first word) or by 1-based number.
+pub fn pick<T: AsRef<str>>(answer: &toml::Value, items: &[T]) -> Result<usize> {
+    let labels: Vec<String> = items.iter().map(|i| console::strip_ansi_codes(i.as_ref()).trim().to_string()).collect();
+    let found = match answer {
+        toml::Value::Integer(n) => usize::try_from(*n).ok().filter(|n| (1..=items.len()).contains(n)).map(|n| n - 1),
+        toml::Value::String(wanted) => labels.iter().position(|l| l.eq_ignore_ascii_case(wanted)).or_else(|| {
+            let mut by_word = labels.iter().enumerate().filter(|(_, l)| l.split_whitespace().next() == Some(wanted.as_str()));
+            match (by_word.next(), by_word.next()) {
+                (Some((i, _)), None) => Some(i),
+                _ => None,
+            }
+        }),
+        _ => None,
+    };
+    found.ok_or_else(|| anyhow!("{} is not one of: {}", answer, labels.join(", ")))
+}
+
+// The answer to `key` when an answers file is in use; asking anyway would hang a script.
+fn answer(key: Key, prompt: &str) -> Result<Option<&'static toml::Value>> {
+    let Some((path, answers)) = ANSWERS.get() else {
+        return Ok(None);
+    };
+    let name = match key.1 {
+        Some(profile) => format!("{}.{}", key.0, profile),
+        None => key.0.to_string(),
+    };
+    lookup(answers, key)
+        .map(Some)
+        .ok_or_else(|| anyhow!("{} has no answer for `{}` ({})", path.display(), name, prompt.trim_end_matches([':', ' '])))
+}
+
+fn echo(prompt: &str, shown: &str) {
+    eprintln!("{} {} (from --answers)", prompt.trim_end(), shown);
+}
+
+pub fn is_plain() -> bool {
+    crate::backend::flags().plain_prompts
+        || matches!(crate::aws_env::current_lookup("AWX_PLAIN_PROMPTS").as_deref(), Some("1") | Some("true"))
+        || crate::aws_env::current_lookup("TERM").as_deref() == Some("dumb")
+}
+
+/// Pick one of `items`; returns its index.
+pub fn select<T: AsRef<str>>(key: Key, prompt: &str, items: &[T], default: usize) -> Result<usize> {
+    if let Some(value) = answer(key, prompt)? {
+        let index = pick(value, items).with_context(|| format!("Answering `{}`", key.0))?;
+        echo(prompt, &console::strip_ansi_codes(items[index].as_ref()));
+        return Ok(index);
+    }
+    if is_plain() {
+        return plain_select(&mut std::io::stdin().lock(), &mut std::io::stderr(), prompt, items, default);
+    }
+    let items: Vec<&str> = items.iter().map(|i| i.as_ref()).collect();
+    Ok(Select::with_theme(&crate::theme::prompts())
+        .with_prompt(prompt)
+        .items(&items)
+        .default(default)
+        .interact()?)
+}
+
+/// A yes/no question.
+pub fn confirm(key: Key, prompt: &str, default: bool) -> Result<bool> {
+    if let Some(value) = answer(key, prompt)? {
+        let yes = value.as_bool().ok_or_else(|| anyhow!("Answering `{}`: expected true or false, not {}", key.0, value))?;
+        echo(prompt, if yes { "yes" } else { "no" });
+        return Ok(yes);
+    }
+    if is_plain() {
+        return plain_confirm(&mut std::io::stdin().lock(), &mut std::io::stderr(), prompt, default);
+    }
+    Ok(Confirm::with_theme(&crate::theme::prompts())
+        .with_prompt(prompt)
+        .default(default)
+        .interact()?)
+}
+
+/// A line of text, shown as it is typed.
+pub fn text(key: Key, prompt: &str) -> Result<String> {
+    if let Some(value) = answer(key, prompt)? {
+        let text = value.as_str().ok_or_else(|| anyhow!("Answering `{}`: expected a string", key.0))?;
+        echo(prompt, text);
+        return Ok(text.to_string());
+    }
+    if is_plain() {
+        let prompt = format!("{}: ", prompt.trim_end().trim_end_matches(':'));
+        return ask_line(&mut std::io::stdin().lock(), &mut std::io::stderr(), &prompt);
+    }
+    Ok(Input::<String>::with_theme(&crate::theme::prompts())
+        .with_prompt(prompt)
+        .allow_empty(true)
+        .interact_text()?)
+}
+
+/// A secret, read without echo. With `confirmation` (prompt, mismatch message) it is
+/// asked twice until both entries match.
+pub fn password(key: Key, prompt: &str, confirmation: Option<(&str, &str)>) -> Result<String> {
+    if let Some(value) = answer(key, prompt)? {
+        let secret = value.as_str().ok_or_else(|| anyhow!("Answering `{}`: expected a string", key.0))?;
+        echo(prompt, "********");
+        return Ok(secret.to_string());
+    }
+    if !is_plain() {
+        let theme = crate::theme::prompts();
+        let mut input = Password::with_theme(&theme).with_prompt(prompt);
+        if let Some((again, mismatch)) = confirmation {
+            input = input.with_confirmation(again, mismatch);
+        }
+        return Ok(input.interact()?);
+    }
+    let term = console::Term::stderr();
+    loop {
+        let value = secret_line(&term, prompt)?;
+        let Some((again, mismatch)) = confirmation else {
+            return Ok(value);
+        };
+        if secret_line(&term, again)? == value {
+            return Ok(value);
+        }
+        term.write_line(mismatch)?;
+    }
+}
+
+fn secret_line(term: &console::Term, prompt: &str) -> Result<String> {
+    loop {
+        term.write_str(&format!("{}: ", prompt.trim_end().trim_end_matches(':')))?;
+        let value = term.read_secure_line()?;
+        if !value.is_empty() {
+            return Ok(value);
+        }
+    }
+}
+
+/// The line-based list: numbered items, answered with a number or Enter for `default`.
+pub fn plain_select<T: AsRef<str>>(
+    input: &mut impl BufRead,
+    out: &mut impl Write,
+    prompt: &str,
+    items: &[T],
+    default: usize,
+) -> Result<usize> {
+    let colon = if prompt.ends_with(['?', ':']) { "" } else { ":" };
+    writeln!(out, "{}{}", prompt, colon)?;
+    for (n, item) in items.iter().enumerate() {
+        writeln!(out, "  {}. {}", n + 1, item.as_ref())?;
+    }
+    loop {
+        let answer = ask_line(input, out, &format!("Enter a number from 1 to {} [{}]: ", items.len(), default + 1))?;
+        if answer.is_empty() {
+            return Ok(default);
+        }
+        match answer.parse::<usize>() {
+            Ok(n) if (1..=items.len()).contains(&n) => return Ok(n - 1),
+            _ => writeln!(out, "'{}' is not one of the numbers listed.", answer)?,
+        }
+    }
+}
+
+/// The line-based yes/no question, answered with y/yes, n/no or Enter for `default`.
+pub fn plain_confirm(input: &mut impl BufRead, out: &mut impl Write, prompt: &str, default: bool) -> Result<bool> {
+    let hint = if default { "Y/n" } else { "y/N" };
+    loop {
+        let answer = ask_line(input, out, &format!("{} [{}]: ", prompt, hint))?;
+        match answer.to_lowercase().as_str() {
+            "" => return Ok(default),
+            "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            _ => writeln!(out, "Please answer yes or no.")?,
+        }
+    }
+}
+
+fn ask_line(input: &mut impl BufRead, out: &mut impl Write, prompt: &str) -> Result<String> {
+    write!(out, "{}", prompt)?;
+    out.flush()?;
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Err(anyhow!("No answer: input ended"));
+    }
+    Ok(line.trim().to_string())
+}
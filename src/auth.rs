@@ -0,0 +1,211 @@
+// Authentication backends.
+//
+// What resolving a profile takes depends on the kind of profile: static keys need
+// nothing from awx, static keys with an mfa_serial need an MFA session, a role needs
+// AssumeRole on top of its base credentials. Each kind is an `AuthBackend`, which says
+// whether it handles a profile and resolves the ones it does. `pipeline::credentials`
+// asks the backends of the current `backend::Backends` in order and uses the first that
+// handles the profile. The built-in ones cover web identity, credential_process,
+// assume-role, static+MFA, SSO and static keys; several of them leave the work to the
+// AWS CLI, which resolves those profiles itself, and a profile no backend handles is
+// left to the AWS CLI as well.
+//
+// Backends outside AWS's own providers come first, so they can take a profile over:
+// built with `--features vault`, awx reads credentials from the HashiCorp Vault AWS
+// secrets engine for profiles with `vault_path` under `[profiles.<name>]` (see
+// `vault`). Embedders and tests add their own to `Backends::auth` and run with
+// `backend::scope`.
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+
+use anyhow::{anyhow, Result};
+
+use crate::backend::BoxFuture;
+use crate::settings::ProfileSettings;
+use crate::{backend, pipeline, Profile, StsCredentials};
+
+static VAULT_PATHS: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+/// A profile to resolve, with what resolving it may need.
+#[derive(Clone, Copy)]
+pub struct Request<'a> {
+    pub profiles: &'a HashMap<String, Profile>,
+    pub profile: &'a Profile,
+    pub no_interactive: bool,
+    pub run: &'a pipeline::Run,
+}
+
+/// One way of resolving profiles.
+pub trait AuthBackend: Send + Sync {
+    /// A short name for `--explain`, e.g. `assume-role`.
+    fn name(&self) -> &'static str;
+    /// Whether this backend resolves `profile`.
+    fn handles(&self, profile: &Profile) -> bool;
+    /// The session to inject for the profile; None when the AWS CLI resolves it itself.
+    fn resolve<'a>(&'a self, request: Request<'a>) -> BoxFuture<'a, Result<Option<StsCredentials>>>;
+}
+
+/// Backends behind feature flags, asked before the built-in ones.
+#[cfg(feature = "vault")]
+fn optional() -> Vec<Arc<dyn AuthBackend>> {
+    vec![Arc::new(crate::vault::Vault)]
+}
+
+#[cfg(not(feature = "vault"))]
+fn optional() -> Vec<Arc<dyn AuthBackend>> {
+    Vec::new()
+}
+
+/// The backends awx was built with, in the order they are asked.
+pub fn defaults() -> Vec<Arc<dyn AuthBackend>> {
+    let builtin: [Arc<dyn AuthBackend>; 6] = [
+        Arc::new(WebIdentity),
+        Arc::new(CredentialProcess),
+        Arc::new(AssumeRole),
+        Arc::new(StaticMfa),
+        Arc::new(Sso),
+        Arc::new(Static),
+    ];
+    optional().into_iter().chain(builtin).collect()
+}
+
+/// Apply the `[profiles.<name>]` backend settings (`vault_path`) for this process.
+pub fn configure(profiles: &HashMap<String, ProfileSettings>) {
+    let paths: HashMap<String, String> = profiles
+        .iter()
+        .filter_map(|(name, p)| Some((name.clone(), p.vault_path.clone()?)))
+        .collect();
+    if !paths.is_empty() {
+        let _ = VAULT_PATHS.set(paths);
+    }
+}
+
+/// The Vault path `profile` reads its credentials from.
+pub fn vault_path(profile: &str) -> Option<&'static str> {
+    VAULT_PATHS.get()?.get(profile).map(String::as_str)
+}
+
+/// The backend that resolves `profile`, if any does.
+pub fn for_profile(profile: &Profile) -> Option<Arc<dyn AuthBackend>> {
+    backend::current().auth.iter().find(|b| b.handles(profile)).cloned()
+}
+
+/// Resolve the profile of `request` with the backend that handles it.
+pub async fn resolve(request: Request<'_>) -> Result<Option<StsCredentials>> {
+    if !cfg!(feature = "vault") && vault_path(&request.profile.name).is_some() {
+        return Err(anyhow!(
+            "Profile '{}' sets vault_path, but this awx was built without Vault support (cargo install awx --features vault)",
+            request.profile.name
+        ));
+    }
+    match for_profile(request.profile) {
+        Some(backend) => backend.resolve(request).await,
+        None => Ok(None),
+    }
+}
+
+/// `role_arn` with `web_identity_token_file`; the AWS CLI assumes the role itself.
+pub struct WebIdentity;
+
+impl AuthBackend for WebIdentity {
+    fn name(&self) -> &'static str {
+        "web-identity"
+    }
+
+    fn handles(&self, profile: &Profile) -> bool {
+        profile.web_identity_token_file.is_some()
+    }
+
+    fn resolve<'a>(&'a self, _request: Request<'a>) -> BoxFuture<'a, Result<Option<StsCredentials>>> {
+        Box::pin(async { Ok(None) })
+    }
+}
+
+/// `credential_process`; the AWS CLI runs the program itself.
+pub struct CredentialProcess;
+
+impl AuthBackend for CredentialProcess {
+    fn name(&self) -> &'static str {
+        "credential-process"
+    }
+
+    fn handles(&self, profile: &Profile) -> bool {
+        profile.credential_process.is_some() && !profile.is_role()
+    }
+
+    fn resolve<'a>(&'a self, _request: Request<'a>) -> BoxFuture<'a, Result<Option<StsCredentials>>> {
+        Box::pin(async { Ok(None) })
+    }
+}
+
+/// `role_arn` with `source_profile` or `credential_source`.
+pub struct AssumeRole;
+
+impl AuthBackend for AssumeRole {
+    fn name(&self) -> &'static str {
+        "assume-role"
+    }
+
+    fn handles(&self, profile: &Profile) -> bool {
+        profile.is_role()
+    }
+
+    fn resolve<'a>(&'a self, r: Request<'a>) -> BoxFuture<'a, Result<Option<StsCredentials>>> {
+        Box::pin(async move { pipeline::assume_role(r.profiles, r.profile, r.no_interactive, r.run).await.map(Some) })
+    }
+}
+
+/// Static keys with `mfa_serial`: a GetSessionToken session.
+pub struct StaticMfa;
+
+impl AuthBackend for StaticMfa {
+    fn name(&self) -> &'static str {
+        "static+mfa"
+    }
+
+    fn handles(&self, profile: &Profile) -> bool {
+        profile.is_static() && profile.requires_mfa() && !profile.is_role()
+    }
+
+    fn resolve<'a>(&'a self, r: Request<'a>) -> BoxFuture<'a, Result<Option<StsCredentials>>> {
+        Box::pin(async move {
+            let serial = r.profile.mfa_serial.as_deref().unwrap_or_default();
+            pipeline::mfa_session(r.profiles, r.profile, serial, r.no_interactive, r.run).await.map(Some)
+        })
+    }
+}
+
+/// IAM Identity Center; the session is checked and signed in before the backends run,
+/// and the AWS CLI reads the role credentials from it.
+pub struct Sso;
+
+impl AuthBackend for Sso {
+    fn name(&self) -> &'static str {
+        "sso"
+    }
+
+    fn handles(&self, profile: &Profile) -> bool {
+        profile.is_sso()
+    }
+
+    fn resolve<'a>(&'a self, _request: Request<'a>) -> BoxFuture<'a, Result<Option<StsCredentials>>> {
+        Box::pin(async { Ok(None) })
+    }
+}
+
+/// Static keys; the AWS CLI reads them from the profile.
+pub struct Static;
+
+impl AuthBackend for Static {
+    fn name(&self) -> &'static str {
+        "static"
+    }
+
+    fn handles(&self, profile: &Profile) -> bool {
+        profile.is_static()
+    }
+
+    fn resolve<'a>(&'a self, _request: Request<'a>) -> BoxFuture<'a, Result<Option<StsCredentials>>> {
+        Box::pin(async { Ok(None) })
+    }
+}
@@ -0,0 +1,254 @@
+// Standard AWS environment variables and how awx honors them.
+//
+// awx resolves profiles, files, regions and session names the same way the AWS CLI
+// and SDKs do, and never overwrites a variable the user already set. Every lookup goes
+// through a `lookup` function so the precedence rules can be tested without touching
+// the process environment. `Env` is the environment of the current run: the process
+// environment, or a fixed set of variables installed with `backend::scope`.
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use tokio::process::Command;
+
+use crate::Profile;
+
+/// Where a resolved value came from, for `--explain` output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Flag(&'static str),
+    Env(&'static str),
+    ProfileKey(&'static str),
+    Selected,
+    Context(String),
+    Default,
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::Flag(name) => write!(f, "{}", name),
+            Source::Env(name) => write!(f, "${}", name),
+            Source::ProfileKey(key) => write!(f, "profile key {}", key),
+            Source::Selected => write!(f, "interactive selection"),
+            Source::Context(name) => write!(f, "context {}", name),
+            Source::Default => write!(f, "default"),
+        }
+    }
+}
+
+/// A variable awx recognizes and what it does to awx's behavior.
+pub struct Knob {
+    pub name: &'static str,
+    pub effect: &'static str,
+    pub secret: bool,
+}
+
+pub const KNOBS: &[Knob] = &[
+    Knob { name: "AWS_PROFILE", effect: "profile when --profile is not given", secret: false },
+    Knob { name: "AWS_DEFAULT_PROFILE", effect: "profile when AWS_PROFILE is unset (legacy)", secret: false },
+    Knob { name: "AWS_CONFIG_FILE", effect: "location of the config file", secret: false },
+    Knob { name: "AWS_SHARED_CREDENTIALS_FILE", effect: "location of the credentials file", secret: false },
+    Knob { name: "AWS_REGION", effect: "region; profile region is not injected over it", secret: false },
+    Knob { name: "AWS_DEFAULT_REGION", effect: "region when AWS_REGION is unset", secret: false },
+    Knob { name: "AWS_ROLE_SESSION_NAME", effect: "session name for assume-role", secret: false },
+    Knob { name: "AWS_STS_REGIONAL_ENDPOINTS", effect: "passed through to STS calls and the child", secret: false },
+    Knob { name: "AWS_ACCESS_KEY_ID", effect: "static profile keys are not injected over it", secret: false },
+    Knob { name: "AWS_SECRET_ACCESS_KEY", effect: "kept unless awx injects an STS session", secret: true },
+    Knob { name: "AWS_SESSION_TOKEN", effect: "kept unless awx injects an STS session", secret: true },
+    Knob { name: "AWS_CA_BUNDLE", effect: "passed through to STS calls and the child", secret: false },
+    Knob { name: "AWS_ENDPOINT_URL", effect: "passed through to the child", secret: false },
+    Knob { name: "AWS_MAX_ATTEMPTS", effect: "passed through to STS calls and the child", secret: false },
+    Knob { name: "AWS_RETRY_MODE", effect: "passed through to STS calls and the child", secret: false },
+    Knob { name: "AWS_DEFAULT_OUTPUT", effect: "passed through to the child over the profile's output", secret: false },
+    Knob { name: "AWS_PAGER", effect: "passed through to the child over the profile's cli_pager", secret: false },
+];
+
+/// Read a variable, treating empty values as unset like the AWS CLI does.
+pub fn get(lookup: &dyn Fn(&str) -> Option<String>, name: &str) -> Option<String> {
+    lookup(name).filter(|v| !v.is_empty())
+}
+
+/// Look a variable up in the environment of the current run.
+pub fn current_lookup(name: &str) -> Option<String> {
+    crate::backend::env().var(name)
+}
+
+/// The variables awx reads and its children inherit.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Env {
+    /// The environment of the awx process.
+    #[default]
+    Process,
+    /// Exactly these variables; children start from them instead of the process
+    /// environment (including PATH for finding programs).
+    Fixed(BTreeMap<String, String>),
+}
+
+impl Env {
+    pub fn var(&self, name: &str) -> Option<String> {
+        match self {
+            Env::Process => std::env::var(name).ok(),
+            Env::Fixed(vars) => vars.get(name).cloned(),
+        }
+    }
+
+    /// This environment with `name` set; a process environment is copied first.
+    pub fn with(self, name: &str, value: impl Into<String>) -> Env {
+        let mut vars = self.into_fixed();
+        vars.insert(name.to_string(), value.into());
+        Env::Fixed(vars)
+    }
+
+    /// This environment without `name`; a process environment is copied first.
+    pub fn without(self, name: &str) -> Env {
+        let mut vars = self.into_fixed();
+        vars.remove(name);
+        Env::Fixed(vars)
+    }
+
+    fn into_fixed(self) -> BTreeMap<String, String> {
+        match self {
+            Env::Process => std::env::vars().collect(),
+            Env::Fixed(vars) => vars,
+        }
+    }
+
+    /// A command for `program` that starts from this environment.
+    pub fn command(&self, program: &str) -> Command {
+        let mut cmd = Command::new(program);
+        if let Env::Fixed(vars) = self {
+            cmd.env_clear().envs(vars);
+        }
+        cmd
+    }
+}
+
+/// Locations of the two shared files.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AwsFiles {
+    pub config: PathBuf,
+    pub config_source: Source,
+    pub credentials: PathBuf,
+    pub credentials_source: Source,
+}
+
+impl AwsFiles {
+    /// Both files inside one directory (e.g. ~/.aws).
+    pub fn in_dir(dir: &Path) -> AwsFiles {
+        AwsFiles {
+            config: dir.join("config"),
+            config_source: Source::Default,
+            credentials: dir.join("credentials"),
+            credentials_source: Source::Default,
+        }
+    }
+
+    /// Honor AWS_CONFIG_FILE / AWS_SHARED_CREDENTIALS_FILE, falling back to `dir`.
+    pub fn resolve(lookup: &dyn Fn(&str) -> Option<String>, dir: &Path) -> AwsFiles {
+        let mut files = AwsFiles::in_dir(dir);
+        if let Some(p) = get(lookup, "AWS_CONFIG_FILE") {
+            files.config = PathBuf::from(p);
+            files.config_source = Source::Env("AWS_CONFIG_FILE");
+        }
+        if let Some(p) = get(lookup, "AWS_SHARED_CREDENTIALS_FILE") {
+            files.credentials = PathBuf::from(p);
+            files.credentials_source = Source::Env("AWS_SHARED_CREDENTIALS_FILE");
+        }
+        files
+    }
+}
+
+/// Profile name from the environment: AWS_PROFILE, then AWS_DEFAULT_PROFILE.
+pub fn env_profile(lookup: &dyn Fn(&str) -> Option<String>) -> Option<(String, Source)> {
+    for name in ["AWS_PROFILE", "AWS_DEFAULT_PROFILE"] {
+        if let Some(v) = get(lookup, name) {
+            return Some((v, Source::Env(name)));
+        }
+    }
+    None
+}
+
+/// Region in effect for the child: --region argument, AWS_REGION,
+/// AWS_DEFAULT_REGION, then the profile's region.
+pub fn region(
+    lookup: &dyn Fn(&str) -> Option<String>,
+    args: &[String],
+    profile: &Profile,
+) -> Option<(String, Source)> {
+    for (i, a) in args.iter().enumerate() {
+        if let Some(v) = a.strip_prefix("--region=") {
+            return Some((v.to_string(), Source::Flag("--region")));
+        }
+        if a == "--region" {
+            if let Some(v) = args.get(i + 1) {
+                return Some((v.clone(), Source::Flag("--region")));
+            }
+        }
+    }
+    for name in ["AWS_REGION", "AWS_DEFAULT_REGION"] {
+        if let Some(v) = get(lookup, name) {
+            return Some((v, Source::Env(name)));
+        }
+    }
+    profile.region.clone().map(|r| (r, Source::ProfileKey("region")))
+}
+
+/// The AWS CLI settings a profile may carry in ~/.aws/config, with the variable that
+/// carries each to a child. `cli_binary_format` and `cli_history` have none: only the
+/// CLI reading the profile itself (through AWS_PROFILE) applies them.
+pub const CLI_SETTINGS: [(&str, Option<&str>); 4] = [
+    ("output", Some("AWS_DEFAULT_OUTPUT")),
+    ("cli_pager", Some("AWS_PAGER")),
+    ("cli_binary_format", None),
+    ("cli_history", None),
+];
+
+/// `profile`'s value of the CLI setting `key`.
+pub fn cli_setting<'a>(profile: &'a Profile, key: &str) -> Option<&'a str> {
+    match key {
+        "output" => profile.output.as_deref(),
+        "cli_pager" => profile.cli_pager.as_deref(),
+        "cli_binary_format" => profile.cli_binary_format.as_deref(),
+        "cli_history" => profile.cli_history.as_deref(),
+        _ => None,
+    }
+}
+
+/// The variables that give a child `profile`'s CLI settings, as running the CLI with
+/// `--profile` would, except where the environment sets them already. An empty
+/// AWS_PAGER is a setting (no pager), not an unset one.
+pub fn cli_settings_env(lookup: &dyn Fn(&str) -> Option<String>, profile: &Profile) -> Vec<(&'static str, String)> {
+    CLI_SETTINGS
+        .iter()
+        .filter_map(|&(key, var)| Some((var?, cli_setting(profile, key)?)))
+        .filter(|&(var, _)| lookup(var).is_none())
+        .map(|(var, value)| (var, value.to_string()))
+        .collect()
+}
+
+/// Session name for assume-role: AWS_ROLE_SESSION_NAME, the profile's
+/// role_session_name, then `awx-<timestamp>`.
+pub fn role_session_name(
+    lookup: &dyn Fn(&str) -> Option<String>,
+    profile: &Profile,
+    timestamp: i64,
+) -> (String, Source) {
+    if let Some(v) = get(lookup, "AWS_ROLE_SESSION_NAME") {
+        return (v, Source::Env("AWS_ROLE_SESSION_NAME"));
+    }
+    if let Some(v) = profile.role_session_name.clone() {
+        return (v, Source::ProfileKey("role_session_name"));
+    }
+    (format!("awx-{}", timestamp), Source::Default)
+}
+
+/// Current value of each known variable, with secrets masked.
+pub fn describe(lookup: &dyn Fn(&str) -> Option<String>) -> Vec<(&'static Knob, Option<String>)> {
+    KNOBS
+        .iter()
+        .map(|k| {
+            let value = get(lookup, k.name).map(|v| if k.secret { "(set)".to_string() } else { v });
+            (k, value)
+        })
+        .collect()
+}
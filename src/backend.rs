@@ -0,0 +1,353 @@
+// The layer between awx and the outside world.
+//
+// STS and SSO operations are reached through the `StsClient` and `SsoClient` traits and
+// helper programs (aws, openssl) through `ProcessRunner`; profiles are resolved by the
+// `auth::AuthBackend`s listed here. The default implementations
+// drive the AWS CLI and the system processes; tests and embedders swap in their own
+// with `scope`, which applies to everything awaited inside it. The scope also carries
+// the environment (`aws_env::Env`) that lookups read and that every command, including
+// the user's own, starts from, and the command-line switches of the run (`Flags`). Only the user's command is spawned outside the runner,
+// since it needs the terminal and signal forwarding.
+use std::future::Future;
+use std::io;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::process::{ExitStatus, Output, Stdio};
+use std::sync::{Arc, OnceLock};
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use crate::auth::AuthBackend;
+use crate::aws_env::Env;
+use crate::{StsCredentials, StsCredsWrapper};
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Runs helper programs to completion.
+pub trait ProcessRunner: Send + Sync {
+    /// Run `cmd` with `input` on stdin and capture stdout and stderr.
+    fn output<'a>(&'a self, cmd: &'a mut Command, input: Option<&'a [u8]>) -> BoxFuture<'a, io::Result<Output>>;
+    /// Run `cmd` attached to the terminal.
+    fn status<'a>(&'a self, cmd: &'a mut Command) -> BoxFuture<'a, io::Result<ExitStatus>>;
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct CallerIdentity {
+    pub account: String,
+    pub arn: String,
+    #[serde(default)]
+    pub user_id: String,
+}
+
+/// Credentials an STS call is made with.
+#[derive(Debug, Clone, Copy)]
+pub enum CredentialSource<'a> {
+    /// Whatever the AWS CLI resolves for the named profile.
+    Profile(&'a str),
+    /// Temporary credentials obtained earlier.
+    Session(&'a StsCredentials),
+}
+
+pub trait StsClient: Send + Sync {
+    fn caller_identity<'a>(&'a self, profile: &'a str) -> BoxFuture<'a, Result<CallerIdentity>>;
+    /// GetSessionToken for `profile`, with `(serial, code)` when MFA is used.
+    fn session_token<'a>(&'a self, profile: &'a str, mfa: Option<(&'a str, &'a str)>)
+        -> BoxFuture<'a, Result<StsCredentials>>;
+    fn assume_role<'a>(
+        &'a self,
+        role_arn: &'a str,
+        session_name: &'a str,
+        duration_secs: i64,
+        source: CredentialSource<'a>,
+    ) -> BoxFuture<'a, Result<StsCredentials>>;
+}
+
+pub trait SsoClient: Send + Sync {
+    /// Interactive `aws sso login` for `profile`.
+    fn login<'a>(&'a self, profile: &'a str, extra_args: &'a [&'a str]) -> BoxFuture<'a, Result<()>>;
+    /// The role credentials the SSO session yields for `profile`.
+    fn export_credentials<'a>(&'a self, profile: &'a str) -> BoxFuture<'a, Result<StsCredentials>>;
+}
+
+/// The set of implementations in effect.
+#[derive(Clone)]
+pub struct Backends {
+    pub sts: Arc<dyn StsClient>,
+    pub sso: Arc<dyn SsoClient>,
+    pub runner: Arc<dyn ProcessRunner>,
+    pub env: Arc<Env>,
+    pub flags: Arc<Flags>,
+    /// Asked in order; the first that handles a profile resolves it.
+    pub auth: Vec<Arc<dyn AuthBackend>>,
+}
+
+impl Default for Backends {
+    fn default() -> Self {
+        Backends {
+            sts: Arc::new(AwsCli),
+            sso: Arc::new(AwsCli),
+            runner: Arc::new(SystemRunner),
+            env: Arc::new(Env::Process),
+            flags: Arc::new(Flags::default()),
+            auth: crate::auth::defaults(),
+        }
+    }
+}
+
+/// The global command-line switches of a run, read by the modules they tune.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Flags {
+    /// `--plain-prompts`
+    pub plain_prompts: bool,
+    /// `--reveal`
+    pub reveal: bool,
+    /// `--tmpdir`
+    pub tmpdir: Option<PathBuf>,
+    /// `--offline`
+    pub offline: bool,
+    /// `--verify-trust`
+    pub verify_trust: bool,
+    /// `--choose-duration`
+    pub choose_duration: bool,
+    /// `--duration`, in seconds.
+    pub duration: Option<i64>,
+    /// `--credentials-endpoint`
+    pub credentials_endpoint: bool,
+}
+
+tokio::task_local! {
+    static SCOPED: Backends;
+}
+
+static DEFAULT: OnceLock<Backends> = OnceLock::new();
+
+/// The implementations for the current task.
+pub fn current() -> Backends {
+    SCOPED
+        .try_with(Clone::clone)
+        .unwrap_or_else(|_| DEFAULT.get_or_init(Backends::default).clone())
+}
+
+/// Run `fut` with `backends`. Tasks spawned inside must be wrapped again.
+pub async fn scope<F: Future>(backends: Backends, fut: F) -> F::Output {
+    SCOPED.scope(backends, fut).await
+}
+
+pub fn sts() -> Arc<dyn StsClient> {
+    current().sts
+}
+
+pub fn sso() -> Arc<dyn SsoClient> {
+    current().sso
+}
+
+pub fn runner() -> Arc<dyn ProcessRunner> {
+    current().runner
+}
+
+pub fn env() -> Arc<Env> {
+    current().env
+}
+
+pub fn flags() -> Arc<Flags> {
+    current().flags
+}
+
+/// A command for `program` in the current environment.
+pub fn command(program: &str) -> Command {
+    env().command(program)
+}
+
+/// Run the system openssl with `args` on `input` and return what it prints; `env` is
+/// set for openssl alone, so passphrases stay out of the process list. `needed_for`
+/// names what openssl is required for when it cannot be run.
+pub async fn openssl(args: &[&str], env: &[(&str, &str)], input: &[u8], needed_for: &str) -> Result<Vec<u8>> {
+    let mut cmd = command("openssl");
+    cmd.args(args).envs(env.iter().copied());
+    let output = runner()
+        .output(&mut cmd, Some(input))
+        .await
+        .with_context(|| format!("failed to run openssl (required for {})", needed_for))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("openssl {} failed: {}", args.first().unwrap_or(&""), stderr.trim()));
+    }
+    Ok(output.stdout)
+}
+
+/// Point the AWS CLI command `cmd` at `profile`: `--profile`, or the profile's keys in
+/// the environment when they are kept in a password manager, where the CLI cannot read
+/// them (see `secrets`).
+pub fn select_profile(cmd: &mut Command, profile: &str) {
+    match crate::secrets::resolved(profile) {
+        Some(keys) => {
+            cmd.env("AWS_PROFILE", profile)
+                .env("AWS_ACCESS_KEY_ID", keys.access_key_id)
+                .env("AWS_SECRET_ACCESS_KEY", keys.secret_access_key)
+                .env_remove("AWS_SESSION_TOKEN");
+        }
+        None => {
+            cmd.args(["--profile", profile]);
+        }
+    }
+}
+
+/// POST `body` as JSON to `url` with curl. The request is passed on stdin, so URLs and
+/// headers carrying secrets do not show up in the process list.
+pub async fn post_json(url: &str, headers: &[(String, String)], body: &serde_json::Value) -> Result<()> {
+    let mut config = format!("url = {}\n", curl_quote(url));
+    for (name, value) in std::iter::once(("Content-Type", "application/json"))
+        .chain(headers.iter().map(|(n, v)| (n.as_str(), v.as_str())))
+    {
+        config.push_str(&format!("header = {}\n", curl_quote(&format!("{}: {}", name, value))));
+    }
+    config.push_str(&format!("data-binary = {}\n", curl_quote(&body.to_string())));
+    let mut cmd = command("curl");
+    cmd.args(["--silent", "--show-error", "--fail", "--max-time", "5", "--config", "-"]);
+    let output = tokio::time::timeout(std::time::Duration::from_secs(10), runner().output(&mut cmd, Some(config.as_bytes())))
+        .await
+        .context("timeout")?
+        .context("failed to run curl")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("{}", stderr.trim()));
+    }
+    Ok(())
+}
+
+/// A double-quoted value for a curl config file.
+fn curl_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Real processes.
+pub struct SystemRunner;
+
+impl ProcessRunner for SystemRunner {
+    fn output<'a>(&'a self, cmd: &'a mut Command, input: Option<&'a [u8]>) -> BoxFuture<'a, io::Result<Output>> {
+        Box::pin(async move {
+            let Some(input) = input else {
+                return cmd.output().await;
+            };
+            cmd.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped());
+            let mut child = cmd.spawn()?;
+            let mut stdin = child
+                .stdin
+                .take()
+                .ok_or_else(|| io::Error::other("child stdin unavailable"))?;
+            stdin.write_all(input).await?;
+            drop(stdin);
+            child.wait_with_output().await
+        })
+    }
+
+    fn status<'a>(&'a self, cmd: &'a mut Command) -> BoxFuture<'a, io::Result<ExitStatus>> {
+        Box::pin(cmd.status())
+    }
+}
+
+/// STS and SSO through the AWS CLI, paced by the rate limiter.
+pub struct AwsCli;
+
+// Run an `aws` API call; `name` labels errors the way the CLI names the operation.
+async fn aws_call(cmd: &mut Command, name: &str) -> Result<Vec<u8>> {
+    let mut span = crate::telemetry::span(format!("aws {}", name));
+    let output = crate::ratelimit::output(cmd)
+        .await
+        .with_context(|| format!("failed to run aws {}", name))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        span.fail(stderr.trim());
+        return Err(anyhow!("{} failed: {}", name, stderr.trim()));
+    }
+    Ok(output.stdout)
+}
+
+fn parse_credentials(stdout: &[u8], name: &str) -> Result<StsCredentials> {
+    let wrap: StsCredsWrapper =
+        serde_json::from_slice(stdout).with_context(|| format!("Parsing {} JSON response failed", name))?;
+    Ok(wrap.credentials)
+}
+
+impl StsClient for AwsCli {
+    fn caller_identity<'a>(&'a self, profile: &'a str) -> BoxFuture<'a, Result<CallerIdentity>> {
+        Box::pin(async move {
+            let mut cmd = command("aws");
+            cmd.args(["sts", "get-caller-identity"]);
+            select_profile(&mut cmd, profile);
+            cmd.args(["--output", "json"]);
+            let stdout = aws_call(&mut cmd, "get-caller-identity").await?;
+            serde_json::from_slice(&stdout).context("Parsing get-caller-identity JSON response failed")
+        })
+    }
+
+    fn session_token<'a>(
+        &'a self,
+        profile: &'a str,
+        mfa: Option<(&'a str, &'a str)>,
+    ) -> BoxFuture<'a, Result<StsCredentials>> {
+        Box::pin(async move {
+            let mut cmd = command("aws");
+            cmd.args(["sts", "get-session-token"]);
+            if let Some((serial, code)) = mfa {
+                cmd.args(["--serial-number", serial, "--token-code", code]);
+            }
+            select_profile(&mut cmd, profile);
+            cmd.args(["--duration-seconds", "3600", "--output", "json"]);
+            parse_credentials(&aws_call(&mut cmd, "get-session-token").await?, "get-session-token")
+        })
+    }
+
+    fn assume_role<'a>(
+        &'a self,
+        role_arn: &'a str,
+        session_name: &'a str,
+        duration_secs: i64,
+        source: CredentialSource<'a>,
+    ) -> BoxFuture<'a, Result<StsCredentials>> {
+        Box::pin(async move {
+            let mut cmd = command("aws");
+            cmd.args(["sts", "assume-role", "--role-arn", role_arn, "--role-session-name", session_name]);
+            match source {
+                CredentialSource::Profile(profile) => select_profile(&mut cmd, profile),
+                CredentialSource::Session(base) => {
+                    cmd.env("AWS_ACCESS_KEY_ID", &base.access_key_id)
+                        .env("AWS_SECRET_ACCESS_KEY", &base.secret_access_key)
+                        .env("AWS_SESSION_TOKEN", &base.session_token);
+                }
+            }
+            let duration = duration_secs.to_string();
+            cmd.args(["--duration-seconds", duration.as_str(), "--output", "json"]);
+            parse_credentials(&aws_call(&mut cmd, "assume-role").await?, "assume-role")
+        })
+    }
+}
+
+impl SsoClient for AwsCli {
+    fn login<'a>(&'a self, profile: &'a str, extra_args: &'a [&'a str]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let mut cmd = command("aws");
+            cmd.args(["sso", "login", "--profile", profile]).args(extra_args);
+            let status = runner().status(&mut cmd).await.context("Failed to run aws sso login")?;
+            if !status.success() {
+                return Err(anyhow!("aws sso login failed"));
+            }
+            Ok(())
+        })
+    }
+
+    fn export_credentials<'a>(&'a self, profile: &'a str) -> BoxFuture<'a, Result<StsCredentials>> {
+        Box::pin(async move {
+            let mut cmd = command("aws");
+            cmd.args(["configure", "export-credentials"]);
+            select_profile(&mut cmd, profile);
+            cmd.args(["--format", "process"]);
+            let stdout = aws_call(&mut cmd, "export-credentials").await?;
+            serde_json::from_slice(&stdout).context("Parsing export-credentials JSON response failed")
+        })
+    }
+}
@@ -0,0 +1,199 @@
+// `awx config backup` / `awx config restore`.
+//
+// A backup is a single JSON document holding the text files under ~/.aws and ~/.awx
+// (minus token caches), optionally with secrets stripped, optionally encrypted with
+// `openssl enc` so the archive can travel between machines.
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+const FORMAT: &str = "awx-backup/1";
+/// Environment variable holding the passphrase for encrypted archives.
+pub const PASSPHRASE_ENV: &str = "AWX_BACKUP_PASSPHRASE";
+// Keys whose values are dropped by --exclude-secrets.
+const SECRET_KEYS: [&str; 3] = ["aws_access_key_id", "aws_secret_access_key", "aws_session_token"];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Archive {
+    pub format: String,
+    pub created_at: DateTime<Utc>,
+    pub secrets_excluded: bool,
+    pub files: Vec<ArchivedFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArchivedFile {
+    /// Path relative to the home directory, e.g. `.aws/config`.
+    pub path: String,
+    pub mode: u32,
+    pub content: String,
+}
+
+/// Roots included in a backup and the subdirectories skipped under each
+/// (short-lived token and awx caches, exports files, S3 Express sessions, session recordings,
+/// warmed sessions, the pending pairing key and registered SSO clients are never archived).
+fn roots(home: &Path) -> Vec<(PathBuf, &'static [&'static str])> {
+    vec![
+        (home.join(".aws"), &["sso", "cli"][..]),
+        (home.join(".awx"), &["cache", "backups", "recordings", "exports", "s3express", "sessions", "pair", "sso"][..]),
+    ]
+}
+
+pub fn collect(home: &Path, exclude_secrets: bool) -> Result<Archive> {
+    let mut files = Vec::new();
+    for (root, skip) in roots(home) {
+        if root.is_dir() {
+            collect_dir(home, &root, skip, &mut files)?;
+        }
+    }
+    if exclude_secrets {
+        files.retain(|f| f.path != ".aws/credentials");
+        for f in files.iter_mut() {
+            f.content = strip_secrets(&f.content);
+        }
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(Archive {
+        format: FORMAT.to_string(),
+        created_at: Utc::now(),
+        secrets_excluded: exclude_secrets,
+        files,
+    })
+}
+
+fn collect_dir(home: &Path, dir: &Path, skip: &[&str], out: &mut Vec<ArchivedFile>) -> Result<()> {
+    let entries = std::fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))?;
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            let name = entry.file_name();
+            if dir.parent() == Some(home) && skip.iter().any(|s| name == *s) {
+                continue;
+            }
+            collect_dir(home, &path, skip, out)?;
+        } else if file_type.is_file() {
+            let rel = path.strip_prefix(home).unwrap_or(&path).to_string_lossy().to_string();
+            match std::fs::read_to_string(&path) {
+                Ok(content) => out.push(ArchivedFile {
+                    path: rel,
+                    mode: file_mode(&path),
+                    content,
+                }),
+                Err(_) => eprintln!("Skipping non-text file {}", rel),
+            }
+        }
+    }
+    Ok(())
+}
+
+// Drop `key = value` lines for static credential keys from INI content.
+fn strip_secrets(content: &str) -> String {
+    content
+        .lines()
+        .filter(|line| {
+            let key = line.split('=').next().unwrap_or("").trim();
+            !SECRET_KEYS.contains(&key)
+        })
+        .map(|l| format!("{}\n", l))
+        .collect()
+}
+
+#[cfg(unix)]
+fn file_mode(path: &Path) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::metadata(path).map(|m| m.permissions().mode() & 0o777).unwrap_or(0o600)
+}
+
+#[cfg(not(unix))]
+fn file_mode(_path: &Path) -> u32 {
+    0o600
+}
+
+pub fn default_archive_path(awx_dir: &Path, encrypted: bool) -> PathBuf {
+    let stamp = Utc::now().format("%Y%m%d-%H%M%S");
+    let ext = if encrypted { "json.enc" } else { "json" };
+    awx_dir.join("backups").join(format!("awx-backup-{}.{}", stamp, ext))
+}
+
+/// Write `archive` to `dest` (0600), encrypting with `passphrase` when given.
+pub async fn write_archive(archive: &Archive, dest: &Path, passphrase: Option<&str>) -> Result<()> {
+    let json = serde_json::to_vec_pretty(archive)?;
+    let bytes = match passphrase {
+        Some(pass) => openssl(&json, pass, false).await?,
+        None => json,
+    };
+    crate::state::write(dest, &bytes)
+}
+
+/// Whether the bytes look like `openssl enc -salt` output.
+pub fn is_encrypted(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"Salted__")
+}
+
+pub async fn read_archive(bytes: &[u8], passphrase: Option<&str>) -> Result<Archive> {
+    let json = if is_encrypted(bytes) {
+        let pass = passphrase.ok_or_else(|| anyhow!("Archive is encrypted; a passphrase is required"))?;
+        openssl(bytes, pass, true).await?
+    } else {
+        bytes.to_vec()
+    };
+    let archive: Archive = serde_json::from_slice(&json).context("Archive is not a valid awx backup")?;
+    if archive.format != FORMAT {
+        return Err(anyhow!("Unsupported backup format '{}'", archive.format));
+    }
+    Ok(archive)
+}
+
+/// Restore every file of `archive` below `home`, returning the written paths. Every
+/// path is checked before anything is written; files get at most the owner's read and
+/// write bits, whatever mode the archive claims.
+pub fn restore(archive: &Archive, home: &Path) -> Result<Vec<PathBuf>> {
+    for f in archive.files.iter() {
+        check_restore_path(home, &f.path)?;
+    }
+    let mut written = Vec::new();
+    for f in archive.files.iter() {
+        let dest = home.join(&f.path);
+        if let Some(dir) = dest.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        crate::state::write_with_mode(&dest, f.content.as_bytes(), f.mode & 0o600)?;
+        written.push(dest);
+    }
+    Ok(written)
+}
+
+// A restored file must be below ~/.aws or ~/.awx, the roots a backup is taken from, and
+// nothing on its way may be a symlink, which would carry the write somewhere else.
+fn check_restore_path(home: &Path, path: &str) -> Result<()> {
+    let rel = Path::new(path);
+    let mut components = rel.components();
+    let in_root = matches!(components.next(), Some(Component::Normal(root)) if root == ".aws" || root == ".awx");
+    if !in_root || components.next().is_none() || !rel.components().all(|c| matches!(c, Component::Normal(_))) {
+        return Err(anyhow!("Refusing to restore '{}': only files under ~/.aws and ~/.awx are restored", path));
+    }
+    let mut at = home.to_path_buf();
+    for component in rel.components() {
+        at.push(component);
+        if std::fs::symlink_metadata(&at).is_ok_and(|m| m.file_type().is_symlink()) {
+            return Err(anyhow!("Refusing to restore '{}': {} is a symbolic link", path, at.display()));
+        }
+    }
+    Ok(())
+}
+
+/// Symmetric encryption via the system openssl; the passphrase is passed through the
+/// child's environment so it never appears in the process list.
+pub async fn openssl(input: &[u8], passphrase: &str, decrypt: bool) -> Result<Vec<u8>> {
+    let pass = format!("env:{}", PASSPHRASE_ENV);
+    let mut args = vec!["enc", "-aes-256-cbc", "-pbkdf2", "-salt"];
+    if decrypt {
+        args.push("-d");
+    }
+    args.extend(["-pass", pass.as_str()]);
+    crate::backend::openssl(&args, &[(PASSPHRASE_ENV, passphrase)], input, "encryption").await
+}
@@ -0,0 +1,133 @@
+// Profile badges and filtering.
+//
+// The profile picker and `awx -c` tag each profile with what it takes to use it:
+// [default], [SSO], [ROLE], [MFA] and [STATIC]. The legend printed next to them spells
+// the badges out. `--only <badge>` (repeatable, any of them) and `--account <id>`
+// (repeatable) narrow both lists; the account is the SSO account, the account of the
+// role, or the account of the MFA device, in that order, so static keys without MFA
+// have none until they are used.
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use clap::ValueEnum;
+
+use crate::Profile;
+
+static FILTER: OnceLock<Filter> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Badge {
+    Default,
+    Sso,
+    Role,
+    Mfa,
+    Static,
+}
+
+impl Badge {
+    pub const ALL: [Badge; 5] = [Badge::Default, Badge::Sso, Badge::Role, Badge::Mfa, Badge::Static];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Badge::Default => "default",
+            Badge::Sso => "SSO",
+            Badge::Role => "ROLE",
+            Badge::Mfa => "MFA",
+            Badge::Static => "STATIC",
+        }
+    }
+
+    pub fn meaning(self) -> &'static str {
+        match self {
+            Badge::Default => "used when no profile is named",
+            Badge::Sso => "signs in through IAM Identity Center (aws sso login)",
+            Badge::Role => "assumes a role (role_arn)",
+            Badge::Mfa => "asks for an MFA code (mfa_serial)",
+            Badge::Static => "long-term access keys",
+        }
+    }
+
+    fn applies(self, p: &Profile) -> bool {
+        match self {
+            Badge::Default => p.name == "default",
+            Badge::Sso => p.is_sso(),
+            Badge::Role => p.is_role(),
+            Badge::Mfa => p.requires_mfa(),
+            Badge::Static => p.is_static(),
+        }
+    }
+}
+
+/// The badges of `p`, in the order they are shown.
+pub fn badges(p: &Profile) -> Vec<Badge> {
+    Badge::ALL.into_iter().filter(|b| b.applies(p)).collect()
+}
+
+/// `[SSO][MFA]`
+pub fn tags(badges: &[Badge]) -> String {
+    badges.iter().map(|b| format!("[{}]", b.label())).collect()
+}
+
+/// The account a profile works in, when its configuration names one.
+pub fn account(p: &Profile) -> Option<String> {
+    if let Some(id) = &p.sso_account_id {
+        return Some(id.clone());
+    }
+    let arn = p.role_arn.as_deref().or(p.mfa_serial.as_deref())?;
+    let arn: crate::arn::Arn = arn.parse().ok()?;
+    arn.account_id().map(str::to_string)
+}
+
+/// `--only` and `--account`; empty lists let everything through.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Filter {
+    pub only: Vec<Badge>,
+    pub accounts: Vec<String>,
+}
+
+impl Filter {
+    pub fn is_empty(&self) -> bool {
+        self.only.is_empty() && self.accounts.is_empty()
+    }
+
+    pub fn matches(&self, p: &Profile) -> bool {
+        // A hidden `default` shows up only when asked for with `--only default`.
+        if p.name == crate::default_profile::NAME && crate::default_profile::hidden() && !self.only.contains(&Badge::Default) {
+            return false;
+        }
+        (self.only.is_empty() || self.only.iter().any(|b| b.applies(p)))
+            && (self.accounts.is_empty() || account(p).is_some_and(|a| self.accounts.contains(&a)))
+    }
+
+    /// The names of the matching profiles, sorted.
+    pub fn names(&self, profiles: &HashMap<String, Profile>) -> Vec<String> {
+        let mut names: Vec<String> = profiles.values().filter(|p| self.matches(p)).map(|p| p.name.clone()).collect();
+        names.sort();
+        names
+    }
+
+    /// `--only sso --account 123456789012`, for messages.
+    pub fn describe(&self) -> String {
+        let mut parts: Vec<String> = self.only.iter().map(|b| format!("--only {}", b.label().to_lowercase())).collect();
+        parts.extend(self.accounts.iter().map(|a| format!("--account {}", a)));
+        parts.join(" ")
+    }
+}
+
+/// Use `filter` for the profile picker of this process.
+pub fn configure(filter: Filter) {
+    let _ = FILTER.set(filter);
+}
+
+pub fn filter() -> Filter {
+    FILTER.get().cloned().unwrap_or_default()
+}
+
+/// One line per badge among `shown`.
+pub fn legend(shown: &[Badge]) -> Vec<String> {
+    Badge::ALL
+        .into_iter()
+        .filter(|b| shown.contains(b))
+        .map(|b| format!("{:<9} {}", format!("[{}]", b.label()), b.meaning()))
+        .collect()
+}
@@ -0,0 +1,40 @@
+// Reuse of MFA sessions across runs.
+//
+// A static profile with `mfa_serial` is exchanged for a GetSessionToken session before
+// it is used, and every role profile chaining from it (`source_profile`) assumes its
+// role with that session. The session is kept so that one MFA code serves all of them
+// for as long as it lasts, and switching between sibling roles does not ask again: in
+// the broker when it is enabled, so nothing lands on disk, otherwise as the static
+// profile's session file in ~/.awx/sessions, the same file `awx warm` writes for it.
+// A kept session is reused while it lasts at least five more minutes. `awx cache ls`
+// lists it, and `--clear-cache <profile>` drops it together with the sessions of the
+// roles chained from it.
+use std::path::Path;
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::session::Descriptor;
+use crate::{broker, warm, Profile, StsCredentials};
+
+/// Kept sessions are reused only while they last this long.
+const MIN_REMAINING_SECS: i64 = broker::MIN_REMAINING_SECS;
+
+/// The MFA session kept for `profile`, when one lasts long enough at `now`.
+pub fn load(awx_dir: &Path, profile: &str, now: DateTime<Utc>) -> Option<StsCredentials> {
+    if broker::is_enabled() {
+        return broker::get_for(profile, None);
+    }
+    let path = warm::default_path(awx_dir, profile, None);
+    warm::reusable(&path, profile, Duration::seconds(MIN_REMAINING_SECS), now).map(|d| d.credentials())
+}
+
+/// Keep the MFA session of `profile` for later runs; failing to keep it only means the
+/// next run asks again.
+pub fn store(awx_dir: &Path, profile: &Profile, credentials: &StsCredentials, now: DateTime<Utc>) {
+    if broker::is_enabled() {
+        return broker::put_for(&profile.name, None, credentials);
+    }
+    if let Ok(descriptor) = Descriptor::new(&profile.name, profile.region.clone(), credentials, now) {
+        let _ = descriptor.write(&warm::default_path(awx_dir, &profile.name, None));
+    }
+}
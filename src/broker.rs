@@ -0,0 +1,600 @@
+// In-memory credential broker (`awx broker`).
+//
+// For users who want no session secrets on disk at all, one long-running `awx broker`
+// keeps the sessions other awx invocations resolve in its memory only. With
+// `broker = true` in ~/.awx/config.toml (or AWX_BROKER=1), awx asks the broker for a
+// profile's session before resolving it, and hands it what it resolved, so an MFA code
+// or an AssumeRole is paid once per session rather than once per command. The broker
+// never authenticates itself; prompts stay with the invocation at the terminal.
+//
+// The socket is ~/.awx/broker.sock, created 0600 in a 0700 directory, and every
+// connection is checked to come from the broker's own user before it is read. The
+// broker also knows which executable is asking (SO_PEERCRED and /proc/<pid>/exe on
+// Linux), and `[broker_acl]` in ~/.awx/config.toml limits which executables get the
+// sessions of matching profiles, by canonical absolute path or path glob, e.g. only
+// /usr/local/bin/terraform for `prod-*`. Nothing the client says about itself counts:
+// an executable is judged by the path the kernel reports for it. When the caller is
+// the broker's own awx binary, which asks on behalf of whatever runs it (a tool's
+// `credential_process`), its parent's executable may match instead. A caller that
+// cannot be identified gets nothing from a restricted profile. Every session handed
+// out or refused is appended to ~/.awx/broker-audit.jsonl. Requests and responses are one
+// JSON line each. The broker process is not dumpable on Linux, so its memory stays out
+// of core files. Nothing is persisted: stopping the broker forgets every session. When
+// the broker is not running, awx carries on as if it were not enabled. Sessions asked
+// for with `--duration` are held next to the profile's usual one rather than in its
+// place, and forgetting a profile forgets all of them.
+//
+// A running broker takes two signals. SIGUSR1 forgets every session at once, e.g.
+// after permissions changed upstream, so the next awx run resolves a fresh session and
+// hands that over instead; SIGUSR2 prints the held sessions and the requests served so
+// far to the broker's stderr.
+//
+// The broker lives on a Unix domain socket. Elsewhere `awx broker` fails, and awx runs
+// as if the broker were not running.
+use std::collections::HashMap;
+#[cfg(unix)]
+use std::io::{BufRead, BufReader, Write};
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+#[cfg(unix)]
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+#[cfg(unix)]
+use std::time::Duration as StdDuration;
+
+#[cfg(unix)]
+use anyhow::Context;
+use anyhow::{anyhow, Result};
+#[cfg(unix)]
+use chrono::Duration;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::StsCredentials;
+
+static SOCKET: OnceLock<PathBuf> = OnceLock::new();
+
+/// A session is handed out only while it lasts at least this long.
+pub const MIN_REMAINING_SECS: i64 = 300;
+#[cfg(unix)]
+const IO_TIMEOUT: StdDuration = StdDuration::from_secs(5);
+/// How long the broker waits for a connection before looking at signals again.
+#[cfg(unix)]
+const POLL_MILLIS: i32 = 500;
+
+/// SIGUSR1 and SIGUSR2 received so far; each broker compares with what it has seen.
+#[cfg(unix)]
+static RELOADS: AtomicU64 = AtomicU64::new(0);
+#[cfg(unix)]
+static DUMPS: AtomicU64 = AtomicU64::new(0);
+
+pub fn socket_path(awx_dir: &Path) -> PathBuf {
+    awx_dir.join("broker.sock")
+}
+
+pub fn enabled_by(settings_flag: bool) -> bool {
+    settings_flag || matches!(crate::aws_env::current_lookup("AWX_BROKER").as_deref(), Some("1") | Some("true"))
+}
+
+/// Use the broker at `socket` for this process.
+pub fn enable(socket: PathBuf) {
+    let _ = SOCKET.set(socket);
+}
+
+pub fn audit_path(awx_dir: &Path) -> PathBuf {
+    awx_dir.join("broker-audit.jsonl")
+}
+
+/// Executable paths or path globs allowed to receive the sessions of profiles matching
+/// each glob.
+pub type Acl = HashMap<String, Vec<String>>;
+
+#[cfg(unix)]
+/// `acl` with every entry an absolute path, and the ones naming an existing file
+/// (rather than a glob) canonical, since the kernel reports executables that way.
+pub fn canonical_acl(acl: &Acl) -> Result<Acl> {
+    let mut canonical = Acl::new();
+    for (profiles, programs) in acl {
+        let mut paths = Vec::new();
+        for program in programs {
+            if !Path::new(program).is_absolute() {
+                return Err(anyhow!(
+                    "[broker_acl] \"{}\" names '{}': give the executable's absolute path or a path glob, e.g. /usr/local/bin/{}",
+                    profiles,
+                    program,
+                    program
+                ));
+            }
+            let glob = program.contains(['*', '?']);
+            match std::fs::canonicalize(program) {
+                Ok(path) if !glob => paths.push(path.to_string_lossy().into_owned()),
+                _ => paths.push(program.clone()),
+            }
+        }
+        canonical.insert(profiles.clone(), paths);
+    }
+    Ok(canonical)
+}
+
+/// The process on the other end of a connection.
+#[cfg(unix)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Peer {
+    pub uid: u32,
+    pub pid: Option<i32>,
+    pub exe: Option<PathBuf>,
+    /// The executable of the peer's parent process.
+    pub parent_exe: Option<PathBuf>,
+}
+
+/// The executables a session would go to, as the kernel reports them: the peer's own
+/// and, when the peer is the broker's own awx binary, its parent's.
+#[cfg(unix)]
+pub fn programs<'a>(peer: &'a Peer, own_exe: Option<&Path>) -> Vec<&'a Path> {
+    let Some(exe) = peer.exe.as_deref() else {
+        return Vec::new();
+    };
+    let mut programs = vec![exe];
+    if Some(exe) == own_exe {
+        programs.extend(peer.parent_exe.as_deref());
+    }
+    programs
+}
+
+/// Whether `acl` lets `programs` have the session of `profile`. Profiles no pattern
+/// matches are not restricted; otherwise one of the programs must be listed by one
+/// that does.
+#[cfg(unix)]
+pub fn allowed(acl: &Acl, profile: &str, programs: &[&Path]) -> std::result::Result<(), String> {
+    let mut matching = acl.iter().filter(|(pattern, _)| crate::exec::glob_match(pattern, profile)).peekable();
+    if matching.peek().is_none() {
+        return Ok(());
+    }
+    let mut allowed: Vec<&str> = matching.flat_map(|(_, programs)| programs.iter().map(String::as_str)).collect();
+    let Some(caller) = programs.first() else {
+        return Err(format!("the requesting program could not be identified; '{}' is restricted", profile));
+    };
+    let listed = |program: &&Path| allowed.iter().any(|pattern| crate::exec::glob_match(pattern, &program.to_string_lossy()));
+    if programs.iter().any(listed) {
+        return Ok(());
+    }
+    allowed.sort_unstable();
+    allowed.dedup();
+    let caller = match programs.get(1) {
+        Some(parent) => format!("{} (run by {})", caller.display(), parent.display()),
+        None => caller.display().to_string(),
+    };
+    Err(format!("{} may not use '{}' (allowed: {})", caller, profile, allowed.join(", ")))
+}
+
+/// One session handed out or refused.
+#[cfg(unix)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub time: DateTime<Utc>,
+    pub profile: String,
+    pub granted: bool,
+    pub uid: u32,
+    pub pid: Option<i32>,
+    pub exe: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_exe: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[cfg(unix)]
+fn audit(log: &Path, entry: &AuditEntry) {
+    let Ok(line) = serde_json::to_string(entry) else {
+        return;
+    };
+    let file = std::fs::OpenOptions::new().create(true).append(true).mode(0o600).open(log);
+    if let Ok(mut file) = file {
+        let _ = writeln!(file, "{}", line);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Request {
+    Get {
+        profile: String,
+        /// The `--duration` the session was asked for with.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        duration: Option<i64>,
+    },
+    Put {
+        profile: String,
+        credentials: StsCredentials,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        duration: Option<i64>,
+    },
+    Forget { profile: String },
+    List,
+    Stop,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Response {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credentials: Option<StsCredentials>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sessions: Vec<Held>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A session the broker holds, without its secrets.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Held {
+    pub profile: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<i64>,
+    pub expiration: DateTime<Utc>,
+}
+
+impl Held {
+    /// `<profile>` or `<profile>@<duration>`, as `awx cache ls` shows it.
+    pub fn key(&self) -> String {
+        crate::duration::cache_key(&self.profile, self.duration)
+    }
+}
+
+#[cfg(unix)]
+impl Response {
+    fn ok() -> Response {
+        Response { ok: true, ..Default::default() }
+    }
+
+    fn error(message: String) -> Response {
+        Response { error: Some(message), ..Default::default() }
+    }
+}
+
+/// The sessions the broker holds, by profile and `--duration`.
+#[cfg(unix)]
+#[derive(Debug, Default)]
+pub struct Store {
+    sessions: HashMap<(String, Option<i64>), (StsCredentials, DateTime<Utc>)>,
+    served: usize,
+}
+
+#[cfg(unix)]
+impl Store {
+    pub fn handle(&mut self, request: Request, now: DateTime<Utc>) -> Response {
+        self.sessions.retain(|_, (_, expiration)| *expiration > now);
+        self.served += 1;
+        match request {
+            Request::Get { profile, duration } => match self.sessions.get(&(profile, duration)) {
+                Some((creds, expiration)) if *expiration - now >= Duration::seconds(MIN_REMAINING_SECS) => Response {
+                    credentials: Some(creds.clone()),
+                    ..Response::ok()
+                },
+                _ => Response::ok(),
+            },
+            Request::Put {
+                profile,
+                credentials,
+                duration,
+            } => match DateTime::parse_from_rfc3339(&credentials.expiration) {
+                Ok(expiration) if expiration > now => {
+                    self.sessions.insert((profile, duration), (credentials, expiration.with_timezone(&Utc)));
+                    Response::ok()
+                }
+                Ok(_) => Response::error("the session has already expired".to_string()),
+                Err(_) => Response::error("sessions without an expiration are not held".to_string()),
+            },
+            Request::Forget { profile } => {
+                self.sessions.retain(|(held, _), _| *held != profile);
+                Response::ok()
+            }
+            Request::List => Response {
+                sessions: self.held(),
+                ..Response::ok()
+            },
+            Request::Stop => Response::ok(),
+        }
+    }
+
+    fn held(&self) -> Vec<Held> {
+        let mut sessions: Vec<Held> = self
+            .sessions
+            .iter()
+            .map(|((profile, duration), (_, expiration))| Held {
+                profile: profile.clone(),
+                duration: *duration,
+                expiration: *expiration,
+            })
+            .collect();
+        sessions.sort_by(|a, b| (&a.profile, a.duration).cmp(&(&b.profile, b.duration)));
+        sessions
+    }
+
+    /// Forget every session (SIGUSR1) and return how many there were.
+    pub fn forget_all(&mut self) -> usize {
+        let count = self.sessions.len();
+        self.sessions.clear();
+        count
+    }
+
+    /// What SIGUSR2 prints: the held sessions, without secrets, and requests served.
+    pub fn status(&self, now: DateTime<Utc>) -> String {
+        let mut out = format!("broker: {} session(s) held, {} request(s) served\n", self.sessions.len(), self.served);
+        for held in self.held() {
+            out.push_str(&format!("  {}  {}\n", held.key(), crate::render::expiration(held.expiration, now)));
+        }
+        out
+    }
+}
+
+#[cfg(unix)]
+extern "C" fn on_signal(signal: libc::c_int) {
+    match signal {
+        libc::SIGUSR1 => RELOADS.fetch_add(1, Ordering::SeqCst),
+        _ => DUMPS.fetch_add(1, Ordering::SeqCst),
+    };
+}
+
+#[cfg(unix)]
+fn catch_signals() {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = on_signal as *const () as libc::sighandler_t;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(libc::SIGUSR1, &action, std::ptr::null_mut());
+        libc::sigaction(libc::SIGUSR2, &action, std::ptr::null_mut());
+    }
+}
+
+/// Signals received since `seen`, which is brought up to date.
+#[cfg(unix)]
+fn received(counter: &AtomicU64, seen: &mut u64) -> bool {
+    let now = counter.load(Ordering::SeqCst);
+    std::mem::replace(seen, now) != now
+}
+
+/// Wait a little for a connection; false when none is pending.
+#[cfg(unix)]
+fn pending(listener: &UnixListener) -> bool {
+    let mut fd = libc::pollfd {
+        fd: listener.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    unsafe { libc::poll(&mut fd, 1, POLL_MILLIS) > 0 }
+}
+
+/// Serve requests on `socket` until a `stop` request, handing out sessions as `acl`
+/// allows and recording each in `audit_log`. Runs on the calling thread.
+#[cfg(unix)]
+pub fn serve(socket: &Path, acl: &Acl, audit_log: &Path) -> Result<()> {
+    let acl = &canonical_acl(acl)?;
+    if let Some(dir) = socket.parent() {
+        std::fs::create_dir_all(dir)?;
+        std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))?;
+    }
+    if UnixStream::connect(socket).is_ok() {
+        return Err(anyhow!("A broker is already listening on {}", socket.display()));
+    }
+    // Before the socket appears, so a client seeing it may signal the broker.
+    catch_signals();
+    // A socket left behind by a broker that did not stop cleanly.
+    let _ = std::fs::remove_file(socket);
+    let listener = UnixListener::bind(socket).with_context(|| format!("Failed to listen on {}", socket.display()))?;
+    std::fs::set_permissions(socket, std::fs::Permissions::from_mode(0o600))?;
+    undumpable();
+
+    let uid = unsafe { libc::geteuid() };
+    let own_exe = std::env::current_exe().and_then(|p| p.canonicalize()).ok();
+    let mut store = Store::default();
+    let (mut reloads, mut dumps) = (RELOADS.load(Ordering::SeqCst), DUMPS.load(Ordering::SeqCst));
+    loop {
+        let ready = pending(&listener);
+        if received(&RELOADS, &mut reloads) {
+            eprintln!("broker: SIGUSR1, forgot {} session(s); they are resolved afresh on next use", store.forget_all());
+        }
+        if received(&DUMPS, &mut dumps) {
+            eprint!("{}", store.status(Utc::now()));
+        }
+        if !ready {
+            continue;
+        }
+        let Ok((mut stream, _)) = listener.accept() else {
+            continue;
+        };
+        let Some(peer) = peer(&stream).ok().filter(|p| p.uid == uid) else {
+            let _ = writeln!(stream, "{}", line(&Response::error("permission denied".to_string())));
+            continue;
+        };
+        let _ = stream.set_read_timeout(Some(IO_TIMEOUT));
+        let _ = stream.set_write_timeout(Some(IO_TIMEOUT));
+        let mut request = String::new();
+        if BufReader::new(&stream).read_line(&mut request).is_err() {
+            continue;
+        }
+        let (response, stop) = match serde_json::from_str::<Request>(&request) {
+            Ok(Request::Get { profile, duration }) => {
+                let now = Utc::now();
+                let (response, reason) = match allowed(acl, &profile, &programs(&peer, own_exe.as_deref())) {
+                    Ok(()) => {
+                        let request = Request::Get { profile: profile.clone(), duration };
+                        (store.handle(request, now), None)
+                    }
+                    Err(reason) => (Response::error(reason.clone()), Some(reason)),
+                };
+                if response.credentials.is_some() || reason.is_some() {
+                    let entry = AuditEntry {
+                        time: now,
+                        profile,
+                        granted: reason.is_none(),
+                        uid: peer.uid,
+                        pid: peer.pid,
+                        exe: peer.exe.clone(),
+                        parent_exe: peer.parent_exe.clone(),
+                        reason,
+                    };
+                    audit(audit_log, &entry);
+                }
+                (response, false)
+            }
+            Ok(request) => {
+                let stop = request == Request::Stop;
+                (store.handle(request, Utc::now()), stop)
+            }
+            Err(e) => (Response::error(format!("bad request: {}", e)), false),
+        };
+        let _ = writeln!(stream, "{}", line(&response));
+        if stop {
+            break;
+        }
+    }
+    let _ = std::fs::remove_file(socket);
+    Ok(())
+}
+
+#[cfg(unix)]
+fn line(response: &Response) -> String {
+    serde_json::to_string(response).unwrap_or_default()
+}
+
+#[cfg(not(unix))]
+pub fn serve(_socket: &Path, _acl: &Acl, _audit_log: &Path) -> Result<()> {
+    Err(anyhow!("awx broker needs Unix domain sockets and is not supported on this platform"))
+}
+
+/// Send `request` to the broker at `socket`.
+#[cfg(unix)]
+pub fn call(socket: &Path, request: &Request) -> Result<Response> {
+    let mut stream =
+        UnixStream::connect(socket).with_context(|| format!("No broker is listening on {}", socket.display()))?;
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    writeln!(stream, "{}", serde_json::to_string(request)?)?;
+    let mut answer = String::new();
+    BufReader::new(&stream).read_line(&mut answer)?;
+    let response: Response = serde_json::from_str(&answer).context("Unexpected answer from the broker")?;
+    match &response.error {
+        Some(error) => Err(anyhow!("Broker: {}", error)),
+        None => Ok(response),
+    }
+}
+
+#[cfg(not(unix))]
+pub fn call(socket: &Path, _request: &Request) -> Result<Response> {
+    Err(anyhow!("No broker is listening on {}", socket.display()))
+}
+
+/// The session the broker holds for `profile` and this run's `--duration`, when the
+/// broker is enabled, has one and hands it to this process.
+pub fn get(profile: &str) -> Option<StsCredentials> {
+    get_for(profile, crate::duration::requested())
+}
+
+/// `get` for the session of `profile` asked for with `duration`.
+pub fn get_for(profile: &str, duration: Option<i64>) -> Option<StsCredentials> {
+    let socket = SOCKET.get()?;
+    let request = Request::Get {
+        profile: profile.to_string(),
+        duration,
+    };
+    match call(socket, &request) {
+        Ok(response) => response.credentials,
+        Err(e) => {
+            if socket.exists() {
+                crate::progress::clear();
+                eprintln!("{}", crate::theme::style(crate::theme::Role::Muted).apply_to(format!("{:#}", e)));
+            }
+            None
+        }
+    }
+}
+
+/// Hand a resolved session to the broker, when enabled; never fails the caller.
+pub fn put(profile: &str, credentials: &StsCredentials) {
+    put_for(profile, crate::duration::requested(), credentials)
+}
+
+/// `put` for a session of `profile` asked for with `duration`.
+pub fn put_for(profile: &str, duration: Option<i64>, credentials: &StsCredentials) {
+    if let Some(socket) = SOCKET.get() {
+        let request = Request::Put {
+            profile: profile.to_string(),
+            credentials: credentials.clone(),
+            duration,
+        };
+        let _ = call(socket, &request);
+    }
+}
+
+/// Whether sessions go to the broker rather than to disk.
+pub fn is_enabled() -> bool {
+    SOCKET.get().is_some()
+}
+
+/// Make the broker forget `profile`'s sessions, when enabled; whether it was reached.
+pub fn forget(profile: &str) -> bool {
+    let Some(socket) = SOCKET.get() else {
+        return false;
+    };
+    call(socket, &Request::Forget { profile: profile.to_string() }).is_ok()
+}
+
+#[cfg(target_os = "linux")]
+fn peer(stream: &UnixStream) -> std::io::Result<Peer> {
+    use std::os::unix::io::AsRawFd;
+    let mut cred: libc::ucred = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let rc = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&mut cred as *mut libc::ucred).cast(),
+            &mut len,
+        )
+    };
+    if rc != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let exe = |pid: i32| std::fs::read_link(format!("/proc/{}/exe", pid)).ok();
+    // The parent pid is the field after the command name, which may hold anything but
+    // ends with the last ')'.
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", cred.pid)).unwrap_or_default();
+    let parent = stat.rsplit_once(')').and_then(|(_, rest)| rest.split_whitespace().nth(1)?.parse().ok());
+    Ok(Peer {
+        uid: cred.uid,
+        pid: Some(cred.pid),
+        exe: exe(cred.pid),
+        parent_exe: parent.and_then(exe),
+    })
+}
+
+// Elsewhere only the user is known, so restricted profiles are refused.
+#[cfg(all(unix, not(target_os = "linux")))]
+fn peer(stream: &UnixStream) -> std::io::Result<Peer> {
+    use std::os::unix::io::AsRawFd;
+    let (mut uid, mut gid) = (0, 0);
+    if unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(Peer {
+        uid,
+        pid: None,
+        exe: None,
+        parent_exe: None,
+    })
+}
+
+#[cfg(unix)]
+fn undumpable() {
+    #[cfg(target_os = "linux")]
+    unsafe {
+        libc::prctl(libc::PR_SET_DUMPABLE, 0);
+    }
+}
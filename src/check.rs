@@ -0,0 +1,132 @@
+// Whether a profile can be used without anyone at the terminal (`awx check`).
+//
+// For Makefiles and cron jobs deciding whether to ask for a login somewhere else: the
+// answer comes from what is cached locally, never from a prompt or an AWS call. A
+// profile is ready when its `awx warm` session file or the broker's session lasts
+// `--min-remaining`, or when everything it is resolved from does: the SSO token the AWS
+// CLI cached, the kept MFA session of a static source profile (see `base_session`), or
+// credentials that need no sign-in at all (static keys without MFA, `credential_source`,
+// web identity and other providers the AWS CLI runs itself). A role counts as ready
+// when its source is, since assuming it asks nothing. `awx check` exits 0 when the
+// profile is ready and 2 when it needs authentication.
+use std::collections::HashMap;
+use std::path::Path;
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::{base_session, broker, sso, warm, Profile};
+
+/// What was found for a profile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    /// Usable without authenticating; `expires` is None for credentials that do not.
+    Ready { source: String, expires: Option<DateTime<Utc>> },
+    NeedsAuth { reason: String },
+}
+
+impl Verdict {
+    pub fn is_ready(&self) -> bool {
+        matches!(self, Verdict::Ready { .. })
+    }
+}
+
+fn lasts(expires: DateTime<Utc>, min_remaining: Duration, now: DateTime<Utc>) -> bool {
+    expires - now >= min_remaining
+}
+
+/// Check `profile` with the files under `aws_dir` and `awx_dir` at `now`.
+pub fn check(
+    profiles: &HashMap<String, Profile>,
+    profile: &Profile,
+    aws_dir: &Path,
+    awx_dir: &Path,
+    min_remaining: Duration,
+    now: DateTime<Utc>,
+) -> Verdict {
+    let path = warm::default_path(awx_dir, &profile.name, crate::duration::requested());
+    if let Some(session) = warm::reusable(&path, &profile.name, min_remaining, now) {
+        return Verdict::Ready {
+            source: format!("session file {}", path.display()),
+            expires: Some(session.expiration),
+        };
+    }
+    let held = broker::get(&profile.name)
+        .and_then(|c| DateTime::parse_from_rfc3339(&c.expiration).ok())
+        .map(|e| e.with_timezone(&Utc));
+    if let Some(expires) = held.filter(|e| lasts(*e, min_remaining, now)) {
+        return Verdict::Ready {
+            source: "broker".to_string(),
+            expires: Some(expires),
+        };
+    }
+    resolvable(profiles, profile, aws_dir, awx_dir, min_remaining, now, 0)
+}
+
+// Whether `profile` resolves without a prompt from what is cached.
+fn resolvable(
+    profiles: &HashMap<String, Profile>,
+    profile: &Profile,
+    aws_dir: &Path,
+    awx_dir: &Path,
+    min_remaining: Duration,
+    now: DateTime<Utc>,
+    depth: usize,
+) -> Verdict {
+    let name = &profile.name;
+    if profile.is_role() {
+        return match (&profile.source_profile, &profile.credential_source) {
+            (Some(source), _) => match profiles.get(source) {
+                // Guard against source_profile loops.
+                Some(base) if depth < 10 => resolvable(profiles, base, aws_dir, awx_dir, min_remaining, now, depth + 1),
+                _ => Verdict::NeedsAuth {
+                    reason: format!("source_profile '{}' of '{}' cannot be resolved", source, name),
+                },
+            },
+            (None, Some(source)) => Verdict::Ready {
+                source: format!("credential_source {}", source),
+                expires: None,
+            },
+            (None, None) => Verdict::Ready {
+                source: "the AWS CLI (web identity)".to_string(),
+                expires: None,
+            },
+        };
+    }
+    if profile.is_sso() || profile.sso_session.is_some() {
+        let key = profile.sso_session.as_deref().or(profile.sso_start_url.as_deref()).unwrap_or_default();
+        return match sso::cached_token_expiry(aws_dir, key) {
+            Some(expires) if lasts(expires, min_remaining, now) => Verdict::Ready {
+                source: format!("SSO token of '{}'", name),
+                expires: Some(expires),
+            },
+            Some(expires) if expires > now => Verdict::NeedsAuth {
+                reason: format!("the SSO token of '{}' expires in {}", name, crate::render::duration(expires - now)),
+            },
+            _ => Verdict::NeedsAuth {
+                reason: format!("'{}' needs an SSO login", name),
+            },
+        };
+    }
+    if let Some(mfa) = &profile.mfa_serial {
+        let kept = base_session::load(awx_dir, name, now)
+            .and_then(|c| DateTime::parse_from_rfc3339(&c.expiration).ok())
+            .map(|e| e.with_timezone(&Utc));
+        return match kept {
+            Some(expires) if lasts(expires, min_remaining, now) => Verdict::Ready {
+                source: format!("MFA session of '{}'", name),
+                expires: Some(expires),
+            },
+            _ => Verdict::NeedsAuth {
+                reason: format!("'{}' needs an MFA code ({})", name, mfa),
+            },
+        };
+    }
+    Verdict::Ready {
+        source: if profile.is_static() {
+            format!("static keys of '{}'", name)
+        } else {
+            format!("the AWS CLI's own providers for '{}'", name)
+        },
+        expires: None,
+    }
+}
@@ -0,0 +1,217 @@
+// Selective cache clearing (`--clear-cache`).
+//
+// A selector names what to forget: one profile, `all`, `expired`, everything signed in
+// through one SSO identity (`sso:<start-url>`, or `sso:<session>` for an sso-session
+// block), every profile assuming a role (`role:<arn>`) or every profile in an account
+// (`account:<id>`). For the profiles selected, their `awx warm` session files are
+// deleted and the broker forgets their sessions; profiles that get their credentials
+// through a selected one (`source_profile`) go with it. `sso:` and `all` also delete the
+// SSO access tokens the AWS CLI cached in ~/.aws/sso/cache, which signs the identity
+// out; the other selectors leave tokens alone so sibling profiles stay signed in.
+// `expired` deletes only session files and SSO tokens past their expiration.
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+
+use crate::arn::{self, Arn, RoleSpec};
+use crate::janitor::Entry;
+use crate::{session, sso, warm, Profile};
+
+/// What `--clear-cache` forgets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Selector {
+    All,
+    Expired,
+    Profile(String),
+    /// A legacy start URL or an sso-session name.
+    Sso(String),
+    Role(Arn),
+    Account(String),
+}
+
+impl Selector {
+    pub fn parse(s: &str) -> Result<Selector> {
+        Ok(match s.split_once(':') {
+            Some(("sso", value)) if !value.is_empty() => Selector::Sso(value.to_string()),
+            Some(("role", value)) => Selector::Role(arn::parse_role(value)?),
+            Some(("account", value)) if value.len() == 12 && value.chars().all(|c| c.is_ascii_digit()) => {
+                Selector::Account(value.to_string())
+            }
+            Some(("account", value)) => return Err(anyhow!("'{}' is not a 12-digit account id", value)),
+            Some(_) => {
+                return Err(anyhow!(
+                    "unknown selector '{}': expected a profile, all, expired, sso:<start-url>, role:<arn> or account:<id>",
+                    s
+                ))
+            }
+            None if s == "all" => Selector::All,
+            None if s == "expired" => Selector::Expired,
+            None => Selector::Profile(s.to_string()),
+        })
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Selector::All => write!(f, "all"),
+            Selector::Expired => write!(f, "expired"),
+            Selector::Profile(name) => write!(f, "{}", name),
+            Selector::Sso(url) => write!(f, "sso:{}", url),
+            Selector::Role(arn) => write!(f, "role:{}", arn),
+            Selector::Account(id) => write!(f, "account:{}", id),
+        }
+    }
+}
+
+/// What clearing a selector deletes and which profiles the broker forgets.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Plan {
+    pub entries: Vec<Entry>,
+    pub profiles: Vec<String>,
+}
+
+/// What `selector` covers at `now`.
+pub fn plan(
+    selector: &Selector,
+    profiles: &HashMap<String, Profile>,
+    aws_dir: &Path,
+    awx_dir: &Path,
+    now: DateTime<Utc>,
+) -> Result<Plan> {
+    let mut entries = Vec::new();
+    let mut add = |path: std::path::PathBuf, reason| {
+        if let Ok(meta) = std::fs::metadata(&path) {
+            entries.push(Entry { path, reason, bytes: meta.len() });
+        }
+    };
+    let identities = sso::identities(profiles);
+    let selected: BTreeSet<String> = match selector {
+        Selector::Expired => {
+            for cached in warm::cached(awx_dir).into_iter().filter(|c| c.expiration <= now) {
+                add(cached.path, "expired session");
+            }
+            for identity in identities.iter().filter(|i| !i.signed_in(aws_dir, now)) {
+                add(token_path(aws_dir, identity), "expired SSO token");
+            }
+            BTreeSet::new()
+        }
+        Selector::All => {
+            // Including sessions of profiles no longer in the config.
+            for cached in warm::cached(awx_dir) {
+                add(cached.path, if cached.expiration <= now { "expired session" } else { "session" });
+            }
+            for identity in &identities {
+                add(token_path(aws_dir, identity), "SSO token");
+            }
+            profiles.keys().filter(|name| !name.starts_with("sso-session ")).cloned().collect()
+        }
+        Selector::Profile(name) => {
+            if !profiles.contains_key(name) && warm::session_files(awx_dir, name).is_empty() {
+                return Err(anyhow!(
+                    "no profile named '{}'; --clear-cache takes a profile, all, expired, sso:<start-url>, role:<arn> or account:<id>",
+                    name
+                ));
+            }
+            BTreeSet::from([name.clone()])
+        }
+        Selector::Sso(target) => {
+            let target = target.trim_end_matches('/');
+            let matching: Vec<&sso::Identity> = identities
+                .iter()
+                .filter(|i| i.start_url.trim_end_matches('/') == target || i.session.as_deref() == Some(target))
+                .collect();
+            if matching.is_empty() {
+                return Err(anyhow!("no profile signs in with SSO at '{}'", target));
+            }
+            for identity in &matching {
+                add(token_path(aws_dir, identity), "SSO token");
+            }
+            matching.iter().flat_map(|i| i.profiles.iter().cloned()).collect()
+        }
+        Selector::Role(target) => profiles
+            .iter()
+            .filter(|(_, p)| p.role_arn.as_deref().is_some_and(|role| assumes(role, target)))
+            .map(|(name, _)| name.clone())
+            .collect(),
+        Selector::Account(id) => profiles
+            .iter()
+            .filter(|(_, p)| account_of(p).as_deref() == Some(id.as_str()))
+            .map(|(name, _)| name.clone())
+            .collect(),
+    };
+    let selected = with_dependents(profiles, selected);
+    if *selector == Selector::All {
+        return Ok(Plan {
+            entries,
+            profiles: selected.into_iter().collect(),
+        });
+    }
+    for name in &selected {
+        for path in warm::session_files(awx_dir, name) {
+            let expired = std::fs::read_to_string(&path)
+                .ok()
+                .and_then(|c| serde_json::from_str::<session::Descriptor>(&c).ok())
+                .is_none_or(|s| s.expiration <= now);
+            add(path, if expired { "expired session" } else { "session" });
+        }
+    }
+    Ok(Plan {
+        entries,
+        profiles: selected.into_iter().collect(),
+    })
+}
+
+fn token_path(aws_dir: &Path, identity: &sso::Identity) -> std::path::PathBuf {
+    sso::token_cache_path(aws_dir, identity.session.as_deref().unwrap_or(&identity.start_url))
+}
+
+/// Whether a profile's `role_arn`, as written, names `target`. A bare role name may be
+/// in any account, so it matches the role of that name everywhere.
+fn assumes(role_arn: &str, target: &Arn) -> bool {
+    let resource = |name: &str| target.resource == format!("role/{}", name);
+    match RoleSpec::parse(role_arn) {
+        Ok(RoleSpec::Arn(arn)) => arn.account == target.account && arn.resource == target.resource,
+        Ok(RoleSpec::InAccount { account, name }) => account == target.account && resource(&name),
+        Ok(RoleSpec::Name(name)) => resource(&name),
+        Err(_) => false,
+    }
+}
+
+/// The account a profile's sessions belong to, when the config says.
+fn account_of(profile: &Profile) -> Option<String> {
+    if let Some(role) = &profile.role_arn {
+        return match RoleSpec::parse(role).ok()? {
+            RoleSpec::Arn(arn) => arn.account_id().map(str::to_string),
+            RoleSpec::InAccount { account, .. } => Some(account),
+            RoleSpec::Name(_) => None,
+        };
+    }
+    profile.sso_account_id.clone()
+}
+
+/// `selected` and every profile that gets its credentials through one of them.
+fn with_dependents(profiles: &HashMap<String, Profile>, mut selected: BTreeSet<String>) -> BTreeSet<String> {
+    loop {
+        let more: Vec<String> = profiles
+            .iter()
+            .filter(|(name, p)| !selected.contains(*name) && p.source_profile.as_ref().is_some_and(|s| selected.contains(s)))
+            .map(|(name, _)| name.clone())
+            .collect();
+        if more.is_empty() {
+            return selected;
+        }
+        selected.extend(more);
+    }
+}
+
+/// Delete the plan's files and make the broker forget its profiles; returns the files
+/// removed and whether the broker was reached.
+pub fn apply(plan: Plan) -> (Vec<Entry>, bool) {
+    let reached: Vec<bool> = plan.profiles.iter().map(|name| crate::broker::forget(name)).collect();
+    let forgot = reached.contains(&true);
+    (crate::janitor::remove(plan.entries), forgot)
+}
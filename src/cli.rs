@@ -0,0 +1,119 @@
+// AWS CLI conveniences per profile (`cli_auto_prompt`, `cli_alias_file`).
+//
+// `cli_auto_prompt = "on-partial"` under `[profiles.<name>]` sets AWS_CLI_AUTO_PROMPT
+// for the AWS CLI commands run with that profile, so an unfinished command such as
+// `awx -p sandbox -- ec2` opens the CLI's guided prompt, which helps in an unfamiliar
+// account; "on" prompts for every command. awx's own calls never get it, and a value
+// already in the environment wins. `cli_alias_file` names a file of CLI aliases in the
+// format of ~/.aws/cli/alias (`[toplevel]`, `name = command`). The CLI itself only reads
+// ~/.aws/cli/alias, so awx expands the first word of the command from this file before
+// passing it on, which also picks the right `[services.<name>]` preset. Multi-line
+// aliases, shell aliases (`!f() { ... }`) and aliases with quotes are left to the CLI.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+
+use crate::settings::ProfileSettings;
+use crate::{aws_env, config_parser, service};
+
+pub const AUTO_PROMPT_ENV: &str = "AWS_CLI_AUTO_PROMPT";
+
+static PROFILES: OnceLock<HashMap<String, Options>> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AutoPrompt {
+    On,
+    OnPartial,
+}
+
+impl AutoPrompt {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AutoPrompt::On => "on",
+            AutoPrompt::OnPartial => "on-partial",
+        }
+    }
+}
+
+/// The CLI options of one profile.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Options {
+    pub auto_prompt: Option<AutoPrompt>,
+    pub alias_file: Option<PathBuf>,
+}
+
+/// Apply the `[profiles.<name>]` CLI options for this process.
+pub fn configure(profiles: &HashMap<String, ProfileSettings>) {
+    let options: HashMap<String, Options> = profiles
+        .iter()
+        .filter(|(_, p)| p.cli_auto_prompt.is_some() || p.cli_alias_file.is_some())
+        .map(|(name, p)| {
+            let alias_file = p.cli_alias_file.as_deref().map(expand_home);
+            (name.clone(), Options { auto_prompt: p.cli_auto_prompt, alias_file })
+        })
+        .collect();
+    if !options.is_empty() {
+        let _ = PROFILES.set(options);
+    }
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+fn options(profile: &str) -> Option<&'static Options> {
+    PROFILES.get()?.get(profile)
+}
+
+/// The aliases in `path` awx can expand, by name.
+pub fn aliases(path: &Path) -> HashMap<String, Vec<String>> {
+    let Ok(content) = std::fs::read_to_string(path) else {
+        return HashMap::new();
+    };
+    let mut sections = config_parser::parse(&content).sections;
+    sections
+        .remove("toplevel")
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(name, value)| name != config_parser::DESCRIPTION_KEY && !value.is_empty())
+        .filter(|(_, value)| !value.starts_with('!') && !value.contains(['"', '\'']))
+        .map(|(name, value)| (name, value.split_whitespace().map(str::to_string).collect()))
+        .collect()
+}
+
+/// `args` with an alias from `aliases` in place of the command's first word.
+pub fn expand(args: &[String], aliases: &HashMap<String, Vec<String>>) -> Vec<String> {
+    let Some(word) = service::name(args) else {
+        return args.to_vec();
+    };
+    let Some(at) = args.iter().position(|a| a == word) else {
+        return args.to_vec();
+    };
+    match aliases.get(word) {
+        Some(expansion) => args[..at].iter().chain(expansion).chain(&args[at + 1..]).cloned().collect(),
+        None => args.to_vec(),
+    }
+}
+
+/// The AWS CLI arguments `profile` runs `args` with.
+pub fn args_for(profile: &str, args: &[String]) -> Vec<String> {
+    match options(profile).and_then(|o| o.alias_file.as_deref()) {
+        Some(path) if !args.is_empty() => expand(args, &aliases(path)),
+        _ => args.to_vec(),
+    }
+}
+
+/// AWS_CLI_AUTO_PROMPT for an AWS CLI command of `profile`, unless already set.
+pub fn env_for(profile: &str, lookup: &impl Fn(&str) -> Option<String>) -> Option<(&'static str, &'static str)> {
+    let mode = options(profile)?.auto_prompt?;
+    if aws_env::get(lookup, AUTO_PROMPT_ENV).is_some() {
+        return None;
+    }
+    Some((AUTO_PROMPT_ENV, mode.as_str()))
+}
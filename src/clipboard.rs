@@ -0,0 +1,98 @@
+// The system clipboard, for `awx export --clipboard`.
+//
+// awx has no clipboard of its own; it drives the platform's tools: pbcopy/pbpaste on
+// macOS, wl-copy/wl-paste under Wayland, xclip or xsel under X11 and clip.exe (with
+// PowerShell to read back) under WSL. The first candidate that runs is used. Copied
+// credentials are cleared after a delay, but only when the clipboard still holds them,
+// so something the user copied in the meantime is left alone.
+use anyhow::{anyhow, Result};
+
+use crate::backend;
+
+/// Seconds the clipboard keeps copied exports unless configured otherwise.
+pub const DEFAULT_CLEAR_SECS: i64 = 30;
+
+/// One way to write and read the clipboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tool {
+    pub copy: &'static [&'static str],
+    /// None where the clipboard cannot be read back; clearing is then unconditional.
+    pub paste: Option<&'static [&'static str]>,
+}
+
+const PBCOPY: Tool = Tool {
+    copy: &["pbcopy"],
+    paste: Some(&["pbpaste"]),
+};
+const WL_COPY: Tool = Tool {
+    copy: &["wl-copy"],
+    paste: Some(&["wl-paste", "--no-newline"]),
+};
+const XCLIP: Tool = Tool {
+    copy: &["xclip", "-selection", "clipboard"],
+    paste: Some(&["xclip", "-selection", "clipboard", "-o"]),
+};
+const XSEL: Tool = Tool {
+    copy: &["xsel", "--clipboard", "--input"],
+    paste: Some(&["xsel", "--clipboard", "--output"]),
+};
+const CLIP_EXE: Tool = Tool {
+    copy: &["clip.exe"],
+    paste: Some(&["powershell.exe", "-NoProfile", "-Command", "Get-Clipboard"]),
+};
+
+/// The tools to try on `os` with the display variables `lookup` sees, in order.
+pub fn candidates(os: &str, lookup: &dyn Fn(&str) -> Option<String>) -> Vec<Tool> {
+    if os == "macos" {
+        return vec![PBCOPY];
+    }
+    let mut tools = Vec::new();
+    if lookup("WAYLAND_DISPLAY").is_some() {
+        tools.push(WL_COPY);
+    }
+    if lookup("DISPLAY").is_some() {
+        tools.extend([XCLIP, XSEL]);
+    }
+    if lookup("WSL_DISTRO_NAME").is_some() {
+        tools.push(CLIP_EXE);
+    }
+    tools
+}
+
+async fn run(argv: &[&str], input: Option<&[u8]>) -> std::io::Result<std::process::Output> {
+    let mut cmd = backend::command(argv[0]);
+    cmd.args(&argv[1..]);
+    backend::runner().output(&mut cmd, input).await
+}
+
+/// Copy `text` with the first of `tools` that works, and return that tool.
+pub async fn copy(tools: &[Tool], text: &str) -> Result<Tool> {
+    for tool in tools {
+        match run(tool.copy, Some(text.as_bytes())).await {
+            Ok(output) if output.status.success() => return Ok(*tool),
+            _ => continue,
+        }
+    }
+    let tried: Vec<&str> = tools.iter().map(|t| t.copy[0]).collect();
+    Err(anyhow!(
+        "No clipboard tool worked{}; install wl-clipboard, xclip or xsel, or copy the output of `awx export`",
+        if tried.is_empty() { String::new() } else { format!(" (tried {})", tried.join(", ")) }
+    ))
+}
+
+/// Empty the clipboard if it still holds `text`. Returns whether it was cleared.
+pub async fn clear_if_unchanged(tool: Tool, text: &str) -> Result<bool> {
+    if let Some(paste) = tool.paste {
+        let output = run(paste, None).await?;
+        let current = String::from_utf8_lossy(&output.stdout);
+        // Some tools add or drop a trailing newline.
+        if output.status.success() && current.trim_end() != text.trim_end() {
+            return Ok(false);
+        }
+    }
+    let output = run(tool.copy, Some(b"")).await?;
+    if !output.status.success() {
+        return Err(anyhow!("{} failed to clear the clipboard", tool.copy[0]));
+    }
+    Ok(true)
+}
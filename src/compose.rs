@@ -0,0 +1,118 @@
+// Credentials for containerized dev stacks (`awx compose`).
+//
+// `awx compose -- up` runs `docker compose` with a profile's short-lived credentials in
+// its environment, where compose's `${AWS_ACCESS_KEY_ID}` interpolation (or a bare
+// `environment: [AWS_ACCESS_KEY_ID, ...]` entry) hands them to the services. For stacks
+// started elsewhere, such as a dev container's `initializeCommand`, `--env-file` writes
+// the same credentials as a dotenv file instead: readable by the owner only, added to
+// the repository's .git/info/exclude when git would otherwise track it, and registered
+// in ~/.awx/compose-env.json so `awx cache gc` deletes it once the credentials expire.
+// Static keys are never written; a static profile is exchanged for a session first.
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+
+use crate::session::Descriptor;
+
+/// First line of every file awx writes; only such files are ever deleted.
+const HEADER: &str = "# Written by awx";
+
+fn registry_path(awx_dir: &Path) -> PathBuf {
+    awx_dir.join("compose-env.json")
+}
+
+/// Dotenv lines carrying the session in `descriptor`.
+pub fn env_file(descriptor: &Descriptor) -> String {
+    let expiration = descriptor.expiration.to_rfc3339();
+    let mut out = format!(
+        "{} for profile '{}'; expires {}. Do not commit.\n",
+        HEADER, descriptor.profile, expiration
+    );
+    let mut vars = vec![
+        ("AWS_ACCESS_KEY_ID", descriptor.access_key_id.as_str()),
+        ("AWS_SECRET_ACCESS_KEY", descriptor.secret_access_key.as_str()),
+        ("AWS_SESSION_TOKEN", descriptor.session_token.as_str()),
+        ("AWS_CREDENTIAL_EXPIRATION", expiration.as_str()),
+    ];
+    if let Some(region) = &descriptor.region {
+        vars.push(("AWS_REGION", region));
+        vars.push(("AWS_DEFAULT_REGION", region));
+    }
+    for (key, value) in vars {
+        out.push_str(&format!("{}={}\n", key, value));
+    }
+    out
+}
+
+/// Write the env file for `descriptor` to `path` and register it for cleanup.
+pub fn write_env_file(awx_dir: &Path, path: &Path, descriptor: &Descriptor) -> Result<()> {
+    crate::state::write(path, env_file(descriptor).as_bytes())?;
+    let path = std::path::absolute(path).with_context(|| format!("Failed to resolve {}", path.display()))?;
+    let mut registry = registered(awx_dir);
+    registry.retain(|p, _| p.exists());
+    registry.insert(path, descriptor.expiration);
+    crate::state::write_json(&registry_path(awx_dir), &registry)
+}
+
+fn registered(awx_dir: &Path) -> BTreeMap<PathBuf, DateTime<Utc>> {
+    crate::state::read_json(&registry_path(awx_dir)).unwrap_or_default()
+}
+
+/// Registered env files whose credentials have expired at `now` and that still hold
+/// what awx wrote.
+pub fn expired(awx_dir: &Path, now: DateTime<Utc>) -> Vec<PathBuf> {
+    registered(awx_dir)
+        .into_iter()
+        .filter(|(path, expiration)| {
+            *expiration <= now
+                && std::fs::read_to_string(path).is_ok_and(|c| c.starts_with(HEADER))
+        })
+        .map(|(path, _)| path)
+        .collect()
+}
+
+/// Make sure git does not track `path`: if it lies in a work tree and is not ignored
+/// yet, add it to the repository's info/exclude. Returns whether the file was added.
+pub async fn exclude_from_git(path: &Path) -> Result<bool> {
+    let dir = match path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        Some(dir) => dir.to_path_buf(),
+        None => PathBuf::from("."),
+    };
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let git = |args: &[&str]| {
+        let mut cmd = crate::backend::command("git");
+        cmd.arg("-C").arg(&dir).args(args);
+        cmd
+    };
+    let runner = crate::backend::runner();
+    // 0: ignored, 1: not ignored, anything else: not a work tree or no git.
+    let Ok(check) = runner.output(&mut git(&["check-ignore", "-q", &name]), None).await else {
+        return Ok(false);
+    };
+    if check.status.code() != Some(1) {
+        return Ok(false);
+    }
+    let output = runner
+        .output(&mut git(&["rev-parse", "--show-prefix", "--git-path", "info/exclude"]), None)
+        .await
+        .context("Failed to run git")?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut lines = stdout.lines();
+    let (Some(prefix), Some(exclude)) = (lines.next(), lines.next()) else {
+        return Ok(false);
+    };
+    let exclude = dir.join(exclude);
+    if let Some(parent) = exclude.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&exclude)
+        .with_context(|| format!("Failed to update {}", exclude.display()))?;
+    writeln!(file, "/{}{}", prefix, name)?;
+    Ok(true)
+}
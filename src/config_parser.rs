@@ -0,0 +1,145 @@
+// Parser for the INI dialect of ~/.aws/config and ~/.aws/credentials.
+//
+// It never fails: anything it cannot make sense of is skipped and reported as a
+// `Diagnostic` with its line number, so one bad line does not hide every profile. Input
+// may be arbitrary bytes; invalid UTF-8 is replaced rather than rejected. The module
+// depends only on std so the fuzz target in fuzz/ can include it directly.
+//
+// A `# awx: <description>` comment documents a profile: placed right above a section
+// header it describes that section, anywhere else it describes the section it is in. It
+// is stored as the `awx_description` key, which can also be written out directly and
+// then wins over the comment.
+use std::collections::HashMap;
+
+/// Lines longer than this are skipped rather than parsed.
+pub const MAX_LINE_LEN: usize = 64 * 1024;
+
+/// Key holding a section's description.
+pub const DESCRIPTION_KEY: &str = "awx_description";
+
+pub type Sections = HashMap<String, HashMap<String, String>>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// 1-based line number.
+    pub line: usize,
+    pub message: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Parsed {
+    pub sections: Sections,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Parse raw file contents.
+pub fn parse_bytes(bytes: &[u8]) -> Parsed {
+    match std::str::from_utf8(bytes) {
+        Ok(content) => parse(content),
+        Err(_) => {
+            let content = String::from_utf8_lossy(bytes);
+            let mut parsed = parse(&content);
+            // Point at each line that lost bytes, in line order with the rest.
+            for (idx, line) in content.split('\n').enumerate() {
+                if line.contains(char::REPLACEMENT_CHARACTER) {
+                    parsed.diagnostics.push(Diagnostic {
+                        line: idx + 1,
+                        message: "invalid UTF-8 replaced".to_string(),
+                    });
+                }
+            }
+            parsed.diagnostics.sort_by_key(|d| d.line);
+            parsed
+        }
+    }
+}
+
+pub fn parse(content: &str) -> Parsed {
+    let mut parsed = Parsed::default();
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+    // `None` before the first header and under a rejected one; keys there are dropped,
+    // and only reported before the first header since a rejected one already was.
+    let mut current: Option<String> = None;
+    let mut seen_header = false;
+    // Whether indented lines continue the previous key (AWS nested settings such as `s3 =`).
+    let mut in_nested = false;
+    // A `# awx:` description not yet attached to a section.
+    let mut description: Option<String> = None;
+    for (idx, raw_line) in content.split('\n').enumerate() {
+        let line_no = idx + 1;
+        let mut report = |message: &str| {
+            parsed.diagnostics.push(Diagnostic {
+                line: line_no,
+                message: message.to_string(),
+            })
+        };
+        if raw_line.len() > MAX_LINE_LEN {
+            report("line too long, skipped");
+            continue;
+        }
+        let raw_line = raw_line.strip_suffix('\r').unwrap_or(raw_line);
+        let line = raw_line.trim();
+        if let Some(text) = line.strip_prefix('#').and_then(|c| c.trim_start().strip_prefix("awx:")) {
+            let text = text.trim();
+            if !text.is_empty() {
+                description = Some(text.to_string());
+            }
+            continue;
+        }
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        let indented = raw_line.starts_with([' ', '\t']);
+        if indented && in_nested {
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix('[') {
+            in_nested = false;
+            current = None;
+            seen_header = true;
+            let Some(name) = rest.strip_suffix(']') else {
+                report("unterminated section header, section skipped");
+                continue;
+            };
+            let name = name.trim();
+            if name.is_empty() {
+                report("empty section name, section skipped");
+            } else if name.contains(['[', ']']) {
+                report("brackets inside section name, section skipped");
+            } else {
+                let section = parsed.sections.entry(name.to_string()).or_default();
+                if let Some(text) = description.take() {
+                    section.entry(DESCRIPTION_KEY.to_string()).or_insert(text);
+                }
+                current = Some(name.to_string());
+            }
+            description = None;
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            report("expected `key = value`, line skipped");
+            continue;
+        };
+        let (key, value) = (key.trim(), value.trim());
+        if key.is_empty() {
+            report("missing key before `=`, line skipped");
+            continue;
+        }
+        let Some(section) = current.as_ref() else {
+            if !seen_header {
+                report("key outside of any section, line skipped");
+            }
+            continue;
+        };
+        in_nested = value.is_empty();
+        let section = parsed.sections.entry(section.clone()).or_default();
+        if let Some(text) = description.take() {
+            section.entry(DESCRIPTION_KEY.to_string()).or_insert(text);
+        }
+        section.insert(key.to_string(), value.to_string());
+    }
+    if let (Some(text), Some(section)) = (description, current) {
+        parsed.sections.entry(section).or_default().entry(DESCRIPTION_KEY.to_string()).or_insert(text);
+    }
+    parsed
+}
@@ -0,0 +1,93 @@
+// Named contexts (`awx ctx`).
+//
+// A context bundles a profile with the region, endpoint and extra environment its
+// commands run with, a default AWS command and an optional confirmation prompt, under
+// `[contexts.<name>]` in ~/.awx/config.toml. `awx ctx use <name>` saves the name in
+// ~/.awx/context, much like kubectl's current context; AWX_CONTEXT overrides it for one
+// shell (empty for none). A context only fills in what a run leaves open: `--profile`
+// selects another profile (which the context then leaves alone), and `--region` or a
+// variable already set in the environment wins over the context's.
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::settings::{ContextSettings, Settings};
+
+pub const CONTEXT_ENV: &str = "AWX_CONTEXT";
+
+static ACTIVE: OnceLock<Active> = OnceLock::new();
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Active {
+    pub name: String,
+    pub settings: ContextSettings,
+}
+
+impl Active {
+    /// Environment for the context's commands, leaving out variables `lookup` already has.
+    pub fn env(&self, lookup: &impl Fn(&str) -> Option<String>) -> Vec<(String, String)> {
+        let mut env: Vec<(String, String)> = self.settings.env.clone().into_iter().collect();
+        if let Some(url) = &self.settings.endpoint_url {
+            env.push(("AWS_ENDPOINT_URL".to_string(), url.clone()));
+        }
+        env.retain(|(k, _)| lookup(k).map(|v| v.is_empty()).unwrap_or(true));
+        env.sort();
+        env
+    }
+}
+
+pub fn state_path() -> Result<PathBuf> {
+    Ok(crate::settings::awx_dir()?.join("context"))
+}
+
+/// The active context's name: AWX_CONTEXT if set, else the one saved by `awx ctx use`.
+pub fn active_name(lookup: &impl Fn(&str) -> Option<String>, state: &Path) -> Option<String> {
+    let name = match lookup(CONTEXT_ENV) {
+        Some(name) => name,
+        None => std::fs::read_to_string(state).ok()?,
+    };
+    let name = name.trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// The active context, which must be defined in `settings`.
+pub fn load(settings: &Settings, lookup: &impl Fn(&str) -> Option<String>, state: &Path) -> Result<Option<Active>> {
+    let Some(name) = active_name(lookup, state) else {
+        return Ok(None);
+    };
+    let context = settings.contexts.get(&name).ok_or_else(|| {
+        anyhow!(
+            "Active context '{}' is not defined under [contexts.{}] in ~/.awx/config.toml (run `awx ctx clear`)",
+            name,
+            name
+        )
+    })?;
+    Ok(Some(Active {
+        name,
+        settings: context.clone(),
+    }))
+}
+
+/// Save `name` as the active context, or deactivate it with `None`.
+pub fn save(state: &Path, name: Option<&str>) -> Result<()> {
+    match name {
+        Some(name) => crate::state::write(state, format!("{}\n", name).as_bytes()),
+        None => match std::fs::remove_file(state) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("Failed to remove {}", state.display()))
+            }
+            _ => Ok(()),
+        },
+    }
+}
+
+/// Apply `active` for this process.
+pub fn configure(active: Active) {
+    let _ = ACTIVE.set(active);
+}
+
+/// The active context, if it belongs to `profile`.
+pub fn for_profile(profile: &str) -> Option<&'static Active> {
+    ACTIVE.get().filter(|a| a.settings.profile == profile)
+}
@@ -0,0 +1,249 @@
+// Signed HTTP requests to IAM-authorized APIs (`awx curl`).
+//
+// APIs behind API Gateway with IAM authorization, Lambda function URLs with AWS_IAM
+// auth, OpenSearch domains and the like take requests signed with Signature Version 4.
+// `awx curl` resolves a profile and has curl sign the request with the session
+// (`--aws-sigv4`, curl 7.75 or later), which covers what awscurl or Postman are used
+// for. The credentials, extra headers and body go into a curl config file in awx's
+// private temporary directory instead of the command line, so none of them show up in
+// the process list. The body is hashed first (`openssl dgst -sha256`) and sent as
+// `x-amz-content-sha256`, which S3 requires and which curl signs as the payload hash
+// rather than reading the body again. Service and region come from the URL of an AWS
+// endpoint (`execute-api`, `lambda` for function URLs, `es`, ...); --service and
+// --region override them, and the profile's region fills in for other hosts.
+//
+// S3 Multi-Region Access Points only take SigV4A, the asymmetric multi-region variant,
+// which curl cannot produce. For their URLs (`*.mrap.accesspoint.s3-global.amazonaws.com`)
+// and with `--sigv4a`, awx signs the request itself (see sigv4.rs) and hands curl the
+// finished `Authorization`, `X-Amz-Date`, `X-Amz-Region-Set` and token headers
+// instead; the region set is `*` unless --region names one. `--presign` prints a URL
+// presigned the same way (SigV4 or SigV4A) instead of sending anything.
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+
+use crate::sigv4::{self, Algorithm};
+use crate::{backend, StsCredentials};
+
+/// SHA-256 of an empty body.
+pub const EMPTY_PAYLOAD_HASH: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+/// The payload hash of presigned S3 URLs, whose body is not known in advance.
+pub const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+/// The longest a presigned URL may be valid for.
+pub const MAX_PRESIGN_SECS: i64 = 7 * 24 * 3600;
+
+/// What `--body` sends.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Body {
+    Text(String),
+    /// `@path`: the file's bytes, as is.
+    File(PathBuf),
+}
+
+impl Body {
+    pub fn parse(s: &str) -> Result<Body> {
+        Ok(match s.strip_prefix('@') {
+            Some("") => return Err(anyhow!("--body @ needs a file name")),
+            Some(path) => Body::File(PathBuf::from(path)),
+            None => Body::Text(s.to_string()),
+        })
+    }
+}
+
+/// Parse `--extra-header`: `Name: value`.
+pub fn parse_header(s: &str) -> Result<String> {
+    match s.split_once(':') {
+        Some((name, _)) if !name.trim().is_empty() && !name.contains(char::is_whitespace) => Ok(s.to_string()),
+        _ => Err(anyhow!("'{}' is not a header: expected 'Name: value'", s)),
+    }
+}
+
+fn is_region(label: &str) -> bool {
+    let parts: Vec<&str> = label.split('-').collect();
+    parts.len() >= 3
+        && parts[0].len() == 2
+        && parts[..parts.len() - 1].iter().all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_lowercase()))
+        && parts[parts.len() - 1].chars().all(|c| c.is_ascii_digit())
+}
+
+/// The signing service and region of an AWS endpoint URL, e.g. `execute-api` and
+/// `eu-west-1` for `https://abc123.execute-api.eu-west-1.amazonaws.com/prod`.
+pub fn endpoint_scope(url: &str) -> Option<(String, String)> {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority.rsplit('@').next()?.split(':').next()?.to_ascii_lowercase();
+    let labels: Vec<&str> = host
+        .strip_suffix(".amazonaws.com")
+        .or_else(|| host.strip_suffix(".amazonaws.com.cn"))
+        .or_else(|| host.strip_suffix(".on.aws"))?
+        .split('.')
+        .collect();
+    let at = labels.iter().rposition(|l| is_region(l))?;
+    // Most endpoints are `<service>.<region>`, a few (`es`, `aoss`) `<region>.<service>`.
+    let service = labels.get(at + 1).or_else(|| labels.get(at.checked_sub(1)?));
+    let service = match service? {
+        &"lambda-url" => "lambda",
+        service => service,
+    };
+    Some((service.to_string(), labels[at].to_string()))
+}
+
+/// Whether `url` is an S3 Multi-Region Access Point, which needs SigV4A.
+pub fn needs_sigv4a(url: &str) -> bool {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let host = rest.split(['/', '?', '#', ':']).next().unwrap_or_default().to_ascii_lowercase();
+    host.ends_with(".mrap.accesspoint.s3-global.amazonaws.com")
+}
+
+/// A request for curl to sign.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    pub url: String,
+    pub method: Option<String>,
+    pub service: String,
+    pub region: String,
+    pub headers: Vec<String>,
+    pub body: Option<Body>,
+    /// Sign with SigV4A; `region` is then the region set.
+    pub sigv4a: bool,
+}
+
+impl Request {
+    /// The method curl sends: the given one, else GET, or POST with a body.
+    pub fn method(&self) -> &str {
+        match (&self.method, &self.body) {
+            (Some(method), _) => method,
+            (None, Some(_)) => "POST",
+            (None, None) => "GET",
+        }
+    }
+
+    fn algorithm(&self) -> Algorithm {
+        match self.sigv4a {
+            true => Algorithm::V4a { region_set: self.region.clone() },
+            false => Algorithm::V4 { region: self.region.clone() },
+        }
+    }
+}
+
+/// A double-quoted value for a curl config file.
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// The curl config that signs `request` with `credentials` at `now`; `payload_hash`
+/// is the SHA-256 of the body. SigV4 is left to curl, SigV4A signed here.
+pub fn config(request: &Request, credentials: &StsCredentials, payload_hash: &str, now: DateTime<Utc>) -> String {
+    let mut lines = vec![format!("url = {}", quote(&request.url))];
+    let mut headers = vec![format!("x-amz-content-sha256: {}", payload_hash)];
+    if request.sigv4a {
+        lines.push(format!("request = {}", quote(request.method())));
+        headers.extend(request.headers.iter().cloned());
+        let signed: Vec<(String, String)> = headers
+            .iter()
+            .filter_map(|h| h.split_once(':'))
+            .map(|(name, value)| (name.to_string(), value.trim().to_string()))
+            .collect();
+        let added = sigv4::sign_headers(
+            &request.algorithm(),
+            request.method(),
+            &request.url,
+            &request.service,
+            &signed,
+            payload_hash,
+            credentials,
+            now,
+        );
+        headers.extend(added.iter().map(|(name, value)| format!("{}: {}", name, value)));
+    } else {
+        lines.push(format!("aws-sigv4 = {}", quote(&format!("aws:amz:{}:{}", request.region, request.service))));
+        lines.push(format!("user = {}", quote(&format!("{}:{}", credentials.access_key_id, credentials.secret_access_key))));
+        if let Some(method) = &request.method {
+            lines.push(format!("request = {}", quote(method)));
+        }
+        if !credentials.session_token.is_empty() {
+            headers.push(format!("x-amz-security-token: {}", credentials.session_token));
+        }
+        headers.extend(request.headers.iter().cloned());
+    }
+    lines.extend(headers.iter().map(|h| format!("header = {}", quote(h))));
+    match &request.body {
+        Some(Body::Text(text)) => lines.push(format!("data-binary = {}", quote(text))),
+        Some(Body::File(path)) => lines.push(format!("data-binary = {}", quote(&format!("@{}", path.display())))),
+        None => {}
+    }
+    lines.iter().map(|l| format!("{}\n", l)).collect()
+}
+
+/// `request`'s URL presigned with `credentials` at `now`, valid for `expires`. Only
+/// the host is signed, so extra headers and a body cannot be part of it.
+pub fn presign(
+    request: &Request,
+    credentials: &StsCredentials,
+    expires: chrono::Duration,
+    now: DateTime<Utc>,
+) -> Result<String> {
+    if !request.headers.is_empty() || request.body.is_some() {
+        return Err(anyhow!("--presign signs the URL only; drop --extra-header and --body"));
+    }
+    let secs = expires.num_seconds();
+    if !(1..=MAX_PRESIGN_SECS).contains(&secs) {
+        return Err(anyhow!("--presign takes at most 7d (presigned URLs expire within a week)"));
+    }
+    let payload_hash = if request.service == "s3" { UNSIGNED_PAYLOAD } else { EMPTY_PAYLOAD_HASH };
+    Ok(sigv4::presign(
+        &request.algorithm(),
+        request.method(),
+        &request.url,
+        &request.service,
+        payload_hash,
+        credentials,
+        secs,
+        now,
+    ))
+}
+
+/// The hex SHA-256 of `body`, computed by openssl.
+pub async fn payload_hash(body: Option<&Body>) -> Result<String> {
+    let mut cmd = backend::command("openssl");
+    cmd.args(["dgst", "-sha256", "-r"]);
+    let output = match body {
+        None => return Ok(EMPTY_PAYLOAD_HASH.to_string()),
+        Some(Body::Text(text)) => backend::runner().output(&mut cmd, Some(text.as_bytes())).await,
+        Some(Body::File(path)) => {
+            std::fs::metadata(path).with_context(|| format!("Cannot read the body {}", path.display()))?;
+            cmd.arg(path);
+            backend::runner().output(&mut cmd, None).await
+        }
+    }
+    .context("failed to run openssl (needed to hash the body)")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("openssl could not hash the body: {}", stderr.trim()));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    stdout
+        .split_whitespace()
+        .next()
+        .filter(|h| h.len() == 64 && h.chars().all(|c| c.is_ascii_hexdigit()))
+        .map(str::to_ascii_lowercase)
+        .ok_or_else(|| anyhow!("unexpected openssl output: {}", stdout.trim()))
+}
+
+/// Send `request` signed with `credentials`, passing `curl_args` on to curl; returns
+/// curl's exit code.
+pub async fn run(request: &Request, credentials: &StsCredentials, curl_args: &[String]) -> Result<i32> {
+    let hash = payload_hash(request.body.as_ref()).await?;
+    let file = crate::tmp::file()?;
+    std::fs::write(file.path(), config(request, credentials, &hash, Utc::now()))?;
+    let mut cmd = backend::command("curl");
+    cmd.arg("--config").arg(file.path()).args(curl_args);
+    let status = backend::runner()
+        .status(&mut cmd)
+        .await
+        .context("failed to run curl (7.75 or later is needed for --aws-sigv4)")?;
+    Ok(status.code().unwrap_or(1))
+}
@@ -0,0 +1,167 @@
+// Sanitized snapshots for bug reports (`awx debug-bundle`).
+//
+// The bundle is a single JSON document: awx and AWS CLI versions, the OS, the
+// environment checks of `awx selftest`, the AWS environment variables awx honors, the
+// shape of ~/.aws/config, ~/.aws/credentials and ~/.awx/config.toml, the last error awx
+// reported and, for a profile, the `--explain` trace. Nothing secret goes in: key
+// values in the AWS files and webhook URLs, telemetry headers and context variables in
+// config.toml are replaced with `<redacted>`, secret variables are shown as set or
+// unset, and anything shaped like an access key id left in free text is masked. The
+// last error is kept in ~/.awx/last-error.json, written each time a run fails.
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+const FORMAT: &str = "awx-debug-bundle/1";
+const REDACTED: &str = "<redacted>";
+/// Keys of the AWS files whose values never leave the machine. credential_process is
+/// a command line that may carry a secret of its own.
+const SECRET_KEYS: [&str; 4] = ["aws_access_key_id", "aws_secret_access_key", "aws_session_token", "credential_process"];
+/// Keys of config.toml whose values are redacted, and tables whose values all are.
+const SECRET_SETTINGS: [&str; 1] = ["webhook"];
+const SECRET_TABLES: [&str; 2] = ["headers", "env"];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LastError {
+    pub at: DateTime<Utc>,
+    /// The subcommand, e.g. `login`; never its arguments.
+    pub command: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BundledFile {
+    pub path: String,
+    pub content: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Bundle {
+    pub format: String,
+    pub created_at: DateTime<Utc>,
+    pub awx_version: String,
+    pub os: String,
+    pub aws_cli_version: Option<String>,
+    pub checks: String,
+    pub environment: Vec<(String, String)>,
+    pub files: Vec<BundledFile>,
+    pub last_error: Option<LastError>,
+    pub explain: Option<String>,
+}
+
+pub fn last_error_path(awx_dir: &Path) -> PathBuf {
+    awx_dir.join("last-error.json")
+}
+
+/// Remember `err` as the last error of a `command` run. Failures to write are ignored:
+/// the run has already failed and says why.
+pub fn record_error(awx_dir: &Path, command: &str, err: &anyhow::Error, now: DateTime<Utc>) {
+    let last = LastError {
+        at: now,
+        command: command.to_string(),
+        message: mask_keys(&format!("{:#}", err)),
+    };
+    if let Ok(json) = serde_json::to_vec_pretty(&last) {
+        let _ = crate::state::write(&last_error_path(awx_dir), &json);
+    }
+}
+
+pub fn last_error(awx_dir: &Path) -> Option<LastError> {
+    serde_json::from_slice(&std::fs::read(last_error_path(awx_dir)).ok()?).ok()
+}
+
+impl Bundle {
+    pub fn new(os: &str, aws_cli_version: Option<String>, checks: String, now: DateTime<Utc>) -> Bundle {
+        Bundle {
+            format: FORMAT.to_string(),
+            created_at: now,
+            awx_version: env!("CARGO_PKG_VERSION").to_string(),
+            os: os.to_string(),
+            aws_cli_version,
+            checks,
+            environment: Vec::new(),
+            files: Vec::new(),
+            last_error: None,
+            explain: None,
+        }
+    }
+
+    /// Add the file at `path`, shown as `shown`, sanitized by `sanitize`; missing files
+    /// are noted as such.
+    pub fn add_file(&mut self, shown: &str, path: &Path, sanitize: fn(&str) -> String) {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => sanitize(&content),
+            Err(e) => format!("(not read: {})", e.kind()),
+        };
+        self.files.push(BundledFile {
+            path: shown.to_string(),
+            content,
+        });
+    }
+}
+
+/// INI content with the values of secret keys redacted; sections, comments and other
+/// keys are kept.
+pub fn sanitize_ini(content: &str) -> String {
+    let mut out = String::new();
+    for line in content.lines() {
+        let key = line.split('=').next().unwrap_or_default().trim();
+        if line.contains('=') && SECRET_KEYS.iter().any(|k| key.eq_ignore_ascii_case(k)) {
+            out.push_str(&format!("{} = {}\n", key, REDACTED));
+        } else {
+            out.push_str(&mask_keys(line));
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// config.toml with webhook URLs, telemetry headers and context variables redacted.
+/// Content that does not parse is left out entirely.
+pub fn sanitize_settings(content: &str) -> String {
+    let Ok(mut value) = content.parse::<toml::Table>() else {
+        return "(not valid TOML; left out)".to_string();
+    };
+    redact_table(&mut value, false);
+    toml::to_string(&value).unwrap_or_default()
+}
+
+fn redact_table(table: &mut toml::Table, all: bool) {
+    for (key, value) in table.iter_mut() {
+        let secret = all || SECRET_SETTINGS.contains(&key.as_str());
+        match value {
+            toml::Value::Table(inner) => redact_table(inner, secret || SECRET_TABLES.contains(&key.as_str())),
+            toml::Value::String(s) if secret => *s = REDACTED.to_string(),
+            _ => {}
+        }
+    }
+}
+
+/// `text` with anything shaped like an access key id (AKIA…, ASIA…) masked.
+pub fn mask_keys(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut word = String::new();
+    let flush = |word: &mut String, out: &mut String| {
+        let key_like = word.len() >= 16
+            && ["AKIA", "ASIA"].iter().any(|p| word.starts_with(p))
+            && word.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit());
+        out.push_str(if key_like { REDACTED } else { word.as_str() });
+        word.clear();
+    };
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() {
+            word.push(c);
+        } else {
+            flush(&mut word, &mut out);
+            out.push(c);
+        }
+    }
+    flush(&mut word, &mut out);
+    out
+}
+
+/// Where the bundle goes unless `--output` says otherwise.
+pub fn default_path(awx_dir: &Path, now: DateTime<Utc>) -> PathBuf {
+    awx_dir.join("debug").join(format!("awx-debug-{}.json", now.format("%Y%m%dT%H%M%SZ")))
+}
@@ -0,0 +1,88 @@
+// What the `default` profile is allowed to do (`[default_profile]`).
+//
+// The AWS CLI uses `default` whenever no profile is named, and so does awx in
+// non-interactive mode. That is convenient until `default` holds long-term keys of a
+// production account and a script forgets its `--profile`. `hide = true` leaves
+// `default` out of the profile picker, `awx -c` and the profile list of input requests
+// (`--only default` still shows it). `explicit = true` makes `--no-interactive` without
+// a profile fail like when there is no `default` at all, instead of falling back to it;
+// naming it with `-p default` or AWS_PROFILE still works. `production_accounts` lists
+// account IDs `default` must not hold static keys for: awx warns whenever it
+// authenticates such a `default`. The account is the one of its MFA device, or the one
+// its access key ID encodes.
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+
+use crate::{badge, secrets, Profile};
+
+pub const NAME: &str = "default";
+
+static SETTINGS: OnceLock<DefaultProfileSettings> = OnceLock::new();
+
+/// `[default_profile]`.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct DefaultProfileSettings {
+    /// Leave `default` out of the profile picker and profile lists.
+    pub hide: bool,
+    /// Never use `default` unless it is named; `--no-interactive` fails instead.
+    pub explicit: bool,
+    /// Accounts `default` must not hold static keys for.
+    pub production_accounts: Vec<String>,
+}
+
+/// Apply `[default_profile]` for this process.
+pub fn configure(settings: &DefaultProfileSettings) {
+    let _ = SETTINGS.set(settings.clone());
+}
+
+fn settings() -> DefaultProfileSettings {
+    SETTINGS.get().cloned().unwrap_or_default()
+}
+
+/// Whether `default` is left out of profile lists.
+pub fn hidden() -> bool {
+    settings().hide
+}
+
+/// Whether awx may use `default` when no profile is named.
+pub fn implicit_allowed() -> bool {
+    !settings().explicit
+}
+
+/// The account an access key belongs to, decoded from its ID (`AKIA…`, `ASIA…`).
+pub fn key_account(access_key_id: &str) -> Option<String> {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+    let encoded = access_key_id.get(4..14)?;
+    let mut bits: u64 = 0;
+    for c in encoded.bytes() {
+        bits = bits << 5 | ALPHABET.iter().position(|&a| a == c)? as u64;
+    }
+    // 50 bits decoded; the account is bits 7..47 of the first 48.
+    let account = ((bits >> 2) & 0x7fff_ffff_ff80) >> 7;
+    Some(format!("{:012}", account))
+}
+
+/// The warning for using `profile` when it is a `default` holding static keys of one of
+/// `production_accounts`.
+pub fn production_warning(profile: &Profile, production_accounts: &[String]) -> Option<String> {
+    if profile.name != NAME || !profile.is_static() || production_accounts.is_empty() {
+        return None;
+    }
+    let key_id = secrets::resolved(&profile.name)
+        .map(|k| k.access_key_id)
+        .or_else(|| profile.aws_access_key_id.clone().filter(|id| !secrets::is_reference(id)));
+    let account = badge::account(profile).or_else(|| key_account(key_id.as_deref()?))?;
+    production_accounts.contains(&account).then(|| {
+        format!(
+            "Warning: the default profile holds long-term keys of production account {}; name the profile you mean and move these keys to a named profile.",
+            account
+        )
+    })
+}
+
+/// `production_warning` with the configured accounts.
+pub fn warning(profile: &Profile) -> Option<String> {
+    production_warning(profile, &settings().production_accounts)
+}
@@ -0,0 +1,119 @@
+// Choosing how long an assumed-role session lasts (`--choose-duration`).
+//
+// STS bounds AssumeRole sessions three ways: at least 15 minutes, at most the role's
+// MaxSessionDuration (1 to 12 hours, 1 hour unless raised), and at most 1 hour for
+// role chaining. Instead of asking for a `duration_seconds` that fails at STS, the
+// picker lists the usual lengths that fit the role (15m, 1h, 4h, 8h, 12h, plus the
+// role's own maximum when it is none of those). The role's maximum is read with
+// iam:GetRole where the source credentials may; otherwise 12 hours is assumed and STS
+// has the last word. The last choice per profile is kept in ~/.awx/durations.json and
+// preselected next time; without a prompt (`-n`) it is used as is when it still fits.
+//
+// `--duration` asks for one length outright, e.g. `awx -p deployer --duration 15m` for a
+// short-lived deploy session next to a 4h one for exploring. Sessions obtained that way
+// are cached apart from the profile's usual one, under the key `<profile>@<length>`
+// (`deployer@15m`), in the broker and in `awx warm` session files alike, so the
+// short and the long session do not replace each other; `awx cache ls` lists both.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+
+pub fn chooses() -> bool {
+    crate::backend::flags().choose_duration
+}
+
+/// The session length asked for with `--duration`.
+pub fn requested() -> Option<i64> {
+    crate::backend::flags().duration
+}
+
+/// Parse a `--duration` value: whole minutes, and STS grants nothing outside 15 minutes
+/// to 12 hours.
+pub fn parse(s: &str) -> Result<i64> {
+    let secs = crate::settings::parse_duration(s)?.num_seconds();
+    if !(MIN_SECS..=MAX_SECS).contains(&secs) || secs % 60 != 0 {
+        return Err(anyhow!("session duration '{}' is out of range: use whole minutes from 15m to 12h", s));
+    }
+    Ok(secs)
+}
+
+/// Where a session of `profile` for a `--duration` of `secs` is cached: the profile
+/// itself without one, else `<profile>@<length>`.
+pub fn cache_key(profile: &str, secs: Option<i64>) -> String {
+    match secs {
+        Some(secs) => format!("{}@{}", profile, label(secs)),
+        None => profile.to_string(),
+    }
+}
+
+/// The profile and `--duration` a cache key stands for.
+pub fn split_key(key: &str) -> (&str, Option<i64>) {
+    if let Some((profile, length)) = key.rsplit_once('@') {
+        if let Ok(secs) = parse(length) {
+            return (profile, Some(secs));
+        }
+    }
+    (key, None)
+}
+
+/// STS accepts no shorter session.
+pub const MIN_SECS: i64 = 900;
+/// The highest MaxSessionDuration a role can have.
+pub const MAX_SECS: i64 = 43_200;
+/// The lengths offered, in seconds.
+pub const CHOICES: [i64; 5] = [900, 3600, 14_400, 28_800, 43_200];
+
+/// The longest session STS grants: the role's maximum when known, capped at an hour
+/// for chained roles.
+pub fn upper_bound(role_max: Option<i64>, chained: bool) -> i64 {
+    let max = role_max.unwrap_or(MAX_SECS).clamp(MIN_SECS, MAX_SECS);
+    if chained {
+        max.min(crate::CHAINED_ROLE_MAX_SECS)
+    } else {
+        max
+    }
+}
+
+/// The lengths to offer up to `max`, which is offered itself when it is none of them.
+pub fn options(max: i64) -> Vec<i64> {
+    let mut options: Vec<i64> = CHOICES.iter().copied().filter(|&secs| secs <= max).collect();
+    if !options.contains(&max) {
+        options.push(max);
+    }
+    options
+}
+
+/// `15m`, `1h`, `1h30m`.
+pub fn label(secs: i64) -> String {
+    let (hours, minutes) = (secs / 3600, secs % 3600 / 60);
+    match (hours, minutes) {
+        (0, m) => format!("{}m", m),
+        (h, 0) => format!("{}h", h),
+        (h, m) => format!("{}h{}m", h, m),
+    }
+}
+
+/// The option preselected in the picker: the remembered choice, else the length
+/// awx would request anyway, else the longest that is shorter.
+pub fn preselect(options: &[i64], remembered: Option<i64>, requested: i64) -> usize {
+    if let Some(i) = remembered.and_then(|r| options.iter().position(|&secs| secs == r)) {
+        return i;
+    }
+    options.iter().rposition(|&secs| secs <= requested).unwrap_or(0)
+}
+
+pub fn remembered_path(awx_dir: &Path) -> PathBuf {
+    awx_dir.join("durations.json")
+}
+
+/// Durations chosen before, in seconds per profile.
+pub fn load_remembered(awx_dir: &Path) -> HashMap<String, i64> {
+    crate::state::read_json(&remembered_path(awx_dir)).unwrap_or_default()
+}
+
+pub fn remember(awx_dir: &Path, profile: &str, secs: i64) -> Result<()> {
+    let mut remembered = load_remembered(awx_dir);
+    remembered.insert(profile.to_string(), secs);
+    crate::state::write_json(&remembered_path(awx_dir), &remembered)
+}
@@ -0,0 +1,215 @@
+// A container credentials endpoint per child (`--credentials-endpoint`).
+//
+// Instead of putting keys in the child's environment, awx can serve them the way ECS
+// does: on a loopback HTTP endpoint named by AWS_CONTAINER_CREDENTIALS_FULL_URI, which
+// the SDKs and the AWS CLI read through their container provider. Every child gets an
+// endpoint of its own, with a random path and a random bearer token in
+// AWS_CONTAINER_AUTHORIZATION_TOKEN, both minted for that exec and gone when the child
+// exits. Another process on the machine sees a listening port but cannot guess the URL
+// or the token, so it cannot harvest the session. Keys and AWS_PROFILE are left out of
+// the child's environment, since both would answer before the container provider.
+//
+// SDKs too old for the container provider only look for instance metadata, so the same
+// endpoint answers the IMDS calls (PUT /latest/api/token, then
+// /latest/meta-data/iam/security-credentials/ and the role listed there) below the
+// child's random path, and AWS_EC2_METADATA_SERVICE_ENDPOINT points there with
+// AWS_EC2_METADATA_DISABLED=false. The SDKs append those paths to the endpoint they
+// are given, so the instance metadata is exactly as hard to guess as the container
+// URL, and no SDK goes on to the host's real IMDS and its instance role.
+// Every connection is answered on a thread of its own with a read timeout, so a
+// process that connects and sends nothing cannot hold up the child's refreshes.
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::json;
+use tokio::process::Command;
+
+use crate::StsCredentials;
+
+pub fn serves() -> bool {
+    crate::backend::flags().credentials_endpoint
+        || matches!(crate::aws_env::current_lookup("AWX_CREDENTIALS_ENDPOINT").as_deref(), Some("1") | Some("true"))
+}
+
+pub const URI_ENV: &str = "AWS_CONTAINER_CREDENTIALS_FULL_URI";
+pub const TOKEN_ENV: &str = "AWS_CONTAINER_AUTHORIZATION_TOKEN";
+pub const IMDS_ENDPOINT_ENV: &str = "AWS_EC2_METADATA_SERVICE_ENDPOINT";
+pub const IMDS_DISABLED_ENV: &str = "AWS_EC2_METADATA_DISABLED";
+
+/// The role name the instance metadata lists.
+pub const IMDS_ROLE: &str = "awx";
+
+/// A running endpoint; it stops when dropped.
+pub struct Endpoint {
+    pub url: String,
+    pub token: String,
+    addr: SocketAddr,
+    stopped: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Endpoint {
+    /// Serve `creds` on a fresh loopback port, path and token.
+    pub fn start(creds: StsCredentials) -> Result<Endpoint> {
+        let listener = TcpListener::bind("127.0.0.1:0").context("Failed to open a loopback port for the credentials endpoint")?;
+        let addr = listener.local_addr()?;
+        let path = format!("/awx/{}", secret_hex(16)?);
+        let token = secret_hex(32)?;
+        let stopped = Arc::new(AtomicBool::new(false));
+        let served = Arc::new((path.clone(), token.clone(), creds));
+        let stop = stopped.clone();
+        let thread = std::thread::Builder::new()
+            .name("credentials-endpoint".to_string())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    if stop.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    if let Ok(stream) = stream {
+                        let served = served.clone();
+                        let _ = std::thread::Builder::new()
+                            .name("credentials-request".to_string())
+                            .spawn(move || answer(stream, &served.0, &served.1, &served.2));
+                    }
+                }
+            })?;
+        Ok(Endpoint {
+            url: format!("http://{}{}", addr, path),
+            token,
+            addr,
+            stopped,
+            thread: Some(thread),
+        })
+    }
+
+    /// Point `cmd` at this endpoint instead of handing it keys.
+    pub fn attach(&self, cmd: &mut Command) {
+        cmd.env_remove("AWS_ACCESS_KEY_ID")
+            .env_remove("AWS_SECRET_ACCESS_KEY")
+            .env_remove("AWS_SESSION_TOKEN")
+            .env_remove("AWS_PROFILE")
+            .env_remove("AWS_DEFAULT_PROFILE")
+            .env_remove("AWS_CONTAINER_CREDENTIALS_RELATIVE_URI")
+            .env(URI_ENV, &self.url)
+            .env(TOKEN_ENV, &self.token)
+            .env(IMDS_ENDPOINT_ENV, &self.url)
+            .env("AWS_EC2_METADATA_SERVICE_ENDPOINT_MODE", "IPv4")
+            .env(IMDS_DISABLED_ENV, "false");
+    }
+}
+
+impl Drop for Endpoint {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        // Wake the accept loop so it sees the flag.
+        let _ = TcpStream::connect(self.addr);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// An endpoint for `cmd` when `--credentials-endpoint` is on and there is a session to
+/// serve; keep it alive until the child exits.
+pub fn for_child(cmd: &mut Command, creds: Option<&StsCredentials>) -> Result<Option<Endpoint>> {
+    let Some(creds) = creds.filter(|_| serves()) else {
+        return Ok(None);
+    };
+    let endpoint = Endpoint::start(creds.clone())?;
+    endpoint.attach(cmd);
+    Ok(Some(endpoint))
+}
+
+// Answer one request: the session for the right path and token, or the instance
+// metadata below that path; 404 or 401 otherwise.
+fn answer(mut stream: TcpStream, path: &str, token: &str, creds: &StsCredentials) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(stream.try_clone()?.take(16 * 1024));
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut authorization = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("authorization") {
+                authorization = Some(value.trim().to_string());
+            }
+        }
+    }
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+    let metadata = target.get(..path.len()).filter(|prefix| same(prefix, path)).map(|_| &target[path.len()..]);
+    let (status, content_type, body) = match (method, metadata) {
+        (_, Some(rest)) if !rest.is_empty() => instance_metadata(method, rest, token, creds),
+        ("GET", Some(_)) if !authorization.as_deref().is_some_and(|a| same(a, token)) => {
+            ("401 Unauthorized", "application/json", json!({"message": "missing or wrong authorization token"}).to_string())
+        }
+        ("GET", Some(_)) => {
+            let body = json!({
+                "AccessKeyId": creds.access_key_id,
+                "SecretAccessKey": creds.secret_access_key,
+                "Token": creds.session_token,
+                "Expiration": creds.expiration,
+            });
+            ("200 OK", "application/json", body.to_string())
+        }
+        _ => not_found(),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+fn not_found() -> (&'static str, &'static str, String) {
+    ("404 Not Found", "application/json", json!({"message": "not found"}).to_string())
+}
+
+// The IMDS calls the SDKs make for credentials, at `rest` below the child's path. The
+// path is the secret here: IMDS clients cannot send the bearer token, so the session
+// token they ask for first is handed out as is.
+fn instance_metadata(method: &str, rest: &str, token: &str, creds: &StsCredentials) -> (&'static str, &'static str, String) {
+    const CREDENTIALS: &str = "/latest/meta-data/iam/security-credentials";
+    match (method, rest) {
+        ("PUT", "/latest/api/token") => ("200 OK", "text/plain", token.to_string()),
+        ("GET", rest) if rest.strip_suffix('/').unwrap_or(rest) == CREDENTIALS => ("200 OK", "text/plain", IMDS_ROLE.to_string()),
+        ("GET", rest) if rest.strip_prefix(CREDENTIALS).and_then(|r| r.strip_prefix('/')) == Some(IMDS_ROLE) => {
+            let body = json!({
+                "Code": "Success",
+                "LastUpdated": chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+                "Type": "AWS-HMAC",
+                "AccessKeyId": creds.access_key_id,
+                "SecretAccessKey": creds.secret_access_key,
+                "Token": creds.session_token,
+                "Expiration": creds.expiration,
+            });
+            ("200 OK", "application/json", body.to_string())
+        }
+        _ => not_found(),
+    }
+}
+
+/// Compare without stopping at the first difference, so timing tells nothing.
+fn same(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// `bytes` bytes from the operating system's random source as hex. Unlike trace ids
+/// these guard a session, so there is no fallback.
+fn secret_hex(bytes: usize) -> Result<String> {
+    let mut buf = vec![0u8; bytes];
+    getrandom::fill(&mut buf).map_err(|e| anyhow!("Cannot mint a credentials endpoint token: {}", e))?;
+    Ok(buf.iter().map(|b| format!("{:02x}", b)).collect())
+}
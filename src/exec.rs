@@ -0,0 +1,453 @@
+// `awx exec`: run one command against many profiles (and regions).
+//
+// Role targets are grouped by their source_profile. The base session of each group is
+// minted once (SSO login check, MFA prompt or an earlier role hop) and the AssumeRole
+// calls for its targets then run concurrently, at most `parallel` at a time; targets
+// asking for the very same session share one call (`singleflight`). Children
+// run one after the other afterwards so their output stays readable; the failure
+// policy decides when to stop and what the overall exit code is.
+use std::collections::{BTreeMap, HashMap};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use crate::settings::{Settings, TargetSet};
+use crate::theme::{self, Role};
+use crate::{Profile, StsCredentials};
+
+/// Default number of concurrent AssumeRole calls.
+pub const DEFAULT_PARALLEL: usize = 8;
+
+/// Shell-style match supporting `*` and `?`.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let n: Vec<char> = name.chars().collect();
+    let (mut pi, mut ni) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while ni < n.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == n[ni]) {
+            pi += 1;
+            ni += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ni));
+            pi += 1;
+        } else if let Some((sp, sn)) = star {
+            pi = sp + 1;
+            ni = sn + 1;
+            star = Some((sp, sn + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|c| *c == '*')
+}
+
+/// One profile, optionally pinned to a region.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    pub profile: String,
+    pub region: Option<String>,
+}
+
+impl std::fmt::Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.region {
+            Some(region) => write!(f, "{} ({})", self.profile, region),
+            None => write!(f, "{}", self.profile),
+        }
+    }
+}
+
+/// Every profile in every region (profile-major), or each profile once without regions.
+pub fn expand(profiles: &[String], regions: &[String]) -> Vec<Target> {
+    let mut targets = Vec::new();
+    for profile in profiles {
+        if regions.is_empty() {
+            targets.push(Target {
+                profile: profile.clone(),
+                region: None,
+            });
+        }
+        for region in regions {
+            targets.push(Target {
+                profile: profile.clone(),
+                region: Some(region.clone()),
+            });
+        }
+    }
+    targets
+}
+
+/// When a multi-target run stops and which exit code it ends with.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FailurePolicy {
+    /// Stop at the first failing target and exit with its code.
+    FailFast,
+    /// Run every target and exit with the first failing code.
+    #[default]
+    KeepGoing,
+    /// Run every target and exit 0 if at least one succeeded.
+    AnySuccess,
+}
+
+impl FailurePolicy {
+    pub fn stops_after(self, exit_code: i32) -> bool {
+        self == FailurePolicy::FailFast && exit_code != 0
+    }
+
+    pub fn exit_code(self, outcomes: &[Outcome]) -> i32 {
+        let codes: Vec<i32> = outcomes.iter().filter_map(|o| o.exit_code).collect();
+        if self == FailurePolicy::AnySuccess && codes.contains(&0) {
+            return 0;
+        }
+        codes.into_iter().find(|c| *c != 0).unwrap_or(0)
+    }
+}
+
+/// What happened to one target.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+    pub target: Target,
+    /// None when the target was skipped after a --fail-fast stop.
+    pub exit_code: Option<i32>,
+    pub duration: Duration,
+    /// Why credentials could not be resolved, if they could not.
+    pub error: Option<String>,
+}
+
+impl Outcome {
+    pub fn status(&self) -> &'static str {
+        match (self.exit_code, &self.error) {
+            (None, _) => "skipped",
+            (_, Some(_)) => "auth failed",
+            (Some(0), None) => "ok",
+            (Some(_), None) => "failed",
+        }
+    }
+}
+
+/// Machine-readable per-target results written to stdout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ResultsFormat {
+    /// One JSON object per target, with the child's output captured.
+    Jsonl,
+}
+
+/// One line of `--results jsonl`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ResultRecord {
+    pub profile: String,
+    pub region: Option<String>,
+    pub status: &'static str,
+    pub exit_code: Option<i32>,
+    pub duration_ms: u64,
+    pub stdout: String,
+    pub stderr: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ResultRecord {
+    pub fn new(outcome: &Outcome, stdout: String, stderr: String) -> ResultRecord {
+        ResultRecord {
+            profile: outcome.target.profile.clone(),
+            region: outcome.target.region.clone(),
+            status: outcome.status(),
+            exit_code: outcome.exit_code,
+            duration_ms: outcome.duration.as_millis() as u64,
+            stdout,
+            stderr,
+            error: outcome.error.clone(),
+        }
+    }
+}
+
+/// Targets named with --profile (in order) followed by the sorted --all-matching hits.
+pub fn select_targets(
+    profiles: &HashMap<String, Profile>,
+    explicit: &[String],
+    pattern: Option<&str>,
+) -> Result<Vec<String>> {
+    let mut targets: Vec<String> = Vec::new();
+    for name in explicit {
+        if !profiles.contains_key(name) {
+            return Err(anyhow!("Profile '{}' not found", name));
+        }
+        if !targets.contains(name) {
+            targets.push(name.clone());
+        }
+    }
+    if let Some(pattern) = pattern {
+        let mut matched: Vec<&String> = profiles.keys().filter(|n| glob_match(pattern, n)).collect();
+        if matched.is_empty() {
+            return Err(anyhow!("No profiles match '{}'", pattern));
+        }
+        matched.sort();
+        for name in matched {
+            if !targets.contains(name) {
+                targets.push(name.clone());
+            }
+        }
+    }
+    Ok(targets)
+}
+
+/// Targets of a saved set; each entry is a profile name or a glob.
+pub fn expand_set(profiles: &HashMap<String, Profile>, name: &str, set: &TargetSet) -> Result<Vec<Target>> {
+    let mut names: Vec<String> = Vec::new();
+    for entry in set.profiles.iter() {
+        let matched = if entry.contains(['*', '?']) {
+            select_targets(profiles, &[], Some(entry))
+        } else {
+            select_targets(profiles, std::slice::from_ref(entry), None)
+        };
+        for profile in matched.with_context(|| format!("in target set '{}'", name))? {
+            if !names.contains(&profile) {
+                names.push(profile);
+            }
+        }
+    }
+    if names.is_empty() {
+        return Err(anyhow!("Target set '{}' has no profiles", name));
+    }
+    Ok(expand(&names, &set.regions))
+}
+
+/// Distinct profiles of `targets`, in first-seen order.
+pub fn profile_names(targets: &[Target]) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for t in targets {
+        if !names.contains(&t.profile) {
+            names.push(t.profile.clone());
+        }
+    }
+    names
+}
+
+/// How the targets of one run are resolved.
+#[derive(Debug, Default, PartialEq)]
+pub struct Plan {
+    /// Resolved one by one, like a single-profile run.
+    pub direct: Vec<String>,
+    /// Role targets keyed by the source profile whose session they share.
+    pub groups: BTreeMap<String, Vec<String>>,
+}
+
+pub fn plan(profiles: &HashMap<String, Profile>, targets: &[String]) -> Plan {
+    let mut plan = Plan::default();
+    for name in targets {
+        let source = profiles
+            .get(name)
+            .filter(|p| p.is_role())
+            .and_then(|p| p.source_profile.clone())
+            .filter(|s| profiles.contains_key(s));
+        match source {
+            Some(source) => plan.groups.entry(source).or_default().push(name.clone()),
+            None => plan.direct.push(name.clone()),
+        }
+    }
+    plan
+}
+
+/// Resolve credentials for every target, keeping the order of `targets`. A failure
+/// only affects its own target (or the targets sharing a failed source profile).
+pub async fn resolve_targets(
+    profiles: &HashMap<String, Profile>,
+    targets: &[String],
+    no_interactive: bool,
+    settings: &Settings,
+    parallel: usize,
+    inherited: Option<&str>,
+) -> Result<Vec<(String, Result<Option<StsCredentials>>)>> {
+    let mut results: HashMap<String, Result<Option<StsCredentials>>> = HashMap::new();
+    // A profile whose credentials an outer awx already put in the environment.
+    let pending: Vec<String> = targets
+        .iter()
+        .filter(|t| Some(t.as_str()) != inherited)
+        .cloned()
+        .collect();
+    if let Some(name) = inherited.filter(|n| targets.iter().any(|t| t == n)) {
+        results.insert(name.to_string(), Ok(None));
+    }
+    let plan = plan(profiles, &pending);
+
+    // Direct targets may prompt, so they are resolved serially.
+    for name in plan.direct {
+        let creds = crate::perform_authentication(profiles, &name, no_interactive, settings).await;
+        results.insert(name, creds);
+    }
+
+    let semaphore = Arc::new(Semaphore::new(parallel.max(1)));
+    let mut tasks = JoinSet::new();
+    for (source, members) in plan.groups {
+        let base = match base_session(profiles, &source, no_interactive, settings).await {
+            Ok(base) => Arc::new(base),
+            Err(e) => {
+                let msg = format!("{:#}", e);
+                for name in members {
+                    crate::metrics::record(&name, crate::metrics::Event::AuthFailure);
+                    results.insert(name, Err(anyhow!("source profile '{}': {}", source, msg)));
+                }
+                continue;
+            }
+        };
+        for name in members {
+            let profile = profiles[&name].clone();
+            let role_arn = profile.role_arn.clone().unwrap_or_default();
+            let session_name = crate::role_session_name(&profile);
+            let duration = crate::role_duration(profiles, &profile).secs;
+            let (base, semaphore, source) = (base.clone(), semaphore.clone(), source.clone());
+            let backends = crate::backend::current();
+            tasks.spawn(crate::backend::scope(backends, async move {
+                let _permit = semaphore.acquire_owned().await.expect("semaphore is never closed");
+                let creds = match crate::expand_role_arn(&role_arn, &profile, &source).await {
+                    Ok(role_arn) => match base.as_ref() {
+                        Some(base) => crate::assume_role_with_env(&role_arn, &session_name, duration, base).await,
+                        None => crate::assume_role_with_profile(&role_arn, &session_name, duration, &source).await,
+                    },
+                    Err(e) => Err(e),
+                };
+                (name, creds.map(Some))
+            }));
+        }
+    }
+    while let Some(joined) = tasks.join_next().await {
+        let (name, creds) = joined.map_err(|e| anyhow!("assume-role task failed: {}", e))?;
+        if creds.is_err() {
+            crate::metrics::record(&name, crate::metrics::Event::AuthFailure);
+        }
+        results.insert(name, creds);
+    }
+
+    Ok(targets
+        .iter()
+        .map(|t| {
+            let creds = results.remove(t).unwrap_or_else(|| Err(anyhow!("Profile '{}' was not resolved", t)));
+            (t.clone(), creds)
+        })
+        .collect())
+}
+
+// Credentials every role of a group can assume from, or None when the AWS CLI has to
+// resolve the source profile itself (static keys, or an SSO session it cannot export).
+async fn base_session(
+    profiles: &HashMap<String, Profile>,
+    source: &str,
+    no_interactive: bool,
+    settings: &Settings,
+) -> Result<Option<StsCredentials>> {
+    if let Some(creds) = crate::perform_authentication(profiles, source, no_interactive, settings).await? {
+        return Ok(Some(creds));
+    }
+    if profiles.get(source).map(|p| p.is_sso()).unwrap_or(false) {
+        match crate::export_credentials(source).await {
+            Ok(creds) => return Ok(Some(creds)),
+            Err(e) => eprintln!("Could not export SSO credentials for '{}', assuming roles through the profile: {}", source, e),
+        }
+    }
+    Ok(None)
+}
+
+/// Run `command` once per target with resolved credentials, honoring `policy`. With
+/// `results`, each child's output is captured and streamed as one record per target.
+pub async fn run_targets(
+    profiles: &HashMap<String, Profile>,
+    targets: &[Target],
+    resolved: Vec<(String, Result<Option<StsCredentials>>)>,
+    command: &[String],
+    policy: FailurePolicy,
+    results: Option<ResultsFormat>,
+) -> Result<Vec<Outcome>> {
+    let resolved: HashMap<String, std::result::Result<Option<StsCredentials>, String>> = resolved
+        .into_iter()
+        .map(|(name, creds)| (name, creds.map_err(|e| format!("{:#}", e))))
+        .collect();
+    let multi = targets.len() > 1;
+    let capture = results.is_some();
+    let mut outcomes = Vec::new();
+    let mut stopped = false;
+    for target in targets {
+        let mut captured = (String::new(), String::new());
+        let outcome = if stopped {
+            Outcome {
+                target: target.clone(),
+                exit_code: None,
+                duration: Duration::ZERO,
+                error: None,
+            }
+        } else {
+            if multi && !capture {
+                eprintln!("{}", theme::style(Role::Heading).apply_to(format!("==> {}", target)));
+            }
+            let started = Instant::now();
+            let (exit_code, error) = match &resolved[&target.profile] {
+                Ok(creds) => {
+                    let (code, output) = run_one(profiles, target, command, creds.clone(), capture).await?;
+                    captured = output;
+                    (code, None)
+                }
+                Err(e) => {
+                    if !capture {
+                        eprintln!("Error: {}", e);
+                    }
+                    (1, Some(e.clone()))
+                }
+            };
+            stopped = policy.stops_after(exit_code);
+            Outcome {
+                target: target.clone(),
+                exit_code: Some(exit_code),
+                duration: started.elapsed(),
+                error,
+            }
+        };
+        if let Some(ResultsFormat::Jsonl) = results {
+            let record = ResultRecord::new(&outcome, captured.0, captured.1);
+            println!("{}", serde_json::to_string(&record)?);
+        }
+        outcomes.push(outcome);
+    }
+    Ok(outcomes)
+}
+
+// Run the command for one target. When capturing, stdin is closed and stdout/stderr
+// are returned instead of being passed through.
+pub async fn run_one(
+    profiles: &HashMap<String, Profile>,
+    target: &Target,
+    command: &[String],
+    creds: Option<StsCredentials>,
+    capture: bool,
+) -> Result<(i32, (String, String))> {
+    let mut cmd = crate::child_command(&command[0], &command[1..], creds.clone(), &profiles[&target.profile]);
+    if let Some(region) = &target.region {
+        cmd.env("AWS_REGION", region).env("AWS_DEFAULT_REGION", region);
+    }
+    // A fresh endpoint, path and token for every child.
+    let _endpoint = crate::endpoint::for_child(&mut cmd, creds.as_ref())?;
+    if capture {
+        let mut span = crate::telemetry::span("child");
+        span.attr("awx.profile", &target.profile);
+        let output = cmd
+            .stdin(Stdio::null())
+            .output()
+            .await
+            .with_context(|| format!("failed to run child command '{}'", command[0]))?;
+        let code = crate::exit_code(output.status);
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+        span.attr("process.exit_code", code);
+        return Ok((code, (stdout, stderr)));
+    }
+    cmd.stdin(Stdio::inherit()).stdout(Stdio::inherit()).stderr(Stdio::inherit());
+    let child = cmd
+        .spawn()
+        .with_context(|| format!("failed to spawn child command '{}'", command[0]))?;
+    let code = crate::child_span(crate::wait_forwarding_signals(child)).await?;
+    Ok((code, (String::new(), String::new())))
+}
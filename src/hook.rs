@@ -0,0 +1,194 @@
+// Shell hook (`awx hook <shell>`).
+//
+// The hook runs `awx hook-env` before each prompt, so a `cd` into a project or an
+// `awx ctx use` takes effect at the next prompt. hook-env works out which profile the
+// shell should be on and prints the exports that bring the environment there, and
+// nothing when it already is. Only names are exported, never credentials:
+//
+// - AWS_PROFILE: `profile` from the nearest .awx.toml, else the profile of its
+//   `context`, else the profile of the active context.
+// - AWX_CONTEXT: the `context` of the nearest .awx.toml, so awx uses it there.
+// - AWX_PROJECT: the directory holding that .awx.toml, for prompts.
+//
+// AWX_HOOK lists the variables the hook exported; they are unset again once nothing
+// asks for them, e.g. after leaving the project. Variables the user set are only
+// replaced while a project or context asks for something else.
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+
+use crate::context;
+use crate::settings::Settings;
+
+pub const PROJECT_FILE: &str = ".awx.toml";
+pub const HOOK_ENV: &str = "AWX_HOOK";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Shell {
+    Zsh,
+    Bash,
+    Fish,
+}
+
+/// A project's .awx.toml.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProjectFile {
+    pub profile: Option<String>,
+    /// A context from ~/.awx/config.toml.
+    pub context: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Project {
+    pub dir: PathBuf,
+    pub file: ProjectFile,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Set(String, String),
+    Unset(String),
+}
+
+/// The snippet to source from the shell's rc file.
+pub fn snippet(shell: Shell) -> &'static str {
+    match shell {
+        Shell::Zsh => {
+            r#"# awx hook: keep AWS_PROFILE in step with .awx.toml and `awx ctx`
+_awx_hook() {
+    eval "$(command awx hook-env zsh)"
+}
+typeset -ag precmd_functions
+if (( ! ${precmd_functions[(I)_awx_hook]} )); then
+    precmd_functions=(_awx_hook $precmd_functions)
+fi
+# Add to ~/.zshrc: eval "$(command awx hook zsh)"
+"#
+        }
+        Shell::Bash => {
+            r#"# awx hook: keep AWS_PROFILE in step with .awx.toml and `awx ctx`
+_awx_hook() {
+    local previous_exit_status=$?
+    eval "$(command awx hook-env bash)"
+    return $previous_exit_status
+}
+if [[ ";${PROMPT_COMMAND:-};" != *";_awx_hook;"* ]]; then
+    PROMPT_COMMAND="_awx_hook${PROMPT_COMMAND:+;$PROMPT_COMMAND}"
+fi
+# Add to ~/.bashrc: eval "$(command awx hook bash)"
+"#
+        }
+        Shell::Fish => {
+            r#"# awx hook: keep AWS_PROFILE in step with .awx.toml and `awx ctx`
+function _awx_hook --on-event fish_prompt
+    command awx hook-env fish | source
+end
+# Add to config.fish: command awx hook fish | source
+"#
+        }
+    }
+}
+
+/// The nearest .awx.toml in `dir` or above.
+pub fn find_project(dir: &Path) -> Result<Option<Project>> {
+    for dir in dir.ancestors() {
+        let path = dir.join(PROJECT_FILE);
+        if !path.is_file() {
+            continue;
+        }
+        let content = std::fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let file = toml::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))?;
+        return Ok(Some(Project {
+            dir: dir.to_path_buf(),
+            file,
+        }));
+    }
+    Ok(None)
+}
+
+/// The variables the shell should have, given the nearest project and the active context.
+pub fn desired(project: Option<&Project>, settings: &Settings, active: Option<&str>) -> Result<Vec<(String, String)>> {
+    let context_profile = |name: &str| {
+        settings
+            .contexts
+            .get(name)
+            .map(|c| c.profile.clone())
+            .ok_or_else(|| anyhow!("Context '{}' is not defined under [contexts.{}] in ~/.awx/config.toml", name, name))
+    };
+    let mut vars = Vec::new();
+    let mut profile = None;
+    if let Some(project) = project {
+        vars.push(("AWX_PROJECT".to_string(), project.dir.display().to_string()));
+        if let Some(name) = &project.file.context {
+            profile = Some(context_profile(name)?);
+            vars.push((context::CONTEXT_ENV.to_string(), name.clone()));
+        }
+        if let Some(p) = &project.file.profile {
+            profile = Some(p.clone());
+        }
+    }
+    if profile.is_none() {
+        if let Some(name) = active {
+            profile = Some(context_profile(name)?);
+        }
+    }
+    if let Some(profile) = profile {
+        vars.push(("AWS_PROFILE".to_string(), profile));
+    }
+    vars.sort();
+    Ok(vars)
+}
+
+/// Variables exported by an earlier run of the hook.
+pub fn managed(lookup: &impl Fn(&str) -> Option<String>) -> Vec<String> {
+    lookup(HOOK_ENV)
+        .map(|v| v.split(':').filter(|n| !n.is_empty()).map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+/// What to export or unset to get from the current environment to `desired`.
+pub fn changes(desired: &[(String, String)], lookup: &impl Fn(&str) -> Option<String>) -> Vec<Change> {
+    let mut changes: Vec<Change> = desired
+        .iter()
+        .filter(|(k, v)| lookup(k).as_ref() != Some(v))
+        .map(|(k, v)| Change::Set(k.clone(), v.clone()))
+        .collect();
+    for name in managed(lookup) {
+        if !desired.iter().any(|(k, _)| *k == name) {
+            changes.push(Change::Unset(name));
+        }
+    }
+    let names = desired.iter().map(|(k, _)| k.as_str()).collect::<Vec<_>>().join(":");
+    match lookup(HOOK_ENV) {
+        Some(current) if current == names => {}
+        None if names.is_empty() => {}
+        _ if names.is_empty() => changes.push(Change::Unset(HOOK_ENV.to_string())),
+        _ => changes.push(Change::Set(HOOK_ENV.to_string(), names)),
+    }
+    changes
+}
+
+/// `changes` as commands for `shell` to evaluate.
+pub fn script(shell: Shell, changes: &[Change]) -> String {
+    let mut out = String::new();
+    for change in changes {
+        let line = match (shell, change) {
+            (Shell::Fish, Change::Set(k, v)) => format!("set -gx {} {};\n", k, fish_quote(v)),
+            (Shell::Fish, Change::Unset(k)) => format!("set -e {};\n", k),
+            (_, Change::Set(k, v)) => format!("export {}={};\n", k, posix_quote(v)),
+            (_, Change::Unset(k)) => format!("unset {};\n", k),
+        };
+        out.push_str(&line);
+    }
+    out
+}
+
+fn posix_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+fn fish_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\\', r"\\").replace('\'', r"\'"))
+}
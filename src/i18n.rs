@@ -0,0 +1,314 @@
+// Localized messages.
+//
+// Guidance people act on (what is needed to log in, what went wrong and how to fix it,
+// prompts) is looked up in this catalog instead of written inline, in English or
+// Japanese. The language is `language` in ~/.awx/config.toml, else the first of
+// LC_ALL, LC_MESSAGES and LANG that is set (`ja_JP.UTF-8` selects Japanese); anything
+// else is English. Output stays English until `configure` runs, so tests and the
+// golden snapshots do not depend on the environment. Placeholders such as `{profile}`
+// are filled in by `t`.
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+
+static LANG: OnceLock<Lang> = OnceLock::new();
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Lang {
+    #[default]
+    En,
+    Ja,
+}
+
+/// The language to use: `setting` if given, else the locale variables.
+pub fn detect(setting: Option<Lang>, lookup: &impl Fn(&str) -> Option<String>) -> Lang {
+    if let Some(lang) = setting {
+        return lang;
+    }
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .into_iter()
+        .find_map(|name| lookup(name).filter(|v| !v.is_empty()));
+    match locale {
+        Some(locale) if locale.starts_with("ja") => Lang::Ja,
+        _ => Lang::En,
+    }
+}
+
+/// Use `lang` for this process.
+pub fn configure(lang: Lang) {
+    let _ = LANG.set(lang);
+}
+
+pub fn current() -> Lang {
+    LANG.get().copied().unwrap_or_default()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Msg {
+    OfflineCause,
+    OfflineFix,
+    SsoExpiredCause,
+    SsoLoginFix,
+    ClockSkewCause,
+    ClockSkewFix,
+    InvalidKeysCause,
+    InvalidKeysFix,
+    MfaRejectedCause,
+    MfaRejectedFix,
+    RoleChainingCause,
+    RoleChainingFix,
+    AssumeRoleDeniedCause,
+    AssumeRoleDeniedFix,
+    TrustPolicyCause,
+    TrustPolicyFix,
+    TrustPolicyExcludesCause,
+    TrustPolicyExcludesFix,
+    RoleMissingCause,
+    TrustPolicyConditional,
+    TrustPolicyAccountOnly,
+    NotPermittedCause,
+    NotPermittedFix,
+    ThrottledCause,
+    ThrottledFix,
+    NetworkCause,
+    NetworkFix,
+    CredentialsBrokenCause,
+    CredentialsBrokenFix,
+    UnknownCause,
+    UnknownFix,
+    Diagnostics,
+    MostLikelyCause,
+    SuggestedFix,
+    LookIntoFailure,
+    ConfirmContext,
+    ContextNeedsConfirmation,
+    Cancelled,
+    SsoLoginRequired,
+    SsoLoginRunning,
+    SsoDeviceAuthorization,
+    SsoLoginCompleted,
+    MfaPrompt,
+    MfaInvalidFormat,
+    MfaAttemptFailed,
+    MfaFailed,
+    MfaCodeRequired,
+    CredentialsReady,
+    CopyExports,
+    Aborted,
+    Error,
+}
+
+impl Msg {
+    /// The message in `lang`, with its `{name}` placeholders.
+    pub fn text(self, lang: Lang) -> &'static str {
+        let (en, ja) = match self {
+            Msg::OfflineCause => (
+                "awx is running with --offline and the profile needs the network",
+                "awx は --offline で実行されていますが、このプロファイルにはネットワークが必要です",
+            ),
+            Msg::OfflineFix => (
+                "Run again without --offline (or unset AWX_OFFLINE)",
+                "--offline を付けずに (または AWX_OFFLINE を解除して) 再実行してください",
+            ),
+            Msg::SsoExpiredCause => (
+                "The SSO session of '{profile}' has expired",
+                "'{profile}' の SSO セッションの有効期限が切れています",
+            ),
+            Msg::SsoLoginFix => (
+                "Run: aws sso login --profile {profile}",
+                "次を実行してください: aws sso login --profile {profile}",
+            ),
+            Msg::ClockSkewCause => (
+                "The local clock is off, so AWS rejects the request signatures",
+                "ローカルの時計がずれているため、AWS がリクエストの署名を拒否しています",
+            ),
+            Msg::ClockSkewFix => (
+                "Synchronize the system clock (e.g. timedatectl set-ntp true) and retry",
+                "システム時計を同期して (例: timedatectl set-ntp true) 再試行してください",
+            ),
+            Msg::InvalidKeysCause => (
+                "The credentials of '{profile}' are invalid or expired",
+                "'{profile}' の認証情報が無効か、有効期限が切れています",
+            ),
+            Msg::InvalidKeysFix => (
+                "Replace the keys with: aws configure --profile {profile}",
+                "次のコマンドでキーを置き換えてください: aws configure --profile {profile}",
+            ),
+            Msg::MfaRejectedCause => ("The MFA code was rejected", "MFA コードが拒否されました"),
+            Msg::MfaRejectedFix => (
+                "Wait for the next code and retry; check that mfa_serial of '{profile}' names your device",
+                "次のコードを待って再試行してください。'{profile}' の mfa_serial がお使いのデバイスを指しているかも確認してください",
+            ),
+            Msg::RoleChainingCause => (
+                "The requested session is longer than the 1 hour allowed for chained roles",
+                "要求したセッションの長さが、ロールの連鎖で許される 1 時間を超えています",
+            ),
+            Msg::RoleChainingFix => (
+                "Set duration_seconds = 3600 (or less) on profile '{profile}'",
+                "プロファイル '{profile}' に duration_seconds = 3600 (以下) を設定してください",
+            ),
+            Msg::AssumeRoleDeniedCause => (
+                "The identity of '{profile}' is not allowed sts:AssumeRole on {role}",
+                "'{profile}' の ID には {role} に対する sts:AssumeRole が許可されていません",
+            ),
+            Msg::AssumeRoleDeniedFix => (
+                "Grant sts:AssumeRole on the role in the source identity's IAM policy",
+                "ソース ID の IAM ポリシーで、このロールに対する sts:AssumeRole を許可してください",
+            ),
+            Msg::TrustPolicyCause => (
+                "The trust policy of {role} does not accept the identity of '{profile}'",
+                "{role} の信頼ポリシーが '{profile}' の ID を受け入れていません",
+            ),
+            Msg::TrustPolicyFix => (
+                "Check role_arn, and add the source identity to the role's trust policy (or add mfa_serial if the role requires MFA)",
+                "role_arn を確認し、ロールの信頼ポリシーにソース ID を追加してください (ロールが MFA を要求する場合は mfa_serial を追加してください)",
+            ),
+            Msg::TrustPolicyExcludesCause => (
+                "The trust policy of {role} does not include {principal}, the user or role you are signed in as",
+                "{role} の信頼ポリシーに、サインイン中のユーザーまたはロール {principal} が含まれていません",
+            ),
+            Msg::TrustPolicyExcludesFix => (
+                "Ask the role's owner to add {principal} to the Principal of its trust policy, or check role_arn",
+                "ロールの管理者に {principal} を信頼ポリシーの Principal へ追加するよう依頼するか、role_arn を確認してください",
+            ),
+            Msg::RoleMissingCause => (
+                "Role {role} does not exist; check role_arn",
+                "ロール {role} は存在しません。role_arn を確認してください",
+            ),
+            Msg::TrustPolicyConditional => (
+                "The trust policy of {role} lets you in only under conditions, such as MFA or an external id",
+                "{role} の信頼ポリシーは、MFA や外部 ID などの条件を満たす場合にのみアクセスを許可します",
+            ),
+            Msg::TrustPolicyAccountOnly => (
+                "The trust policy of {role} trusts your whole account, so your own IAM policies must also allow sts:AssumeRole on it",
+                "{role} の信頼ポリシーはアカウント全体を信頼しているため、あなた自身の IAM ポリシーでもこのロールへの sts:AssumeRole を許可する必要があります",
+            ),
+            Msg::NotPermittedCause => (
+                "'{profile}' is not permitted to perform the call",
+                "'{profile}' にはこの呼び出しを実行する権限がありません",
+            ),
+            Msg::NotPermittedFix => (
+                "Check the IAM policies attached to the identity",
+                "この ID にアタッチされている IAM ポリシーを確認してください",
+            ),
+            Msg::ThrottledCause => ("STS is throttling the requests", "STS がリクエストを制限 (スロットリング) しています"),
+            Msg::ThrottledFix => (
+                "Lower rps under [rate_limit] in ~/.awx/config.toml, or retry later",
+                "~/.awx/config.toml の [rate_limit] で rps を下げるか、時間をおいて再試行してください",
+            ),
+            Msg::NetworkCause => ("AWS could not be reached", "AWS に接続できませんでした"),
+            Msg::NetworkFix => (
+                "Check the network, proxy (HTTPS_PROXY) and AWS_CA_BUNDLE settings",
+                "ネットワーク、プロキシ (HTTPS_PROXY)、AWS_CA_BUNDLE の設定を確認してください",
+            ),
+            Msg::CredentialsBrokenCause => (
+                "The credentials of '{profile}' do not work",
+                "'{profile}' の認証情報が使えません",
+            ),
+            Msg::CredentialsBrokenFix => (
+                "Run: aws sts get-caller-identity --profile {profile} --debug",
+                "次を実行してください: aws sts get-caller-identity --profile {profile} --debug",
+            ),
+            Msg::UnknownCause => (
+                "The failure does not match a known cause",
+                "既知の原因に当てはまらない失敗です",
+            ),
+            Msg::UnknownFix => (
+                "Run: awx --explain -p {profile} and check the resolution steps",
+                "awx --explain -p {profile} を実行して、解決の手順を確認してください",
+            ),
+            Msg::Diagnostics => ("Diagnostics", "診断"),
+            Msg::MostLikelyCause => ("Most likely cause:", "考えられる原因:"),
+            Msg::SuggestedFix => ("Suggested fix:", "対処方法:"),
+            Msg::LookIntoFailure => (
+                "Resolving '{profile}' failed. Look into why?",
+                "'{profile}' の解決に失敗しました。原因を調べますか?",
+            ),
+            Msg::ConfirmContext => (
+                "Run with profile '{profile}' (context '{context}')?",
+                "プロファイル '{profile}' (コンテキスト '{context}') で実行しますか?",
+            ),
+            Msg::ContextNeedsConfirmation => (
+                "Context '{context}' asks for confirmation before running commands with profile '{profile}'; run interactively",
+                "コンテキスト '{context}' では、プロファイル '{profile}' でコマンドを実行する前に確認が必要です。対話モードで実行してください",
+            ),
+            Msg::Cancelled => ("Cancelled", "キャンセルしました"),
+            Msg::SsoLoginRequired => (
+                "SSO login required for profile \"{profile}\". Run: aws sso login --profile {profile}",
+                "プロファイル \"{profile}\" には SSO ログインが必要です。次を実行してください: aws sso login --profile {profile}",
+            ),
+            Msg::SsoLoginRunning => (
+                "SSO token is not valid. Running: aws sso login --profile {profile}{options}",
+                "SSO トークンが無効です。実行します: aws sso login --profile {profile}{options}",
+            ),
+            Msg::SsoDeviceAuthorization => (
+                "SSO token is not valid. Starting device authorization for {start_url}",
+                "SSO トークンが無効です。{start_url} のデバイス認証を開始します",
+            ),
+            Msg::SsoLoginCompleted => ("SSO login completed.", "SSO ログインが完了しました。"),
+            Msg::MfaPrompt => (
+                "Enter MFA code (6 digits) for {serial}: ",
+                "{serial} の MFA コード (6 桁) を入力してください: ",
+            ),
+            Msg::MfaInvalidFormat => ("Invalid code format", "コードの形式が正しくありません"),
+            Msg::MfaAttemptFailed => (
+                "MFA attempt {attempt} failed: {error}",
+                "MFA の {attempt} 回目の試行に失敗しました: {error}",
+            ),
+            Msg::MfaFailed => (
+                "MFA for profile '{profile}' failed after {attempts} attempt(s)",
+                "プロファイル '{profile}' の MFA に {attempts} 回失敗しました",
+            ),
+            Msg::MfaCodeRequired => (
+                "MFA code required for profile \"{profile}\". Set {env} and retry.",
+                "プロファイル \"{profile}\" には MFA コードが必要です。{env} を設定して再実行してください。",
+            ),
+            Msg::CredentialsReady => (
+                "# AWS credentials for profile '{profile}' are ready.",
+                "# プロファイル '{profile}' の AWS 認証情報の準備ができました。",
+            ),
+            Msg::CopyExports => (
+                "# Copy and paste the following commands into your terminal:",
+                "# 次のコマンドをコピーしてターミナルに貼り付けてください:",
+            ),
+            Msg::Aborted => ("Aborted.", "中断しました。"),
+            Msg::Error => ("Error:", "エラー:"),
+        };
+        match lang {
+            Lang::En => en,
+            Lang::Ja => ja,
+        }
+    }
+}
+
+/// `msg` in the current language with `args` filled in.
+pub fn t(msg: Msg, args: &[(&str, &str)]) -> String {
+    fill(msg.text(current()), args)
+}
+
+/// Replace each `{name}` in `template` with its value from `args`; other text,
+/// including braces in the values, is kept as it is.
+pub fn fill(template: &str, args: &[(&str, &str)]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let tail = &rest[start + 1..];
+        let value = tail
+            .find('}')
+            .and_then(|end| args.iter().find(|(name, _)| *name == &tail[..end]).map(|(_, v)| (end, v)));
+        match value {
+            Some((end, value)) => {
+                out.push_str(value);
+                rest = &tail[end + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = tail;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
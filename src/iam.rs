@@ -0,0 +1,390 @@
+// IAM policy simulation and principal details.
+//
+// `SimulatePrincipalPolicy` evaluates the identity-based policies of a user or role
+// against actions and resources without performing them. awx runs it with the
+// principal's own credentials, so it only works where the principal may call
+// iam:SimulatePrincipalPolicy on itself. Resource-based policies such as a role's trust
+// policy or a bucket policy are not part of the evaluation. `describe` reads what is
+// attached to a user or role (`awx whoami --deep`), again as far as the caller may read
+// its own IAM entity; whatever IAM refuses is reported instead of failing the report.
+// `assumable_roles` reads the trust policies of the roles in the caller's account
+// (`awx ls-roles`). A trust policy is only half of the decision when it trusts the
+// whole account, and conditions are not evaluated, so the result lists candidates.
+// `check_trust` reads the trust policy of one role before it is assumed
+// (`--verify-trust`), so that a role which leaves the caller out fails with that reason
+// instead of a bare AccessDenied. IAM only shows roles of the caller's own account;
+// roles elsewhere are not checked. `max_session_duration` reads how long a session of
+// a role may last, for the `--choose-duration` picker.
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::process::Command;
+
+use crate::arn::Arn;
+use crate::{backend, ratelimit, StsCredentials};
+
+pub fn verifies_trust() -> bool {
+    backend::flags().verify_trust
+        || matches!(crate::aws_env::current_lookup("AWX_VERIFY_TRUST").as_deref(), Some("1") | Some("true"))
+}
+
+/// The outcome for one action on one resource.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Evaluation {
+    pub eval_action_name: String,
+    #[serde(default)]
+    pub eval_resource_name: String,
+    /// `allowed`, `implicitDeny` or `explicitDeny`.
+    pub eval_decision: String,
+}
+
+impl Evaluation {
+    pub fn allowed(&self) -> bool {
+        self.eval_decision == "allowed"
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SimulateResponse {
+    evaluation_results: Vec<Evaluation>,
+}
+
+/// The IAM principal behind a caller identity ARN: assumed-role sessions map to their
+/// role. Role paths are not part of the session ARN, so roles with a path (such as SSO
+/// permission sets) cannot be recovered.
+pub fn principal_arn(caller: &Arn) -> Option<String> {
+    match (caller.service.as_str(), caller.resource_type()) {
+        ("iam", Some("user")) | ("iam", Some("role")) => Some(caller.to_string()),
+        ("sts", Some("assumed-role")) => {
+            let role = caller.resource.strip_prefix("assumed-role/")?.split('/').next()?;
+            Some(crate::arn::role(&caller.partition, &caller.account, role).to_string())
+        }
+        _ => None,
+    }
+}
+
+/// `aws --profile <profile>`, for calling IAM as a configured profile.
+pub fn as_profile(profile: &str) -> Command {
+    let mut cmd = backend::command("aws");
+    backend::select_profile(&mut cmd, profile);
+    cmd
+}
+
+/// `aws` with the session `creds` in its environment, for calling IAM as a resolved
+/// session.
+pub fn with_session(creds: &StsCredentials) -> Command {
+    let mut cmd = backend::command("aws");
+    cmd.env_remove("AWS_PROFILE")
+        .env("AWS_ACCESS_KEY_ID", &creds.access_key_id)
+        .env("AWS_SECRET_ACCESS_KEY", &creds.secret_access_key)
+        .env("AWS_SESSION_TOKEN", &creds.session_token);
+    cmd
+}
+
+/// Evaluate `actions` on `resources` for `principal`. `aws` is the AWS CLI set up with
+/// the credentials to call IAM with, through its environment or `as_profile`.
+pub async fn simulate(mut aws: Command, principal: &str, actions: &[String], resources: &[String]) -> Result<Vec<Evaluation>> {
+    if actions.is_empty() {
+        return Err(anyhow!("no actions to simulate"));
+    }
+    let cmd = &mut aws;
+    cmd.args(["iam", "simulate-principal-policy", "--policy-source-arn", principal, "--action-names"])
+        .args(actions);
+    if !resources.is_empty() {
+        cmd.arg("--resource-arns").args(resources);
+    }
+    cmd.args(["--output", "json"]);
+    let output = ratelimit::output(cmd)
+        .await
+        .context("failed to run aws iam simulate-principal-policy")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("simulate-principal-policy failed: {}", stderr.trim()));
+    }
+    let resp: SimulateResponse = serde_json::from_slice(&output.stdout)
+        .context("Parsing simulate-principal-policy JSON response failed")?;
+    Ok(resp.evaluation_results)
+}
+
+/// A lookup that IAM may refuse; the error is the short reason, e.g. `AccessDenied`.
+pub type Lookup<T> = std::result::Result<T, String>;
+
+/// What is attached to a user or role.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Details {
+    pub principal: String,
+    /// The IAM Identity Center permission set behind an `AWSReservedSSO_` role.
+    pub permission_set: Option<String>,
+    pub attached_policies: Lookup<Vec<String>>,
+    pub inline_policies: Lookup<Vec<String>>,
+    /// Group memberships; `None` for roles.
+    pub groups: Option<Lookup<Vec<String>>>,
+    pub permissions_boundary: Lookup<Option<String>>,
+}
+
+/// The permission set name in `AWSReservedSSO_<PermissionSet>_<suffix>`.
+pub fn permission_set(role_name: &str) -> Option<&str> {
+    let (name, _) = role_name.strip_prefix("AWSReservedSSO_")?.rsplit_once('_')?;
+    (!name.is_empty()).then_some(name)
+}
+
+/// Read the details of `principal`, a user or role ARN. `aws` makes a fresh AWS CLI
+/// command set up with the credentials to call IAM with.
+pub async fn describe(aws: impl Fn() -> Command, principal: &Arn) -> Result<Details> {
+    let name = principal.resource.rsplit('/').next().unwrap_or_default();
+    let (kind, flag) = match principal.resource_type() {
+        Some("role") if principal.service == "iam" => ("role", "--role-name"),
+        Some("user") if principal.service == "iam" => ("user", "--user-name"),
+        _ => return Err(anyhow!("'{}' is not an IAM user or role", principal)),
+    };
+    let entity = read(aws(), &[&format!("get-{}", kind), flag, name]).await;
+    let attached = read(aws(), &[&format!("list-attached-{}-policies", kind), flag, name]).await;
+    let inline = read(aws(), &[&format!("list-{}-policies", kind), flag, name]).await;
+    let groups = match kind {
+        "user" => Some(read(aws(), &["list-groups-for-user", flag, name]).await),
+        _ => None,
+    };
+
+    let names = |v: &Value, list: &str, field: &str| -> Vec<String> {
+        v[list]
+            .as_array()
+            .map(|items| {
+                items
+                    .iter()
+                    .filter_map(|i| if field.is_empty() { i.as_str() } else { i[field].as_str() })
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    let entity_key = if kind == "role" { "Role" } else { "User" };
+    Ok(Details {
+        principal: principal.to_string(),
+        permission_set: (kind == "role").then(|| permission_set(name)).flatten().map(str::to_string),
+        attached_policies: attached.map(|v| names(&v, "AttachedPolicies", "PolicyName")),
+        inline_policies: inline.map(|v| names(&v, "PolicyNames", "")),
+        groups: groups.map(|g| g.map(|v| names(&v, "Groups", "GroupName"))),
+        permissions_boundary: entity.map(|v| {
+            v[entity_key]["PermissionsBoundary"]["PermissionsBoundaryArn"]
+                .as_str()
+                .map(str::to_string)
+        }),
+    })
+}
+
+/// Run `aws iam <args>` and return its JSON, or the reason it failed.
+async fn read(aws: Command, args: &[&str]) -> Lookup<Value> {
+    call(aws, "iam", args).await
+}
+
+/// Run `aws <service> <args>` and return its JSON, or the reason it failed.
+async fn call(mut aws: Command, service: &str, args: &[&str]) -> Lookup<Value> {
+    aws.arg(service).args(args).args(["--output", "json"]);
+    let output = match ratelimit::output(&mut aws).await {
+        Ok(output) => output,
+        Err(e) => return Err(e.to_string()),
+    };
+    if !output.status.success() {
+        return Err(error_code(&String::from_utf8_lossy(&output.stderr)));
+    }
+    serde_json::from_slice(&output.stdout).map_err(|e| format!("unexpected response: {}", e))
+}
+
+/// `AccessDenied` from `An error occurred (AccessDenied) when calling ...`.
+fn error_code(stderr: &str) -> String {
+    let stderr = stderr.trim();
+    stderr
+        .split_once("An error occurred (")
+        .and_then(|(_, rest)| rest.split_once(')'))
+        .map(|(code, _)| code.to_string())
+        .unwrap_or_else(|| stderr.lines().next().unwrap_or("failed").to_string())
+}
+
+/// How a role's trust policy lets the caller in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Trust {
+    /// The policy names the caller's user or role.
+    Principal,
+    /// The policy trusts the caller's account; the caller's own policies must also allow
+    /// sts:AssumeRole on the role.
+    Account,
+    /// The policy trusts any AWS principal.
+    Anyone,
+}
+
+/// A role the caller may be able to assume.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assumable {
+    pub arn: String,
+    pub trust: Trust,
+    /// Every statement letting the caller in has conditions, e.g. MFA or an external id.
+    pub conditional: bool,
+}
+
+/// List the roles in the caller's account whose trust policy lets `caller` (the
+/// identity ARN) in, sorted by ARN. `aws` is the AWS CLI set up with the caller's
+/// credentials.
+pub async fn assumable_roles(aws: Command, caller: &Arn) -> Result<Vec<Assumable>> {
+    let principal: Arn = principal_arn(caller)
+        .ok_or_else(|| anyhow!("{} is not an IAM user or role", caller))?
+        .parse()?;
+    let roles = read(aws, &["list-roles"])
+        .await
+        .map_err(|reason| anyhow!("iam list-roles failed: {} (needs iam:ListRoles)", reason))?;
+    let mut found: Vec<Assumable> = roles["Roles"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|role| {
+            let arn = role["Arn"].as_str()?;
+            let document = policy_document(&role["AssumeRolePolicyDocument"])?;
+            let (trust, conditional) = trusts(&document, caller, &principal)?;
+            Some(Assumable {
+                arn: arn.to_string(),
+                trust,
+                conditional,
+            })
+        })
+        .collect();
+    found.sort_by(|a, b| a.arn.cmp(&b.arn));
+    Ok(found)
+}
+
+// The API returns the document URL-encoded; the AWS CLI decodes it.
+fn policy_document(v: &Value) -> Option<Value> {
+    match v {
+        Value::String(s) => serde_json::from_str(s).ok(),
+        Value::Null => None,
+        v => Some(v.clone()),
+    }
+}
+
+/// What the trust policy of a role says about the caller.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrustCheck {
+    /// The policy lets the caller in, under conditions when `conditional`.
+    Trusted { trust: Trust, conditional: bool },
+    /// The policy leaves out `principal`, the caller's IAM user or role.
+    Excluded { principal: String },
+    /// There is no such role in the caller's account.
+    Missing,
+    /// The policy could not be read; the reason.
+    Unknown(String),
+}
+
+/// Read the trust policy of `role` and check it against the caller. `aws` makes a fresh
+/// AWS CLI command set up with the credentials the role would be assumed with.
+pub async fn check_trust(aws: impl Fn() -> Command, role: &Arn) -> TrustCheck {
+    let caller: Arn = match call(aws(), "sts", &["get-caller-identity"]).await {
+        Ok(identity) => match identity["Arn"].as_str().map(str::parse) {
+            Some(Ok(arn)) => arn,
+            _ => return TrustCheck::Unknown("get-caller-identity returned no ARN".to_string()),
+        },
+        Err(reason) => return TrustCheck::Unknown(format!("get-caller-identity failed: {}", reason)),
+    };
+    let Some(principal) = principal_arn(&caller).and_then(|p| p.parse::<Arn>().ok()) else {
+        return TrustCheck::Unknown(format!("{} is not an IAM user or role", caller));
+    };
+    if role.account != caller.account {
+        return TrustCheck::Unknown(format!(
+            "the role is in account {}, and IAM only shows roles of account {}",
+            role.account, caller.account
+        ));
+    }
+    let name = role.resource.rsplit('/').next().unwrap_or_default();
+    let document = match read(aws(), &["get-role", "--role-name", name]).await {
+        Ok(v) => policy_document(&v["Role"]["AssumeRolePolicyDocument"]),
+        Err(reason) if reason == "NoSuchEntity" => return TrustCheck::Missing,
+        Err(reason) => return TrustCheck::Unknown(format!("iam get-role failed: {} (needs iam:GetRole)", reason)),
+    };
+    let Some(document) = document else {
+        return TrustCheck::Unknown("get-role returned no trust policy".to_string());
+    };
+    match trusts(&document, &caller, &principal) {
+        Some((trust, conditional)) => TrustCheck::Trusted { trust, conditional },
+        None => TrustCheck::Excluded { principal: principal.to_string() },
+    }
+}
+
+/// The longest session `role` allows (its MaxSessionDuration), in seconds. Like
+/// `check_trust`, this only works for roles in the caller's own account.
+pub async fn max_session_duration(aws: Command, role: &Arn) -> Lookup<i64> {
+    let name = role.resource.rsplit('/').next().unwrap_or_default();
+    let v = read(aws, &["get-role", "--role-name", name]).await?;
+    v["Role"]["MaxSessionDuration"]
+        .as_i64()
+        .ok_or_else(|| "get-role returned no MaxSessionDuration".to_string())
+}
+
+/// Whether the trust policy `document` lets `caller` (an identity ARN, whose IAM
+/// principal is `principal`) call sts:AssumeRole, and whether only under conditions. An
+/// unconditional Deny for the caller wins; a conditional one makes the result conditional.
+pub fn trusts(document: &Value, caller: &Arn, principal: &Arn) -> Option<(Trust, bool)> {
+    let statements = match &document["Statement"] {
+        Value::Array(items) => items.clone(),
+        Value::Object(_) => vec![document["Statement"].clone()],
+        _ => return None,
+    };
+    let mut allowed: Vec<(Trust, bool)> = Vec::new();
+    let mut denied_if = false;
+    for statement in statements.iter() {
+        if !values(&statement["Action"]).iter().any(|a| matches_assume_role(a)) {
+            continue;
+        }
+        let Some(trust) = principal_trust(&statement["Principal"], caller, principal) else {
+            continue;
+        };
+        let conditional = statement.get("Condition").is_some_and(|c| c.as_object().is_some_and(|c| !c.is_empty()));
+        match statement["Effect"].as_str() {
+            Some("Deny") if conditional => denied_if = true,
+            Some("Deny") => return None,
+            Some("Allow") => allowed.push((trust, conditional)),
+            _ => {}
+        }
+    }
+    let trust = allowed.iter().map(|(t, _)| *t).min()?;
+    let conditional = allowed.iter().filter(|(t, _)| *t == trust).all(|(_, c)| *c);
+    Some((trust, conditional || denied_if))
+}
+
+fn values(v: &Value) -> Vec<&str> {
+    match v {
+        Value::String(s) => vec![s.as_str()],
+        Value::Array(items) => items.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn matches_assume_role(action: &str) -> bool {
+    ["sts:AssumeRole", "sts:*", "*"].iter().any(|a| a.eq_ignore_ascii_case(action))
+}
+
+fn principal_trust(principal_field: &Value, caller: &Arn, principal: &Arn) -> Option<Trust> {
+    let listed = match principal_field {
+        Value::String(s) if s == "*" => return Some(Trust::Anyone),
+        Value::Object(_) => values(&principal_field["AWS"]),
+        _ => return None,
+    };
+    let root = format!("arn:{}:iam::{}:root", principal.partition, principal.account);
+    listed
+        .iter()
+        .filter_map(|entry| {
+            if *entry == "*" {
+                return Some(Trust::Anyone);
+            }
+            if *entry == principal.account || *entry == root {
+                return Some(Trust::Account);
+            }
+            let named: Arn = entry.parse().ok()?;
+            (named == *caller || same_entity(&named, principal)).then_some(Trust::Principal)
+        })
+        .min()
+}
+
+/// Same account, kind and name; paths are left out of session ARNs.
+fn same_entity(a: &Arn, b: &Arn) -> bool {
+    let name = |arn: &Arn| arn.resource.rsplit('/').next().map(str::to_string);
+    a.service == b.service && a.account == b.account && a.resource_type() == b.resource_type() && name(a) == name(b)
+}
@@ -0,0 +1,69 @@
+// The shell function `awx init` prints.
+//
+// A program cannot change the environment of the shell that started it, so commands
+// that set credentials print `export` lines for the shell to evaluate. The function
+// `awx init <shell>` prints wraps the binary the way rbenv and direnv do: `awx login`,
+// `awx use <profile>` and `awx export` run the binary and evaluate what it printed in
+// the current shell, when it succeeded; everything else, and those three when asked for
+// help, a QR code or the clipboard, runs as is. Add `eval "$(command awx init zsh)"` to
+// ~/.zshrc (or bash), or `command awx init fish | source` to config.fish.
+use crate::hook::Shell;
+
+/// The shell named by a path such as $SHELL, e.g. `/usr/local/bin/zsh`.
+pub fn detect(shell_path: &str) -> Option<Shell> {
+    match std::path::Path::new(shell_path).file_name()?.to_str()? {
+        "zsh" => Some(Shell::Zsh),
+        "bash" => Some(Shell::Bash),
+        "fish" => Some(Shell::Fish),
+        _ => None,
+    }
+}
+
+/// The function for `shell`, with the line that loads it.
+pub fn snippet(shell: Shell) -> String {
+    match shell {
+        Shell::Zsh | Shell::Bash => {
+            let name = if shell == Shell::Zsh { "zsh" } else { "bash" };
+            format!(
+                r#"# awx shell function: `awx login`, `awx use` and `awx export` set credentials in
+# this shell. Load it from ~/.{name}rc with: eval "$(command awx init {name})"
+awx() {{
+    case "$1" in
+        login|use|export)
+            case " $* " in
+                *" --qr"*|*" --clipboard "*|*" -h "*|*" --help "*)
+                    command awx "$@"
+                    return
+                    ;;
+            esac
+            local __awx_exports
+            __awx_exports="$(command awx "$@")" || return $?
+            eval "$__awx_exports"
+            ;;
+        *)
+            command awx "$@"
+            ;;
+    esac
+}}
+"#
+            )
+        }
+        Shell::Fish => r#"# awx shell function: `awx login`, `awx use` and `awx export` set credentials in
+# this shell. Load it from config.fish with: command awx init fish | source
+function awx
+    switch "$argv[1]"
+        case login use export
+            if contains -- --clipboard $argv; or contains -- -h $argv; or contains -- --help $argv; or string match -q -- '--qr*' $argv
+                command awx $argv
+                return
+            end
+            set -l exports (command awx $argv); or return
+            printf '%s\n' $exports | source
+        case '*'
+            command awx $argv
+    end
+end
+"#
+        .to_string(),
+    }
+}
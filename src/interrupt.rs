@@ -0,0 +1,91 @@
+// Clean exits on Ctrl-C at awx's own prompts.
+//
+// dialoguer reads keys in raw mode, so Ctrl-C in the profile picker or a confirmation
+// comes back as an interrupted read, while a password prompt (MFA code, backup
+// passphrase) has echo turned off when SIGINT arrives. Left alone, that ended with a
+// hidden cursor or a terminal that no longer echoes, and a generic I/O error. awx
+// therefore handles SIGINT itself: unless a child command is running (it gets the
+// signal forwarded, see `wait_forwarding_signals`), the terminal settings saved at
+// start are restored, "Aborted." is printed and awx exits with 130. An interrupted
+// prompt ends the same way from `main`.
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use console::Term;
+
+use crate::i18n::{t, Msg};
+use crate::theme::{self, Role};
+
+/// Exit code after Ctrl-C, as the shell reports for SIGINT.
+pub const EXIT_CODE: u8 = 130;
+
+static CHILDREN: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(unix)]
+static SAVED: std::sync::OnceLock<libc::termios> = std::sync::OnceLock::new();
+
+/// Save the terminal settings and take over SIGINT. The handler runs on its own thread
+/// because prompts block the runtime's workers.
+pub fn install() {
+    #[cfg(unix)]
+    unsafe {
+        let mut termios: libc::termios = std::mem::zeroed();
+        if libc::isatty(libc::STDIN_FILENO) == 1 && libc::tcgetattr(libc::STDIN_FILENO, &mut termios) == 0 {
+            let _ = SAVED.set(termios);
+        }
+    }
+    let _ = std::thread::Builder::new().name("sigint".to_string()).spawn(|| {
+        let Ok(runtime) = tokio::runtime::Builder::new_current_thread().enable_all().build() else {
+            return;
+        };
+        runtime.block_on(async {
+            while tokio::signal::ctrl_c().await.is_ok() {
+                if CHILDREN.load(Ordering::SeqCst) == 0 {
+                    crate::telemetry::export(Some("aborted".to_string())).await;
+                    aborted();
+                    crate::tmp::cleanup();
+                    std::process::exit(EXIT_CODE as i32);
+                }
+            }
+        });
+    });
+}
+
+/// Held while a child command runs in the foreground; Ctrl-C is then the child's.
+pub struct ChildRunning(());
+
+pub fn child_running() -> ChildRunning {
+    CHILDREN.fetch_add(1, Ordering::SeqCst);
+    ChildRunning(())
+}
+
+impl Drop for ChildRunning {
+    fn drop(&mut self) {
+        CHILDREN.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Whether `err` comes from a prompt the user interrupted.
+pub fn is_interrupt(err: &anyhow::Error) -> bool {
+    err.chain().any(|e| {
+        let io = match e.downcast_ref::<dialoguer::Error>() {
+            Some(dialoguer::Error::IO(io)) => Some(io),
+            None => e.downcast_ref::<std::io::Error>(),
+        };
+        io.is_some_and(|io| io.kind() == std::io::ErrorKind::Interrupted)
+    })
+}
+
+/// Restore the terminal and say that awx stopped.
+pub fn aborted() {
+    crate::progress::clear();
+    for term in [Term::stderr(), Term::stdout()] {
+        if term.is_term() {
+            let _ = term.show_cursor();
+        }
+    }
+    #[cfg(unix)]
+    if let Some(termios) = SAVED.get() {
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, termios) };
+    }
+    eprintln!("\n{}", theme::style(Role::Warning).apply_to(t(Msg::Aborted, &[])));
+}
@@ -0,0 +1,215 @@
+// Cleanup of expired awx state (`awx cache gc`, and at most once a day on start).
+//
+// Only files awx wrote under ~/.awx are considered, and only ones no run can use any
+// more: the parsed-config cache of an older ~/.aws/config, cache files left by an
+// interrupted write, session files (`awx pair`) past their expiration, SSO client
+// registrations whose secret has expired, pairing keys nobody answered, exports files
+// (see `reveal`) past their expiration and corrupted state files quarantined more
+// than 30 days ago (see `state`). The one exception outside ~/.awx are env files
+// written by `awx compose --env-file`, deleted once their credentials expire. Recordings, backups, metrics and settings are never touched, and
+// ~/.aws stays the AWS CLI's. `awx cache gc` also removes the temporary directories of
+// runs that died without cleaning up (see `tmp`).
+//
+// Sessions are also dropped when the profile they were minted for is redefined. Every
+// run compares a fingerprint of each profile's definition (role ARN, SSO start URL and
+// account, source profile or credential source, MFA device, access key ID) with the
+// one seen by the last run, and for a profile that changed or disappeared deletes its
+// `awx warm` session files (one per `--duration`) and makes the broker forget its session, so credentials
+// obtained under the old definition are not handed out again.
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Duration, Utc};
+use sha1::{Digest, Sha1};
+
+use crate::{compose, pair, profile_cache, reveal, s3express, session, sso, warm, Profile};
+
+/// Where the time of the last sweep is kept.
+const STAMP: &str = "last-gc";
+/// Where the fingerprints of the profile definitions seen last are kept.
+const DEFINITIONS: &str = "definitions.json";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub path: PathBuf,
+    pub reason: &'static str,
+    pub bytes: u64,
+}
+
+/// Everything under `awx_dir` that has expired at `now`; `aws_config` is the config file
+/// the profile cache is built from.
+pub fn expired(awx_dir: &Path, aws_config: &Path, now: DateTime<Utc>) -> Vec<Entry> {
+    let mut found = Vec::new();
+    let mut add = |path: PathBuf, reason| {
+        let bytes = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        found.push(Entry { path, reason, bytes });
+    };
+
+    let cache = awx_dir.join("cache");
+    for path in files(&cache) {
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+        if name.ends_with(".tmp") && older_than(&path, now, Duration::hours(1)) {
+            add(path, "left by an interrupted write");
+        } else if name == "config.bin" && !profile_cache::is_current(aws_config, &path) {
+            add(path, "parsed from an older ~/.aws/config");
+        }
+    }
+    for path in files(&awx_dir.join("sessions")) {
+        let session = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|c| serde_json::from_str::<session::Descriptor>(&c).ok());
+        if session.is_some_and(|s| s.expiration <= now) {
+            add(path, "expired session");
+        }
+    }
+    for path in files(&awx_dir.join("sso").join("clients")) {
+        if sso::load_client(&path).is_some_and(|c| c.client_secret_expires_at <= now.timestamp()) {
+            add(path, "expired SSO client registration");
+        }
+    }
+    for dir in [awx_dir.to_path_buf(), awx_dir.join("sso").join("clients")] {
+        for path in files(&dir) {
+            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+            if name.contains(".corrupt-") && older_than(&path, now, Duration::days(30)) {
+                add(path, "quarantined corrupted state");
+            }
+        }
+    }
+    for path in compose::expired(awx_dir, now) {
+        add(path, "expired compose credentials");
+    }
+    for path in s3express::expired(awx_dir, now) {
+        add(path, "expired S3 Express session");
+    }
+    for path in reveal::expired(awx_dir, now) {
+        add(path, "expired exports");
+    }
+    let pending = pair::pending_key_path(awx_dir);
+    if older_than(&pending, now, Duration::days(1)) {
+        add(pending, "unanswered pairing request");
+    }
+    found
+}
+
+/// Temporary directories in `tmp_dir` of runs that are gone.
+pub fn abandoned_temp(tmp_dir: &Path) -> Vec<Entry> {
+    crate::tmp::abandoned(tmp_dir)
+        .into_iter()
+        .map(|path| {
+            let bytes = walk_size(&path);
+            Entry { path, reason: "left by a run that did not exit cleanly", bytes }
+        })
+        .collect()
+}
+
+fn walk_size(path: &Path) -> u64 {
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() => std::fs::read_dir(path).into_iter().flatten().flatten().map(|e| walk_size(&e.path())).sum(),
+        Ok(meta) => meta.len(),
+        Err(_) => 0,
+    }
+}
+
+/// Delete `entries`, returning the ones removed.
+pub fn remove(entries: Vec<Entry>) -> Vec<Entry> {
+    entries
+        .into_iter()
+        .filter(|e| {
+            if e.path.is_dir() {
+                std::fs::remove_dir_all(&e.path).is_ok()
+            } else {
+                std::fs::remove_file(&e.path).is_ok()
+            }
+        })
+        .collect()
+}
+
+/// Sweep quietly unless that happened within the last day.
+pub fn sweep_if_due(awx_dir: &Path, aws_config: &Path, now: DateTime<Utc>) {
+    let stamp = awx_dir.join("cache").join(STAMP);
+    let last = std::fs::read_to_string(&stamp)
+        .ok()
+        .and_then(|s| DateTime::parse_from_rfc3339(s.trim()).ok());
+    if last.is_some_and(|last| last + Duration::days(1) > now) {
+        return;
+    }
+    if crate::state::write(&stamp, now.to_rfc3339().as_bytes()).is_err() {
+        return;
+    }
+    let mut entries = expired(awx_dir, aws_config, now);
+    entries.extend(abandoned_temp(&crate::tmp::dir()));
+    remove(entries);
+}
+
+/// A digest of what `profile`'s sessions are obtained from; descriptions, regions and
+/// durations do not count.
+pub fn fingerprint(profile: &Profile) -> String {
+    let fields = [
+        ("sso_start_url", &profile.sso_start_url),
+        ("sso_session", &profile.sso_session),
+        ("sso_account_id", &profile.sso_account_id),
+        ("role_arn", &profile.role_arn),
+        ("source_profile", &profile.source_profile),
+        ("credential_source", &profile.credential_source),
+        ("mfa_serial", &profile.mfa_serial),
+        ("aws_access_key_id", &profile.aws_access_key_id),
+    ];
+    let mut hasher = Sha1::new();
+    for (key, value) in fields {
+        if let Some(value) = value {
+            hasher.update(format!("{}={}\n", key, value));
+        }
+    }
+    hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Profiles of `previous` whose definition is different or gone in `current`; new
+/// profiles have nothing cached yet.
+pub fn redefined(previous: &BTreeMap<String, String>, current: &BTreeMap<String, String>) -> Vec<String> {
+    previous
+        .iter()
+        .filter(|(name, print)| current.get(*name) != Some(*print))
+        .map(|(name, _)| name.clone())
+        .collect()
+}
+
+/// Drop the cached sessions of profiles redefined since the last run and return the
+/// profiles that had one (or may have, in the broker).
+pub fn invalidate_redefined(awx_dir: &Path, profiles: &HashMap<String, Profile>) -> Vec<String> {
+    let path = awx_dir.join("cache").join(DEFINITIONS);
+    let current: BTreeMap<String, String> = profiles.iter().map(|(name, p)| (name.clone(), fingerprint(p))).collect();
+    let previous: Option<BTreeMap<String, String>> = crate::state::read_json(&path);
+    if previous.as_ref() == Some(&current) {
+        return Vec::new();
+    }
+    let _ = crate::state::write_json(&path, &current);
+    redefined(&previous.unwrap_or_default(), &current)
+        .into_iter()
+        .filter(|name| {
+            let removed = warm::session_files(awx_dir, name)
+                .into_iter()
+                .filter(|path| std::fs::remove_file(path).is_ok())
+                .count();
+            crate::broker::forget(name) || removed > 0
+        })
+        .collect()
+}
+
+fn files(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.is_file())
+        .collect();
+    files.sort();
+    files
+}
+
+fn older_than(path: &Path, now: DateTime<Utc>, age: Duration) -> bool {
+    std::fs::metadata(path)
+        .and_then(|m| m.modified())
+        .map(|t| DateTime::<Utc>::from(t) + age < now)
+        .unwrap_or(false)
+}
@@ -1,45 +1,92 @@
 use std::collections::HashMap;
-use std::env;
+use std::io::IsTerminal;
 use std::path::PathBuf;
 use std::process::ExitCode;
 
 use anyhow::{anyhow, Context, Result};
 use chrono::Utc;
-use clap::{Parser, Subcommand};
-use console::Style;
-use dialoguer::{theme::ColorfulTheme, Password, Select};
-// Minimal INI parser used to read AWS config/credentials for tests and MVP.
-fn parse_ini(content: &str) -> std::collections::HashMap<String, std::collections::HashMap<String, String>> {
-    let mut map: std::collections::HashMap<String, std::collections::HashMap<String, String>> =
-        std::collections::HashMap::new();
-    let mut current_section = String::from("default");
-    for raw_line in content.lines() {
-        let line = raw_line.trim();
-        if line.is_empty() {
-            continue;
-        }
-        if line.starts_with('#') || line.starts_with(';') {
-            continue;
-        }
-        if line.starts_with('[') && line.ends_with(']') {
-            current_section = line[1..line.len() - 1].trim().to_string();
-            continue;
-        }
-        if let Some(idx) = line.find('=') {
-            let key = line[..idx].trim();
-            let val = line[idx + 1..].trim();
-            let section = map.entry(current_section.clone()).or_insert_with(|| {
-                std::collections::HashMap::new()
-            });
-            section.insert(key.to_string(), val.to_string());
-        }
-    }
-    map
-}
-use serde::Deserialize;
+use clap::{CommandFactory, Parser, Subcommand};
+use serde::{Deserialize, Serialize};
 use tokio::process::Command;
 use tokio::time::{timeout, Duration};
 
+mod ambient;
+mod arn;
+mod ask;
+mod auth;
+mod aws_env;
+mod backend;
+mod backup;
+mod badge;
+mod base_session;
+mod broker;
+mod check;
+mod clear;
+mod cli;
+mod clipboard;
+mod compose;
+mod config_parser;
+mod context;
+mod curl;
+mod debug_bundle;
+mod default_profile;
+mod duration;
+mod endpoint;
+mod exec;
+mod hook;
+mod i18n;
+mod iam;
+mod init;
+mod interrupt;
+mod janitor;
+mod metrics;
+mod notify;
+mod offline;
+mod orphan;
+mod output;
+mod pair;
+mod passthrough;
+mod pipeline;
+mod profile_cache;
+mod projects;
+mod progress;
+mod prune;
+mod prompt;
+mod qr;
+mod ratelimit;
+mod record;
+mod render;
+mod reveal;
+mod root;
+mod s3express;
+mod schema;
+mod sdk;
+mod secrets;
+mod selftest;
+mod service;
+mod session;
+mod singleflight;
+mod settings;
+mod sigv4;
+mod spend;
+mod sso;
+mod state;
+mod suggest;
+mod telemetry;
+mod theme;
+mod tmp;
+mod tools;
+mod trail;
+mod troubleshoot;
+#[cfg(feature = "vault")]
+mod vault;
+mod warm;
+
+use i18n::{t, Msg};
+use prompt::InputRequest;
+use settings::ConflictResolution;
+use theme::Role;
+
 #[derive(Debug, Parser)]
 #[clap(name = "awx", version)]
 struct Opt {
@@ -51,15 +98,104 @@ struct Opt {
     #[clap(short = 'c', long = "config")]
     config: bool,
 
-    /// Clear cache (profile or 'all')
-    #[clap(long = "clear-cache")]
-    clear_cache: Option<String>,
+    /// List only profiles with this badge in the picker and -c; repeatable
+    #[clap(long = "only", value_enum, global = true)]
+    only: Vec<badge::Badge>,
+
+    /// List only profiles in this AWS account in the picker and -c; repeatable
+    #[clap(long = "account", global = true)]
+    account: Vec<String>,
+
+    /// Clear cached sessions: a profile, all, expired, sso:<start-url>, role:<arn> or account:<id>
+    #[clap(long = "clear-cache", value_parser = clear::Selector::parse)]
+    clear_cache: Option<clear::Selector>,
 
     /// Skip interactive UI (for CI)
     #[clap(short = 'n', long = "no-interactive")]
     no_interactive: bool,
 
-    /// Any remaining arguments are passed to the aws CLI
+    /// Explain how the profile, files, region and credentials would be resolved, then exit
+    #[clap(long = "explain")]
+    explain: bool,
+
+    /// After authenticating, write a session descriptor (temporary credentials) to this file
+    #[clap(long = "session-file", value_name = "PATH")]
+    session_file: Option<PathBuf>,
+
+    /// Use the session in a descriptor written by --session-file instead of authenticating
+    #[clap(long = "from-session-file", value_name = "PATH", conflicts_with = "session_file")]
+    from_session_file: Option<PathBuf>,
+
+    /// Pass --no-browser to `aws sso login` (print the URL instead of opening it)
+    #[clap(long = "no-browser", global = true)]
+    no_browser: bool,
+
+    /// Pass --use-device-code to `aws sso login`
+    #[clap(long = "use-device-code", global = true)]
+    use_device_code: bool,
+
+    /// Make no network calls: use only cached tokens and credentials (also AWX_OFFLINE=1)
+    #[clap(long = "offline", global = true)]
+    offline: bool,
+
+    /// Before assuming a role, read its trust policy and stop with the reason when it
+    /// leaves you out (also AWX_VERIFY_TRUST=1)
+    #[clap(long = "verify-trust", global = true)]
+    verify_trust: bool,
+
+    /// Pick how long an assumed-role session lasts from the lengths the role allows;
+    /// the choice is remembered per profile
+    #[clap(long = "choose-duration", global = true)]
+    choose_duration: bool,
+
+    /// How long an assumed-role session lasts this time (e.g. 15m, 4h), in place of
+    /// duration_seconds; cached apart from the profile's other sessions
+    #[clap(long = "duration", value_name = "DURATION", value_parser = duration::parse, conflicts_with = "choose_duration", global = true)]
+    duration: Option<i64>,
+
+    /// Serve the session to the child on a loopback container credentials endpoint with
+    /// its own random path and token, instead of putting keys in its environment (also
+    /// AWX_CREDENTIALS_ENDPOINT=1)
+    #[clap(long = "credentials-endpoint", global = true)]
+    credentials_endpoint: bool,
+
+    /// Private directory for awx's temporary files (also AWX_TMPDIR; default
+    /// $XDG_RUNTIME_DIR/awx or <system temp>/awx-<uid>)
+    #[clap(long = "tmpdir", value_name = "PATH", global = true)]
+    tmpdir: Option<PathBuf>,
+
+    /// Ask questions as numbered lines instead of interactive widgets, for screen readers
+    /// and dumb terminals (also AWX_PLAIN_PROMPTS=1; implied by TERM=dumb)
+    #[clap(long = "plain-prompts", global = true)]
+    plain_prompts: bool,
+
+    /// Ignore credentials inherited from an outer awx (AWX_SESSION) and resolve again
+    #[clap(long = "fresh", global = true)]
+    fresh: bool,
+
+    /// Record the command's terminal session to ~/.awx/recordings (asciicast)
+    #[clap(long = "record")]
+    record: bool,
+
+    /// How long the command will run (e.g. 45m, 2h); warn if the session expires sooner
+    #[clap(long = "needs", value_name = "DURATION", value_parser = settings::parse_duration, global = true)]
+    needs: Option<chrono::Duration>,
+
+    /// Answer awx's questions (profile, duration, confirmations, ...) from this TOML file
+    #[clap(long = "answers", value_name = "FILE", global = true)]
+    answers: Option<PathBuf>,
+
+    /// Print secrets even when stdout is a terminal (otherwise they go to a private file)
+    #[clap(long = "reveal", global = true)]
+    reveal: bool,
+
+    /// Print -c, whoami, cache ls, broker status, selftest, s3express session and the
+    /// exec summary as text, json or yaml; goes before the subcommand
+    #[clap(long = "output", value_enum, default_value = "human")]
+    output: output::Format,
+
+    /// The aws CLI command to run, e.g. `s3 ls`; everything from its first word on is
+    /// passed to the aws CLI (write `--` before it when it is also an awx command)
     #[clap(trailing_var_arg = true)]
     aws_args: Vec<String>,
 
@@ -75,27 +211,552 @@ enum SubCommand {
         #[clap(short = 'p', long = "profile")]
         profile: Option<String>,
 
+        /// Sign in to every SSO start URL and sso-session in ~/.aws/config that needs it,
+        /// one after another
+        #[clap(long = "all-sso", conflicts_with_all = ["profile", "rclone", "mc"])]
+        all_sso: bool,
+
+        /// Also export the session as rclone S3 remote REMOTE (RCLONE_CONFIG_<REMOTE>_*)
+        #[clap(long = "rclone", value_name = "REMOTE", value_parser = tools::parse_name)]
+        rclone: Option<String>,
+
+        /// Also export the session as MinIO client alias ALIAS (MC_HOST_<ALIAS>)
+        #[clap(long = "mc", value_name = "ALIAS", value_parser = tools::parse_name)]
+        mc: Option<String>,
+
+        /// Skip interactive UI (for CI)
+        #[clap(short = 'n', long = "no-interactive")]
+        no_interactive: bool,
+    },
+    /// Print only the `export` lines of a profile's session, for `eval` or pasting
+    /// elsewhere; optionally copy them to the clipboard instead
+    Export {
+        /// Specify profile directly
+        #[clap(short = 'p', long = "profile")]
+        profile: Option<String>,
+
+        /// Copy the lines to the system clipboard and clear it again after a while
+        #[clap(long = "clipboard")]
+        clipboard: bool,
+
+        /// How long the clipboard keeps the lines (default: `clipboard_clear_after` in
+        /// ~/.awx/config.toml, else 30s)
+        #[clap(long = "clear-after", value_name = "DURATION", value_parser = settings::parse_duration, requires = "clipboard")]
+        clear_after: Option<chrono::Duration>,
+
+        /// Draw the session as a QR code instead, for a phone or tablet app under test:
+        /// `credentials` (credential_process JSON, the default) or `descriptor` (the
+        /// session file, with profile and region)
+        #[clap(long = "qr", value_name = "PAYLOAD", num_args = 0..=1, default_missing_value = "credentials", conflicts_with = "clipboard")]
+        qr: Option<qr::Payload>,
+
+        /// Skip interactive UI (for CI)
+        #[clap(short = 'n', long = "no-interactive")]
+        no_interactive: bool,
+    },
+    /// Manage awx and AWS configuration files
+    Config {
+        #[clap(subcommand)]
+        action: ConfigCommand,
+    },
+    /// Run a command with credentials for one or more profiles
+    Exec {
+        /// Target profile; repeat or separate with commas for several
+        #[clap(short = 'p', long = "profile", value_delimiter = ',')]
+        profiles: Vec<String>,
+
+        /// Add every profile whose name matches the glob (e.g. 'prod-*')
+        #[clap(long = "all-matching")]
+        all_matching: Option<String>,
+
+        /// Saved target set from [targets.<name>] in ~/.awx/config.toml; repeatable
+        #[clap(short = 't', long = "targets", value_delimiter = ',')]
+        targets: Vec<String>,
+
+        /// Run each profile in these regions; repeat or separate with commas
+        #[clap(short = 'r', long = "region", value_delimiter = ',')]
+        regions: Vec<String>,
+
+        /// Stop at the first failing target and exit with its code
+        #[clap(long = "fail-fast", conflicts_with_all = ["keep_going", "any_success"])]
+        fail_fast: bool,
+
+        /// Run every target and exit with the first failing code (default)
+        #[clap(long = "keep-going", conflicts_with = "any_success")]
+        keep_going: bool,
+
+        /// Run every target and exit 0 if at least one succeeded
+        #[clap(long = "any-success")]
+        any_success: bool,
+
+        /// Capture each target's output and print one result record per target to stdout
+        #[clap(long = "results", value_enum)]
+        results: Option<exec::ResultsFormat>,
+
+        /// Maximum number of concurrent AssumeRole calls
+        #[clap(long = "parallel", default_value_t = exec::DEFAULT_PARALLEL)]
+        parallel: usize,
+
+        /// STS/SSO API calls per second (overrides rate_limit.rps)
+        #[clap(long = "rps")]
+        rps: Option<f64>,
+
+        /// Skip interactive UI (for CI)
+        #[clap(short = 'n', long = "no-interactive")]
+        no_interactive: bool,
+
+        /// Command and arguments to run for each target
+        #[clap(trailing_var_arg = true, required = true)]
+        command: Vec<String>,
+    },
+    /// Transfer a session to a headless machine: run without arguments there, then
+    /// `pair approve` on a machine where you can log in
+    Pair {
+        #[clap(subcommand)]
+        action: Option<PairCommand>,
+
+        /// Where to save the received session (default: ~/.awx/sessions/<profile>.json)
+        #[clap(short = 'o', long = "output")]
+        output: Option<PathBuf>,
+    },
+    /// Show the identity behind a profile's session
+    Whoami {
+        /// Profile to check
+        #[clap(short = 'p', long = "profile")]
+        profile: Option<String>,
+
+        /// Also list attached policies, permission set, groups and permissions boundary
+        /// (where the identity may read its own IAM entity)
+        #[clap(long = "deep")]
+        deep: bool,
+
+        /// Skip interactive UI (for CI)
+        #[clap(short = 'n', long = "no-interactive")]
+        no_interactive: bool,
+    },
+    /// List the API calls CloudTrail recorded for the session's user or role session name
+    Trail {
+        /// Profile whose session is looked up
+        #[clap(short = 'p', long = "profile")]
+        profile: Option<String>,
+
+        /// How far back to look (e.g. 30m, 1h, 2d)
+        #[clap(long = "last", value_name = "DURATION", default_value = "1h", value_parser = settings::parse_duration)]
+        last: chrono::Duration,
+
+        /// Region whose trail is read (default: the profile's; IAM and STS calls are in us-east-1)
+        #[clap(short = 'r', long = "region")]
+        region: Option<String>,
+
+        /// Skip interactive UI (for CI)
+        #[clap(short = 'n', long = "no-interactive")]
+        no_interactive: bool,
+    },
+    /// Send an HTTP request signed with a profile's session (SigV4), e.g. to an API
+    /// Gateway API or Lambda function URL with IAM authorization
+    Curl {
+        /// Profile whose session signs the request
+        #[clap(short = 'p', long = "profile")]
+        profile: Option<String>,
+
+        /// URL to request
+        url: String,
+
+        /// HTTP method (default: GET, or POST with --body)
+        #[clap(short = 'X', long = "request", value_name = "METHOD")]
+        method: Option<String>,
+
+        /// Extra header, `Name: value`; repeatable
+        #[clap(short = 'H', long = "extra-header", value_name = "HEADER", value_parser = curl::parse_header)]
+        headers: Vec<String>,
+
+        /// Request body: text, or @file for a file's bytes
+        #[clap(long = "body", value_parser = curl::Body::parse)]
+        body: Option<curl::Body>,
+
+        /// Signing service (default: from the URL, e.g. execute-api)
+        #[clap(long = "service")]
+        service: Option<String>,
+
+        /// Signing region (default: from the URL, else the profile's region)
+        #[clap(short = 'r', long = "region")]
+        region: Option<String>,
+
+        /// Sign with SigV4A (multi-region) instead of SigV4; --region is then the
+        /// region set (default: *). Detected for S3 Multi-Region Access Points
+        #[clap(long = "sigv4a")]
+        sigv4a: bool,
+
+        /// Print the URL presigned for this long (e.g. 15m, at most 7d) instead of
+        /// sending the request
+        #[clap(long = "presign", value_name = "DURATION", value_parser = settings::parse_duration)]
+        presign: Option<chrono::Duration>,
+
+        /// Skip interactive UI (for CI)
+        #[clap(short = 'n', long = "no-interactive")]
+        no_interactive: bool,
+
+        /// More curl options, after `--`
+        #[clap(last = true)]
+        curl_args: Vec<String>,
+    },
+    /// Mint and keep S3 Express One Zone session credentials for a directory bucket
+    #[clap(name = "s3express")]
+    S3express {
+        #[clap(subcommand)]
+        action: S3ExpressCommand,
+    },
+    /// Run `docker compose` with a profile's short-lived credentials in its environment,
+    /// or write them to a git-ignored env file for dev containers
+    Compose {
+        /// Profile whose credentials the stack gets
+        #[clap(short = 'p', long = "profile")]
+        profile: Option<String>,
+
+        /// Also write the credentials to this dotenv file (mode 600, deleted by
+        /// `awx cache gc` once expired), e.g. for `env_file:` or a devcontainer
+        #[clap(long = "env-file")]
+        env_file: Option<PathBuf>,
+
+        /// Skip interactive UI (for CI)
+        #[clap(short = 'n', long = "no-interactive")]
+        no_interactive: bool,
+
+        /// Arguments for `docker compose`, e.g. `-- up -d`
+        #[clap(trailing_var_arg = true)]
+        args: Vec<String>,
+    },
+    /// Run steampipe, aws-nuke, cloud-nuke, rclone or terragrunt with a profile's
+    /// credentials and the tool's own configuration conventions applied
+    Tool {
+        #[clap(value_enum)]
+        tool: tools::Tool,
+
+        /// Profile whose credentials the tool gets
+        #[clap(short = 'p', long = "profile")]
+        profile: Option<String>,
+
+        /// Skip interactive UI (for CI)
+        #[clap(short = 'n', long = "no-interactive")]
+        no_interactive: bool,
+
+        /// Arguments for the tool, after `--`
+        #[clap(trailing_var_arg = true)]
+        args: Vec<String>,
+    },
+    /// List the roles in the account whose trust policy lets the profile's identity assume them
+    LsRoles {
+        /// Profile to check
+        #[clap(short = 'p', long = "profile")]
+        profile: Option<String>,
+
+        /// Skip interactive UI (for CI)
+        #[clap(short = 'n', long = "no-interactive")]
+        no_interactive: bool,
+    },
+    /// Ask IAM whether a profile may perform an action, without performing it
+    CanI {
+        /// Profile to check
+        #[clap(short = 'p', long = "profile")]
+        profile: Option<String>,
+
+        /// Action to check, e.g. s3:PutObject
+        action: String,
+
+        /// Resource ARNs the action is performed on (default: any resource)
+        resources: Vec<String>,
+
+        /// Skip interactive UI (for CI)
+        #[clap(short = 'n', long = "no-interactive")]
+        no_interactive: bool,
+    },
+    /// Walk the default credential chain (environment, shared profile, web identity,
+    /// container endpoint, instance metadata) over the environment awx prepares for a
+    /// profile and show whether something shadows awx's credentials; an approximation
+    /// from variables and files, not a run of an SDK
+    CredentialChain {
+        /// Profile to check
+        #[clap(short = 'p', long = "profile")]
+        profile: Option<String>,
+
+        /// Skip interactive UI (for CI)
+        #[clap(short = 'n', long = "no-interactive")]
+        no_interactive: bool,
+    },
+    /// Resolve a profile without prompting and save its session for later pipeline steps;
+    /// prints a JSON readiness report and exits 1 when the profile is not ready
+    Warm {
+        /// Profile to warm
+        #[clap(short = 'p', long = "profile")]
+        profile: Option<String>,
+
+        /// Where to save the session (default: ~/.awx/sessions/<profile>.json)
+        #[clap(short = 'o', long = "output")]
+        output: Option<PathBuf>,
+
+        /// Reuse the saved session while it lasts at least this long (e.g. 15m, 1h)
+        #[clap(long = "min-remaining", value_name = "DURATION", default_value = "15m", value_parser = settings::parse_duration)]
+        min_remaining: chrono::Duration,
+
+        /// Give up resolving the profile after this long (e.g. 2m) and report it not ready
+        #[clap(long = "timeout", value_name = "DURATION", value_parser = settings::parse_duration)]
+        timeout: Option<chrono::Duration>,
+    },
+    /// Exit 0 when the profile's cached credentials last --min-remaining and 2 when it
+    /// needs authentication; never prompts or calls AWS
+    Check {
+        /// Profile to check
+        #[clap(short = 'p', long = "profile")]
+        profile: Option<String>,
+
+        /// How long the credentials must still last (e.g. 10m, 1h; default: any time left)
+        #[clap(long = "min-remaining", value_name = "DURATION", value_parser = settings::parse_duration)]
+        min_remaining: Option<chrono::Duration>,
+    },
+    /// Assume the root user of a member account for one task (centralized root access),
+    /// with the management account's or a delegated administrator's profile
+    Root {
+        /// Management or delegated administrator profile
+        #[clap(short = 'p', long = "profile")]
+        profile: String,
+
+        /// Member account whose root user is assumed
+        #[clap(long = "target", value_name = "ACCOUNT", value_parser = root::parse_account)]
+        target: String,
+
+        /// Task policy: IAMAuditRootUserCredentials, IAMCreateRootUserPassword,
+        /// IAMDeleteRootUserCredentials, S3UnlockBucketPolicy, SQSUnlockQueuePolicy, or its ARN
+        #[clap(long = "task", value_name = "POLICY", value_parser = root::task_policy_arn)]
+        task: String,
+
+        /// The target account again, in place of typing it when asked (for scripts)
+        #[clap(long = "confirm", value_name = "ACCOUNT")]
+        confirm: Option<String>,
+
         /// Skip interactive UI (for CI)
         #[clap(short = 'n', long = "no-interactive")]
         no_interactive: bool,
+
+        /// Command to run with the root session, after `--`; without one the session is
+        /// printed as `export` lines
+        #[clap(trailing_var_arg = true)]
+        command: Vec<String>,
+    },
+    /// Write a sanitized snapshot for bug reports: versions, OS, environment checks, the
+    /// shape of the AWS and awx config files (secrets redacted), the last error and an
+    /// --explain trace
+    DebugBundle {
+        /// Profile to include the --explain trace of (default: AWS_PROFILE, then default)
+        #[clap(short = 'p', long = "profile")]
+        profile: Option<String>,
+
+        /// Where to write the bundle, `-` for stdout (default: ~/.awx/debug/awx-debug-<time>.json)
+        #[clap(short = 'o', long = "output")]
+        output: Option<PathBuf>,
+    },
+    /// List the contexts from [contexts.<name>] in ~/.awx/config.toml, or switch between them
+    Ctx {
+        #[clap(subcommand)]
+        action: Option<CtxCommand>,
     },
-    /// Print shell init snippet so `awx login` works without manual eval
+    /// Print a shell hook that keeps AWS_PROFILE in step with .awx.toml files and the
+    /// active context (names only, never credentials)
+    Hook {
+        #[clap(value_enum)]
+        shell: hook::Shell,
+    },
+    /// The exports run by the shell hook before each prompt
+    #[clap(hide = true)]
+    HookEnv {
+        #[clap(value_enum)]
+        shell: hook::Shell,
+    },
+    /// Manage awx's caches
+    Cache {
+        #[clap(subcommand)]
+        action: CacheCommand,
+    },
+    /// Keep resolved sessions in memory for other awx invocations (opt-in, see `broker`
+    /// in ~/.awx/config.toml); without an action, run the broker in the foreground
+    Broker {
+        #[clap(subcommand)]
+        action: Option<BrokerCommand>,
+    },
+    /// Show local usage metrics per profile (opt-in, see `metrics` in ~/.awx/config.toml)
+    Stats,
+    /// Check the installation and run the credential pipeline against a built-in mock
+    /// of the AWS CLI (no network)
+    #[clap(alias = "doctor")]
+    Selftest,
+    /// Print the shell function that lets `awx login`, `awx use` and `awx export` set
+    /// credentials in the current shell
     Init {
-        /// Target shell (zsh/bash/fish). If omitted, detect from $SHELL.
-        #[clap(long = "shell")]
-        shell: Option<String>,
+        /// Target shell; detected from $SHELL when omitted, else bash
+        #[clap(value_enum)]
+        shell: Option<hook::Shell>,
+
+        /// The same, as a flag (older snippets use it)
+        #[clap(long = "shell", value_enum, hide = true, conflicts_with = "shell")]
+        shell_flag: Option<hook::Shell>,
+    },
+    /// Put a profile's session into the current shell (through the `awx init` function);
+    /// prints the `export` lines otherwise
+    Use {
+        /// Profile to use
+        profile: String,
+
+        /// Skip interactive UI (for CI)
+        #[clap(short = 'n', long = "no-interactive")]
+        no_interactive: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum PairCommand {
+    /// Authenticate and answer a pairing request printed by `awx pair`
+    Approve {
+        /// The request blob (awxpair1:...)
+        request: String,
+
+        /// Profile whose session is sent
+        #[clap(short = 'p', long = "profile")]
+        profile: Option<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum S3ExpressCommand {
+    /// Print session credentials for a directory bucket, reusing a kept one while it lasts
+    Session {
+        /// Profile whose session calls CreateSession
+        #[clap(short = 'p', long = "profile")]
+        profile: Option<String>,
+
+        /// Directory bucket, `<name>--<az-id>--x-s3`
+        #[clap(long = "bucket", value_parser = s3express::parse_bucket)]
+        bucket: String,
+
+        /// What the session may do
+        #[clap(long = "mode", value_enum, default_value = "read-write")]
+        mode: s3express::Mode,
+
+        /// The bucket's region (default: the profile's)
+        #[clap(short = 'r', long = "region")]
+        region: Option<String>,
+
+        /// Skip interactive UI (for CI)
+        #[clap(short = 'n', long = "no-interactive")]
+        no_interactive: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum CtxCommand {
+    /// Make a context active for subsequent commands
+    Use {
+        /// Context name
+        name: String,
+    },
+    /// Print the active context
+    Current,
+    /// Deactivate the active context
+    Clear,
+}
+
+#[derive(Debug, Subcommand)]
+enum BrokerCommand {
+    /// List the sessions the running broker holds
+    Status,
+    /// Drop the sessions of a profile from the broker
+    Forget {
+        /// Profile name
+        profile: String,
+    },
+    /// Stop the broker; every session it holds is forgotten
+    Stop,
+}
+
+#[derive(Debug, Subcommand)]
+enum CacheCommand {
+    /// List cached sessions, one per profile and --duration, and when they expire
+    Ls,
+    /// Delete expired caches, sessions, SSO client registrations and pairing keys
+    Gc {
+        /// Only list what would be deleted
+        #[clap(long = "dry-run")]
+        dry_run: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum ConfigCommand {
+    /// Write a timestamped archive of ~/.aws and awx state
+    Backup {
+        /// Archive path (default: ~/.awx/backups/awx-backup-<timestamp>.json)
+        #[clap(short = 'o', long = "output")]
+        output: Option<PathBuf>,
+
+        /// Leave out ~/.aws/credentials and static keys found in other files
+        #[clap(long = "exclude-secrets")]
+        exclude_secrets: bool,
+
+        /// Encrypt the archive with openssl (passphrase from AWX_BACKUP_PASSPHRASE or a prompt)
+        #[clap(long = "encrypt")]
+        encrypt: bool,
+    },
+    /// Restore files from an archive created by `awx config backup`
+    Restore {
+        /// Archive to restore
+        archive: PathBuf,
+
+        /// Do not ask for confirmation
+        #[clap(short = 'y', long = "yes")]
+        yes: bool,
+    },
+    /// Find stale profiles and comment them out or delete them from ~/.aws
+    Prune {
+        /// Report profiles not used for this many days (usage history from `metrics = true`)
+        #[clap(long = "unused-days", default_value_t = 90)]
+        unused_days: i64,
+
+        /// Skip the AWS calls checking that roles can still be assumed and SSO accounts reached
+        #[clap(long = "no-remote")]
+        no_remote: bool,
+    },
+    /// Print a JSON Schema of ~/.awx/config.toml for editor completion and validation
+    Schema {
+        /// Describe the profile keys awx reads from ~/.aws instead
+        #[clap(long = "aws")]
+        aws: bool,
     },
 }
 
 #[derive(Debug, Default, Clone)]
 struct Profile {
     name: String,
+    /// From `# awx: <description>` or `awx_description` in ~/.aws/config.
+    description: Option<String>,
     region: Option<String>,
     sso_start_url: Option<String>,
     sso_region: Option<String>,
+    sso_session: Option<String>,
+    sso_account_id: Option<String>,
     role_arn: Option<String>,
     source_profile: Option<String>,
+    /// EcsContainer, Ec2InstanceMetadata or Environment, in place of source_profile.
+    credential_source: Option<String>,
+    /// An OIDC token the AWS CLI assumes role_arn with.
+    web_identity_token_file: Option<String>,
+    /// A program the AWS CLI runs for credentials.
+    credential_process: Option<String>,
+    /// The AWS CLI's own settings (see `aws_env::CLI_SETTINGS`).
+    output: Option<String>,
+    cli_pager: Option<String>,
+    cli_binary_format: Option<String>,
+    cli_history: Option<String>,
     mfa_serial: Option<String>,
+    role_session_name: Option<String>,
+    duration_seconds: Option<i64>,
     aws_access_key_id: Option<String>,
     aws_secret_access_key: Option<String>,
     aws_session_token: Option<String>,
@@ -125,7 +786,7 @@ struct StsCredsWrapper {
     credentials: StsCredentials,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 struct StsCredentials {
     access_key_id: String,
@@ -136,361 +797,1723 @@ struct StsCredentials {
 
 #[tokio::main]
 async fn main() -> ExitCode {
-    let opts = Opt::parse();
-    if let Err(err) = run(opts).await {
-        eprintln!("Error: {}", err);
+    if selftest::is_mock_invocation() {
+        return ExitCode::from(selftest::run_mock() as u8);
+    }
+    // `awx -p dev s3 ls` as if written `awx -p dev -- s3 ls`.
+    let opts = match passthrough::normalize(std::env::args_os().collect(), &Opt::command()) {
+        Ok(args) => Opt::parse_from(args),
+        Err(e) => Opt::command().error(clap::error::ErrorKind::InvalidSubcommand, e).exit(),
+    };
+    interrupt::install();
+    let settings = settings::load_settings().unwrap_or_default();
+    i18n::configure(i18n::detect(settings.language, &aws_env::current_lookup));
+    match theme::Theme::from_settings(&settings.theme) {
+        Ok(styles) => theme::configure(styles),
+        Err(e) => {
+            eprintln!("Warning: {:#}; ignoring the [theme] overrides", e);
+            theme::configure(theme::Theme::preset(settings.theme.preset));
+        }
+    }
+    let command = command_name(&opts.command);
+    let backends = backend::Backends { flags: std::sync::Arc::new(flags(&opts)), ..backend::Backends::default() };
+    let result = backend::scope(backends, run(opts)).await;
+    tmp::cleanup();
+    if let Err(err) = result {
+        progress::finish();
+        telemetry::export(Some(format!("{:#}", err))).await;
+        if interrupt::is_interrupt(&err) {
+            interrupt::aborted();
+            return ExitCode::from(interrupt::EXIT_CODE);
+        }
+        if let Some(code) = pipeline::exit_code(&err) {
+            eprintln!("{}", err);
+            return ExitCode::from(code);
+        }
+        if let Ok(awx_dir) = settings::awx_dir() {
+            debug_bundle::record_error(&awx_dir, command, &err, Utc::now());
+        }
+        eprint!("{}", render::error(&err));
         return ExitCode::from(1);
     }
+    telemetry::export(None).await;
     ExitCode::from(0)
 }
 
-async fn run(opts: Opt) -> Result<()> {
+// Exit with `code`, sending the trace first.
+fn exit(code: i32) -> ! {
+    if telemetry::enabled() {
+        // Usually the child's code, which says nothing about how awx did.
+        telemetry::root_attr("process.exit_code", code);
+        tokio::task::block_in_place(|| tokio::runtime::Handle::current().block_on(telemetry::export(None)));
+    }
+    tmp::cleanup();
+    std::process::exit(code)
+}
+
+// The subcommand, for the trace.
+fn command_name(command: &Option<SubCommand>) -> &'static str {
+    match command {
+        None => "run",
+        Some(SubCommand::Login { .. }) => "login",
+        Some(SubCommand::Config { .. }) => "config",
+        Some(SubCommand::Exec { .. }) => "exec",
+        Some(SubCommand::Pair { .. }) => "pair",
+        Some(SubCommand::Whoami { .. }) => "whoami",
+        Some(SubCommand::Trail { .. }) => "trail",
+        Some(SubCommand::Curl { .. }) => "curl",
+        Some(SubCommand::S3express { .. }) => "s3express",
+        Some(SubCommand::Compose { .. }) => "compose",
+        Some(SubCommand::Tool { .. }) => "tool",
+        Some(SubCommand::LsRoles { .. }) => "ls-roles",
+        Some(SubCommand::CanI { .. }) => "can-i",
+        Some(SubCommand::CredentialChain { .. }) => "credential-chain",
+        Some(SubCommand::Warm { .. }) => "warm",
+        Some(SubCommand::Check { .. }) => "check",
+        Some(SubCommand::Root { .. }) => "root",
+        Some(SubCommand::DebugBundle { .. }) => "debug-bundle",
+        Some(SubCommand::Ctx { .. }) => "ctx",
+        Some(SubCommand::Hook { .. }) => "hook",
+        Some(SubCommand::HookEnv { .. }) => "hook-env",
+        Some(SubCommand::Cache { .. }) => "cache",
+        Some(SubCommand::Export { .. }) => "export",
+        Some(SubCommand::Broker { .. }) => "broker",
+        Some(SubCommand::Stats) => "stats",
+        Some(SubCommand::Selftest) => "selftest",
+        Some(SubCommand::Init { .. }) => "init",
+        Some(SubCommand::Use { .. }) => "use",
+    }
+}
+
+// The global switches of `opts`, carried through the run by `backend::scope`.
+fn flags(opts: &Opt) -> backend::Flags {
+    backend::Flags {
+        plain_prompts: opts.plain_prompts,
+        reveal: opts.reveal,
+        tmpdir: opts.tmpdir.clone(),
+        offline: opts.offline,
+        verify_trust: opts.verify_trust,
+        choose_duration: opts.choose_duration,
+        duration: opts.duration,
+        credentials_endpoint: opts.credentials_endpoint,
+    }
+}
+
+async fn run(mut opts: Opt) -> Result<()> {
+    if let Some(path) = &opts.answers {
+        ask::load_answers(path)?;
+    }
+    badge::configure(badge::Filter { only: opts.only.clone(), accounts: opts.account.clone() });
+    output::configure(opts.output);
+    // `awx use <profile>` is `awx export -p <profile>`, evaluated by the shell function.
+    opts.command = match opts.command.take() {
+        Some(SubCommand::Use { profile, no_interactive }) => {
+            if std::io::stdout().is_terminal() {
+                eprintln!("`awx use` changes this shell only through the shell function; see `awx init`.");
+            }
+            Some(SubCommand::Export {
+                profile: Some(profile),
+                clipboard: false,
+                clear_after: None,
+                qr: None,
+                no_interactive,
+            })
+        }
+        command => command,
+    };
+    // File management commands work without the aws binary or any profiles, e.g. when
+    // restoring onto a fresh machine.
+    if let Some(SubCommand::Config { action }) = &opts.command {
+        return run_config_command(action, opts.no_interactive).await;
+    }
+    // The headless side of pairing needs neither the aws binary nor profiles.
+    if let Some(SubCommand::Pair { action: None, output }) = &opts.command {
+        return run_pair_request(output.clone()).await;
+    }
+
+    if let Some(SubCommand::Cache {
+        action: CacheCommand::Gc { dry_run },
+    }) = &opts.command
+    {
+        let awx_dir = settings::awx_dir()?;
+        let mut expired = janitor::expired(&awx_dir, &aws_files()?.config, Utc::now());
+        expired.extend(janitor::abandoned_temp(&tmp::dir()));
+        let removed = if *dry_run { expired } else { janitor::remove(expired) };
+        print!("{}", render::cache_gc(&awx_dir, &removed, *dry_run));
+        return Ok(());
+    }
+    if let Some(SubCommand::Cache { action: CacheCommand::Ls }) = &opts.command {
+        let awx_dir = settings::awx_dir()?;
+        // A broker that is not running holds nothing.
+        let held = broker::call(&broker::socket_path(&awx_dir), &broker::Request::List)
+            .map(|r| r.sessions)
+            .unwrap_or_default();
+        let (cached, now) = (warm::cached(&awx_dir), Utc::now());
+        print!(
+            "{}",
+            output::render(
+                || Ok(render::cache_ls(&awx_dir, &cached, &held, now)),
+                || output::cache_ls(&cached, &held, now)
+            )?
+        );
+        return Ok(());
+    }
+    // The broker only holds what other invocations hand it.
+    if let Some(SubCommand::Broker { action }) = &opts.command {
+        return run_broker_command(action.as_ref());
+    }
+    // Switching contexts only touches awx's own files.
+    if let Some(SubCommand::Ctx { action }) = &opts.command {
+        return run_ctx_command(action.as_ref());
+    }
+    // The hook runs before every prompt and must stay quick and offline.
+    match &opts.command {
+        Some(SubCommand::Hook { shell }) => {
+            print!("{}", hook::snippet(*shell));
+            return Ok(());
+        }
+        Some(SubCommand::HookEnv { shell }) => return run_hook_env(*shell),
+        // Shell startup files load this before there may be any profiles.
+        Some(SubCommand::Init { shell, shell_flag }) => {
+            let shell = shell
+                .or(*shell_flag)
+                .or_else(|| aws_env::current_lookup("SHELL").and_then(|path| init::detect(&path)))
+                .unwrap_or(hook::Shell::Bash);
+            print!("{}", init::snippet(shell));
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    // The self-test reports a missing aws binary instead of stopping at it.
+    if let Some(SubCommand::Selftest) = &opts.command {
+        return run_selftest().await;
+    }
+    // Bug reports are most needed where awx cannot run at all.
+    if let Some(SubCommand::DebugBundle { profile, output }) = &opts.command {
+        return run_debug_bundle(profile.clone(), output.clone()).await;
+    }
 
     // Ensure aws binary exists
     ensure_aws_present().await?;
 
-    let profiles = load_profiles()?;
-    if profiles.is_empty() {
+    let no_interactive = opts.no_interactive
+        || matches!(
+            opts.command,
+            Some(SubCommand::Login { no_interactive: true, .. })
+                | Some(SubCommand::Exec { no_interactive: true, .. })
+                | Some(SubCommand::Whoami { no_interactive: true, .. })
+                | Some(SubCommand::Trail { no_interactive: true, .. })
+                | Some(SubCommand::Curl { no_interactive: true, .. })
+                | Some(SubCommand::S3express {
+                    action: S3ExpressCommand::Session { no_interactive: true, .. },
+                })
+                | Some(SubCommand::Compose { no_interactive: true, .. })
+                | Some(SubCommand::Tool { no_interactive: true, .. })
+                | Some(SubCommand::LsRoles { no_interactive: true, .. })
+                | Some(SubCommand::CanI { no_interactive: true, .. })
+                | Some(SubCommand::CredentialChain { no_interactive: true, .. })
+                | Some(SubCommand::Warm { .. })
+                | Some(SubCommand::Check { .. })
+                | Some(SubCommand::Root { no_interactive: true, .. })
+                | Some(SubCommand::Export { no_interactive: true, .. })
+        );
+    let mut settings = settings::load_settings()?;
+    settings.sso_login_flags = settings::SsoLoginOptions {
+        no_browser: opts.no_browser,
+        use_device_code: opts.use_device_code,
+    };
+    settings.needs_flag = opts.needs;
+    if metrics::enabled_by(settings.metrics) {
+        metrics::enable(metrics::store_path()?);
+    }
+    if broker::enabled_by(settings.broker) {
+        broker::enable(broker::socket_path(&settings::awx_dir()?));
+    }
+    notify::configure(&settings.notify);
+    if let Some(exporter) = telemetry::exporter(&settings.telemetry, &aws_env::current_lookup) {
+        telemetry::enable(exporter, command_name(&opts.command));
+    }
+    service::configure(&settings.services);
+    cli::configure(&settings.profiles);
+    auth::configure(&settings.profiles);
+    default_profile::configure(&settings.default_profile);
+    orphan::configure(settings.orphans);
+    projects::configure(&settings.projects);
+    let active = context::load(&settings, &aws_env::current_lookup, &context::state_path()?)?;
+    let profile_from_context = match &active {
+        Some(active) => apply_context_profile(&mut opts, &active.settings.profile),
+        None => false,
+    };
+    let mut rate_limit = settings.rate_limit.clone();
+    if let Some(SubCommand::Exec { rps: Some(rps), .. }) = opts.command {
+        rate_limit.rps = rps;
+    }
+    ratelimit::configure(&rate_limit);
+    let inherited = if opts.fresh {
+        None
+    } else {
+        session::inherited(&aws_env::current_lookup, Utc::now())
+    };
+    let load = telemetry::span("profile load");
+    let mut profiles = load_profiles(&settings, no_interactive)?;
+    drop(load);
+    janitor::sweep_if_due(&settings::awx_dir()?, &aws_files()?.config, Utc::now());
+    for name in janitor::invalidate_redefined(&settings::awx_dir()?, &profiles) {
+        eprintln!(
+            "{}",
+            theme::style(Role::Muted).apply_to(format!("Profile '{}' changed since the last run; its cached session was discarded.", name))
+        );
+    }
+    if let Some(active) = active {
+        if let (Some(region), Some(profile)) = (&active.settings.region, profiles.get_mut(&active.settings.profile)) {
+            profile.region = Some(region.clone());
+        }
+        telemetry::root_attr("awx.context", &active.name);
+        context::configure(active);
+    }
+    fill_missing_regions(&mut profiles, settings.default_region.as_deref());
+    secrets::apply(&mut profiles, &settings.profiles);
+    // A session file carries everything a run needs, even on a host without profiles.
+    if profiles.is_empty() && opts.from_session_file.is_none() {
         return Err(anyhow!("No AWS profiles found in ~/.aws/config or ~/.aws/credentials"));
     }
 
     match opts.command {
-        Some(SubCommand::Init { shell }) => {
-                        let shell = shell
-                                .or_else(|| std::env::var("SHELL").ok().map(|p| {
-                                        std::path::Path::new(&p)
-                                                .file_name()
-                                                .and_then(|s| s.to_str())
-                                                .unwrap_or("")
-                                                .to_string()
-                                }))
-                                .unwrap_or_else(|| "bash".to_string());
-
-                        let snippet = if shell.contains("zsh") || shell.contains("bash") {
-                                r#"# awx shell wrapper
-awx() {
-    if [ "$#" -ge 1 ] && [ "$1" = "login" ]; then
-        shift
-        eval "$(command awx login "$@")"
-    else
-        command awx "$@"
-    fi
-}
-# To enable automatically, add the following line to your shell rc (e.g., ~/.zshrc or ~/.bashrc):
-# eval "$(command awx init --shell=$(basename "$SHELL"))"
-"#
-                        } else if shell.contains("fish") {
-                                r#"# awx shell wrapper for fish
-function awx
-    if test (count $argv) -ge 1; and test $argv[1] = login
-        set -e argv[1]
-        eval (command awx login $argv)
-    else
-        command awx $argv
-    end
-end
-# To enable automatically, add this to your config.fish:
-# command awx init --shell=fish | source
-"#
-                        } else {
-                                r#"# Defaulting to bash-compatible snippet
-awx() {
-    if [ "$#" -ge 1 ] && [ "$1" = "login" ]; then
-        shift
-        eval "$(command awx login "$@")"
-    else
-        command awx "$@"
-    fi
-}
-# eval "$(command awx init --shell=bash)"
-"#
-                        };
-
-                        print!("{}", snippet);
-                        Ok(())
+        Some(SubCommand::Config { .. })
+        | Some(SubCommand::Pair { action: None, .. })
+        | Some(SubCommand::Ctx { .. })
+        | Some(SubCommand::Cache { .. })
+        | Some(SubCommand::Broker { .. })
+        | Some(SubCommand::Hook { .. })
+        | Some(SubCommand::HookEnv { .. })
+        | Some(SubCommand::Selftest)
+        | Some(SubCommand::DebugBundle { .. })
+        | Some(SubCommand::Init { .. })
+        | Some(SubCommand::Use { .. }) => {
+            unreachable!("handled before profile loading")
+        }
+        Some(SubCommand::Pair {
+            action: Some(PairCommand::Approve { request, profile }),
+            ..
+        }) => {
+            let public = pair::decode_request(&request)?;
+            println!("Pairing code: {}", theme::style(Role::Heading).apply_to(pair::pairing_code(&public)));
+            if !no_interactive {
+                let same = ask::confirm(ask::Key("pairing_code_matches", None), "Does the other machine show the same code?", false)?;
+                if !same {
+                    return Err(anyhow!("Pairing codes differ; not sending a session"));
                 }
-        Some(SubCommand::Login { profile, no_interactive }) => {
-            let selected_profile_name = if let Some(p) = profile {
-                p
-            } else if no_interactive {
-                env::var("AWS_PROFILE").unwrap_or_else(|_| "default".to_string())
+            }
+            let name = resolve_profile_name(profile, no_interactive, &profiles)?;
+            record_use(&name).await;
+            let creds = perform_authentication(&profiles, &name, no_interactive, &settings).await?;
+            let descriptor = session_descriptor(&profiles[&name], creds).await?;
+            let response = pair::seal(&public, &descriptor).await?;
+            println!("Paste this response on the other machine:");
+            println!();
+            println!("{}", response);
+            Ok(())
+        }
+        Some(SubCommand::Whoami { profile, deep, .. }) => {
+            let name = resolve_profile_name(profile, no_interactive, &profiles)?;
+            record_use(&name).await;
+            let profile = &profiles[&name];
+            let creds = authenticate_for(&profiles, &name, no_interactive, &settings).await?;
+            let identity = session_identity(creds.clone(), profile).await?;
+            let details = if deep {
+                let caller: arn::Arn = identity.arn.parse()?;
+                let principal = iam::principal_arn(&caller)
+                    .ok_or_else(|| anyhow!("{} is not an IAM user or role", identity.arn))?;
+                let aws = || child_command("aws", &[], creds.clone(), profile);
+                Some(iam::describe(aws, &principal.parse()?).await?)
             } else {
-                interactive_select_profile(&profiles)?
+                None
             };
-
-            let final_creds = perform_authentication(&profiles, &selected_profile_name, no_interactive).await?;
-
-            // Output environment variables for shell evaluation
-            println!("# AWS credentials for profile '{}' are ready.", selected_profile_name);
-            let green = Style::new().green();
-            println!("{}", green.apply_to("# Copy and paste the following commands into your terminal:\n"));
-            println!("export AWS_PROFILE={}", selected_profile_name);
-            if let Some(creds) = final_creds {
-                println!("export AWS_ACCESS_KEY_ID={}", creds.access_key_id);
-                println!("export AWS_SECRET_ACCESS_KEY={}", creds.secret_access_key);
-                println!("export AWS_SESSION_TOKEN={}", creds.session_token);
-            }
-            // Output region if available
-            if let Some(profile) = profiles.get(&selected_profile_name) {
-                if let Some(region) = &profile.region {
-                    println!("export AWS_DEFAULT_REGION={}", region);
-                }
-            }
+            print!(
+                "{}",
+                output::render(
+                    || Ok(render::whoami(&name, &identity, details.as_ref())),
+                    || output::whoami(&name, &identity, details.as_ref())
+                )?
+            );
             Ok(())
         }
-        None => {
-            // Default Run behavior
-            let profile = opts.profile;
-            let config = opts.config;
-            let clear_cache = opts.clear_cache;
-            let no_interactive = opts.no_interactive;
+        Some(SubCommand::Trail {
+            profile,
+            last,
+            region,
+            ..
+        }) => {
+            let name = resolve_profile_name(profile, no_interactive, &profiles)?;
+            record_use(&name).await;
+            let profile = &profiles[&name];
+            let creds = authenticate_for(&profiles, &name, no_interactive, &settings).await?;
+            let identity = session_identity(creds.clone(), profile).await?;
+            let caller: arn::Arn = identity.arn.parse()?;
+            let username = trail::username(&caller).ok_or_else(|| anyhow!("CloudTrail cannot be searched for {}", identity.arn))?;
+            let since = Utc::now() - last;
+            let aws = child_command("aws", &[], creds, profile);
+            let events = trail::lookup(aws, &username, since, region.as_deref())
+                .await
+                .context("Cannot look up CloudTrail events (needs cloudtrail:LookupEvents)")?;
+            print!("{}", render::trail(&username, since, &events, &chrono::Local));
+            Ok(())
+        }
+        Some(SubCommand::Curl {
+            profile,
+            url,
+            method,
+            headers,
+            body,
+            service,
+            region,
+            sigv4a,
+            presign,
+            curl_args,
+            ..
+        }) => {
+            let sigv4a = sigv4a || curl::needs_sigv4a(&url);
+            let name = resolve_profile_name(profile, no_interactive, &profiles)?;
+            record_use(&name).await;
+            let profile = &profiles[&name];
+            let from_url = curl::endpoint_scope(&url);
+            let service = service
+                .or_else(|| from_url.as_ref().map(|(s, _)| s.clone()))
+                .or_else(|| curl::needs_sigv4a(&url).then(|| "s3".to_string()))
+                .ok_or_else(|| anyhow!("Cannot tell the signing service from {}; name it with --service", url))?;
+            let region = region
+                .or_else(|| sigv4a.then(|| "*".to_string()))
+                .or_else(|| from_url.as_ref().map(|(_, r)| r.clone()))
+                .or_else(|| profile.region.clone())
+                .ok_or_else(|| anyhow!("Cannot tell the signing region from {}; name it with --region", url))?;
+            let creds = authenticate_for(&profiles, &name, no_interactive, &settings).await?;
+            let creds = match creds {
+                Some(creds) => creds,
+                None => session_descriptor(profile, None).await?.credentials(),
+            };
+            let request = curl::Request { url, method, service, region, headers, body, sigv4a };
+            if let Some(expires) = presign {
+                println!("{}", curl::presign(&request, &creds, expires, chrono::Utc::now())?);
+                return Ok(());
+            }
+            exit(curl::run(&request, &creds, &curl_args).await?);
+        }
+        Some(SubCommand::S3express {
+            action:
+                S3ExpressCommand::Session {
+                    profile,
+                    bucket,
+                    mode,
+                    region,
+                    ..
+                },
+        }) => {
+            let name = resolve_profile_name(profile, no_interactive, &profiles)?;
+            record_use(&name).await;
+            let profile = &profiles[&name];
+            let creds = authenticate_for(&profiles, &name, no_interactive, &settings).await?;
+            let region = region.or_else(|| profile.region.clone());
+            let aws = || child_command("aws", &[], creds, profile);
+            let session = s3express::session(&settings::awx_dir()?, &name, &bucket, mode, aws, region.as_deref(), Utc::now())
+                .await
+                .context("Cannot create an S3 Express session (needs s3express:CreateSession on the bucket)")?;
+            if let Some(document) = output::structured(|| serde_json::json!(session))? {
+                print!("{}", document);
+                return Ok(());
+            }
+            let lines = s3express::exports(&session);
+            let label = format!("{}@{}", name, bucket);
+            if !divert_exports(&label, &lines, true, Some(&session.credentials.expiration))? {
+                print!("{}", lines);
+            }
+            Ok(())
+        }
+        Some(SubCommand::Compose {
+            profile,
+            env_file,
+            args,
+            ..
+        }) => {
+            if args.is_empty() && env_file.is_none() {
+                return Err(anyhow!("Nothing to do: pass docker compose arguments after `--` or --env-file"));
+            }
+            let name = resolve_profile_name(profile, no_interactive, &profiles)?;
+            record_use(&name).await;
+            let profile = &profiles[&name];
+            let creds = authenticate_for(&profiles, &name, no_interactive, &settings).await?;
+            // Containers get a session even for static profiles.
+            let descriptor = session_descriptor(profile, creds).await?;
+            if let Some(path) = &env_file {
+                compose::write_env_file(&settings::awx_dir()?, path, &descriptor)?;
+                if compose::exclude_from_git(path).await? {
+                    eprintln!("Added {} to .git/info/exclude", path.display());
+                }
+                eprintln!(
+                    "Wrote credentials for '{}' to {} (expire {})",
+                    name,
+                    path.display(),
+                    render::expiration(descriptor.expiration, Utc::now())
+                );
+            }
+            if args.is_empty() {
+                return Ok(());
+            }
+            confirm_context(&name, no_interactive)?;
+            let mut compose_args = vec!["compose".to_string()];
+            compose_args.extend(args);
+            let code = run_child_capture("docker", &compose_args, Some(descriptor.credentials()), profile.clone()).await?;
+            exit(code);
+        }
+        Some(SubCommand::Tool {
+            tool,
+            profile,
+            args,
+            ..
+        }) => {
+            let name = resolve_profile_name(profile, no_interactive, &profiles)?;
+            record_use(&name).await;
+            let profile = &profiles[&name];
+            let creds = authenticate_for(&profiles, &name, no_interactive, &settings).await?;
+            // Few of these tools understand SSO profiles, so they get the keys.
+            let creds = match creds {
+                None if !profile.is_static() => Some(export_credentials(&name).await?),
+                creds => creds,
+            };
+            confirm_context(&name, no_interactive)?;
+            let region = aws_env::region(&aws_env::current_lookup, &[], profile).map(|(r, _)| r);
+            let args = tool.args(&args, region.as_deref());
+            let mut cmd = child_command(tool.program(), &args, creds, profile);
+            cmd.envs(tool.env(region.as_deref(), &aws_env::current_lookup));
+            exit(run_attached(cmd, tool.program()).await?);
+        }
+        Some(SubCommand::LsRoles { profile, .. }) => {
+            let name = resolve_profile_name(profile, no_interactive, &profiles)?;
+            record_use(&name).await;
+            let profile = &profiles[&name];
+            let creds = authenticate_for(&profiles, &name, no_interactive, &settings).await?;
+            let identity = session_identity(creds.clone(), profile).await?;
+            let caller: arn::Arn = identity.arn.parse()?;
+            let roles = iam::assumable_roles(child_command("aws", &[], creds, profile), &caller).await?;
+            print!("{}", render::assumable_roles(&identity.arn, &identity.account, &roles));
+            Ok(())
+        }
+        Some(SubCommand::CanI {
+            profile,
+            action,
+            resources,
+            ..
+        }) => {
+            let name = resolve_profile_name(profile, no_interactive, &profiles)?;
+            record_use(&name).await;
+            let profile = &profiles[&name];
+            let creds = authenticate_for(&profiles, &name, no_interactive, &settings).await?;
+            let principal = simulation_principal(profile).await?;
+            let aws = child_command("aws", &[], creds, profile);
+            let evaluations = iam::simulate(aws, &principal, std::slice::from_ref(&action), &resources)
+                .await
+                .with_context(|| format!("Cannot simulate policies for {} (needs iam:SimulatePrincipalPolicy)", principal))?;
+            print!("{}", render::can_i(&principal, &evaluations));
+            exit(if evaluations.iter().all(|e| e.allowed()) { 0 } else { 1 });
+        }
+        Some(SubCommand::CredentialChain { profile, .. }) => {
+            let name = resolve_profile_name(profile, no_interactive, &profiles)?;
+            record_use(&name).await;
+            let profile = &profiles[&name];
+            let creds = authenticate_for(&profiles, &name, no_interactive, &settings).await?;
+            let report = chain_report(profile, creds, &aws_dir()?);
+            print!("{}", render::credential_chain(&name, &report));
+            if report.shadowed.is_some() {
+                return Err(anyhow!("The default credential chain would not pick the credentials awx prepared for '{}'", name));
+            }
+            Ok(())
+        }
+        Some(SubCommand::Warm {
+            profile,
+            output,
+            min_remaining,
+            timeout,
+        }) => {
+            let name = resolve_profile_name(profile, true, &profiles)?;
+            record_use(&name).await;
+            let path = match output {
+                Some(path) => path,
+                None => warm::default_path(&settings::awx_dir()?, &name, duration::requested()),
+            };
+            let report = run_warm(&profiles, &name, &path, min_remaining, timeout, &settings).await;
+            println!("{}", serde_json::to_string(&report)?);
+            if !report.ready {
+                exit(1);
+            }
+            Ok(())
+        }
+        Some(SubCommand::Check { profile, min_remaining }) => {
+            let name = resolve_profile_name(profile, true, &profiles)?;
+            let profile = profiles.get(&name).ok_or_else(|| anyhow!("Profile '{}' not found", name))?;
+            let now = Utc::now();
+            let min_remaining = min_remaining.unwrap_or_else(chrono::Duration::zero);
+            let verdict = check::check(&profiles, profile, &aws_dir()?, &settings::awx_dir()?, min_remaining, now);
+            print!("{}", render::check(&name, &verdict, now));
+            if !verdict.is_ready() {
+                exit(2);
+            }
+            Ok(())
+        }
+        Some(SubCommand::Root {
+            profile: name,
+            target,
+            task,
+            confirm,
+            command,
+            ..
+        }) => {
+            let profile = profiles.get(&name).ok_or_else(|| anyhow!("Profile '{}' not found", name))?;
+            let log = root::audit_path(&settings::awx_dir()?);
+            let user = aws_env::current_lookup("USER").unwrap_or_else(|| "unknown".to_string());
+            let audit = |outcome, expiration: Option<String>, reason: Option<String>| {
+                root::record(
+                    &log,
+                    &root::AuditEntry {
+                        time: Utc::now(),
+                        user: user.clone(),
+                        profile: name.clone(),
+                        target: target.clone(),
+                        task_policy_arn: task.clone(),
+                        outcome,
+                        expiration,
+                        reason,
+                    },
+                )
+            };
+            let typed = match confirm {
+                Some(typed) => typed,
+                None if no_interactive => {
+                    return Err(anyhow!("awx root asks for the target account to be typed back; pass --confirm {} with --no-interactive", target))
+                }
+                None => ask::text(
+                    ask::Key("root_target", None),
+                    &format!(
+                        "This assumes the ROOT user of account {} for {}. Type the account id to continue",
+                        target,
+                        root::task_name(&task)
+                    ),
+                )?,
+            };
+            if typed.trim() != target {
+                audit(root::Outcome::Declined, None, None)?;
+                return Err(anyhow!("'{}' is not {}; nothing was assumed", typed.trim(), target));
+            }
+            record_use(&name).await;
+            let creds = authenticate_for(&profiles, &name, no_interactive, &settings).await?;
+            let session = match root::assume(child_command("aws", &[], creds, profile), &target, &task).await {
+                Ok(session) => session,
+                Err(e) => {
+                    audit(root::Outcome::Failed, None, Some(format!("{:#}", e)))?;
+                    return Err(e);
+                }
+            };
+            audit(root::Outcome::Granted, Some(session.expiration.clone()), None)?;
+            notify::send(notify::Event::RootAssumed { target: &target, task_policy_arn: &task }).await;
+            eprintln!(
+                "Assumed the root user of account {} for {} (expires {}); recorded in {}",
+                target,
+                root::task_name(&task),
+                render::expiration_rfc3339(&session.expiration, Utc::now()),
+                log.display()
+            );
+            let Some((program, args)) = command.split_first() else {
+                let lines = root::exports(&session, profile.region.as_deref());
+                if !divert_exports(&format!("root-{}", target), &lines, true, Some(&session.expiration))? {
+                    print!("{}", lines);
+                }
+                return Ok(());
+            };
+            // The child must not fall back to the management profile.
+            let root_profile = Profile {
+                name: format!("root-{}", target),
+                region: profile.region.clone(),
+                ..Default::default()
+            };
+            let mut cmd = child_command(program, args, Some(session), &root_profile);
+            cmd.env_remove("AWS_PROFILE");
+            exit(run_attached(cmd, program).await?);
+        }
+        Some(SubCommand::Stats) => {
+            let store = metrics::MetricsStore::load(&metrics::store_path()?);
+            print!("{}", render::stats(&store, &profiles, metrics::enabled_by(settings.metrics)));
+            Ok(())
+        }
+        Some(SubCommand::Exec {
+            profiles: names,
+            all_matching,
+            targets: target_sets,
+            regions,
+            parallel,
+            fail_fast,
+            any_success,
+            results,
+            command,
+            ..
+        }) => {
+            let mut targets: Vec<exec::Target> = Vec::new();
+            for set_name in target_sets.iter() {
+                let set = settings
+                    .targets
+                    .get(set_name)
+                    .ok_or_else(|| anyhow!("Unknown target set '{}' (define it under [targets.{}] in ~/.awx/config.toml)", set_name, set_name))?;
+                targets.extend(exec::expand_set(&profiles, set_name, set)?);
+            }
+            if !names.is_empty() || all_matching.is_some() {
+                let names = exec::select_targets(&profiles, &names, all_matching.as_deref())?;
+                targets.extend(exec::expand(&names, &regions));
+            } else if targets.is_empty() {
+                let name = match session::reusable_for(inherited.as_ref(), opts.profile.as_deref()) {
+                    Some(name) => name,
+                    None => resolve_profile_name(opts.profile, no_interactive, &profiles)?,
+                };
+                targets = exec::expand(&[name], &regions);
+            }
+            let mut seen = Vec::new();
+            targets.retain(|t| {
+                let new = !seen.contains(t);
+                seen.push(t.clone());
+                new
+            });
+            let names = exec::profile_names(&targets);
+            for name in names.iter() {
+                record_use(name).await;
+            }
+            let policy = if fail_fast {
+                exec::FailurePolicy::FailFast
+            } else if any_success {
+                exec::FailurePolicy::AnySuccess
+            } else {
+                exec::FailurePolicy::KeepGoing
+            };
+            let resolved = exec::resolve_targets(
+                &profiles,
+                &names,
+                no_interactive,
+                &settings,
+                parallel,
+                inherited.as_ref().map(|m| m.profile.as_str()),
+            )
+            .await?;
+            progress::finish();
+            for name in names.iter() {
+                confirm_context(name, no_interactive)?;
+            }
+            let outcomes = exec::run_targets(&profiles, &targets, resolved, &command, policy, results).await?;
+            if targets.len() > 1 {
+                eprint!(
+                    "{}",
+                    output::render(|| Ok(render::exec_summary(&outcomes)), || output::exec_summary(&outcomes))?
+                );
+            }
+            exit(policy.exit_code(&outcomes));
+        }
+        Some(SubCommand::Export {
+            profile,
+            clipboard,
+            clear_after,
+            qr,
+            no_interactive,
+        }) => {
+            let (name, creds, region) = match &opts.from_session_file {
+                Some(path) => {
+                    let descriptor = read_session_file(path, &profiles, settings.session_pinning).await?;
+                    (descriptor.profile.clone(), Some(descriptor.credentials()), descriptor.region.clone())
+                }
+                None => {
+                    let name = resolve_profile_name(profile, no_interactive, &profiles)?;
+                    record_use(&name).await;
+                    let creds = perform_authentication(&profiles, &name, no_interactive, &settings).await?;
+                    progress::finish();
+                    let region = profiles.get(&name).and_then(|p| p.region.clone());
+                    (name, creds, region)
+                }
+            };
+            if let Some(payload) = qr {
+                // A QR code always carries a short-lived session, never static keys.
+                let descriptor = match (&opts.from_session_file, profiles.get(&name)) {
+                    (Some(path), _) => read_session_file(path, &profiles, settings.session_pinning).await?,
+                    (None, Some(profile)) => session_descriptor(profile, creds).await?,
+                    (None, None) => return Err(anyhow!("Profile '{}' not found", name)),
+                };
+                return export_qr(&descriptor, payload);
+            }
+            let lines = export_lines(&name, creds.as_ref(), region.as_deref());
+            if !clipboard {
+                let expiration = creds.as_ref().map(|c| c.expiration.as_str());
+                if !divert_exports(&name, &lines, creds.is_some(), expiration)? {
+                    print!("{}", lines);
+                }
+                return Ok(());
+            }
+            let clear_after = clear_after.unwrap_or_else(|| settings.clipboard_clear_after());
+            export_to_clipboard(&name, &lines, clear_after).await
+        }
+        Some(SubCommand::Login {
+            profile,
+            no_interactive,
+            rclone,
+            mc,
+            all_sso,
+        }) => {
+            if all_sso {
+                return login_all_sso(&profiles, no_interactive, &settings).await;
+            }
+            if let Some(path) = &opts.from_session_file {
+                let descriptor = read_session_file(path, &profiles, settings.session_pinning).await?;
+                let mut lines = format!("# Session for profile '{}' from {}\n", descriptor.profile, path.display());
+                if profiles.contains_key(&descriptor.profile) {
+                    lines.push_str(&format!("export AWS_PROFILE={}\n", descriptor.profile));
+                }
+                lines.push_str(&format!("export AWS_ACCESS_KEY_ID={}\n", descriptor.access_key_id));
+                lines.push_str(&format!("export AWS_SECRET_ACCESS_KEY={}\n", descriptor.secret_access_key));
+                lines.push_str(&format!("export AWS_SESSION_TOKEN={}\n", descriptor.session_token));
+                if let Some(region) = &descriptor.region {
+                    lines.push_str(&format!("export AWS_DEFAULT_REGION={}\n", region));
+                }
+                lines.push_str(&storage_exports(&descriptor, rclone.as_deref(), mc.as_deref()));
+                let expiration = descriptor.expiration.to_rfc3339();
+                if !divert_exports(&descriptor.profile, &lines, true, Some(&expiration))? {
+                    print!("{}", lines);
+                }
+                return Ok(());
+            }
+            let selected_profile_name = resolve_profile_name(profile, no_interactive, &profiles)?;
+            record_use(&selected_profile_name).await;
+
+            let final_creds = authenticate_for(&profiles, &selected_profile_name, no_interactive, &settings).await?;
+            if let (Some(creds), Some(needs)) = (&final_creds, settings.needs(&selected_profile_name)) {
+                warn_if_outlived(&selected_profile_name, &creds.expiration, needs);
+            }
+            if let Some(path) = &opts.session_file {
+                write_session_file(path, &profiles[&selected_profile_name], final_creds.clone(), settings.session_pinning).await?;
+            }
+            // Object-storage tools get a session even for static profiles.
+            let storage = if rclone.is_some() || mc.is_some() {
+                Some(session_descriptor(&profiles[&selected_profile_name], final_creds.clone()).await?)
+            } else {
+                None
+            };
+
+            // Output environment variables for shell evaluation
+            println!("{}", t(Msg::CredentialsReady, &[("profile", &selected_profile_name)]));
+            let region = profiles.get(&selected_profile_name).and_then(|p| p.region.as_deref());
+            let mut lines = export_lines(&selected_profile_name, final_creds.as_ref(), region);
+            if let Some(descriptor) = &storage {
+                lines.push_str(&storage_exports(descriptor, rclone.as_deref(), mc.as_deref()));
+            }
+            let secrets = final_creds.is_some() || storage.is_some();
+            let expiration = final_creds.as_ref().map(|c| c.expiration.clone()).or(storage.map(|d| d.expiration.to_rfc3339()));
+            if !divert_exports(&selected_profile_name, &lines, secrets, expiration.as_deref())? {
+                let green = theme::style(Role::Success);
+                println!("{}\n", green.apply_to(t(Msg::CopyExports, &[])));
+                print!("{}", lines);
+            }
+            Ok(())
+        }
+        None => {
+            // Default Run behavior
+            let profile = opts.profile;
+            let config = opts.config;
+            let clear_cache = opts.clear_cache;
+            let no_interactive = opts.no_interactive;
             let aws_args = opts.aws_args;
             if config {
-                print_config(&profiles).await?;
+                let filter = badge::filter();
+                print!(
+                    "{}",
+                    output::render(|| render::config(&profiles, &filter), || output::config(&profiles, &filter))?
+                );
                 return Ok(());
             }
 
-            if let Some(target) = clear_cache.as_deref() {
-                // MVP: cache not implemented yet; stub behavior
-                println!("Clearing cache for: {} (no-op in MVP)", target);
+            if let Some(selector) = &clear_cache {
+                let awx_dir = settings::awx_dir()?;
+                let plan = clear::plan(selector, &profiles, &aws_dir()?, &awx_dir, Utc::now())?;
+                let forgotten = plan.profiles.len();
+                let (removed, broker_reached) = clear::apply(plan);
+                print!("{}", render::cache_clear(&awx_dir, &aws_dir()?, selector, &removed, broker_reached.then_some(forgotten)));
                 return Ok(());
             }
 
-            // resolve profile precedence: CLI > AWS_PROFILE env > default
-            let selected_profile_name = if let Some(p) = profile {
-                p
-            } else if no_interactive {
-                env::var("AWS_PROFILE").unwrap_or_else(|_| "default".to_string())
-            } else {
-                // interactive selection
-                interactive_select_profile(&profiles)?
+            if opts.explain {
+                let (name, source) = match profile {
+                    Some(p) if profile_from_context => {
+                        let source = aws_env::Source::Context(context::for_profile(&p).map(|a| a.name.clone()).unwrap_or_default());
+                        (p, source)
+                    }
+                    Some(p) => (p, aws_env::Source::Flag("--profile")),
+                    None if !no_interactive => (interactive_select_profile(&profiles)?, aws_env::Source::Selected),
+                    None => aws_env::env_profile(&aws_env::current_lookup)
+                        .unwrap_or_else(|| ("default".to_string(), aws_env::Source::Default)),
+                };
+                let files = aws_env::AwsFiles::resolve(&aws_env::current_lookup, &aws_dir()?);
+                let lookup = aws_env::current_lookup;
+                print!(
+                    "{}",
+                    render::explain(&profiles, &name, source, &files, &aws_args, &lookup, Utc::now().timestamp())?
+                );
+                return Ok(());
+            }
+
+            if let Some(path) = &opts.from_session_file {
+                if aws_args.is_empty() {
+                    println!("No AWS command specified. Use -- to pass AWS CLI arguments.");
+                    return Ok(());
+                }
+                let exit_code = run_from_session_file(path, &profiles, &aws_args, opts.record, settings.session_pinning).await?;
+                exit(exit_code);
+            }
+
+            let reused = session::reusable_for(inherited.as_ref(), profile.as_deref())
+                .filter(|name| inherited_session_lasts(inherited.as_ref(), settings.needs(name)));
+            let selected_profile_name = match &reused {
+                Some(name) => name.clone(),
+                None => resolve_profile_name(profile, no_interactive, &profiles)?,
             };
+            record_use(&selected_profile_name).await;
 
             let profile = profiles
                 .get(&selected_profile_name)
                 .ok_or_else(|| anyhow!("Profile '{}' not found", selected_profile_name))?
                 .clone();
 
-            if profile.is_sso() {
-                match check_sts_identity(&selected_profile_name).await {
-                    Ok(true) => {
-                        // logged in, proceed
-                    }
-                    Ok(false) => {
-                        if no_interactive {
-                            eprintln!(
-                                "SSO login required for profile \"{}\". Run: aws sso login --profile {}",
-                                selected_profile_name, selected_profile_name
-                            );
-                            std::process::exit(2);
-                        }
-                        println!(
-                            "SSO token is not valid. Running: aws sso login --profile {}",
-                            selected_profile_name
-                        );
-                        let status = Command::new("aws")
-                            .arg("sso")
-                            .arg("login")
-                            .arg("--profile")
-                            .arg(&selected_profile_name)
-                            .status()
-                            .await
-                            .context("Failed to run aws sso login")?;
-                        if !status.success() {
-                            return Err(anyhow!("aws sso login failed"));
-                        }
-                        println!("SSO login completed.");
-                    }
-                    Err(_) => {
-                        // timeout or network issues -> treat as not logged in
-                        if no_interactive {
-                            eprintln!(
-                                "SSO login required for profile \"{}\". Run: aws sso login --profile {}",
-                                selected_profile_name, selected_profile_name
-                            );
-                            std::process::exit(2);
-                        }
-                    }
+            let final_creds = if reused.is_some() {
+                // the inherited environment already carries this profile's credentials
+                None
+            } else {
+                authenticate_for(&profiles, &selected_profile_name, no_interactive, &settings).await?
+            };
+
+            if let (Some(creds), Some(needs)) = (&final_creds, settings.needs(&profile.name)) {
+                warn_if_outlived(&profile.name, &creds.expiration, needs);
+            }
+            if let Some(path) = &opts.session_file {
+                write_session_file(path, &profile, final_creds.clone(), settings.session_pinning).await?;
+                if aws_args.is_empty() {
+                    return Ok(());
                 }
             }
 
-            // Resolve credentials (MVP supports single assume-role step and MFA for static creds)
-            let final_creds = if profile.is_role() {
-                // Find base credentials from source_profile
-                let role_arn = profile.role_arn.clone().unwrap();
-                let source_name = profile
-                    .source_profile
-                    .clone()
-                    .ok_or_else(|| anyhow!("source_profile missing for role profile"))?;
-
-                let base_profile = profiles
-                    .get(&source_name)
-                    .ok_or_else(|| anyhow!("source_profile '{}' not found", source_name))?
-                    .clone();
-
-                // If base_profile needs MFA + static keys
-                    if base_profile.requires_mfa() && base_profile.is_static() {
-                    let mfa = base_profile.mfa_serial.clone().unwrap();
-                    let base_temp = get_session_token_interactive(&source_name, &mfa).await?;
-                    // use base_temp credentials in env to call assume-role
-                    let session_name = format!("awx-{}", Utc::now().timestamp());
-                    let assume_resp = assume_role_with_env(&role_arn, &session_name, &base_temp).await?;
-                    Some(assume_resp)
-                } else if base_profile.is_sso() {
-                    // let aws CLI handle using --profile <source_profile>
-                    let session_name = format!("awx-{}", Utc::now().timestamp());
-                    let assume_resp = assume_role_with_profile(&role_arn, &session_name, &source_name).await?;
-                    Some(assume_resp)
-                } else if base_profile.is_static() {
-                    // static keys -> ask aws cli to assume using the source_profile
-                    let session_name = format!("awx-{}", Utc::now().timestamp());
-                    let assume_resp = assume_role_with_profile(&role_arn, &session_name, &source_name).await?;
-                    Some(assume_resp)
-                } else {
-                    return Err(anyhow!(
-                        "Unsupported source_profile auth method for '{}'. MVP supports SSO or static+MFA for source profiles.",
-                        source_name
-                    ));
-                }
-            } else if profile.requires_mfa() && profile.is_static() {
-                // Prompt for MFA for the profile's static keys
-                let mfa = profile.mfa_serial.clone().unwrap();
-                let tmp = get_session_token_interactive(&profile.name, &mfa).await?;
-                Some(tmp)
-            } else {
-                // static-only or SSO-only (no credential injection needed)
-                None
+            let aws_args = match context::for_profile(&profile.name) {
+                Some(active) if aws_args.is_empty() => active.settings.command.clone(),
+                _ => aws_args,
             };
-
             // Execute aws command with credentials injected into environment (if any)
             if aws_args.is_empty() {
                 println!("No AWS command specified. Use -- to pass AWS CLI arguments.");
                 return Ok(());
             }
 
-            let exit_code = run_aws_child_capture(&aws_args, final_creds, profile).await?;
+            confirm_context(&profile.name, no_interactive)?;
+            let mut profile = profile;
+            ask_missing_region(&mut profile, &aws_args, no_interactive)?;
+            if spend::wanted(&settings.spend, &profile.name) && !offline::is_offline() {
+                let now = Utc::now();
+                let aws = || child_command("aws", &[], final_creds.clone(), &profile);
+                let (entry, looked_up) = spend::current(&settings::awx_dir()?, &profile.name, aws, settings.spend_refresh(), now).await;
+                eprint!("{}", render::spend(&profile.name, &entry, looked_up, now));
+            }
+            let exit_code = if opts.record {
+                let cmd = child_command("aws", &aws_args, final_creds, &profile);
+                record::run(cmd, &profile.name, &aws_args).await?
+            } else {
+                run_child_capture("aws", &aws_args, final_creds, profile).await?
+            };
             // Forward child exit code for CLI behavior
-            std::process::exit(exit_code);
+            exit(exit_code);
+        }
+    }
+}
+
+// The `export` lines that put `profile`'s session into a shell.
+fn export_lines(profile: &str, creds: Option<&StsCredentials>, region: Option<&str>) -> String {
+    let mut lines = format!("export AWS_PROFILE={}\n", profile);
+    if let Some(creds) = creds {
+        lines.push_str(&format!("export AWS_ACCESS_KEY_ID={}\n", creds.access_key_id));
+        lines.push_str(&format!("export AWS_SECRET_ACCESS_KEY={}\n", creds.secret_access_key));
+        lines.push_str(&format!("export AWS_SESSION_TOKEN={}\n", creds.session_token));
+    }
+    if let Some(region) = region {
+        lines.push_str(&format!("export AWS_DEFAULT_REGION={}\n", region));
+    }
+    lines
+}
+
+// Copy `lines` to the clipboard, wait `clear_after` and clear it again unless something
+// else was copied meanwhile. Ctrl-C clears at once.
+async fn export_to_clipboard(profile: &str, lines: &str, clear_after: chrono::Duration) -> Result<()> {
+    let tools = clipboard::candidates(std::env::consts::OS, &aws_env::current_lookup);
+    let tool = clipboard::copy(&tools, lines).await?;
+    let _waiting = interrupt::child_running();
+    eprintln!(
+        "Copied the exports for '{}' to the clipboard; it is cleared in {} (Ctrl-C clears it now).",
+        profile,
+        render::duration(clear_after)
+    );
+    let wait = tokio::time::sleep(clear_after.to_std().unwrap_or_default());
+    tokio::select! {
+        _ = wait => {}
+        _ = tokio::signal::ctrl_c() => {}
+    }
+    if clipboard::clear_if_unchanged(tool, lines).await? {
+        eprintln!("Clipboard cleared.");
+    } else {
+        eprintln!("The clipboard holds something else now; left as is.");
+    }
+    Ok(())
+}
+
+// Draw `descriptor` as a QR code on stdout, with what it holds and how long it lasts.
+fn export_qr(descriptor: &session::Descriptor, payload: qr::Payload) -> Result<()> {
+    let code = qr::Code::encode(qr::payload(payload, descriptor)?.as_bytes())?;
+    print!("{}", code.render());
+    eprintln!(
+        "Session for '{}' as a QR code ({}x{} modules); it expires {}. Clear the screen once it has been scanned.",
+        descriptor.profile,
+        code.size(),
+        code.size(),
+        render::expiration_colored(descriptor.expiration, Utc::now())
+    );
+    Ok(())
+}
+
+// When `lines` carry secrets and stdout is a terminal without --reveal, write them to
+// a private file instead and say how to load it (see `reveal`). Returns whether they
+// were diverted; otherwise the caller prints them.
+fn divert_exports(profile: &str, lines: &str, secrets: bool, expiration: Option<&str>) -> Result<bool> {
+    if !secrets || !reveal::guarded() {
+        return Ok(false);
+    }
+    let expiration = expiration
+        .and_then(|e| chrono::DateTime::parse_from_rfc3339(e).ok())
+        .map(|e| e.with_timezone(&Utc));
+    let path = reveal::divert(&settings::awx_dir()?, profile, lines, expiration)?;
+    eprintln!(
+        "stdout is a terminal, so the exports for '{}' were written to {} (readable only by you) instead of shown.",
+        profile,
+        path.display()
+    );
+    eprintln!("Load them with `source {}`, or print them with --reveal.", path.display());
+    Ok(true)
+}
+
+// The `login --rclone` / `--mc` exports for `descriptor`.
+fn storage_exports(descriptor: &session::Descriptor, rclone: Option<&str>, mc: Option<&str>) -> String {
+    let creds = descriptor.credentials();
+    let region = descriptor.region.as_deref();
+    let mut vars = Vec::new();
+    if let Some(remote) = rclone {
+        vars.extend(tools::rclone_remote(remote, &creds, region));
+    }
+    if let Some(alias) = mc {
+        vars.push(tools::mc_host(alias, &creds, region));
+    }
+    vars.into_iter().map(|(key, value)| format!("export {}={}\n", key, value)).collect()
+}
+
+// Use the active context's profile where the command names none. Returns whether it did.
+fn apply_context_profile(opts: &mut Opt, context_profile: &str) -> bool {
+    let profile = match &mut opts.command {
+        None | Some(SubCommand::Exec { .. }) => &mut opts.profile,
+        Some(SubCommand::Login { profile, .. })
+        | Some(SubCommand::Whoami { profile, .. })
+        | Some(SubCommand::Trail { profile, .. })
+        | Some(SubCommand::Curl { profile, .. })
+        | Some(SubCommand::S3express {
+            action: S3ExpressCommand::Session { profile, .. },
+        })
+        | Some(SubCommand::Compose { profile, .. })
+        | Some(SubCommand::Tool { profile, .. })
+        | Some(SubCommand::LsRoles { profile, .. })
+        | Some(SubCommand::CanI { profile, .. })
+        | Some(SubCommand::CredentialChain { profile, .. })
+        | Some(SubCommand::Warm { profile, .. })
+        | Some(SubCommand::Check { profile, .. })
+        | Some(SubCommand::Export { profile, .. })
+        | Some(SubCommand::Pair {
+            action: Some(PairCommand::Approve { profile, .. }),
+            ..
+        }) => profile,
+        _ => return false,
+    };
+    if profile.is_some() {
+        return false;
+    }
+    *profile = Some(context_profile.to_string());
+    true
+}
+
+// A context with `confirm = true` asks before each command run with its profile.
+fn confirm_context(profile: &str, no_interactive: bool) -> Result<()> {
+    let Some(active) = context::for_profile(profile).filter(|a| a.settings.confirm) else {
+        return Ok(());
+    };
+    if no_interactive {
+        return Err(anyhow!(t(
+            Msg::ContextNeedsConfirmation,
+            &[("context", &active.name), ("profile", profile)]
+        )));
+    }
+    let banner = format!(" {} ", active.name.to_uppercase());
+    eprintln!("{}", theme::style(Role::Banner).for_stderr().apply_to(banner));
+    let run = ask::confirm(ask::Key("run_in_context", Some(profile)), &t(Msg::ConfirmContext, &[("profile", profile), ("context", &active.name)]), false)?;
+    if !run {
+        return Err(anyhow!(t(Msg::Cancelled, &[])));
+    }
+    Ok(())
+}
+
+fn run_ctx_command(action: Option<&CtxCommand>) -> Result<()> {
+    let settings = settings::load_settings()?;
+    let state = context::state_path()?;
+    match action {
+        None => {
+            let active = context::active_name(&aws_env::current_lookup, &state);
+            print!("{}", render::contexts(&settings.contexts, active.as_deref()));
+        }
+        Some(CtxCommand::Use { name }) => {
+            let context = settings.contexts.get(name).ok_or_else(|| {
+                anyhow!("Unknown context '{}' (define it under [contexts.{}] in ~/.awx/config.toml)", name, name)
+            })?;
+            context::save(&state, Some(name))?;
+            println!("Switched to context '{}' (profile {}).", name, context.profile);
+            if aws_env::current_lookup(context::CONTEXT_ENV).is_some() {
+                eprintln!("Note: {} is set and takes precedence in this shell.", context::CONTEXT_ENV);
+            }
+        }
+        Some(CtxCommand::Current) => match context::active_name(&aws_env::current_lookup, &state) {
+            Some(name) => println!("{}", name),
+            None => return Err(anyhow!("No context is active")),
+        },
+        Some(CtxCommand::Clear) => {
+            context::save(&state, None)?;
+            println!("No context is active.");
+        }
+    }
+    Ok(())
+}
+
+fn run_broker_command(action: Option<&BrokerCommand>) -> Result<()> {
+    let awx_dir = settings::awx_dir()?;
+    let socket = broker::socket_path(&awx_dir);
+    let request = match action {
+        None => {
+            let acl = settings::load_settings()?.broker_acl;
+            eprintln!(
+                "Holding sessions in memory on {} (stop with `awx broker stop`; kill -USR1 {pid} forgets them all, kill -USR2 {pid} prints status)",
+                socket.display(),
+                pid = std::process::id()
+            );
+            return broker::serve(&socket, &acl, &broker::audit_path(&awx_dir));
+        }
+        Some(BrokerCommand::Status) => broker::Request::List,
+        Some(BrokerCommand::Forget { profile }) => broker::Request::Forget { profile: profile.clone() },
+        Some(BrokerCommand::Stop) => broker::Request::Stop,
+    };
+    let response = broker::call(&socket, &request)?;
+    match action {
+        Some(BrokerCommand::Status) => {
+            let now = Utc::now();
+            let human = || {
+                if response.sessions.is_empty() {
+                    return Ok("The broker holds no sessions.\n".to_string());
+                }
+                Ok(response
+                    .sessions
+                    .iter()
+                    .map(|held| format!("{}  {}\n", held.key(), render::expiration(held.expiration, now)))
+                    .collect())
+            };
+            print!("{}", output::render(human, || output::broker_status(&response.sessions))?);
+        }
+        Some(BrokerCommand::Forget { profile }) => println!("Forgot the session of '{}'.", profile),
+        _ => println!("Broker stopped."),
+    }
+    Ok(())
+}
+
+fn run_hook_env(shell: hook::Shell) -> Result<()> {
+    let settings = settings::load_settings()?;
+    let lookup = aws_env::current_lookup;
+    // AWX_CONTEXT exported by the hook for a project must not count as chosen by the user.
+    let managed = hook::managed(&lookup);
+    let unmanaged = |name: &str| if managed.iter().any(|m| m == name) { None } else { lookup(name) };
+    let active = context::active_name(&unmanaged, &context::state_path()?);
+    let project = hook::find_project(&std::env::current_dir()?)?;
+    let desired = hook::desired(project.as_ref(), &settings, active.as_deref())?;
+    print!("{}", hook::script(shell, &hook::changes(&desired, &lookup)));
+    Ok(())
+}
+
+// A profile was selected for a run or login.
+async fn record_use(profile: &str) {
+    metrics::record(profile, metrics::Event::Use);
+    telemetry::root_attr("awx.profile", profile);
+    notify::send(notify::Event::ProfileUsed { profile }).await;
+}
+
+// Resolve credentials for `name` behind the step-by-step progress display, offering
+// to look into a failure.
+async fn authenticate_for(
+    profiles: &HashMap<String, Profile>,
+    name: &str,
+    no_interactive: bool,
+    settings: &settings::Settings,
+) -> Result<Option<StsCredentials>> {
+    let profile = profiles.get(name).ok_or_else(|| anyhow!("Profile '{}' not found", name))?;
+    let steps = progress::plan(profiles, profile, settings.identity_check(name));
+    progress::begin(name, &steps, settings.round_trip_warning());
+    match perform_authentication(profiles, name, no_interactive, settings).await {
+        Ok(creds) => {
+            progress::finish();
+            Ok(creds)
+        }
+        Err(e) if pipeline::exit_code(&e).is_some() => Err(e),
+        Err(e) => {
+            offer_troubleshooting(profiles, profile, &e, no_interactive).await;
+            Err(e)
         }
     }
 }
 
-async fn perform_authentication(profiles: &HashMap<String, Profile>, selected_profile_name: &str, no_interactive: bool) -> Result<Option<StsCredentials>> {
+// After a failed resolution, offer to look into the cause when someone is at the terminal.
+async fn offer_troubleshooting(
+    profiles: &HashMap<String, Profile>,
+    profile: &Profile,
+    err: &anyhow::Error,
+    no_interactive: bool,
+) {
+    if no_interactive || !console::Term::stderr().is_term() {
+        return;
+    }
+    progress::finish();
+    let run = ask::confirm(ask::Key("troubleshoot", Some(&profile.name)), &t(Msg::LookIntoFailure, &[("profile", &profile.name)]), true);
+    let (Ok(true), Ok(dir)) = (run, aws_dir()) else {
+        return;
+    };
+    let diagnosis = troubleshoot::diagnose(profiles, profile, err, &dir).await;
+    eprint!("{}", render::diagnosis(&diagnosis));
+}
+
+// Whether an inherited session lasts as long as the command needs; if not, say so and
+// resolve a fresh one.
+fn inherited_session_lasts(marker: Option<&session::Marker>, needs: Option<chrono::Duration>) -> bool {
+    let (Some(marker), Some(needs)) = (marker, needs) else {
+        return true;
+    };
+    let Some(expiration) = marker.expiration else {
+        return true;
+    };
+    if session::shortfall(expiration, needs, Utc::now()).is_none() {
+        return true;
+    }
+    eprintln!(
+        "{}",
+        theme::style(Role::Muted).apply_to(format!(
+            "Inherited session for '{}' expires {}, before the {} this command needs; resolving a fresh one.",
+            marker.profile,
+            render::relative(expiration - Utc::now()),
+            render::duration(needs)
+        ))
+    );
+    false
+}
+
+// Warn before starting a command that will outlive its credentials.
+fn warn_if_outlived(profile: &str, expiration: &str, needs: chrono::Duration) {
+    let Ok(expiration) = chrono::DateTime::parse_from_rfc3339(expiration) else {
+        return;
+    };
+    let expiration = expiration.with_timezone(&Utc);
+    let now = Utc::now();
+    let Some(short) = session::shortfall(expiration, needs, now) else {
+        return;
+    };
+    eprintln!(
+        "{}",
+        theme::style(Role::Warning).apply_to(format!(
+            "Warning: credentials for '{}' expire {}, {} before the {} this command needs. It may fail partway through.",
+            profile,
+            render::expiration(expiration, now),
+            render::duration(short),
+            render::duration(needs)
+        ))
+    );
+}
+
+async fn perform_authentication(
+    profiles: &HashMap<String, Profile>,
+    selected_profile_name: &str,
+    no_interactive: bool,
+    settings: &settings::Settings,
+) -> Result<Option<StsCredentials>> {
+    let run = pipeline::Run::cli()
+        .mfa_attempts(settings.mfa_attempts())
+        .keep_mfa_sessions_in(settings::awx_dir()?);
+    authenticate_with(profiles, selected_profile_name, no_interactive, settings, &run).await
+}
+
+// `perform_authentication` reporting to the observers of `run`.
+async fn authenticate_with(
+    profiles: &HashMap<String, Profile>,
+    selected_profile_name: &str,
+    no_interactive: bool,
+    settings: &settings::Settings,
+    run: &pipeline::Run,
+) -> Result<Option<StsCredentials>> {
     let profile = profiles
         .get(selected_profile_name)
         .ok_or_else(|| anyhow!("Profile '{}' not found", selected_profile_name))?
         .clone();
-
-    if profile.is_sso() {
-        match check_sts_identity(&selected_profile_name).await {
-            Ok(true) => {
-                // logged in, proceed
+    if let Some(creds) = broker::get(selected_profile_name) {
+        progress::advance(&format!("use the session the broker holds for {}", selected_profile_name));
+        return Ok(Some(creds));
+    }
+    secrets::prepare(profiles, &profile).await?;
+    if let Some(warning) = default_profile::warning(&profile) {
+        eprintln!("{}", theme::style(Role::Warning).apply_to(warning));
+    }
+    match pipeline::authenticate(profiles, &profile, settings, no_interactive, run).await {
+        Ok(creds) => {
+            if let Some(creds) = &creds {
+                broker::put(selected_profile_name, creds);
             }
-            Ok(false) => {
-                if no_interactive {
-                    return Err(anyhow!(
-                        "SSO login required for profile \"{}\". Run: aws sso login --profile {}",
-                        selected_profile_name, selected_profile_name
-                    ));
-                }
-                println!(
-                    "SSO token is not valid. Running: aws sso login --profile {}",
-                    selected_profile_name
-                );
-                let status = Command::new("aws")
-                    .arg("sso")
-                    .arg("login")
-                    .arg("--profile")
-                    .arg(&selected_profile_name)
-                    .status()
-                    .await
-                    .context("Failed to run aws sso login")?;
-                if !status.success() {
-                    return Err(anyhow!("aws sso login failed"));
-                }
-                println!("SSO login completed.");
+            Ok(creds)
+        }
+        Err(e) => {
+            metrics::record(selected_profile_name, metrics::Event::AuthFailure);
+            Err(e)
+        }
+    }
+}
+
+fn role_session_name(profile: &Profile) -> String {
+    aws_env::role_session_name(&aws_env::current_lookup, profile, Utc::now().timestamp()).0
+}
+
+/// AssumeRole duration without `--duration` or `duration_seconds` (the AWS CLI default).
+const DEFAULT_ROLE_DURATION_SECS: i64 = 3600;
+/// AWS caps sessions of roles assumed with role credentials (role chaining) at 1 hour.
+const CHAINED_ROLE_MAX_SECS: i64 = 3600;
+
+struct RoleDuration {
+    secs: i64,
+    /// The `duration_seconds` the role-chaining cap replaced.
+    capped_from: Option<i64>,
+}
+
+// The duration to request when assuming `profile`'s role. Asking for more than an hour
+// on a chained role fails at STS, so the request is capped here instead.
+fn role_duration(profiles: &HashMap<String, Profile>, profile: &Profile) -> RoleDuration {
+    let requested = duration::requested()
+        .or(profile.duration_seconds)
+        .unwrap_or(DEFAULT_ROLE_DURATION_SECS);
+    if is_role_chained(profiles, profile) && requested > CHAINED_ROLE_MAX_SECS {
+        return RoleDuration {
+            secs: CHAINED_ROLE_MAX_SECS,
+            capped_from: Some(requested),
+        };
+    }
+    RoleDuration {
+        secs: requested,
+        capped_from: None,
+    }
+}
+
+// The duration to request for `profile`'s role: `role_duration`, unless
+// `--choose-duration` asks for a pick among the lengths the role allows. The role's
+// maximum is read with the credentials of profile `iam_as`, when given.
+async fn session_duration(
+    profiles: &HashMap<String, Profile>,
+    profile: &Profile,
+    role_arn: &str,
+    iam_as: Option<&str>,
+    no_interactive: bool,
+) -> Result<i64> {
+    let requested = role_duration(profiles, profile).secs;
+    if !duration::chooses() {
+        return Ok(requested);
+    }
+    let role_max = match iam_as {
+        Some(source) if !offline::is_offline() => {
+            progress::advance(&format!("read the maximum session duration of {}", role_arn));
+            iam::max_session_duration(iam::as_profile(source), &arn::parse_role(role_arn)?).await.ok()
+        }
+        _ => None,
+    };
+    let options = duration::options(duration::upper_bound(role_max, is_role_chained(profiles, profile)));
+    let awx_dir = settings::awx_dir()?;
+    let remembered = duration::load_remembered(&awx_dir).get(&profile.name).copied();
+    if no_interactive {
+        return Ok(remembered.filter(|r| options.contains(r)).unwrap_or(requested));
+    }
+    let labels: Vec<String> = options.iter().map(|&secs| duration::label(secs)).collect();
+    let default = duration::preselect(&options, remembered, requested);
+    progress::clear();
+    let picked = options[ask::select(ask::Key("duration", Some(&profile.name)), &format!("Session duration for {}", profile.name), &labels, default)?];
+    duration::remember(&awx_dir, &profile.name, picked)?;
+    Ok(picked)
+}
+
+// Whether `profile`'s role is assumed with role credentials: those of an SSO permission
+// set, of another role or of a container or instance role. Session tokens from
+// GetSessionToken do not count.
+fn is_role_chained(profiles: &HashMap<String, Profile>, profile: &Profile) -> bool {
+    if profile.source_profile.is_none() {
+        let ambient = profile.credential_source.as_deref().and_then(|s| ambient::Source::parse(s).ok());
+        return ambient.is_some_and(ambient::Source::is_role);
+    }
+    profile
+        .source_profile
+        .as_ref()
+        .and_then(|s| profiles.get(s))
+        .map(|base| base.is_sso() || base.is_role())
+        .unwrap_or(false)
+}
+
+// Write a session descriptor for `profile`. Profiles that do not yield an STS session
+// on their own get one: exported from the SSO cache, or a plain GetSessionToken for
+// static keys, so the file never contains long-lived keys.
+async fn write_session_file(
+    path: &std::path::Path,
+    profile: &Profile,
+    creds: Option<StsCredentials>,
+    pinning: session::Pinning,
+) -> Result<()> {
+    let mut descriptor = session_descriptor(profile, creds).await?;
+    pin_session(&mut descriptor, profile, pinning).await;
+    descriptor.write(path)?;
+    eprintln!(
+        "Session for '{}' written to {} (expires {})",
+        profile.name,
+        path.display(),
+        render::expiration_colored(descriptor.expiration, Utc::now())
+    );
+    Ok(())
+}
+
+async fn session_descriptor(profile: &Profile, creds: Option<StsCredentials>) -> Result<session::Descriptor> {
+    let creds = match creds {
+        Some(c) => c,
+        None if profile.is_static() => get_plain_session_token(&profile.name).await?,
+        None => export_credentials(&profile.name).await?,
+    };
+    session::Descriptor::new(&profile.name, profile.region.clone(), &creds, Utc::now())
+}
+
+// Record the identity `descriptor`'s credentials resolve to, when sessions are pinned.
+// A session that cannot be pinned now is pinned on its first check instead.
+async fn pin_session(descriptor: &mut session::Descriptor, profile: &Profile, pinning: session::Pinning) {
+    if pinning == session::Pinning::Never || offline::is_offline() {
+        return;
+    }
+    match session_identity(Some(descriptor.credentials()), profile).await {
+        Ok(identity) => {
+            let _ = descriptor.confirm_identity(&identity.arn, Utc::now());
+        }
+        Err(e) => eprintln!(
+            "{}",
+            theme::style(Role::Muted).apply_to(format!("Session for '{}' not pinned to its identity: {:#}", profile.name, e))
+        ),
+    }
+}
+
+// Read the session file at `path` and, when `pinning` says a check is due, confirm its
+// credentials still resolve to the pinned identity. The time of the check is written
+// back when the file is writable. Offline runs skip the check.
+async fn read_session_file(
+    path: &std::path::Path,
+    profiles: &HashMap<String, Profile>,
+    pinning: session::Pinning,
+) -> Result<session::Descriptor> {
+    let mut descriptor = session::Descriptor::read(path, Utc::now())?;
+    if !descriptor.check_due(pinning, Utc::now()) || offline::is_offline() {
+        return Ok(descriptor);
+    }
+    let known = profiles.get(&descriptor.profile);
+    let mut profile = known.cloned().unwrap_or_else(|| Profile {
+        name: descriptor.profile.clone(),
+        ..Default::default()
+    });
+    profile.region = descriptor.region.clone().or(profile.region);
+    let args = ["sts", "get-caller-identity", "--output", "json"].map(String::from);
+    let mut cmd = child_command("aws", &args, Some(descriptor.credentials()), &profile);
+    if known.is_none() {
+        cmd.env_remove("AWS_PROFILE");
+    }
+    let identity = caller_identity_output(cmd)
+        .await
+        .with_context(|| format!("The session for '{}' in {} no longer resolves", descriptor.profile, path.display()))?;
+    descriptor.confirm_identity(&identity.arn, Utc::now())?;
+    let _ = descriptor.write(path);
+    Ok(descriptor)
+}
+
+// Resolve `name` unattended into the session file at `path`, unless the one there
+// still lasts `min_remaining` (and, when pinned, still resolves to its identity). The
+// stages of the resolution end up in the report; `limit` cancels them when it passes.
+async fn run_warm(
+    profiles: &HashMap<String, Profile>,
+    name: &str,
+    path: &std::path::Path,
+    min_remaining: chrono::Duration,
+    limit: Option<chrono::Duration>,
+    settings: &settings::Settings,
+) -> warm::Report {
+    if let Some(saved) = warm::reusable(path, name, min_remaining, Utc::now()) {
+        match read_session_file(path, profiles, settings.session_pinning).await {
+            Ok(_) => return warm::Report::ready(name, warm::Method::Cached, path, saved.expiration, Utc::now()),
+            Err(e) => eprintln!("{}", theme::style(Role::Muted).apply_to(format!("{:#}", e))),
+        }
+    }
+    let profile = &profiles[name];
+    let method = warm::method(profiles, profile);
+    let (run, mut stages) = pipeline::Run::channel();
+    let cancel = pipeline::Cancel::default();
+    let run = run.cancelled_by(cancel.clone()).mfa_attempts(settings.mfa_attempts());
+    let deadline = limit.and_then(|l| l.to_std().ok()).map(|limit| {
+        tokio::spawn(async move {
+            tokio::time::sleep(limit).await;
+            cancel.cancel();
+        })
+    });
+    let resolved = async {
+        let creds = match method {
+            warm::Method::Mfa => return Err(anyhow!("'{}' needs an MFA code, which cannot be entered unattended", name)),
+            // awx cannot resolve these itself; the AWS CLI can.
+            warm::Method::AwsCli => Some(export_credentials(name).await?),
+            _ => authenticate_with(profiles, name, true, settings, &run).await?,
+        };
+        let mut descriptor = session_descriptor(profile, creds).await?;
+        pin_session(&mut descriptor, profile, settings.session_pinning).await;
+        descriptor.write(path)?;
+        Ok(descriptor)
+    };
+    let mut report = match resolved.await {
+        Ok(descriptor) => warm::Report::ready(name, method, path, descriptor.expiration, Utc::now()),
+        Err(e) => warm::Report::not_ready(name, method, path, format!("{:#}", e)),
+    };
+    if let Some(deadline) = deadline {
+        deadline.abort();
+    }
+    while let Ok(event) = stages.try_recv() {
+        report.stages.push(event);
+    }
+    report
+}
+
+// Headless side of `awx pair`: print the request, wait for the pasted response and
+// store the session it carries.
+async fn run_pair_request(output: Option<PathBuf>) -> Result<()> {
+    let awx_dir = settings::awx_dir()?;
+    let key = pair::pending_key_path(&awx_dir);
+    let public = pair::generate_keypair(&key).await?;
+    println!("Pairing code: {}", theme::style(Role::Heading).apply_to(pair::pairing_code(&public)));
+    println!("On a machine where you can log in, run:");
+    println!();
+    println!("  awx pair approve -p <profile> {}", pair::encode_request(&public));
+    println!();
+    println!("Then paste the response here:");
+    let mut line = String::new();
+    std::io::stdin().read_line(&mut line).context("Failed to read the pairing response")?;
+    let descriptor = pair::open(&key, &line).await?;
+    let _ = std::fs::remove_file(&key);
+    let dest = output.unwrap_or_else(|| warm::default_path(&awx_dir, &descriptor.profile, None));
+    descriptor.write(&dest)?;
+    println!(
+        "Session for '{}' saved to {} (expires {}).",
+        descriptor.profile,
+        dest.display(),
+        render::expiration_colored(descriptor.expiration, Utc::now())
+    );
+    println!("Use it with: awx --from-session-file {} -- <aws args>", dest.display());
+    Ok(())
+}
+
+// The aws command with the credentials of a session file, and the profile it runs as.
+async fn session_file_command(
+    path: &std::path::Path,
+    profiles: &HashMap<String, Profile>,
+    aws_args: &[String],
+    pinning: session::Pinning,
+) -> Result<(Command, Profile)> {
+    let descriptor = read_session_file(path, profiles, pinning).await?;
+    let known = profiles.get(&descriptor.profile);
+    let mut profile = known.cloned().unwrap_or_else(|| Profile {
+        name: descriptor.profile.clone(),
+        ..Default::default()
+    });
+    profile.region = descriptor.region.clone().or(profile.region);
+    let mut cmd = child_command("aws", aws_args, Some(descriptor.credentials()), &profile);
+    if known.is_none() {
+        cmd.env_remove("AWS_PROFILE");
+    }
+    Ok((cmd, profile))
+}
+
+// Run the aws command with the credentials of a session file. The profile does not
+// need to exist on this host; AWS_PROFILE is only set when it does.
+async fn run_from_session_file(
+    path: &std::path::Path,
+    profiles: &HashMap<String, Profile>,
+    aws_args: &[String],
+    record: bool,
+    pinning: session::Pinning,
+) -> Result<i32> {
+    use std::process::Stdio;
+
+    let (mut cmd, profile) = session_file_command(path, profiles, aws_args, pinning).await?;
+    if record {
+        return record::run(cmd, &profile.name, aws_args).await;
+    }
+    cmd.stdin(Stdio::inherit()).stdout(Stdio::inherit()).stderr(Stdio::inherit());
+    let child = cmd.spawn().context("failed to spawn aws child command")?;
+    child_span(wait_forwarding_signals(child)).await
+}
+
+// Resolve profile precedence: CLI > interactive selection, or in non-interactive mode
+// CLI > AWS_PROFILE > AWS_DEFAULT_PROFILE > default, unless `[default_profile]` says
+// `explicit = true`. In non-interactive mode with nothing to fall back on, emit a
+// profile input request and exit 2 instead of prompting.
+fn resolve_profile_name(
+    profile: Option<String>,
+    no_interactive: bool,
+    profiles: &HashMap<String, Profile>,
+) -> Result<String> {
+    if let Some(p) = profile {
+        return Ok(p);
+    }
+    let project = projects::current().filter(|(m, _)| profiles.contains_key(&m.profile));
+    if !no_interactive {
+        if let Some((mapping, reason)) = project {
+            if ask::confirm(ask::Key("use_project_profile", None), &format!("Use profile '{}' for {}?", mapping.profile, reason), true)? {
+                return Ok(mapping.profile.clone());
             }
-            Err(_) => {
-                // timeout or network issues -> treat as not logged in
-                if no_interactive {
-                    return Err(anyhow!(
-                        "SSO login required for profile \"{}\". Run: aws sso login --profile {}",
-                        selected_profile_name, selected_profile_name
-                    ));
-                }
-                println!(
-                    "SSO token is not valid. Running: aws sso login --profile {}",
-                    selected_profile_name
-                );
-                let status = Command::new("aws")
-                    .arg("sso")
-                    .arg("login")
-                    .arg("--profile")
-                    .arg(&selected_profile_name)
-                    .status()
-                    .await
-                    .context("Failed to run aws sso login")?;
-                if !status.success() {
-                    return Err(anyhow!("aws sso login failed"));
-                }
-                println!("SSO login completed.");
-            }
-        }
-    }
-
-    // Resolve credentials (MVP supports single assume-role step and MFA for static creds)
-    let final_creds = if profile.is_role() {
-        // Find base credentials from source_profile
-        let role_arn = profile.role_arn.clone().unwrap();
-        let source_name = profile
-            .source_profile
-            .clone()
-            .ok_or_else(|| anyhow!("source_profile missing for role profile"))?;
-
-        let base_profile = profiles
-            .get(&source_name)
-            .ok_or_else(|| anyhow!("source_profile '{}' not found", source_name))?
-            .clone();
-
-        // If base_profile needs MFA + static keys
-            if base_profile.requires_mfa() && base_profile.is_static() {
-            let mfa = base_profile.mfa_serial.clone().unwrap();
-            let base_temp = get_session_token_interactive(&source_name, &mfa).await?;
-            // use base_temp credentials in env to call assume-role
-            let session_name = format!("awx-{}", Utc::now().timestamp());
-            let assume_resp = assume_role_with_env(&role_arn, &session_name, &base_temp).await?;
-            Some(assume_resp)
-        } else if base_profile.is_sso() {
-            // let aws CLI handle using --profile <source_profile>
-            let session_name = format!("awx-{}", Utc::now().timestamp());
-            let assume_resp = assume_role_with_profile(&role_arn, &session_name, &source_name).await?;
-            Some(assume_resp)
-        } else if base_profile.is_static() {
-            // static keys -> ask aws cli to assume using the source_profile
-            let session_name = format!("awx-{}", Utc::now().timestamp());
-            let assume_resp = assume_role_with_profile(&role_arn, &session_name, &source_name).await?;
-            Some(assume_resp)
-        } else {
+        }
+        return interactive_select_profile(profiles);
+    }
+    if let Some((p, _)) = aws_env::env_profile(&aws_env::current_lookup) {
+        return Ok(p);
+    }
+    if let Some((mapping, _)) = project {
+        return Ok(mapping.profile.clone());
+    }
+    if profiles.contains_key(default_profile::NAME) {
+        if default_profile::implicit_allowed() {
+            return Ok(default_profile::NAME.to_string());
+        }
+        InputRequest::profile(badge::filter().names(profiles)).emit();
+        eprintln!("No profile specified, and [default_profile] explicit = true keeps awx from using 'default'. Pass --profile or set AWS_PROFILE.");
+        exit(2);
+    }
+    InputRequest::profile(badge::filter().names(profiles)).emit();
+    eprintln!("No profile specified. Pass --profile or set AWS_PROFILE.");
+    exit(2);
+}
+
+fn sso_login_request(profile: &Profile) -> InputRequest {
+    InputRequest::sso_login(
+        &profile.name,
+        profile.sso_start_url.as_deref(),
+        profile.sso_region.as_deref(),
+    )
+}
+
+async fn run_sso_login(profile: &Profile, settings: &settings::Settings) -> Result<()> {
+    let profile_name = profile.name.as_str();
+    metrics::record(profile_name, metrics::Event::SsoLogin);
+    let mut span = telemetry::span("sso login");
+    span.attr("awx.profile", profile_name);
+    progress::clear();
+    offline::ensure_online(&format!("SSO login for profile '{}'", profile_name))?;
+    let options = settings.sso_login_options(profile_name);
+    if settings.sso_login == settings::SsoLoginMode::Native {
+        let (Some(start_url), Some(region)) = (&profile.sso_start_url, &profile.sso_region) else {
             return Err(anyhow!(
-                "Unsupported source_profile auth method for '{}'. MVP supports SSO or static+MFA for source profiles.",
-                source_name
+                "Native SSO login needs sso_start_url and sso_region on profile '{}'",
+                profile_name
             ));
-        }
-    } else if profile.requires_mfa() && profile.is_static() {
-        // Prompt for MFA for the profile's static keys
-        let mfa = profile.mfa_serial.clone().unwrap();
-        let tmp = get_session_token_interactive(&profile.name, &mfa).await?;
-        Some(tmp)
-    } else {
-        // static-only or SSO-only (no credential injection needed)
-        None
-    };
+        };
+        println!("{}", t(Msg::SsoDeviceAuthorization, &[("start_url", start_url)]));
+        let open_url = !options.no_browser;
+        let session = profile.sso_session.as_deref();
+        sso::native_login(&settings::awx_dir()?, &aws_dir()?, start_url, session, region, open_url).await?;
+        println!("{}", t(Msg::SsoLoginCompleted, &[]));
+        return Ok(());
+    }
+    let extra = options.args();
+    let options: String = extra.iter().map(|a| format!(" {}", a)).collect();
+    println!("{}", t(Msg::SsoLoginRunning, &[("profile", profile_name), ("options", &options)]));
+    backend::sso().login(profile_name, &extra).await?;
+    println!("{}", t(Msg::SsoLoginCompleted, &[]));
+    Ok(())
+}
 
-    Ok(final_creds)
+// `awx login --all-sso`: log in to each identity without a valid token, one browser
+// flow at a time, and report the ones that failed at the end.
+async fn login_all_sso(profiles: &HashMap<String, Profile>, no_interactive: bool, settings: &settings::Settings) -> Result<()> {
+    let aws_dir = aws_dir()?;
+    let now = Utc::now();
+    let identities: Vec<(sso::Identity, bool)> = sso::identities(profiles)
+        .into_iter()
+        .map(|i| {
+            let signed_in = i.signed_in(&aws_dir, now);
+            (i, signed_in)
+        })
+        .collect();
+    print!("{}", render::sso_identities(&identities));
+    // The first profile logs in; sso-session profiles name the start URL only in their block.
+    let pending: Vec<(&sso::Identity, Profile)> = identities
+        .iter()
+        .filter(|(_, signed_in)| !signed_in)
+        .map(|(identity, _)| {
+            let profile = Profile {
+                sso_start_url: Some(identity.start_url.clone()),
+                sso_region: identity.region.clone(),
+                ..profiles[&identity.profiles[0]].clone()
+            };
+            (identity, profile)
+        })
+        .collect();
+    if pending.is_empty() {
+        return Ok(());
+    }
+    offline::ensure_online("SSO login")?;
+    if no_interactive {
+        for (_, profile) in &pending {
+            sso_login_request(profile).emit();
+        }
+        return Err(anyhow!("{} SSO login(s) required; run `awx login --all-sso` interactively", pending.len()));
+    }
+    let mut failed = Vec::new();
+    for (n, (identity, profile)) in pending.iter().enumerate() {
+        println!();
+        println!(
+            "{} {} (profile {})",
+            theme::style(Role::Heading).apply_to(format!("[{}/{}]", n + 1, pending.len())),
+            identity.start_url,
+            profile.name
+        );
+        if let Err(e) = run_sso_login(profile, settings).await {
+            // Outside `authenticate_with`, which counts the failures of runs.
+            metrics::record(&profile.name, metrics::Event::AuthFailure);
+            eprintln!("{}", theme::style(Role::Error).apply_to(format!("{:#}", e)));
+            failed.push(identity.start_url.clone());
+        }
+    }
+    if !failed.is_empty() {
+        return Err(anyhow!("SSO login failed for {}", failed.join(", ")));
+    }
+    Ok(())
 }
 
 async fn ensure_aws_present() -> Result<()> {
-    match Command::new("aws").arg("--version").output().await {
+    match backend::runner().output(backend::command("aws").arg("--version"), None).await {
         Ok(output) => {
             if output.status.success() {
                 Ok(())
@@ -498,69 +2521,303 @@ async fn ensure_aws_present() -> Result<()> {
                 Err(anyhow!("aws binary not found or returned non-zero --version"))
             }
         }
-        Err(_) => Err(anyhow!(
-            "aws binary not found. Please install AWS CLI v2 and ensure 'aws' is on PATH"
-        )),
+        Err(_) => Err(anyhow!(
+            "aws binary not found. Please install AWS CLI v2 and ensure 'aws' is on PATH"
+        )),
+    }
+}
+
+fn aws_dir() -> Result<PathBuf> {
+    let home = dirs::home_dir().ok_or_else(|| anyhow!("Could not determine home directory"))?;
+    Ok(home.join(".aws"))
+}
+
+fn aws_files() -> Result<aws_env::AwsFiles> {
+    Ok(aws_env::AwsFiles::resolve(&aws_env::current_lookup, &aws_dir()?))
+}
+
+fn load_profiles(settings: &settings::Settings, no_interactive: bool) -> Result<HashMap<String, Profile>> {
+    let files = aws_files()?;
+    let cache_dir = settings::awx_dir()?.join("cache");
+    let sources = load_profile_sources(&files, Some(&cache_dir))?;
+    let remembered = settings::load_remembered_conflicts();
+    sources.merge(|conflict| {
+        choose_conflict_resolution(conflict, settings, &remembered, no_interactive)
+    })
+}
+
+// Profiles without a region, typically ones defined only in ~/.aws/credentials, take
+// `default_region`, else the region of `default`, so their commands get one through
+// AWS_DEFAULT_REGION instead of failing with "You must specify a region".
+fn fill_missing_regions(profiles: &mut HashMap<String, Profile>, default_region: Option<&str>) {
+    let fallback = default_region
+        .map(str::to_string)
+        .or_else(|| profiles.get("default").and_then(|p| p.region.clone()));
+    let Some(fallback) = fallback else {
+        return;
+    };
+    for profile in profiles.values_mut().filter(|p| p.region.is_none()) {
+        profile.region = Some(fallback.clone());
+    }
+}
+
+// Ask for a region when none resolves for `profile` this run. Without a prompt, say
+// where one can be set; some commands (IAM, S3 listing) do without.
+fn ask_missing_region(profile: &mut Profile, aws_args: &[String], no_interactive: bool) -> Result<()> {
+    if aws_env::region(&aws_env::current_lookup, aws_args, profile).is_some() {
+        return Ok(());
+    }
+    let hint = "set region in ~/.aws/config, default_region in ~/.awx/config.toml, or pass --region";
+    if no_interactive {
+        eprintln!(
+            "{}",
+            theme::style(Role::Muted).apply_to(format!("Profile '{}' has no region; {}.", profile.name, hint))
+        );
+        return Ok(());
+    }
+    let region = ask::text(
+        ask::Key("region", Some(&profile.name)),
+        &format!("Region for '{}' (none set; empty to go without)", profile.name),
+    )?;
+    let region = region.trim();
+    if !region.is_empty() {
+        profile.region = Some(region.to_string());
+        eprintln!("{}", theme::style(Role::Muted).apply_to(format!("To keep it, {}.", hint)));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+fn load_profiles_from_dir(aws: &std::path::Path) -> Result<HashMap<String, Profile>> {
+    load_profile_sources(&aws_env::AwsFiles::in_dir(aws), None)?.merge(|_| Ok(ConflictResolution::Merge))
+}
+
+// Profiles as read from each file, before they are combined.
+#[derive(Debug, Default)]
+struct ProfileSources {
+    config: HashMap<String, Profile>,
+    credentials: HashMap<String, Profile>,
+}
+
+// A profile defined in both files whose keys disagree.
+#[derive(Debug, Clone, PartialEq)]
+struct ProfileConflict {
+    name: String,
+    config_keys: Vec<String>,
+    credentials_keys: Vec<String>,
+}
+
+impl ProfileSources {
+    fn conflicts(&self) -> Vec<ProfileConflict> {
+        let mut conflicts = Vec::new();
+        for (name, conf) in self.config.iter() {
+            let Some(creds) = self.credentials.get(name) else {
+                continue;
+            };
+            let mut config_keys = Vec::new();
+            let mut credentials_keys = Vec::new();
+            // A role or SSO definition in config next to static keys in credentials
+            // means two different auth methods claim the same name.
+            if creds.is_static() && (conf.is_role() || conf.is_sso()) {
+                for (key, val) in [
+                    ("role_arn", &conf.role_arn),
+                    ("source_profile", &conf.source_profile),
+                    ("sso_start_url", &conf.sso_start_url),
+                    ("sso_region", &conf.sso_region),
+                ] {
+                    if val.is_some() {
+                        config_keys.push(key.to_string());
+                    }
+                }
+                credentials_keys.push("aws_access_key_id".to_string());
+                credentials_keys.push("aws_secret_access_key".to_string());
+            }
+            // The same key set to different values in both files.
+            for (key, a, b) in [
+                ("aws_access_key_id", &conf.aws_access_key_id, &creds.aws_access_key_id),
+                ("aws_secret_access_key", &conf.aws_secret_access_key, &creds.aws_secret_access_key),
+                ("aws_session_token", &conf.aws_session_token, &creds.aws_session_token),
+            ] {
+                if a.is_some() && b.is_some() && a != b && !credentials_keys.iter().any(|k| k == key) {
+                    config_keys.push(key.to_string());
+                    credentials_keys.push(key.to_string());
+                }
+            }
+            if !config_keys.is_empty() {
+                conflicts.push(ProfileConflict {
+                    name: name.clone(),
+                    config_keys,
+                    credentials_keys,
+                });
+            }
+        }
+        conflicts.sort_by(|a, b| a.name.cmp(&b.name));
+        conflicts
+    }
+
+    fn merge<F>(self, mut resolve: F) -> Result<HashMap<String, Profile>>
+    where
+        F: FnMut(&ProfileConflict) -> Result<ConflictResolution>,
+    {
+        let mut resolutions: HashMap<String, ConflictResolution> = HashMap::new();
+        for conflict in self.conflicts() {
+            let resolution = resolve(&conflict)?;
+            resolutions.insert(conflict.name, resolution);
+        }
+
+        let mut profiles = self.config;
+        for (name, creds) in self.credentials.into_iter() {
+            let resolution = resolutions.get(&name).copied().unwrap_or(ConflictResolution::Merge);
+            let entry = profiles.entry(name.clone()).or_insert_with(|| Profile {
+                name: name.clone(),
+                ..Default::default()
+            });
+            match resolution {
+                ConflictResolution::PreferConfig => continue,
+                ConflictResolution::PreferCredentials => {
+                    entry.role_arn = None;
+                    entry.source_profile = None;
+                    entry.sso_start_url = None;
+                    entry.sso_region = None;
+                }
+                ConflictResolution::Merge => {}
+            }
+            if creds.aws_access_key_id.is_some() {
+                entry.aws_access_key_id = creds.aws_access_key_id;
+            }
+            if creds.aws_secret_access_key.is_some() {
+                entry.aws_secret_access_key = creds.aws_secret_access_key;
+            }
+            if creds.aws_session_token.is_some() {
+                entry.aws_session_token = creds.aws_session_token;
+            }
+            if entry.description.is_none() {
+                entry.description = creds.description;
+            }
+        }
+        Ok(profiles)
     }
 }
 
-fn aws_dir() -> Result<PathBuf> {
-    let home = dirs::home_dir().ok_or_else(|| anyhow!("Could not determine home directory"))?;
-    Ok(home.join(".aws"))
+#[cfg(test)]
+fn parse_config_profiles(content: &str) -> HashMap<String, Profile> {
+    config_profiles(config_parser::parse(content).sections)
+}
+
+fn config_profiles(sections: config_parser::Sections) -> HashMap<String, Profile> {
+    let mut profiles: HashMap<String, Profile> = HashMap::new();
+    for (section_name, prop) in sections.into_iter() {
+        let profile_name = match section_name.strip_prefix("profile ") {
+            Some(stripped) => stripped.to_string(),
+            None => section_name.clone(),
+        };
+        let entry = profiles.entry(profile_name.clone()).or_insert_with(|| Profile {
+            name: profile_name.clone(),
+            ..Default::default()
+        });
+        if let Some(d) = prop.get(config_parser::DESCRIPTION_KEY) {
+            entry.description = Some(d.to_string());
+        }
+        if let Some(r) = prop.get("region") {
+            entry.region = Some(r.to_string());
+        }
+        if let Some(s) = prop.get("sso_start_url") {
+            entry.sso_start_url = Some(s.to_string());
+        }
+        if let Some(s) = prop.get("sso_region") {
+            entry.sso_region = Some(s.to_string());
+        }
+        if let Some(s) = prop.get("sso_session") {
+            entry.sso_session = Some(s.to_string());
+        }
+        if let Some(a) = prop.get("sso_account_id") {
+            entry.sso_account_id = Some(a.to_string());
+        }
+        if let Some(r) = prop.get("role_arn") {
+            entry.role_arn = Some(r.to_string());
+        }
+        if let Some(s) = prop.get("source_profile") {
+            entry.source_profile = Some(s.to_string());
+        }
+        if let Some(s) = prop.get("credential_source") {
+            entry.credential_source = Some(s.to_string());
+        }
+        if let Some(t) = prop.get("web_identity_token_file") {
+            entry.web_identity_token_file = Some(t.to_string());
+        }
+        if let Some(c) = prop.get("credential_process") {
+            entry.credential_process = Some(c.to_string());
+        }
+        if let Some(o) = prop.get("output") {
+            entry.output = Some(o.to_string());
+        }
+        if let Some(p) = prop.get("cli_pager") {
+            entry.cli_pager = Some(p.to_string());
+        }
+        if let Some(f) = prop.get("cli_binary_format") {
+            entry.cli_binary_format = Some(f.to_string());
+        }
+        if let Some(h) = prop.get("cli_history") {
+            entry.cli_history = Some(h.to_string());
+        }
+        if let Some(m) = prop.get("mfa_serial") {
+            entry.mfa_serial = Some(m.to_string());
+        }
+        if let Some(n) = prop.get("role_session_name") {
+            entry.role_session_name = Some(n.to_string());
+        }
+        if let Some(d) = prop.get("duration_seconds").and_then(|d| d.parse().ok()) {
+            entry.duration_seconds = Some(d);
+        }
+        // The AWS CLI also accepts static keys directly in the config file.
+        if let Some(a) = prop.get("aws_access_key_id") {
+            entry.aws_access_key_id = Some(a.to_string());
+        }
+        if let Some(a) = prop.get("aws_secret_access_key") {
+            entry.aws_secret_access_key = Some(a.to_string());
+        }
+        if let Some(a) = prop.get("aws_session_token") {
+            entry.aws_session_token = Some(a.to_string());
+        }
+    }
+    profiles
 }
 
-fn load_profiles() -> Result<HashMap<String, Profile>> {
-    let aws = aws_dir()?;
-    load_profiles_from_dir(&aws)
+// Parse an AWS INI file, warning about the lines that had to be skipped.
+fn parse_ini_file(path: &std::path::Path, bytes: &[u8]) -> config_parser::Sections {
+    let parsed = config_parser::parse_bytes(bytes);
+    let yellow = theme::style(Role::Warning);
+    for d in &parsed.diagnostics {
+        eprintln!("{}", yellow.apply_to(format!("Warning: {}:{}: {}", path.display(), d.line, d.message)));
+    }
+    parsed.sections
 }
 
-fn load_profiles_from_dir(aws: &std::path::Path) -> Result<HashMap<String, Profile>> {
-    let mut profiles: HashMap<String, Profile> = HashMap::new();
+// Read both files. With `cache_dir`, the parsed config is reused from the binary
+// profile cache while the config file is unchanged.
+fn load_profile_sources(files: &aws_env::AwsFiles, cache_dir: Option<&std::path::Path>) -> Result<ProfileSources> {
+    let mut sources = ProfileSources::default();
 
-    let config_path = aws.join("config");
+    let config_path = &files.config;
     if config_path.exists() {
-        let content = std::fs::read_to_string(&config_path)
-            .with_context(|| format!("Failed to read {}", config_path.display()))?;
-        let conf = parse_ini(&content);
-        for (section_name, prop) in conf.into_iter() {
-            let profile_name = if section_name.starts_with("profile ") {
-                section_name[8..].to_string()
-            } else {
-                section_name.clone()
-            };
-            let entry = profiles.entry(profile_name.clone()).or_insert_with(|| Profile {
-                name: profile_name.clone(),
-                ..Default::default()
-            });
-            if let Some(r) = prop.get("region") {
-                entry.region = Some(r.to_string());
-            }
-            if let Some(s) = prop.get("sso_start_url") {
-                entry.sso_start_url = Some(s.to_string());
-            }
-            if let Some(s) = prop.get("sso_region") {
-                entry.sso_region = Some(s.to_string());
+        sources.config = match cache_dir {
+            Some(dir) => profile_cache::load_or_parse(config_path, &dir.join("config.bin"), |bytes| {
+                config_profiles(parse_ini_file(config_path, bytes))
+            })?,
+            None => {
+                let bytes =
+                    std::fs::read(config_path).with_context(|| format!("Failed to read {}", config_path.display()))?;
+                config_profiles(parse_ini_file(config_path, &bytes))
             }
-            if let Some(r) = prop.get("role_arn") {
-                entry.role_arn = Some(r.to_string());
-            }
-            if let Some(s) = prop.get("source_profile") {
-                entry.source_profile = Some(s.to_string());
-            }
-            if let Some(m) = prop.get("mfa_serial") {
-                entry.mfa_serial = Some(m.to_string());
-            }
-        }
+        };
     }
 
-    let creds_path = aws.join("credentials");
+    let creds_path = &files.credentials;
     if creds_path.exists() {
-        let content = std::fs::read_to_string(&creds_path)
-            .with_context(|| format!("Failed to read {}", creds_path.display()))?;
-        let conf = parse_ini(&content);
-        for (section_name, prop) in conf.into_iter() {
+        let bytes = std::fs::read(creds_path).with_context(|| format!("Failed to read {}", creds_path.display()))?;
+        for (section_name, prop) in parse_ini_file(creds_path, &bytes).into_iter() {
             let profile_name = section_name.clone();
-            let entry = profiles.entry(profile_name.clone()).or_insert_with(|| Profile {
+            let entry = sources.credentials.entry(profile_name.clone()).or_insert_with(|| Profile {
                 name: profile_name.clone(),
                 ..Default::default()
             });
@@ -573,63 +2830,136 @@ fn load_profiles_from_dir(aws: &std::path::Path) -> Result<HashMap<String, Profi
             if let Some(a) = prop.get("aws_session_token") {
                 entry.aws_session_token = Some(a.to_string());
             }
+            if let Some(d) = prop.get(config_parser::DESCRIPTION_KEY) {
+                entry.description = Some(d.to_string());
+            }
         }
     }
 
-    Ok(profiles)
+    Ok(sources)
+}
+
+// Pick how to resolve a conflicting profile: awx config first, then a remembered
+// interactive choice, then ask (interactive) or merge with a warning (non-interactive).
+fn choose_conflict_resolution(
+    conflict: &ProfileConflict,
+    settings: &settings::Settings,
+    remembered: &HashMap<String, ConflictResolution>,
+    no_interactive: bool,
+) -> Result<ConflictResolution> {
+    if let Some(r) = settings.conflict_resolution {
+        return Ok(r);
+    }
+    if let Some(r) = remembered.get(&conflict.name) {
+        return Ok(*r);
+    }
+    let yellow = theme::style(Role::Warning);
+    eprintln!(
+        "{}",
+        yellow.apply_to(format!(
+            "Profile '{}' is defined in both ~/.aws/config ({}) and ~/.aws/credentials ({}).",
+            conflict.name,
+            conflict.config_keys.join(", "),
+            conflict.credentials_keys.join(", ")
+        ))
+    );
+    if no_interactive {
+        eprintln!(
+            "Merging both definitions. Set conflict_resolution in ~/.awx/config.toml to choose explicitly."
+        );
+        return Ok(ConflictResolution::Merge);
+    }
+    let items: Vec<&str> = ConflictResolution::ALL.iter().map(|r| r.label()).collect();
+    let selection = ask::select(ask::Key("conflict_resolution", Some(&conflict.name)), &format!("Resolve conflict for '{}'", conflict.name), &items, 2)?;
+    let resolution = ConflictResolution::ALL[selection];
+    let remember = ask::confirm(ask::Key("remember_conflict_resolution", Some(&conflict.name)), "Remember this choice?", true)?;
+    if remember {
+        settings::remember_conflict(&conflict.name, resolution)?;
+    }
+    Ok(resolution)
 }
 
 fn interactive_select_profile(profiles: &HashMap<String, Profile>) -> Result<String> {
+    let filter = badge::filter();
+    let mut mapping = filter.names(profiles);
+    if mapping.is_empty() {
+        return Err(anyhow!("No profiles match {}", filter.describe()));
+    }
+    // The likely next profile goes first, where the cursor starts.
+    let suggested = suggest::for_picker(&mapping);
+    if let Some(pos) = suggested.as_ref().and_then(|s| mapping.iter().position(|n| n == s)) {
+        let name = mapping.remove(pos);
+        mapping.insert(0, name);
+    }
+    let muted = theme::style(Role::Muted).for_stderr();
     let mut items: Vec<String> = Vec::new();
-    let mut mapping: Vec<String> = Vec::new();
-    for (name, p) in profiles.iter() {
-        let mut badges = String::new();
-        if name == "default" {
-            badges.push_str("[default]");
-        }
-        if p.is_sso() {
-            badges.push_str("[SSO]");
+    let mut shown = Vec::new();
+    // Names and badges line up by terminal columns, whatever script the names are in.
+    let name_width = mapping.iter().map(|n| render::width(n)).max().unwrap_or(0);
+    let tags_width = mapping.iter().map(|n| render::width(&badge::tags(&badge::badges(&profiles[n])))).max().unwrap_or(0);
+    for name in &mapping {
+        let badges = badge::badges(&profiles[name]);
+        let tags = render::pad(&badge::tags(&badges), tags_width);
+        let mut item = format!("{} {}", render::pad(name, name_width), theme::style(Role::Badge).for_stderr().apply_to(tags));
+        if let Some(description) = &profiles[name].description {
+            item.push_str(&format!("  {}", muted.apply_to(description)));
         }
-        if p.is_role() {
-            badges.push_str("[ROLE]");
+        if suggested.as_ref() == Some(name) {
+            item.push_str(&format!("  {}", muted.apply_to("(suggested from your recent use)")));
         }
-        if p.requires_mfa() {
-            badges.push_str("[MFA]");
-        }
-        if p.is_static() {
-            badges.push_str("[STATIC]");
-        }
-        let display = format!("{} {}", name, badges);
-        items.push(display);
-        mapping.push(name.clone());
+        items.push(item);
+        shown.extend(badges);
     }
-    let selection = Select::with_theme(&ColorfulTheme::default())
-        .with_prompt("Select profile")
-        .items(&items)
-        .default(0)
-        .interact()?;
+    for line in badge::legend(&shown) {
+        eprintln!("{}", muted.apply_to(line));
+    }
+    let selection = ask::select(ask::Key("profile", None), "Select profile", &items, 0)?;
     Ok(mapping[selection].clone())
 }
 
+/// Whether the SSO session of `profile` is active, using the configured identity check.
+async fn check_sso_session(profile: &Profile, settings: &settings::Settings) -> Result<bool> {
+    let check = match settings.identity_check(&profile.name) {
+        settings::IdentityCheck::Sts if offline::is_offline() => settings::IdentityCheck::TokenFile,
+        check => check,
+    };
+    let mut span = telemetry::span("sso check");
+    span.attr("awx.profile", &profile.name);
+    span.attr("awx.identity_check", format!("{:?}", check));
+    match check {
+        settings::IdentityCheck::None => Ok(true),
+        settings::IdentityCheck::TokenFile => {
+            let start_url = profile.sso_start_url.as_deref().ok_or_else(|| {
+                anyhow!(
+                    "identity_check = \"token-file\" needs sso_start_url on profile '{}'",
+                    profile.name
+                )
+            })?;
+            Ok(sso::cached_token_valid(&aws_dir()?, start_url, Utc::now()))
+        }
+        settings::IdentityCheck::Sts => check_sts_identity(&profile.name).await,
+    }
+}
+
 async fn check_sts_identity(profile: &str) -> Result<bool> {
-    let mut cmd = Command::new("aws");
-    cmd.arg("sts")
-        .arg("get-caller-identity")
-        .arg("--profile")
-        .arg(profile)
-        .arg("--output")
-        .arg("json");
-    let fut = cmd.output();
-    match timeout(Duration::from_secs(5), fut).await {
-        Ok(Ok(output)) => Ok(output.status.success()),
-        Ok(Err(e)) => Err(anyhow!(e)),
+    progress::advance(&format!("check SSO session of '{}'", profile));
+    let sts = backend::sts();
+    match timeout(Duration::from_secs(5), sts.caller_identity(profile)).await {
+        Ok(Ok(_)) => Ok(true),
+        Ok(Err(_)) => Ok(false),
         Err(_) => Err(anyhow!("Timeout while checking STS identity")),
     }
 }
 
-async fn get_session_token_interactive(profile: &str, mfa_serial: &str) -> Result<StsCredentials> {
+// Get an MFA session for `profile`, asking for up to `attempts` codes.
+async fn get_session_token_interactive(
+    profile: &str,
+    mfa_serial: &str,
+    no_interactive: bool,
+    attempts: u32,
+) -> Result<StsCredentials> {
     // Verify MFA serial account matches the profile's account before prompting.
-    if let Some(mfa_account) = extract_account_from_arn(mfa_serial) {
+    if let Some(mfa_account) = mfa_account(mfa_serial) {
         match get_profile_account(profile).await {
             Ok(profile_account) => {
                 if profile_account != mfa_account {
@@ -645,172 +2975,441 @@ async fn get_session_token_interactive(profile: &str, mfa_serial: &str) -> Resul
             }
         }
     }
-    for attempt in 1..=3 {
-        let prompt = format!("Enter MFA code (6 digits) for {}: ", mfa_serial);
-        let code = Password::with_theme(&ColorfulTheme::default())
-            .with_prompt(prompt)
-            .allow_empty_password(false)
-            .interact()?;
+    metrics::record(profile, metrics::Event::MfaPrompt);
+    progress::clear();
+    if no_interactive {
+        // No prompt in non-interactive mode: take the code from the environment or
+        // describe the missing input.
+        let code = match aws_env::current_lookup(prompt::MFA_CODE_ENV) {
+            Some(c) if !c.trim().is_empty() => c.trim().to_string(),
+            _ => {
+                InputRequest::mfa_code(profile, mfa_serial).emit();
+                return Err(pipeline::MfaCodeRequired(profile.to_string()).into());
+            }
+        };
+        return get_session_token(profile, mfa_serial, &code).await;
+    }
+    for attempt in 1..=attempts {
+        let prompt = t(Msg::MfaPrompt, &[("serial", mfa_serial)]);
+        let mut wait = telemetry::span("mfa wait");
+        wait.attr("awx.profile", profile);
+        let code = ask::password(ask::Key("mfa_code", Some(profile)), &prompt, None)?;
+        drop(wait);
         let code = code.trim().to_string();
         if !code.chars().all(|c| c.is_ascii_digit()) || code.len() != 6 {
-            eprintln!("Invalid code format");
+            eprintln!("{}", t(Msg::MfaInvalidFormat, &[]));
             continue;
         }
         match get_session_token(profile, mfa_serial, &code).await {
             Ok(creds) => return Ok(creds),
             Err(e) => {
-                eprintln!("MFA attempt {} failed: {}", attempt, e);
-                if attempt == 3 {
-                    std::process::exit(3);
-                }
+                let (n, error) = (attempt.to_string(), e.to_string());
+                eprintln!("{}", t(Msg::MfaAttemptFailed, &[("attempt", &n), ("error", &error)]));
             }
         }
     }
-    Err(anyhow!("MFA failed after retries"))
+    Err(pipeline::MfaFailed {
+        profile: profile.to_string(),
+        attempts,
+    }
+    .into())
+}
+
+fn is_access_denied(err: &anyhow::Error) -> bool {
+    let msg = err.to_string();
+    msg.contains("AccessDenied") || msg.contains("not authorized to perform")
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct MfaDevicesResponse {
+    #[serde(rename = "MFADevices")]
+    mfa_devices: Vec<MfaDevice>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct MfaDevice {
+    serial_number: String,
+}
+
+async fn list_mfa_devices(profile: &str) -> Result<Vec<String>> {
+    let mut cmd = backend::command("aws");
+    cmd.arg("iam").arg("list-mfa-devices");
+    backend::select_profile(&mut cmd, profile);
+    cmd.arg("--output").arg("json");
+    let output = ratelimit::output(&mut cmd)
+        .await
+        .context("failed to run aws iam list-mfa-devices")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("list-mfa-devices failed: {}", stderr));
+    }
+    let resp: MfaDevicesResponse = serde_json::from_slice(&output.stdout)
+        .context("Parsing list-mfa-devices JSON response failed")?;
+    Ok(resp.mfa_devices.into_iter().map(|d| d.serial_number).collect())
+}
+
+// Ask IAM for the source profile's MFA devices, let the user pick one and offer to
+// persist it as `mfa_serial`. Returns None when nothing usable was found.
+async fn discover_mfa_serial(profile: &str) -> Result<Option<String>> {
+    progress::clear();
+    eprintln!(
+        "assume-role was denied and profile '{}' has no mfa_serial. Looking up MFA devices...",
+        profile
+    );
+    let devices = match list_mfa_devices(profile).await {
+        Ok(d) => d,
+        Err(e) => {
+            eprintln!("Could not list MFA devices: {}", e);
+            return Ok(None);
+        }
+    };
+    let serial = match devices.len() {
+        0 => {
+            eprintln!("No MFA devices are registered for the user behind '{}'.", profile);
+            return Ok(None);
+        }
+        1 => devices[0].clone(),
+        _ => {
+            let selection = ask::select(ask::Key("mfa_device", Some(profile)), "Select MFA device", &devices, 0)?;
+            devices[selection].clone()
+        }
+    };
+    let save = ask::confirm(ask::Key("save_mfa_serial", Some(profile)), &format!("Save mfa_serial = {} to profile '{}'?", serial, profile), true)?;
+    if save {
+        let config_path = aws_files()?.config;
+        // A config that cannot be read must not be replaced by one holding only this key.
+        let content = match std::fs::read_to_string(&config_path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e).with_context(|| format!("Cannot read {} to save mfa_serial", config_path.display())),
+        };
+        state::replace(&config_path, set_profile_key(&content, profile, "mfa_serial", &serial).as_bytes())?;
+        eprintln!("Saved mfa_serial to {}", config_path.display());
+    }
+    Ok(Some(serial))
 }
 
-fn extract_account_from_arn(arn: &str) -> Option<String> {
-    // ARN format: arn:partition:service:region:account-id:resource
-    // For IAM MFA: arn:aws:iam::<ACCOUNT_ID>:mfa/username
-    let parts: Vec<&str> = arn.split(':').collect();
-    if parts.len() >= 5 {
-        let account = parts[4];
-        if !account.is_empty() {
-            return Some(account.to_string());
+// Set `key = value` in the config section for `profile`, replacing an existing key or
+// appending the section when it does not exist yet.
+fn set_profile_key(content: &str, profile: &str, key: &str, value: &str) -> String {
+    let header = if profile == "default" {
+        "default".to_string()
+    } else {
+        format!("profile {}", profile)
+    };
+    let mut out: Vec<String> = Vec::new();
+    let mut in_section = false;
+    let mut done = false;
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') && trimmed.ends_with(']') {
+            if in_section && !done {
+                // insert before the next section, after the section's last non-blank line
+                let pos = out.iter().rposition(|l| !l.trim().is_empty()).map(|i| i + 1).unwrap_or(out.len());
+                out.insert(pos, format!("{} = {}", key, value));
+                done = true;
+            }
+            in_section = trimmed[1..trimmed.len() - 1].trim() == header;
+        } else if in_section && !done {
+            if let Some((k, _)) = trimmed.split_once('=') {
+                if k.trim() == key {
+                    out.push(format!("{} = {}", key, value));
+                    done = true;
+                    continue;
+                }
+            }
         }
+        out.push(line.to_string());
     }
-    None
+    if in_section && !done {
+        let pos = out.iter().rposition(|l| !l.trim().is_empty()).map(|i| i + 1).unwrap_or(out.len());
+        out.insert(pos, format!("{} = {}", key, value));
+        done = true;
+    }
+    if !done {
+        if out.last().map(|l| !l.trim().is_empty()).unwrap_or(false) {
+            out.push(String::new());
+        }
+        out.push(format!("[{}]", header));
+        out.push(format!("{} = {}", key, value));
+    }
+    let mut result = out.join("\n");
+    result.push('\n');
+    result
 }
 
 async fn get_profile_account(profile: &str) -> Result<String> {
-    let mut cmd = Command::new("aws");
-    cmd.arg("sts")
-        .arg("get-caller-identity")
-        .arg("--profile")
-        .arg(profile)
-        .arg("--output")
-        .arg("json");
-    let output = timeout(Duration::from_secs(5), cmd.output())
+    progress::advance(&format!("verify account of '{}'", profile));
+    let sts = backend::sts();
+    let identity = timeout(Duration::from_secs(5), sts.caller_identity(profile)).await
+        .context("get_caller_identity timeout")??;
+    Ok(identity.account)
+}
+
+// Expand a role as written in the config into a full ARN. Only a bare role name costs a
+// round-trip: it is looked up in the account of `source`, whose identity also gives the
+// partition. Otherwise the partition follows the profile's region.
+async fn expand_role_arn(spec: &str, profile: &Profile, source: &str) -> Result<String> {
+    let role = match arn::RoleSpec::parse(spec)? {
+        arn::RoleSpec::Arn(role) => role,
+        arn::RoleSpec::InAccount { account, name } => {
+            let region = aws_env::region(&aws_env::current_lookup, &[], profile).map(|(r, _)| r);
+            arn::role(arn::partition_for_region(region.as_deref().unwrap_or_default()), &account, &name)
+        }
+        arn::RoleSpec::Name(name) => {
+            progress::advance(&format!("look up account of '{}'", source));
+            let sts = backend::sts();
+            let identity = sts.caller_identity(source).await?;
+            let caller: arn::Arn = identity.arn.parse()?;
+            arn::role(&caller.partition, &identity.account, &name)
+        }
+    };
+    Ok(role.to_string())
+}
+
+// The identity of the session awx resolved for `profile`, as the child command sees it.
+async fn session_identity(creds: Option<StsCredentials>, profile: &Profile) -> Result<backend::CallerIdentity> {
+    let args = ["sts", "get-caller-identity", "--output", "json"].map(String::from);
+    caller_identity_output(child_command("aws", &args, creds, profile)).await
+}
+
+// Run a prepared `aws sts get-caller-identity` and parse its answer.
+async fn caller_identity_output(mut cmd: tokio::process::Command) -> Result<backend::CallerIdentity> {
+    let output = ratelimit::output(&mut cmd)
         .await
-        .context("get_caller_identity timeout")?
         .context("failed to run aws sts get-caller-identity")?;
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(anyhow!(format!("get-caller-identity failed: {}", stderr)));
+        return Err(anyhow!("get-caller-identity failed: {}", stderr.trim()));
     }
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let v: serde_json::Value = serde_json::from_str(&stdout)
-        .context("Parsing get-caller-identity JSON response failed")?;
-    if let Some(account) = v.get("Account").and_then(|a| a.as_str()) {
-        return Ok(account.to_string());
+    serde_json::from_slice(&output.stdout).context("Parsing get-caller-identity JSON response failed")
+}
+
+// The IAM principal whose policies decide what a profile may do: the role of a role
+// profile, otherwise the user or role behind its credentials.
+async fn simulation_principal(profile: &Profile) -> Result<String> {
+    if let (Some(spec), Some(source)) = (profile.role_arn.as_deref(), profile.source_profile.as_deref()) {
+        return expand_role_arn(spec, profile, source).await;
     }
-    Err(anyhow!("Account not found in get-caller-identity response"))
+    let sts = backend::sts();
+    let identity = sts.caller_identity(&profile.name).await?;
+    let caller: arn::Arn = identity.arn.parse()?;
+    iam::principal_arn(&caller).ok_or_else(|| anyhow!("Cannot simulate policies for {}", identity.arn))
+}
+
+// The account of an MFA device ARN; hardware tokens have plain serial numbers instead.
+fn mfa_account(mfa_serial: &str) -> Option<String> {
+    let device: arn::Arn = mfa_serial.parse().ok()?;
+    device.account_id().map(str::to_string)
 }
 
 async fn get_session_token(profile: &str, mfa_serial: &str, code: &str) -> Result<StsCredentials> {
-    let mut cmd = Command::new("aws");
-    cmd.arg("sts")
-        .arg("get-session-token")
-        .arg("--serial-number")
-        .arg(mfa_serial)
-        .arg("--token-code")
-        .arg(code)
-        .arg("--profile")
-        .arg(profile)
-        .arg("--duration-seconds")
-        .arg("3600")
-        .arg("--output")
-        .arg("json");
-
-    let output = timeout(Duration::from_secs(30), cmd.output())
-        .await
-        .context("get_session_token timeout")?
-        .context("failed to run aws sts get-session-token")?;
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(anyhow!("get-session-token failed: {}", stderr));
-    }
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let wrap: StsCredsWrapper = serde_json::from_str(&stdout)
-        .context("Parsing get-session-token JSON response failed")?;
-    // touch expiration so the field is considered read (used) and does not emit dead_code warning
-    let _ = &wrap.credentials.expiration;
-    Ok(wrap.credentials)
-}
-
-async fn assume_role_with_profile(role_arn: &str, session_name: &str, profile: &str) -> Result<StsCredentials> {
-    let mut cmd = Command::new("aws");
-    cmd.arg("sts")
-        .arg("assume-role")
-        .arg("--role-arn")
-        .arg(role_arn)
-        .arg("--role-session-name")
-        .arg(session_name)
-        .arg("--profile")
-        .arg(profile)
-        .arg("--duration-seconds")
-        .arg("3600")
-        .arg("--output")
-        .arg("json");
-
-    let output = timeout(Duration::from_secs(30), cmd.output())
-        .await
-        .context("assume-role timeout")?
-        .context("failed to run aws sts assume-role")?;
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(anyhow!("assume-role failed: {}", stderr));
-    }
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let wrap: StsCredsWrapper = serde_json::from_str(&stdout)
-        .context("Parsing assume-role JSON response failed")?;
-    let _ = &wrap.credentials.expiration;
-    Ok(wrap.credentials)
-}
-
-async fn assume_role_with_env(role_arn: &str, session_name: &str, base: &StsCredentials) -> Result<StsCredentials> {
-    let mut cmd = Command::new("aws");
-    cmd.env("AWS_ACCESS_KEY_ID", &base.access_key_id)
-        .env("AWS_SECRET_ACCESS_KEY", &base.secret_access_key)
-        .env("AWS_SESSION_TOKEN", &base.session_token)
-        .arg("sts")
-        .arg("assume-role")
-        .arg("--role-arn")
-        .arg(role_arn)
-        .arg("--role-session-name")
-        .arg(session_name)
-        .arg("--duration-seconds")
-        .arg("3600")
-        .arg("--output")
-        .arg("json");
-
-    let output = timeout(Duration::from_secs(30), cmd.output())
-        .await
-        .context("assume-role-with-env timeout")?
-        .context("failed to run aws sts assume-role with env creds")?;
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(anyhow!("assume-role (env) failed: {}", stderr));
+    progress::advance(&format!("GetSessionToken with MFA for '{}'", profile));
+    let sts = backend::sts();
+    sts.session_token(profile, Some((mfa_serial, code))).await
+}
+
+async fn assume_role_with_profile(
+    role_arn: &str,
+    session_name: &str,
+    duration_secs: i64,
+    profile: &str,
+) -> Result<StsCredentials> {
+    arn::parse_role(role_arn)?;
+    let trust = verify_trust(role_arn, || iam::as_profile(profile)).await?;
+    progress::advance(&format!("AssumeRole {}", role_arn));
+    let sts = backend::sts();
+    let source = backend::CredentialSource::Profile(profile);
+    let key = singleflight::key(&sts, &["assume-role", role_arn, session_name, &duration_secs.to_string(), "profile", profile]);
+    let call = async { sts.assume_role(role_arn, session_name, duration_secs, source).await };
+    let result = singleflight::share(key, call).await;
+    let creds = explain_denied(explain_chaining_limit(result), role_arn, trust.as_ref())?;
+    notify::send(notify::Event::RoleAssumed { role_arn, session_name }).await;
+    Ok(creds)
+}
+
+// STS rejects long sessions for chained roles with a message that does not say what to change.
+fn explain_chaining_limit(result: Result<StsCredentials>) -> Result<StsCredentials> {
+    result.map_err(|e| {
+        if format!("{:#}", e).contains("role chaining") {
+            e.context("AWS limits chained role sessions to 1 hour; set duration_seconds to 3600 or less")
+        } else {
+            e
+        }
+    })
+}
+
+// `--verify-trust`: read the role's trust policy before AssumeRole, so that a role that
+// leaves the caller out fails with that reason instead of a bare AccessDenied. A policy
+// that cannot be read is noted and the role assumed as usual.
+async fn verify_trust(role_arn: &str, aws: impl Fn() -> Command) -> Result<Option<iam::TrustCheck>> {
+    if !iam::verifies_trust() || offline::is_offline() {
+        return Ok(None);
+    }
+    let role = arn::parse_role(role_arn)?;
+    progress::advance(&format!("Reading the trust policy of {}", role_arn));
+    let check = iam::check_trust(aws, &role).await;
+    match &check {
+        iam::TrustCheck::Excluded { principal } => {
+            let args = [("role", role_arn), ("principal", principal.as_str())];
+            return Err(anyhow!(
+                "{}\n{}",
+                t(Msg::TrustPolicyExcludesCause, &args),
+                t(Msg::TrustPolicyExcludesFix, &args)
+            ));
+        }
+        iam::TrustCheck::Missing => return Err(anyhow!("{}", t(Msg::RoleMissingCause, &[("role", role_arn)]))),
+        iam::TrustCheck::Unknown(reason) => {
+            progress::clear();
+            eprintln!(
+                "{}",
+                theme::style(Role::Muted).apply_to(format!("Trust policy of {} not verified: {}", role_arn, reason))
+            );
+        }
+        iam::TrustCheck::Trusted { .. } => {}
     }
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let wrap: StsCredsWrapper = serde_json::from_str(&stdout)
-        .context("Parsing assume-role (env) JSON response failed")?;
-    let _ = &wrap.credentials.expiration;
-    Ok(wrap.credentials)
+    Ok(Some(check))
+}
+
+// Once the trust policy is known to let the caller in, an AccessDenied from AssumeRole
+// has a narrower cause: an unmet condition, or the caller's own policies.
+fn explain_denied(
+    result: Result<StsCredentials>,
+    role_arn: &str,
+    trust: Option<&iam::TrustCheck>,
+) -> Result<StsCredentials> {
+    result.map_err(|e| {
+        if troubleshoot::classify(&format!("{:#}", e)) != troubleshoot::ErrorKind::AccessDenied {
+            return e;
+        }
+        let args = [("role", role_arn)];
+        match trust {
+            Some(iam::TrustCheck::Trusted { conditional: true, .. }) => e.context(t(Msg::TrustPolicyConditional, &args)),
+            Some(iam::TrustCheck::Trusted { trust: iam::Trust::Account, .. }) => {
+                e.context(t(Msg::TrustPolicyAccountOnly, &args))
+            }
+            _ => e,
+        }
+    })
+}
+
+// GetSessionToken without MFA, for static keys that should not leave the machine.
+async fn get_plain_session_token(profile: &str) -> Result<StsCredentials> {
+    let sts = backend::sts();
+    sts.session_token(profile, None).await
+}
+
+// Export the credentials the AWS CLI resolves for `profile` (e.g. an SSO role) so
+// they can be reused without resolving the profile again.
+async fn export_credentials(profile: &str) -> Result<StsCredentials> {
+    let sso = backend::sso();
+    let key = singleflight::key(&sso, &["export-credentials", profile]);
+    let call = async {
+        sso.export_credentials(profile).await
+    };
+    singleflight::share(key, call).await
+}
+
+async fn assume_role_with_env(
+    role_arn: &str,
+    session_name: &str,
+    duration_secs: i64,
+    base: &StsCredentials,
+) -> Result<StsCredentials> {
+    arn::parse_role(role_arn)?;
+    let trust = verify_trust(role_arn, || iam::with_session(base)).await?;
+    progress::advance(&format!("AssumeRole {}", role_arn));
+    let sts = backend::sts();
+    let source = backend::CredentialSource::Session(base);
+    let duration = duration_secs.to_string();
+    let key = singleflight::key(&sts, &["assume-role", role_arn, session_name, &duration, "session", &base.access_key_id]);
+    let call = async { sts.assume_role(role_arn, session_name, duration_secs, source).await };
+    let result = singleflight::share(key, call).await;
+    let creds = explain_denied(explain_chaining_limit(result), role_arn, trust.as_ref())?;
+    notify::send(notify::Event::RoleAssumed { role_arn, session_name }).await;
+    Ok(creds)
+}
+
+async fn run_child_capture(
+    program: &str,
+    args: &[String],
+    creds: Option<StsCredentials>,
+    profile: Profile,
+) -> Result<i32> {
+    let mut cmd = child_command(program, args, creds.clone(), &profile);
+    let _endpoint = endpoint::for_child(&mut cmd, creds.as_ref())?;
+    run_attached(cmd, program).await
 }
 
-async fn run_aws_child_capture(args: &[String], creds: Option<StsCredentials>, profile: Profile) -> Result<i32> {
-    use std::os::unix::process::ExitStatusExt;
+// Run `cmd` attached to the terminal and return its exit code.
+async fn run_attached(mut cmd: Command, program: &str) -> Result<i32> {
     use std::process::Stdio;
 
-    let mut cmd = Command::new("aws");
-    for a in args {
-        cmd.arg(a);
-    }
     // inherit stdio so child interacts directly
     cmd.stdin(Stdio::inherit())
         .stdout(Stdio::inherit())
         .stderr(Stdio::inherit());
+    let child = cmd
+        .spawn()
+        .with_context(|| format!("failed to spawn child command '{}'", program))?;
+    child_span(wait_forwarding_signals(child)).await
+}
+
+// Time the child command for the trace.
+async fn child_span(wait: impl std::future::Future<Output = Result<i32>>) -> Result<i32> {
+    let mut span = telemetry::span("child");
+    let code = wait.await;
+    match &code {
+        Ok(code) => span.attr("process.exit_code", code),
+        Err(e) => span.fail(format!("{:#}", e)),
+    }
+    code
+}
+
+// Walk the default credential chain over the environment a command run with `profile` gets.
+fn chain_report(profile: &Profile, creds: Option<StsCredentials>, aws_dir: &std::path::Path) -> sdk::Report {
+    let expected = match (&creds, &profile.aws_access_key_id) {
+        (Some(c), _) => sdk::Expected::Keys(c.access_key_id.clone()),
+        (None, Some(id)) if profile.is_static() => sdk::Expected::Keys(id.clone()),
+        _ => sdk::Expected::Profile(profile.name.clone()),
+    };
+    let env = sdk::environment(&child_command("aws", &[], creds, profile));
+    let lookup = |name: &str| env.get(name).cloned();
+    let (files, config, credentials) = sdk::read_files(&lookup, aws_dir);
+    sdk::walk(&lookup, &files, &config, &credentials, expected)
+}
+
+// The child command with the profile's credentials, region and AWS_PROFILE applied.
+fn child_command(program: &str, args: &[String], creds: Option<StsCredentials>, profile: &Profile) -> Command {
+    let mut cmd = backend::command(program);
+    let args = if program == "aws" { cli::args_for(&profile.name, args) } else { args.to_vec() };
+    let args = &args[..];
+    for a in args {
+        cmd.arg(a);
+    }
+    orphan::tie(&mut cmd);
+
+    // Tell nested awx invocations which profile this environment belongs to. A session
+    // injected by an outer awx for another profile must not leak into this child.
+    let outer = aws_env::current_lookup(session::SESSION_ENV).and_then(|v| session::Marker::decode(&v));
+    let mut outer_keys_removed = false;
+    match (&creds, &outer) {
+        (Some(c), _) => {
+            cmd.env(session::SESSION_ENV, session::Marker::new(&profile.name, Some(&c.expiration)).encode());
+        }
+        (None, Some(m)) if m.profile == profile.name => {}
+        (None, outer) => {
+            if outer.as_ref().map(|m| m.expiration.is_some()).unwrap_or(false) {
+                cmd.env_remove("AWS_ACCESS_KEY_ID")
+                    .env_remove("AWS_SECRET_ACCESS_KEY")
+                    .env_remove("AWS_SESSION_TOKEN");
+                outer_keys_removed = true;
+            }
+            cmd.env(session::SESSION_ENV, session::Marker::new(&profile.name, None).encode());
+        }
+    }
 
     if let Some(creds) = creds {
         cmd.env("AWS_ACCESS_KEY_ID", creds.access_key_id)
@@ -819,15 +3418,17 @@ async fn run_aws_child_capture(args: &[String], creds: Option<StsCredentials>, p
     } else if profile.is_static() {
         // Inject static credentials from profile if the environment does not already provide them
         // (treat empty string as not provided).
-        let ak_present = match env::var("AWS_ACCESS_KEY_ID") {
-            Ok(v) => !v.is_empty(),
-            Err(_) => false,
+        let ak_present = aws_env::get(&aws_env::current_lookup, "AWS_ACCESS_KEY_ID").is_some() && !outer_keys_removed;
+        // Keys kept in a password manager were read when the profile was authenticated.
+        let (key, secret) = match secrets::resolved(&profile.name) {
+            Some(keys) => (Some(keys.access_key_id), Some(keys.secret_access_key)),
+            None => (profile.aws_access_key_id.clone(), profile.aws_secret_access_key.clone()),
         };
         if !ak_present {
-            if let Some(k) = profile.aws_access_key_id.clone() {
+            if let Some(k) = key.filter(|k| !secrets::is_reference(k)) {
                 cmd.env("AWS_ACCESS_KEY_ID", k);
             }
-            if let Some(s) = profile.aws_secret_access_key.clone() {
+            if let Some(s) = secret.filter(|s| !secrets::is_reference(s)) {
                 cmd.env("AWS_SECRET_ACCESS_KEY", s);
             }
             if let Some(t) = profile.aws_session_token.clone() {
@@ -836,32 +3437,47 @@ async fn run_aws_child_capture(args: &[String], creds: Option<StsCredentials>, p
         }
     }
     // region precedence: do not override if user provided --region or env has AWS_REGION
-    let region_env = env::var("AWS_REGION").ok().or_else(|| env::var("AWS_DEFAULT_REGION").ok());
-    let provided_region_in_args = args.iter().any(|a| a.starts_with("--region"));
-    if region_env.is_none() && !provided_region_in_args {
-        if let Some(r) = profile.region {
-            cmd.env("AWS_DEFAULT_REGION", r);
+    if let Some((r, aws_env::Source::ProfileKey(_))) = aws_env::region(&aws_env::current_lookup, args, profile) {
+        cmd.env("AWS_DEFAULT_REGION", r);
+    }
+    let provided_profile_in_args = args.iter().any(|a| a == "--profile" || a.starts_with("--profile="));
+    if !provided_profile_in_args {
+        cmd.envs(aws_env::cli_settings_env(&aws_env::current_lookup, profile));
+    }
+
+    if let Some(active) = context::for_profile(&profile.name) {
+        cmd.envs(active.env(&aws_env::current_lookup));
+    }
+    if program == "aws" {
+        cmd.envs(service::env_for(args, &aws_env::current_lookup));
+        // Only the user's commands; awx's own calls must never stop at a prompt.
+        if !args.is_empty() {
+            cmd.envs(cli::env_for(&profile.name, &aws_env::current_lookup));
         }
     }
 
     // Ensure the child uses the selected profile unless the aws command already included a --profile flag.
-    let provided_profile_in_args = args.iter().any(|a| a == "--profile" || a.starts_with("--profile="));
     if !provided_profile_in_args {
         cmd.env("AWS_PROFILE", profile.name.clone());
     }
+    cmd
+}
 
-    let mut child = cmd.spawn().context("failed to spawn aws child command")?;
-    let child_id = child.id();
+// Wait for the child, forwarding SIGINT / SIGTERM, and map its status to an exit code.
+async fn wait_forwarding_signals(mut child: tokio::process::Child) -> Result<i32> {
+    orphan::adopt(&child);
+    let _running = interrupt::child_running();
 
     // Forward signals (SIGINT / SIGTERM) to the child process
     let sigint = tokio::signal::ctrl_c();
     tokio::pin!(sigint);
 
     #[cfg(unix)]
-    {
+    let status = {
         use tokio::signal::unix::{signal, SignalKind};
+        let child_id = child.id();
         let mut sigterm = signal(SignalKind::terminate())?;
-        let status = tokio::select! {
+        tokio::select! {
             res = child.wait() => {
                 res.context("failed while waiting for child")?
             }
@@ -878,68 +3494,239 @@ async fn run_aws_child_capture(args: &[String], creds: Option<StsCredentials>, p
                 }
                 child.wait().await.context("waiting for child after SIGTERM")?
             }
-        };
-
-        if let Some(code) = status.code() {
-            return Ok(code);
-        } else if let Some(sig) = status.signal() {
-            let exit_code = match sig {
-                libc::SIGINT => 130,
-                libc::SIGTERM => 143,
-                _ => 128 + sig,
-            };
-            return Ok(exit_code as i32);
         }
-    }
+    };
 
+    // Ctrl-C reaches every process attached to the console, the child included; awx
+    // only has to wait for it.
     #[cfg(not(unix))]
+    let status = tokio::select! {
+        res = child.wait() => {
+            res.context("failed while waiting for child")?
+        }
+        _ = &mut sigint => {
+            child.wait().await.context("waiting for child after Ctrl-C")?
+        }
+    };
+
+    Ok(exit_code(status))
+}
+
+/// The exit code a shell reports for `status`: the child's own, or 128 + the signal
+/// that ended it (130 for SIGINT, 143 for SIGTERM).
+pub fn exit_code(status: std::process::ExitStatus) -> i32 {
+    if let Some(code) = status.code() {
+        return code;
+    }
+    #[cfg(unix)]
     {
-        let status = tokio::select! {
-            res = child.wait() => {
-                res.context("failed while waiting for child")?
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(sig) = status.signal() {
+            return 128 + sig;
+        }
+    }
+    1
+}
+
+async fn run_config_command(action: &ConfigCommand, no_interactive: bool) -> Result<()> {
+    let home = dirs::home_dir().ok_or_else(|| anyhow!("Could not determine home directory"))?;
+    match action {
+        ConfigCommand::Schema { aws } => {
+            let schema = if *aws { schema::aws_profile() } else { schema::settings() };
+            println!("{}", serde_json::to_string_pretty(&schema)?);
+            Ok(())
+        }
+        ConfigCommand::Backup { output, exclude_secrets, encrypt } => {
+            let archive = backup::collect(&home, *exclude_secrets)?;
+            let passphrase = if *encrypt {
+                Some(backup_passphrase(no_interactive, true)?)
+            } else {
+                None
+            };
+            let dest = match output {
+                Some(p) => p.clone(),
+                None => backup::default_archive_path(&settings::awx_dir()?, *encrypt),
+            };
+            backup::write_archive(&archive, &dest, passphrase.as_deref()).await?;
+            println!("Backed up {} files to {}", archive.files.len(), dest.display());
+            if !*exclude_secrets && !*encrypt {
+                eprintln!("Note: the archive contains credentials in plaintext. Use --exclude-secrets or --encrypt when moving it.");
             }
-            _ = &mut sigint => {
-                if let Some(pid) = child_id {
-                    // send SIGINT to child
-                    unsafe { libc::kill(pid as i32, libc::SIGINT) };
+            Ok(())
+        }
+        ConfigCommand::Restore { archive, yes } => {
+            let bytes = std::fs::read(archive).with_context(|| format!("Failed to read {}", archive.display()))?;
+            let passphrase = if backup::is_encrypted(&bytes) {
+                Some(backup_passphrase(no_interactive, false)?)
+            } else {
+                None
+            };
+            let contents = backup::read_archive(&bytes, passphrase.as_deref()).await?;
+            println!(
+                "Archive from {} ({} files{}):",
+                contents.created_at.format("%Y-%m-%d %H:%M:%S UTC"),
+                contents.files.len(),
+                if contents.secrets_excluded { ", secrets excluded" } else { "" }
+            );
+            for f in contents.files.iter() {
+                println!("  ~/{}", f.path);
+            }
+            if !*yes {
+                if no_interactive {
+                    return Err(anyhow!("Restore overwrites existing files; pass --yes to confirm in non-interactive mode"));
+                }
+                let ok = ask::confirm(ask::Key("overwrite_on_restore", None), "Overwrite these files?", false)?;
+                if !ok {
+                    println!("Restore cancelled.");
+                    return Ok(());
                 }
-                child.wait().await.context("waiting for child after SIGINT")?
             }
+            // Keep the current state so a bad restore can itself be undone, as protected
+            // as the archive being restored.
+            let safety = backup::collect(&home, false)?;
+            if !safety.files.is_empty() {
+                let dest = backup::default_archive_path(&settings::awx_dir()?, passphrase.is_some());
+                backup::write_archive(&safety, &dest, passphrase.as_deref()).await?;
+                println!("Saved current files to {}", dest.display());
+            }
+            let written = backup::restore(&contents, &home)?;
+            println!("Restored {} files.", written.len());
+            Ok(())
+        }
+        ConfigCommand::Prune { unused_days, no_remote } => {
+            run_prune(&home, *unused_days, *no_remote || offline::is_offline(), no_interactive).await
+        }
+    }
+}
+
+async fn run_prune(home: &std::path::Path, unused_days: i64, no_remote: bool, no_interactive: bool) -> Result<()> {
+    let settings = settings::load_settings()?;
+    let profiles = load_profiles(&settings, no_interactive)?;
+    let now = Utc::now();
+    let history = metrics::enabled_by(settings.metrics);
+    let mut found = Vec::new();
+    if history {
+        found.extend(prune::unused(&profiles, &metrics::MetricsStore::load(&metrics::store_path()?), unused_days, now));
+    }
+    let mut unchecked = Vec::new();
+    if !no_remote {
+        let (stale, skipped) = prune::unreachable(&profiles, &aws_dir()?, now).await;
+        progress::finish();
+        found.extend(stale);
+        unchecked = skipped;
+    }
+    let candidates = prune::candidates(found);
+    print!("{}", render::prune_report(&candidates, &unchecked, history));
+    if candidates.is_empty() {
+        return Ok(());
+    }
+    if no_interactive {
+        println!("Run `awx config prune` interactively to comment out or delete them.");
+        return Ok(());
+    }
+    let labels: Vec<&str> = prune::Action::ALL.iter().map(|a| a.label()).collect();
+    let mut config_actions = Vec::new();
+    let mut credentials_actions = Vec::new();
+    for c in &candidates {
+        let action = prune::Action::ALL[ask::select(ask::Key("prune", Some(&c.profile)), &format!("What to do with '{}'?", c.profile), &labels, 0)?];
+        let header = if c.profile == "default" { c.profile.clone() } else { format!("profile {}", c.profile) };
+        config_actions.push((header, action));
+        credentials_actions.push((c.profile.clone(), action));
+    }
+    let files = aws_files()?;
+    let today = now.format("%Y-%m-%d").to_string();
+    let mut edits = Vec::new();
+    for (path, actions) in [(&files.config, &config_actions), (&files.credentials, &credentials_actions)] {
+        let Ok(old) = std::fs::read_to_string(path) else {
+            continue;
         };
+        let new = prune::apply(&old, actions, &today);
+        if new != old {
+            print!("{}", render::file_diff(path, &prune::diff(&old, &new)));
+            edits.push((path.clone(), new));
+        }
+    }
+    if edits.is_empty() {
+        println!("Nothing changed.");
+        return Ok(());
+    }
+    if !ask::confirm(ask::Key("write_pruned_config", None), "Write these changes?", false)? {
+        println!("Prune cancelled.");
+        return Ok(());
+    }
+    let safety = backup::collect(home, false)?;
+    let dest = backup::default_archive_path(&settings::awx_dir()?, false);
+    backup::write_archive(&safety, &dest, None).await?;
+    println!("Saved current files to {}", dest.display());
+    for (path, content) in edits {
+        state::replace(&path, content.as_bytes())?;
+        println!("Updated {}", path.display());
+    }
+    Ok(())
+}
 
-        if let Some(code) = status.code() {
-            return Ok(code);
-        } else if let Some(sig) = status.signal() {
-            let exit_code = 128 + sig;
-            return Ok(exit_code as i32);
+fn backup_passphrase(no_interactive: bool, confirm: bool) -> Result<String> {
+    if let Some(p) = aws_env::current_lookup(backup::PASSPHRASE_ENV) {
+        if !p.is_empty() {
+            return Ok(p);
         }
     }
+    if no_interactive {
+        return Err(anyhow!("Set {} to use encrypted backups in non-interactive mode", backup::PASSPHRASE_ENV));
+    }
+    let confirmation = confirm.then_some(("Repeat passphrase", "Passphrases do not match"));
+    ask::password(ask::Key("backup_passphrase", None), "Backup passphrase", confirmation)
+}
 
-    Ok(0)
+async fn run_selftest() -> Result<()> {
+    let mut checks = selftest::environment_checks(&aws_files()?, &settings::awx_dir()?).await;
+    checks.extend(selftest::pipeline_checks().await?);
+    print!("{}", output::render(|| Ok(render::selftest_report(&checks)), || output::checks(&checks))?);
+    let failed = checks.iter().filter(|c| c.status == selftest::Status::Fail).count();
+    if failed > 0 {
+        return Err(anyhow!("{} self-test check(s) failed", failed));
+    }
+    Ok(())
 }
 
-async fn print_config(profiles: &HashMap<String, Profile>) -> Result<()> {
-    let bold = Style::new().bold();
-    println!("Discovered profiles:");
-    for (name, p) in profiles.iter() {
-        let mut badges = Vec::new();
-        if name == "default" {
-            badges.push("default");
-        }
-        if p.is_sso() {
-            badges.push("SSO");
-        }
-        if p.is_role() {
-            badges.push("ROLE");
-        }
-        if p.requires_mfa() {
-            badges.push("MFA");
-        }
-        if p.is_static() {
-            badges.push("STATIC");
+// Gather the sanitized bug-report snapshot and write it to `output`.
+async fn run_debug_bundle(profile: Option<String>, output: Option<PathBuf>) -> Result<()> {
+    let awx_dir = settings::awx_dir()?;
+    let files = aws_files()?;
+    let now = Utc::now();
+    let checks = selftest::environment_checks(&files, &awx_dir).await;
+    let os = format!("{} {}", std::env::consts::OS, std::env::consts::ARCH);
+    let aws_version = selftest::version_of("aws", &["--version"]).await;
+    let report = console::strip_ansi_codes(&render::selftest_report(&checks)).to_string();
+    let mut bundle = debug_bundle::Bundle::new(&os, aws_version, report, now);
+    bundle.environment = aws_env::describe(&aws_env::current_lookup)
+        .into_iter()
+        .map(|(knob, value)| (knob.name.to_string(), value.unwrap_or_else(|| "unset".to_string())))
+        .collect();
+    bundle.add_file("aws config", &files.config, debug_bundle::sanitize_ini);
+    bundle.add_file("aws credentials", &files.credentials, debug_bundle::sanitize_ini);
+    bundle.add_file("awx config.toml", &awx_dir.join("config.toml"), debug_bundle::sanitize_settings);
+    bundle.last_error = debug_bundle::last_error(&awx_dir);
+
+    let profiles = settings::load_settings().and_then(|s| load_profiles(&s, true)).unwrap_or_default();
+    let name = profile
+        .or_else(|| aws_env::env_profile(&aws_env::current_lookup).map(|(p, _)| p))
+        .unwrap_or_else(|| "default".to_string());
+    if profiles.contains_key(&name) {
+        let source = aws_env::Source::Flag("--profile");
+        let explain = render::explain(&profiles, &name, source, &files, &[], &aws_env::current_lookup, now.timestamp())?;
+        bundle.explain = Some(debug_bundle::mask_keys(&console::strip_ansi_codes(&explain)));
+    }
+
+    let json = serde_json::to_string_pretty(&bundle)?;
+    match output {
+        Some(path) if path.as_os_str() == "-" => println!("{}", json),
+        output => {
+            let dest = output.unwrap_or_else(|| debug_bundle::default_path(&awx_dir, now));
+            state::write(&dest, json.as_bytes())?;
+            eprintln!("Debug bundle written to {}", dest.display());
+            eprintln!("{}", theme::style(Role::Muted).apply_to("Secrets are redacted; read it through before attaching it to a bug report."));
         }
-        let badge_str = badges.iter().map(|b| format!("[{}]", b)).collect::<Vec<_>>().join("");
-        println!("  {} {}", bold.apply_to(name), badge_str);
     }
     Ok(())
 }
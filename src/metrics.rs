@@ -0,0 +1,134 @@
+// Opt-in, local-only usage metrics.
+//
+// Nothing is sent anywhere: when enabled (`metrics = true` in ~/.awx/config.toml or
+// AWX_METRICS=1) awx keeps per-profile counters in ~/.awx/metrics.json so `awx stats`
+// can show which profiles are dead weight and how often re-auth interrupts work. The
+// last uses are also kept with their time and working directory, from which the
+// profile picker suggests the likely next profile (see `suggest`).
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+static STORE_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// The profile was selected for a run or login.
+    Use,
+    /// Resolving credentials for the profile failed.
+    AuthFailure,
+    /// An MFA code was requested.
+    MfaPrompt,
+    /// `aws sso login` had to be run.
+    SsoLogin,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProfileStats {
+    pub uses: u64,
+    pub auth_failures: u64,
+    pub mfa_prompts: u64,
+    pub sso_logins: u64,
+    pub last_used: Option<DateTime<Utc>>,
+}
+
+impl ProfileStats {
+    /// Share of uses that ended in an auth failure, 0.0 when unused.
+    pub fn failure_rate(&self) -> f64 {
+        if self.uses == 0 {
+            0.0
+        } else {
+            self.auth_failures as f64 / self.uses as f64
+        }
+    }
+}
+
+/// Uses kept in the history, newest last.
+pub const HISTORY_LEN: usize = 200;
+
+/// One use of a profile.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UseRecord {
+    pub profile: String,
+    pub at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetricsStore {
+    pub profiles: HashMap<String, ProfileStats>,
+    pub history: Vec<UseRecord>,
+}
+
+impl MetricsStore {
+    pub fn load(path: &Path) -> MetricsStore {
+        crate::state::read_json(path).unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        crate::state::write_json(path, self)
+    }
+
+    pub fn apply(&mut self, profile: &str, event: Event, now: DateTime<Utc>) {
+        let stats = self.profiles.entry(profile.to_string()).or_default();
+        match event {
+            Event::Use => {
+                stats.uses += 1;
+                stats.last_used = Some(now);
+            }
+            Event::AuthFailure => stats.auth_failures += 1,
+            Event::MfaPrompt => stats.mfa_prompts += 1,
+            Event::SsoLogin => stats.sso_logins += 1,
+        }
+    }
+
+    /// Add a use in `dir` to the history, dropping the oldest beyond `HISTORY_LEN`.
+    pub fn log_use(&mut self, profile: &str, at: DateTime<Utc>, dir: Option<PathBuf>) {
+        self.history.push(UseRecord {
+            profile: profile.to_string(),
+            at,
+            dir,
+        });
+        let excess = self.history.len().saturating_sub(HISTORY_LEN);
+        self.history.drain(..excess);
+    }
+}
+
+pub fn store_path() -> Result<PathBuf> {
+    Ok(crate::settings::awx_dir()?.join("metrics.json"))
+}
+
+pub fn enabled_by(settings_flag: bool) -> bool {
+    settings_flag || matches!(crate::aws_env::current_lookup("AWX_METRICS").as_deref(), Some("1") | Some("true"))
+}
+
+/// Turn recording on for this process.
+pub fn enable(path: PathBuf) {
+    let _ = STORE_PATH.set(path);
+}
+
+/// Record an event when metrics are enabled; never fails the caller.
+pub fn record(profile: &str, event: Event) {
+    let Some(path) = STORE_PATH.get() else {
+        return;
+    };
+    let mut store = MetricsStore::load(path);
+    let now = Utc::now();
+    store.apply(profile, event, now);
+    if event == Event::Use {
+        store.log_use(profile, now, std::env::current_dir().ok());
+    }
+    let _ = store.save(path);
+}
+
+/// The recorded metrics when enabled.
+pub fn load_enabled() -> Option<MetricsStore> {
+    STORE_PATH.get().map(|path| MetricsStore::load(path))
+}
@@ -0,0 +1,128 @@
+// Webhook notifications for sensitive access.
+//
+// With a `[notify]` webhook in ~/.awx/config.toml, awx posts a message when a listed
+// profile is used or a listed role (such as a break-glass role) is assumed, and whenever
+// `awx root` assumes a member account's root user, before the user's command runs. The JSON body carries `text` for Slack-style incoming webhooks
+// and the event fields for anything else. Delivery goes through curl (see
+// `backend::post_json`), which keeps the webhook's secret out of the process list. A
+// failed delivery is reported but never stops the command.
+use std::sync::OnceLock;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+
+use crate::exec::glob_match;
+use crate::settings::NotifySettings;
+use crate::theme::{self, Role};
+use crate::{aws_env, backend, offline};
+
+static SETTINGS: OnceLock<NotifySettings> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event<'a> {
+    /// The profile was selected for a run or login.
+    ProfileUsed { profile: &'a str },
+    /// A role was assumed.
+    RoleAssumed { role_arn: &'a str, session_name: &'a str },
+    /// The root user of a member account was assumed (`awx root`).
+    RootAssumed { target: &'a str, task_policy_arn: &'a str },
+}
+
+impl Event<'_> {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::ProfileUsed { .. } => "profile_used",
+            Event::RoleAssumed { .. } => "role_assumed",
+            Event::RootAssumed { .. } => "root_assumed",
+        }
+    }
+
+    /// Whether `settings` ask for this event. Root sessions are always announced.
+    pub fn wanted(&self, settings: &NotifySettings) -> bool {
+        let (patterns, value) = match self {
+            Event::ProfileUsed { profile } => (&settings.profiles, profile),
+            Event::RoleAssumed { role_arn, .. } => (&settings.roles, role_arn),
+            Event::RootAssumed { .. } => return true,
+        };
+        patterns.iter().any(|p| glob_match(p, value))
+    }
+}
+
+/// Turn notifications on for this process.
+pub fn configure(settings: &NotifySettings) {
+    let _ = SETTINGS.set(settings.clone());
+}
+
+/// The webhook body for `event`, done by `user` on `host`.
+pub fn payload(event: &Event<'_>, user: &str, host: &str, at: DateTime<Utc>) -> Value {
+    let (text, mut body) = match event {
+        Event::ProfileUsed { profile } => (
+            format!("awx: {}@{} is using profile '{}'", user, host, profile),
+            json!({ "profile": profile }),
+        ),
+        Event::RoleAssumed { role_arn, session_name } => (
+            format!("awx: {}@{} assumed role {} (session {})", user, host, role_arn, session_name),
+            json!({ "role_arn": role_arn, "session_name": session_name }),
+        ),
+        Event::RootAssumed { target, task_policy_arn } => (
+            format!(
+                "awx: {}@{} assumed the root user of account {} for {}",
+                user,
+                host,
+                target,
+                crate::root::task_name(task_policy_arn)
+            ),
+            json!({ "target": target, "task_policy_arn": task_policy_arn }),
+        ),
+    };
+    body["text"] = json!(text);
+    body["event"] = json!(event.name());
+    body["user"] = json!(user);
+    body["host"] = json!(host);
+    body["time"] = json!(at.to_rfc3339());
+    body
+}
+
+/// Post `event` when it is configured; never fails the caller.
+pub async fn send(event: Event<'_>) {
+    let Some(settings) = SETTINGS.get() else {
+        return;
+    };
+    let Some(url) = settings.webhook.as_deref() else {
+        return;
+    };
+    if !event.wanted(settings) {
+        return;
+    }
+    let user = aws_env::current_lookup("USER").unwrap_or_else(|| "unknown".to_string());
+    let body = payload(&event, &user, &hostname(), Utc::now());
+    if let Err(e) = deliver(url, &body).await {
+        eprintln!(
+            "{}",
+            theme::style(Role::Warning)
+                .apply_to(format!("Warning: {} notification not sent: {:#}", event.name(), e))
+        );
+    }
+}
+
+/// POST `body` as JSON to `url`.
+pub async fn deliver(url: &str, body: &Value) -> Result<()> {
+    offline::ensure_online("webhook notification")?;
+    backend::post_json(url, &[], body).await
+}
+
+#[cfg(unix)]
+pub fn hostname() -> String {
+    let mut buf = [0u8; 256];
+    if unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) } != 0 {
+        return "unknown".to_string();
+    }
+    let len = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+#[cfg(not(unix))]
+pub fn hostname() -> String {
+    crate::aws_env::current_lookup("COMPUTERNAME").unwrap_or_else(|| "unknown".to_string())
+}
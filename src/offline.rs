@@ -0,0 +1,20 @@
+// Offline mode (`--offline` or AWX_OFFLINE=1).
+//
+// awx then makes no network calls of its own: SSO sessions are judged by the cached
+// token file, credentials come from caches, static keys or session files, and any step
+// that would call STS, IAM or SSO fails at once with a message naming that step. The
+// command awx runs for the user is not restricted.
+use anyhow::{anyhow, Result};
+
+pub fn is_offline() -> bool {
+    crate::backend::flags().offline
+        || matches!(crate::aws_env::current_lookup("AWX_OFFLINE").as_deref(), Some("1") | Some("true"))
+}
+
+/// Fail when offline; `action` names the network step, e.g. "aws sts get-session-token".
+pub fn ensure_online(action: &str) -> Result<()> {
+    if is_offline() {
+        return Err(anyhow!("{} needs the network, but awx is running with --offline", action));
+    }
+    Ok(())
+}
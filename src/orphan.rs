@@ -0,0 +1,121 @@
+// Children that would outlive awx.
+//
+// awx waits for the command it runs and forwards Ctrl-C and SIGTERM to it, but when
+// awx itself crashes or is killed with SIGKILL nothing is left to stop the child, which
+// keeps running with the injected session. On Linux every child awx starts is
+// therefore tied to it with PR_SET_PDEATHSIG: the kernel sends the child SIGTERM (or
+// SIGKILL) as soon as awx goes away, for whatever reason. `orphans` in
+// ~/.awx/config.toml picks the signal, or "keep" to let children run on as before.
+// Only the direct child is signalled; what it started itself is up to it.
+//
+// The kernel actually signals when the *thread* that forked the child exits, not the
+// process. awx starts children from its runtime's worker threads, which live as long
+// as awx does; a child started from a thread that ends earlier (a blocking-pool
+// thread retiring after being idle, say) would be signalled while awx still runs, so
+// children must not be spawned from such threads.
+//
+// On Windows each child joins a job object that kills its processes when the job's
+// last handle closes. awx holds that handle and never closes it, so the job goes, and
+// the child with whatever it started since joining, when the awx process ends; both
+// "terminate" and "kill" mean this, as Windows has no SIGTERM. Other systems have no
+// equivalent and children may outlive awx there.
+use serde::Deserialize;
+use std::sync::OnceLock;
+use tokio::process::Command;
+
+/// What happens to a child when awx dies before it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Policy {
+    /// The child gets SIGTERM.
+    #[default]
+    Terminate,
+    /// The child gets SIGKILL.
+    Kill,
+    /// The child keeps running.
+    Keep,
+}
+
+impl Policy {
+    #[cfg(target_os = "linux")]
+    pub fn signal(self) -> Option<libc::c_int> {
+        match self {
+            Policy::Terminate => Some(libc::SIGTERM),
+            Policy::Kill => Some(libc::SIGKILL),
+            Policy::Keep => None,
+        }
+    }
+}
+
+static POLICY: OnceLock<Policy> = OnceLock::new();
+
+/// Apply `orphans` from the settings for this process.
+pub fn configure(policy: Policy) {
+    let _ = POLICY.set(policy);
+}
+
+/// Have `cmd`'s process signalled when awx exits before it.
+pub fn tie(cmd: &mut Command) {
+    #[cfg(target_os = "linux")]
+    {
+        let Some(signal) = POLICY.get().copied().unwrap_or_default().signal() else {
+            return;
+        };
+        let parent = std::process::id() as libc::pid_t;
+        unsafe {
+            cmd.pre_exec(move || {
+                if libc::prctl(libc::PR_SET_PDEATHSIG, signal as libc::c_ulong) == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                // awx may have died between fork and prctl.
+                if libc::getppid() != parent {
+                    libc::raise(signal);
+                }
+                Ok(())
+            });
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = cmd;
+}
+
+/// Have `child`, spawned from a command `tie` prepared, killed when awx exits before it
+/// (Windows; `tie` covers Linux).
+#[cfg(windows)]
+pub fn adopt(child: &tokio::process::Child) {
+    use windows_sys::Win32::Foundation::HANDLE;
+    use windows_sys::Win32::System::JobObjects::{
+        AssignProcessToJobObject, CreateJobObjectW, JobObjectExtendedLimitInformation, SetInformationJobObject,
+        JOBOBJECT_EXTENDED_LIMIT_INFORMATION, JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+    };
+
+    // The job's handle, as an address so it can live in a static.
+    static JOB: OnceLock<Option<usize>> = OnceLock::new();
+    if POLICY.get().copied().unwrap_or_default() == Policy::Keep {
+        return;
+    }
+    let Some(process) = child.raw_handle() else {
+        return;
+    };
+    let job = JOB.get_or_init(|| unsafe {
+        let job = CreateJobObjectW(std::ptr::null(), std::ptr::null());
+        if job.is_null() {
+            return None;
+        }
+        let mut limits: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = std::mem::zeroed();
+        limits.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+        let set = SetInformationJobObject(
+            job,
+            JobObjectExtendedLimitInformation,
+            (&limits as *const JOBOBJECT_EXTENDED_LIMIT_INFORMATION).cast(),
+            std::mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+        );
+        (set != 0).then_some(job as usize)
+    });
+    if let Some(job) = *job {
+        unsafe { AssignProcessToJobObject(job as HANDLE, process as HANDLE) };
+    }
+}
+
+#[cfg(not(windows))]
+pub fn adopt(_child: &tokio::process::Child) {}
@@ -0,0 +1,241 @@
+// Structured output (`--output human|json|yaml`).
+//
+// `-c`, `whoami`, `cache ls`, `broker status`, `selftest`, `s3express session` and the
+// summary of `exec` over several targets print a table for people by default. With
+// `--output json` or `--output yaml` they print the same facts as one document
+// instead, built here from what the command found, so a script reads every command
+// the same way rather than scraping text. The summary of `exec` stays on stderr, where
+// it does not mix with the children's output. YAML is written by hand: strings that a
+// YAML parser could read as anything else are double-quoted, as JSON is valid YAML.
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::OnceLock;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use serde_json::{json, Value};
+
+use crate::{backend, badge, broker, exec, iam, selftest, warm, Profile};
+
+static FORMAT: OnceLock<Format> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    #[default]
+    Human,
+    Json,
+    Yaml,
+}
+
+/// Use `format` for the rest of this process.
+pub fn configure(format: Format) {
+    let _ = FORMAT.set(format);
+}
+
+pub fn format() -> Format {
+    FORMAT.get().copied().unwrap_or_default()
+}
+
+/// `human()` in the human format, else `value()` as a JSON or YAML document.
+pub fn render(human: impl FnOnce() -> Result<String>, value: impl FnOnce() -> Value) -> Result<String> {
+    match structured(value)? {
+        Some(document) => Ok(document),
+        None => human(),
+    }
+}
+
+/// `value()` as a JSON or YAML document; None in the human format, for commands whose
+/// text output is not a plain string.
+pub fn structured(value: impl FnOnce() -> Value) -> Result<Option<String>> {
+    Ok(match format() {
+        Format::Human => None,
+        Format::Json => Some(format!("{}\n", serde_json::to_string_pretty(&value())?)),
+        Format::Yaml => Some(yaml(&value())),
+    })
+}
+
+/// `value` as a YAML document in block style.
+pub fn yaml(value: &Value) -> String {
+    let mut out = String::new();
+    match value {
+        Value::Object(map) if !map.is_empty() => block(&mut out, value, 0),
+        Value::Array(items) if !items.is_empty() => block(&mut out, value, 0),
+        scalar => {
+            let _ = writeln!(out, "{}", inline(scalar));
+        }
+    }
+    out
+}
+
+// The lines of a non-empty map or list, indented by `indent`.
+fn block(out: &mut String, value: &Value, indent: usize) {
+    let pad = " ".repeat(indent);
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                if nested(value) {
+                    let _ = writeln!(out, "{}{}:", pad, string(key));
+                    block(out, value, indent + 2);
+                } else {
+                    let _ = writeln!(out, "{}{}: {}", pad, string(key), inline(value));
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                if nested(item) {
+                    // The item's first line goes after the dash.
+                    let mut lines = String::new();
+                    block(&mut lines, item, indent + 2);
+                    let _ = write!(out, "{}- {}", pad, &lines[indent + 2..]);
+                } else {
+                    let _ = writeln!(out, "{}- {}", pad, inline(item));
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn nested(value: &Value) -> bool {
+    match value {
+        Value::Object(map) => !map.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        _ => false,
+    }
+}
+
+fn inline(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => string(s),
+        Value::Object(_) => "{}".to_string(),
+        Value::Array(_) => "[]".to_string(),
+    }
+}
+
+// A string as is when YAML reads it back as that string, else quoted.
+fn string(s: &str) -> String {
+    let reserved = ["true", "false", "yes", "no", "on", "off", "null", "~", "y", "n"];
+    let plain = !s.is_empty()
+        && s.trim() == s
+        && !s.starts_with(|c: char| c.is_ascii_digit() || "-?:,[]{}#&*!|>'\"%@`.+".contains(c))
+        && !s.contains(": ")
+        && !s.contains(" #")
+        && !s.ends_with(':')
+        && !s.chars().any(char::is_control)
+        && !reserved.contains(&s.to_ascii_lowercase().as_str());
+    if plain {
+        s.to_string()
+    } else {
+        Value::String(s.to_string()).to_string()
+    }
+}
+
+/// `awx -c`: the discovered profiles matching `filter`.
+pub fn config(profiles: &HashMap<String, Profile>, filter: &badge::Filter) -> Value {
+    let list: Vec<Value> = filter
+        .names(profiles)
+        .iter()
+        .map(|name| {
+            let p = &profiles[name];
+            json!({
+                "name": name,
+                "badges": badge::badges(p).iter().map(|b| b.label()).collect::<Vec<_>>(),
+                "account": badge::account(p),
+                "region": p.region,
+                "role_arn": p.role_arn,
+                "source_profile": p.source_profile,
+                "description": p.description,
+            })
+        })
+        .collect();
+    json!({ "profiles": list })
+}
+
+/// `awx whoami`.
+pub fn whoami(profile: &str, identity: &backend::CallerIdentity, details: Option<&iam::Details>) -> Value {
+    let lookup = |l: &iam::Lookup<Vec<String>>| match l {
+        Ok(names) => json!(names),
+        Err(reason) => json!({ "error": reason }),
+    };
+    let iam = details.map(|d| {
+        json!({
+            "principal": d.principal,
+            "permission_set": d.permission_set,
+            "attached_policies": lookup(&d.attached_policies),
+            "inline_policies": lookup(&d.inline_policies),
+            "groups": d.groups.as_ref().map(lookup),
+            "permissions_boundary": match &d.permissions_boundary {
+                Ok(boundary) => json!(boundary),
+                Err(reason) => json!({ "error": reason }),
+            },
+        })
+    });
+    json!({
+        "profile": profile,
+        "account": identity.account,
+        "arn": identity.arn,
+        "user_id": identity.user_id,
+        "iam": iam,
+    })
+}
+
+fn held(sessions: &[broker::Held]) -> Vec<Value> {
+    sessions
+        .iter()
+        .map(|h| json!({ "key": h.key(), "profile": h.profile, "duration": h.duration, "expiration": h.expiration }))
+        .collect()
+}
+
+/// `awx cache ls`.
+pub fn cache_ls(files: &[warm::Cached], sessions: &[broker::Held], now: DateTime<Utc>) -> Value {
+    let files: Vec<Value> = files
+        .iter()
+        .map(|f| json!({ "key": f.key, "path": f.path, "expiration": f.expiration, "expired": f.expiration <= now }))
+        .collect();
+    json!({ "files": files, "broker": held(sessions) })
+}
+
+/// `awx broker status`.
+pub fn broker_status(sessions: &[broker::Held]) -> Value {
+    json!({ "sessions": held(sessions) })
+}
+
+/// `awx selftest`.
+pub fn checks(checks: &[selftest::Check]) -> Value {
+    let list: Vec<Value> = checks
+        .iter()
+        .map(|c| {
+            let status = match c.status {
+                selftest::Status::Ok => "ok",
+                selftest::Status::Warn => "warn",
+                selftest::Status::Fail => "fail",
+                selftest::Status::Skip => "skip",
+            };
+            json!({ "name": c.name, "status": status, "detail": c.detail })
+        })
+        .collect();
+    json!({ "checks": list })
+}
+
+/// `awx exec` over several targets.
+pub fn exec_summary(outcomes: &[exec::Outcome]) -> Value {
+    let list: Vec<Value> = outcomes
+        .iter()
+        .map(|o| {
+            json!({
+                "profile": o.target.profile,
+                "region": o.target.region,
+                "exit_code": o.exit_code,
+                "seconds": (o.duration.as_secs_f64() * 10.0).round() / 10.0,
+                "status": o.status(),
+                "error": o.error,
+            })
+        })
+        .collect();
+    json!({ "targets": list })
+}
@@ -0,0 +1,169 @@
+// `awx pair`: hand a session to a headless machine.
+//
+// The headless side creates a throwaway RSA key pair and prints a request blob holding
+// the public key plus a short pairing code derived from it. On a laptop, where SSO and
+// MFA are easy, `awx pair approve <request>` shows the same code for comparison,
+// authenticates and answers with a response blob: the session descriptor encrypted
+// (`openssl enc`) under a key derived from a random secret, which is itself wrapped
+// with RSA-OAEP for the requester, and an HMAC-SHA256 over the wrapped secret and the
+// ciphertext under a second key derived from that secret. Only the machine holding
+// the private key can open it, and a response changed on the way fails its MAC before
+// anything is decrypted, so the blobs can travel over any copy-paste channel.
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+
+use crate::backend::openssl;
+use crate::session::Descriptor;
+
+pub const REQUEST_PREFIX: &str = "awxpair1:";
+pub const RESPONSE_PREFIX: &str = "awxpair1r:";
+// Crockford-style alphabet without easily confused characters.
+const CODE_ALPHABET: &[u8] = b"ABCDEFGHJKMNPQRSTVWXYZ23456789";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Response {
+    /// RSA-OAEP wrapped passphrase, base64.
+    key: String,
+    /// `openssl enc` output of the descriptor JSON, base64.
+    data: String,
+    /// HMAC-SHA256 of the wrapped key and the data, base64.
+    mac: String,
+}
+
+/// The `openssl enc` passphrase and the MAC key derived from the pairing secret.
+fn derive_keys(secret: &[u8]) -> (String, Hmac<Sha256>) {
+    let derive = |label: &[u8]| {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC takes keys of any length");
+        mac.update(label);
+        mac.finalize().into_bytes()
+    };
+    let passphrase = derive(b"awx pair encryption").iter().map(|b| format!("{:02x}", b)).collect();
+    let mac = Hmac::<Sha256>::new_from_slice(&derive(b"awx pair authentication")).expect("HMAC takes keys of any length");
+    (passphrase, mac)
+}
+
+/// The MAC over a wrapped key and the data it unlocks.
+fn authenticate(mut mac: Hmac<Sha256>, key: &[u8], data: &[u8]) -> Hmac<Sha256> {
+    mac.update(&(key.len() as u64).to_be_bytes());
+    mac.update(key);
+    mac.update(data);
+    mac
+}
+
+/// Short code shown on both machines so the user can check they are paired with
+/// the right requester, e.g. `K7QM-4XZP`.
+pub fn pairing_code(public_pem: &str) -> String {
+    let digest = Sha1::digest(public_pem.trim().as_bytes());
+    let chars: Vec<char> = digest
+        .iter()
+        .take(8)
+        .map(|b| CODE_ALPHABET[*b as usize % CODE_ALPHABET.len()] as char)
+        .collect();
+    format!("{}-{}", chars[..4].iter().collect::<String>(), chars[4..].iter().collect::<String>())
+}
+
+pub fn encode_request(public_pem: &str) -> String {
+    format!("{}{}", REQUEST_PREFIX, BASE64.encode(public_pem.trim()))
+}
+
+pub fn decode_request(blob: &str) -> Result<String> {
+    let b64 = blob
+        .trim()
+        .strip_prefix(REQUEST_PREFIX)
+        .ok_or_else(|| anyhow!("Not an awx pairing request (expected '{}...')", REQUEST_PREFIX))?;
+    let pem = String::from_utf8(BASE64.decode(b64).context("Pairing request is not valid base64")?)?;
+    if !pem.contains("PUBLIC KEY") {
+        return Err(anyhow!("Pairing request does not contain a public key"));
+    }
+    Ok(pem)
+}
+
+/// Where the pending private key of this machine is kept until the response arrives.
+pub fn pending_key_path(awx_dir: &Path) -> PathBuf {
+    awx_dir.join("pair").join("pending.pem")
+}
+
+/// Create the key pair, returning the public key PEM.
+pub async fn generate_keypair(private_key: &Path) -> Result<String> {
+    if let Some(dir) = private_key.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let key = private_key.to_string_lossy();
+    openssl(
+        &["genpkey", "-algorithm", "RSA", "-pkeyopt", "rsa_keygen_bits:3072", "-out", &key],
+        &[],
+        b"",
+        "pairing",
+    )
+    .await?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(private_key, std::fs::Permissions::from_mode(0o600))?;
+    }
+    let public = openssl(&["pkey", "-in", &key, "-pubout"], &[], b"", "pairing").await?;
+    Ok(String::from_utf8(public)?)
+}
+
+/// Encrypt `descriptor` for the holder of `public_pem`.
+pub async fn seal(public_pem: &str, descriptor: &Descriptor) -> Result<String> {
+    let mut secret = [0u8; 32];
+    getrandom::fill(&mut secret).map_err(|e| anyhow!("Cannot create a pairing secret: {}", e))?;
+    let pubkey = crate::tmp::file()?;
+    std::fs::write(pubkey.path(), public_pem)?;
+    let pubkey_path = pubkey.path().to_string_lossy();
+    let key = openssl(
+        &["pkeyutl", "-encrypt", "-pubin", "-inkey", &pubkey_path, "-pkeyopt", "rsa_padding_mode:oaep"],
+        &[],
+        &secret,
+        "pairing",
+    )
+    .await?;
+    let (passphrase, mac) = derive_keys(&secret);
+    let data = crate::backup::openssl(&serde_json::to_vec(descriptor)?, &passphrase, false).await?;
+    let mac = authenticate(mac, &key, &data).finalize().into_bytes();
+    let response = Response {
+        key: BASE64.encode(&key),
+        data: BASE64.encode(&data),
+        mac: BASE64.encode(mac),
+    };
+    Ok(format!("{}{}", RESPONSE_PREFIX, BASE64.encode(serde_json::to_vec(&response)?)))
+}
+
+/// Decrypt a response blob with the pending private key, once its MAC checks out.
+pub async fn open(private_key: &Path, blob: &str) -> Result<Descriptor> {
+    let b64 = blob
+        .trim()
+        .strip_prefix(RESPONSE_PREFIX)
+        .ok_or_else(|| anyhow!("Not an awx pairing response (expected '{}...')", RESPONSE_PREFIX))?;
+    let response: Response = serde_json::from_slice(&BASE64.decode(b64).context("Pairing response is not valid base64")?)
+        .context("Pairing response is malformed")?;
+    let malformed = || anyhow!("Pairing response is malformed");
+    let (key, data, tag) = (
+        BASE64.decode(&response.key).map_err(|_| malformed())?,
+        BASE64.decode(&response.data).map_err(|_| malformed())?,
+        BASE64.decode(&response.mac).map_err(|_| malformed())?,
+    );
+    let key_path = private_key.to_string_lossy();
+    let secret = openssl(
+        &["pkeyutl", "-decrypt", "-inkey", &key_path, "-pkeyopt", "rsa_padding_mode:oaep"],
+        &[],
+        &key,
+        "pairing",
+    )
+    .await
+    .context("Pairing response was not created for this machine's request")?;
+    let (passphrase, mac) = derive_keys(&secret);
+    authenticate(mac, &key, &data)
+        .verify_slice(&tag)
+        .map_err(|_| anyhow!("Pairing response was changed on the way or is damaged; approve the request again"))?;
+    let json = crate::backup::openssl(&data, &passphrase, true).await?;
+    serde_json::from_slice(&json).context("Pairing response does not contain a session")
+}
@@ -0,0 +1,83 @@
+// The AWS CLI command without `--` (`awx -p dev s3 ls`).
+//
+// The command awx passes on starts at the first argument that is neither an awx option,
+// an option's value nor an awx subcommand; from there on everything belongs to the CLI,
+// so `awx -p dev s3 ls --output json` runs `aws s3 ls --output json` even though awx has
+// an `--output` of its own. `normalize` marks that start with `--` before clap parses
+// the arguments. `--` written by hand still marks it unambiguously, and is needed for
+// the CLI commands sharing a name with an awx subcommand (`awx -p dev -- login`). A
+// first word one typo away from an awx subcommand (`awx whomai`) is refused instead of
+// being sent to the CLI after authenticating.
+use std::ffi::OsString;
+
+use anyhow::{anyhow, Result};
+
+/// `args` (program first) with `--` in front of the AWS CLI command, if there is one.
+pub fn normalize(args: Vec<OsString>, cmd: &clap::Command) -> Result<Vec<OsString>> {
+    let Some(at) = command_start(&args, cmd) else {
+        return Ok(args);
+    };
+    let word = args[at].to_string_lossy();
+    let subcommands = cmd.get_subcommands().flat_map(|s| std::iter::once(s.get_name()).chain(s.get_all_aliases()));
+    if let Some(near) = subcommands.filter(|name| name.len() >= 4).find(|name| one_edit_apart(&word, name)) {
+        return Err(anyhow!(
+            "'{}' is not an awx command; did you mean `awx {}`? To run `aws {}`, write `awx -- {}`",
+            word,
+            near,
+            word,
+            word
+        ));
+    }
+    let mut args = args;
+    args.insert(at, OsString::from("--"));
+    Ok(args)
+}
+
+// The index of the first argument of the AWS CLI command: the first positional
+// argument that is not an awx subcommand. None when there is none, or `--` comes first.
+fn command_start(args: &[OsString], cmd: &clap::Command) -> Option<usize> {
+    let mut i = 1;
+    while i < args.len() {
+        let arg = args[i].to_string_lossy();
+        if arg == "--" {
+            return None;
+        }
+        if let Some(long) = arg.strip_prefix("--") {
+            let takes_value = !long.contains('=') && cmd.get_arguments().any(|a| a.get_long() == Some(long) && takes_value(a));
+            i += if takes_value { 2 } else { 1 };
+            continue;
+        }
+        if let Some(shorts) = arg.strip_prefix('-').filter(|s| !s.is_empty()) {
+            // In `-np dev` only the last flag of the cluster can take the next argument.
+            let last = shorts.chars().position(|c| cmd.get_arguments().any(|a| a.get_short() == Some(c) && takes_value(a)));
+            i += if last == Some(shorts.chars().count() - 1) { 2 } else { 1 };
+            continue;
+        }
+        if cmd.find_subcommand(arg.as_ref()).is_some() {
+            return None;
+        }
+        return Some(i);
+    }
+    None
+}
+
+fn takes_value(arg: &clap::Arg) -> bool {
+    arg.get_action().takes_values()
+}
+
+// Whether `a` becomes `b` with one insertion, deletion, substitution or swap of
+// neighbouring characters.
+fn one_edit_apart(a: &str, b: &str) -> bool {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    if a == b {
+        return false;
+    }
+    let prefix = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+    let (a, b) = (&a[prefix..], &b[prefix..]);
+    match a.len() as isize - b.len() as isize {
+        0 => a[1..] == b[1..] || (a.len() >= 2 && a[0] == b[1] && a[1] == b[0] && a[2..] == b[2..]),
+        1 => a[1..] == *b,
+        -1 => *a == b[1..],
+        _ => false,
+    }
+}
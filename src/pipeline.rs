@@ -0,0 +1,463 @@
+// The credential-resolution pipeline.
+//
+// Resolving a profile is a short sequence of stages: check that its SSO session is
+// live and sign in when it is not, obtain base credentials (the host's for
+// `credential_source`, an MFA session for static keys that need one, or the source
+// profile as the AWS CLI resolves it), then assume the role on top. `authenticate`
+// plans the stages for a profile and runs them one after another; `credentials` runs
+// only the ones after the SSO check, through the authentication backend that handles
+// the profile (see `auth`). `mfa_session` and `assume_role` are the stages of the
+// built-in static+MFA and assume-role backends.
+//
+// Each stage runs under its own time limit. Stages that may ask something (an SSO
+// sign-in, an MFA code, the duration picker) are left unlimited at a terminal, since a
+// person sets the pace there. A `Cancel` handle stops a run between or during stages.
+// Every stage reports itself to the run's observers as it starts, finishes or fails:
+// the CLI's spinners are one observer (`progress::Renderer`), and a frontend that is
+// not a terminal subscribes with a channel (`Run::channel`) instead. The network calls
+// inside a stage still report their own finer steps through `progress::advance`.
+//
+// Failures that need the user rather than a fix (an SSO sign-in or an MFA code nobody
+// can give under `-n`, MFA codes rejected as many times as `mfa_attempts` allows) are
+// returned as typed errors instead of ending the process; `exit_code` maps them to the
+// CLI's exit codes, and other callers decide for themselves.
+//
+// A run given a directory with `keep_mfa_sessions_in` keeps the MFA sessions it obtains
+// and reuses one still kept from an earlier run (see `base_session`), so role profiles
+// sharing an MFA-protected source ask for one code between them.
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use tokio::sync::{mpsc, Notify};
+
+use crate::i18n::{t, Msg};
+use crate::{ambient, settings, Profile, StsCredentials};
+
+/// MFA codes asked for before giving up unless `mfa_attempts` says otherwise.
+pub const DEFAULT_MFA_ATTEMPTS: u32 = 3;
+
+/// A step of the resolution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Stage {
+    /// Whether the profile's SSO session is live.
+    SsoCheck,
+    /// Signing in to SSO.
+    SsoLogin,
+    /// The credentials a role is assumed with: the host's, or those of the source profile.
+    BaseCredentials,
+    /// A GetSessionToken session with an MFA code.
+    Mfa,
+    /// AssumeRole with the base credentials.
+    RoleHop,
+}
+
+impl Stage {
+    pub fn label(self) -> &'static str {
+        match self {
+            Stage::SsoCheck => "SSO session check",
+            Stage::SsoLogin => "SSO sign-in",
+            Stage::BaseCredentials => "base credentials",
+            Stage::Mfa => "MFA session",
+            Stage::RoleHop => "role assumption",
+        }
+    }
+
+    /// Whether the stage may prompt.
+    pub fn interactive(self) -> bool {
+        matches!(self, Stage::SsoLogin | Stage::Mfa | Stage::RoleHop)
+    }
+
+    /// How long the stage may take; None for interactive stages at a terminal.
+    pub fn limit(self, no_interactive: bool) -> Option<Duration> {
+        if self.interactive() && !no_interactive {
+            return None;
+        }
+        Some(Duration::from_secs(match self {
+            Stage::SsoCheck => 30,
+            Stage::SsoLogin => 300,
+            Stage::BaseCredentials | Stage::Mfa => 60,
+            Stage::RoleHop => 120,
+        }))
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.label())
+    }
+}
+
+/// What a run reports about its stages.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event {
+    Started { profile: String, stage: Stage },
+    Finished { profile: String, stage: Stage, elapsed_ms: u64 },
+    Failed { profile: String, stage: Stage, elapsed_ms: u64, error: String },
+}
+
+/// Receives the events of a run.
+pub trait Observer: Send + Sync {
+    fn event(&self, event: &Event);
+}
+
+impl Observer for mpsc::UnboundedSender<Event> {
+    fn event(&self, event: &Event) {
+        // A frontend that went away does not stop the run.
+        let _ = self.send(event.clone());
+    }
+}
+
+/// Stops a run; clones share the same state.
+#[derive(Debug, Clone, Default)]
+pub struct Cancel(Arc<(AtomicBool, Notify)>);
+
+impl Cancel {
+    pub fn cancel(&self) {
+        self.0 .0.store(true, Ordering::SeqCst);
+        self.0 .1.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0 .0.load(Ordering::SeqCst)
+    }
+
+    async fn cancelled(&self) {
+        loop {
+            let notified = self.0 .1.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// The stage a run stopped at because it was cancelled.
+#[derive(Debug)]
+pub struct Cancelled(pub Stage);
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Cancelled during the {}", self.0)
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// An SSO sign-in is needed and nobody is there to do it (`-n`).
+#[derive(Debug)]
+pub struct SsoLoginRequired(pub String);
+
+impl fmt::Display for SsoLoginRequired {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&t(Msg::SsoLoginRequired, &[("profile", &self.0)]))
+    }
+}
+
+impl std::error::Error for SsoLoginRequired {}
+
+/// Whether `err` says the profile needs an SSO sign-in first.
+pub fn needs_sso_login(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<SsoLoginRequired>().is_some()
+}
+
+/// An MFA code is needed and nobody is there to type it (`-n` without AWX_MFA_CODE).
+#[derive(Debug)]
+pub struct MfaCodeRequired(pub String);
+
+impl fmt::Display for MfaCodeRequired {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&t(Msg::MfaCodeRequired, &[("profile", &self.0), ("env", crate::prompt::MFA_CODE_ENV)]))
+    }
+}
+
+impl std::error::Error for MfaCodeRequired {}
+
+/// Every MFA code allowed was rejected or malformed.
+#[derive(Debug)]
+pub struct MfaFailed {
+    pub profile: String,
+    pub attempts: u32,
+}
+
+impl fmt::Display for MfaFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let attempts = self.attempts.to_string();
+        f.write_str(&t(Msg::MfaFailed, &[("profile", &self.profile), ("attempts", &attempts)]))
+    }
+}
+
+impl std::error::Error for MfaFailed {}
+
+/// The CLI's exit code for failures that ask something of the user: 2 for input that
+/// is missing, 3 for MFA codes that were all rejected.
+pub fn exit_code(err: &anyhow::Error) -> Option<u8> {
+    if needs_sso_login(err) || err.downcast_ref::<MfaCodeRequired>().is_some() {
+        Some(2)
+    } else if err.downcast_ref::<MfaFailed>().is_some() {
+        Some(3)
+    } else {
+        None
+    }
+}
+
+/// Observers and cancellation for one or more resolutions.
+#[derive(Clone, Default)]
+pub struct Run {
+    observers: Vec<Arc<dyn Observer>>,
+    cancel: Cancel,
+    mfa_attempts: Option<u32>,
+    mfa_sessions: Option<PathBuf>,
+}
+
+impl Run {
+    /// A run reporting to the terminal's spinners.
+    pub fn cli() -> Run {
+        Run::default().observe(Arc::new(crate::progress::Renderer))
+    }
+
+    /// A run whose events arrive on the returned channel.
+    pub fn channel() -> (Run, mpsc::UnboundedReceiver<Event>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (Run::default().observe(Arc::new(tx)), rx)
+    }
+
+    pub fn observe(mut self, observer: Arc<dyn Observer>) -> Run {
+        self.observers.push(observer);
+        self
+    }
+
+    pub fn cancelled_by(mut self, cancel: Cancel) -> Run {
+        self.cancel = cancel;
+        self
+    }
+
+    /// How many MFA codes are asked for before giving up (`mfa_attempts`).
+    pub fn mfa_attempts(mut self, attempts: u32) -> Run {
+        self.mfa_attempts = Some(attempts.max(1));
+        self
+    }
+
+    /// Keep MFA sessions in, and reuse them from, `awx_dir`.
+    pub fn keep_mfa_sessions_in(mut self, awx_dir: PathBuf) -> Run {
+        self.mfa_sessions = Some(awx_dir);
+        self
+    }
+
+    fn attempts(&self) -> u32 {
+        self.mfa_attempts.unwrap_or(DEFAULT_MFA_ATTEMPTS)
+    }
+
+    // The MFA session of the static profile `name`: a kept one when it lasts, else a
+    // fresh one, kept for the next profile that needs it.
+    async fn mfa_session(&self, profiles: &HashMap<String, Profile>, name: &str, mfa: &str, no_interactive: bool) -> Result<StsCredentials> {
+        let now = chrono::Utc::now();
+        if let Some(creds) = self.mfa_sessions.as_deref().and_then(|dir| crate::base_session::load(dir, name, now)) {
+            crate::progress::advance(&format!("reuse the MFA session of '{}'", name));
+            return Ok(creds);
+        }
+        let creds = crate::get_session_token_interactive(name, mfa, no_interactive, self.attempts()).await?;
+        if let (Some(dir), Some(profile)) = (&self.mfa_sessions, profiles.get(name)) {
+            crate::base_session::store(dir, profile, &creds, now);
+        }
+        Ok(creds)
+    }
+
+    fn emit(&self, event: Event) {
+        for observer in &self.observers {
+            observer.event(&event);
+        }
+    }
+
+    /// Run `work` as `stage` of `profile`'s resolution: reported, limited and cancellable.
+    pub async fn stage<T, F>(&self, profile: &str, stage: Stage, no_interactive: bool, work: F) -> Result<T>
+    where
+        F: std::future::Future<Output = Result<T>>,
+    {
+        if self.cancel.is_cancelled() {
+            return Err(Cancelled(stage).into());
+        }
+        self.emit(Event::Started {
+            profile: profile.to_string(),
+            stage,
+        });
+        let started = Instant::now();
+        let limited = async {
+            match stage.limit(no_interactive) {
+                Some(limit) => tokio::time::timeout(limit, work)
+                    .await
+                    .unwrap_or_else(|_| Err(anyhow!("The {} of '{}' took longer than {}s", stage, profile, limit.as_secs()))),
+                None => work.await,
+            }
+        };
+        let result = tokio::select! {
+            result = limited => result,
+            _ = self.cancel.cancelled() => Err(Cancelled(stage).into()),
+        };
+        let elapsed_ms = started.elapsed().as_millis() as u64;
+        let profile = profile.to_string();
+        self.emit(match &result {
+            Ok(_) => Event::Finished { profile, stage, elapsed_ms },
+            Err(e) => Event::Failed {
+                profile,
+                stage,
+                elapsed_ms,
+                error: format!("{:#}", e),
+            },
+        });
+        result
+    }
+}
+
+/// Resolve `profile` from start to end: the SSO stages, then `credentials`.
+pub async fn authenticate(
+    profiles: &HashMap<String, Profile>,
+    profile: &Profile,
+    settings: &settings::Settings,
+    no_interactive: bool,
+    run: &Run,
+) -> Result<Option<StsCredentials>> {
+    if profile.is_sso() {
+        let check = run.stage(&profile.name, Stage::SsoCheck, no_interactive, crate::check_sso_session(profile, settings));
+        // A check that fails (timeout, network) counts as not signed in.
+        if !check.await.unwrap_or(false) {
+            crate::offline::ensure_online(&format!("SSO login for profile '{}'", profile.name))?;
+            if no_interactive {
+                crate::sso_login_request(profile).emit();
+                return Err(SsoLoginRequired(profile.name.clone()).into());
+            }
+            run.stage(&profile.name, Stage::SsoLogin, no_interactive, crate::run_sso_login(profile, settings))
+                .await?;
+        }
+    }
+    credentials(profiles, profile, no_interactive, run).await
+}
+
+/// Where an AssumeRole gets its credentials from.
+enum Base {
+    /// The AWS CLI resolves the source profile itself.
+    Profile(String),
+    /// A session already in hand.
+    Session(StsCredentials),
+}
+
+/// The session to inject for `profile`, once any SSO session it needs is live; None
+/// when the AWS CLI resolves the profile itself (plain SSO or static keys).
+pub async fn credentials(
+    profiles: &HashMap<String, Profile>,
+    profile: &Profile,
+    no_interactive: bool,
+    run: &Run,
+) -> Result<Option<StsCredentials>> {
+    crate::auth::resolve(crate::auth::Request { profiles, profile, no_interactive, run }).await
+}
+
+/// The MFA session of the static `profile`, as the MFA stage.
+pub async fn mfa_session(
+    profiles: &HashMap<String, Profile>,
+    profile: &Profile,
+    serial: &str,
+    no_interactive: bool,
+    run: &Run,
+) -> Result<StsCredentials> {
+    let session = run.mfa_session(profiles, &profile.name, serial, no_interactive);
+    run.stage(&profile.name, Stage::Mfa, no_interactive, session).await
+}
+
+/// `profile`'s role, assumed with the host's credentials or those of its source profile.
+pub async fn assume_role(
+    profiles: &HashMap<String, Profile>,
+    profile: &Profile,
+    no_interactive: bool,
+    run: &Run,
+) -> Result<StsCredentials> {
+    let name = profile.name.as_str();
+    let base = match (&profile.source_profile, &profile.credential_source) {
+        (None, Some(source)) => {
+            let source = ambient::Source::parse(source)?;
+            Base::Session(run.stage(name, Stage::BaseCredentials, no_interactive, ambient::credentials(source)).await?)
+        }
+        (None, None) => return Err(anyhow!("source_profile or credential_source missing for role profile")),
+        (Some(source_name), _) => {
+            let base_profile = profiles
+                .get(source_name)
+                .ok_or_else(|| anyhow!("source_profile '{}' not found", source_name))?;
+            match &base_profile.mfa_serial {
+                Some(mfa) if base_profile.is_static() => {
+                    let session = run.mfa_session(profiles, source_name, mfa, no_interactive);
+                    Base::Session(run.stage(name, Stage::Mfa, no_interactive, session).await?)
+                }
+                _ if base_profile.is_sso() || base_profile.is_static() => Base::Profile(source_name.clone()),
+                _ => {
+                    return Err(anyhow!(
+                        "Unsupported source_profile auth method for '{}'. MVP supports SSO or static+MFA for source profiles.",
+                        source_name
+                    ))
+                }
+            }
+        }
+    };
+    let hop = role_hop(profiles, profile, base, no_interactive, run);
+    run.stage(name, Stage::RoleHop, no_interactive, hop).await
+}
+
+// Assume `profile`'s role with `base`.
+async fn role_hop(
+    profiles: &HashMap<String, Profile>,
+    profile: &Profile,
+    base: Base,
+    no_interactive: bool,
+    run: &Run,
+) -> Result<StsCredentials> {
+    let spec = profile.role_arn.as_deref().unwrap_or_default();
+    let session_name = crate::role_session_name(profile);
+    let source_name = match &base {
+        Base::Profile(source) => source.clone(),
+        Base::Session(_) => profile.source_profile.clone().unwrap_or_default(),
+    };
+    let role_arn = match (&profile.source_profile, &profile.credential_source) {
+        (None, Some(source)) => {
+            if let crate::arn::RoleSpec::Name(role) = crate::arn::RoleSpec::parse(spec)? {
+                return Err(anyhow!(
+                    "role_arn '{}' of profile '{}' names no account; with credential_source write <account>/{}",
+                    spec,
+                    profile.name,
+                    role
+                ));
+            }
+            crate::expand_role_arn(spec, profile, ambient::Source::parse(source)?.name()).await?
+        }
+        _ => crate::expand_role_arn(spec, profile, &source_name).await?,
+    };
+    // IAM is asked for the role's maximum only where that needs no MFA code.
+    let iam_as = match &base {
+        Base::Profile(source) => Some(source.as_str()),
+        Base::Session(_) => None,
+    };
+    let duration = crate::session_duration(profiles, profile, &role_arn, iam_as, no_interactive).await?;
+    let source = match base {
+        Base::Session(creds) => return crate::assume_role_with_env(&role_arn, &session_name, duration, &creds).await,
+        Base::Profile(source) => source,
+    };
+    match crate::assume_role_with_profile(&role_arn, &session_name, duration, &source).await {
+        Ok(creds) => Ok(creds),
+        // The role may require MFA while a static source profile has no mfa_serial:
+        // look up the user's devices instead of failing with a config hint.
+        Err(e) if !no_interactive && crate::is_access_denied(&e) && profiles.get(&source).is_some_and(Profile::is_static) => {
+            let Some(mfa) = crate::discover_mfa_serial(&source).await? else {
+                return Err(e);
+            };
+            let base = run.mfa_session(profiles, &source, &mfa, no_interactive).await?;
+            crate::assume_role_with_env(&role_arn, &session_name, duration, &base).await
+        }
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,234 @@
+// Binary cache of the parsed ~/.aws/config profile store.
+//
+// Large generated configs (hundreds of SSO profiles) make INI parsing a visible part
+// of startup. The parsed profiles are written to ~/.awx/cache/config.bin together with
+// the source file's mtime and size; a later run reuses them while both still match.
+// Secrets are never written: ~/.aws/credentials is always read directly, and a config
+// file carrying inline static keys or a credential_process command line (which may hold
+// one) is parsed every time instead of cached.
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use anyhow::{anyhow, Result};
+
+use crate::Profile;
+
+const MAGIC: &[u8; 4] = b"AWXP";
+// Bump whenever the encoded Profile layout changes.
+const FORMAT_VERSION: u8 = 9;
+
+/// Identity of a source file: modification time and size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceStamp {
+    secs: u64,
+    nanos: u32,
+    len: u64,
+}
+
+impl SourceStamp {
+    pub fn of(path: &Path) -> Option<SourceStamp> {
+        let meta = std::fs::metadata(path).ok()?;
+        let modified = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(SourceStamp {
+            secs: modified.as_secs(),
+            nanos: modified.subsec_nanos(),
+            len: meta.len(),
+        })
+    }
+}
+
+/// Return the cached profiles for `source` if the cache is current, otherwise parse
+/// with `parse` and refresh the cache. Cache problems never fail the load.
+pub fn load_or_parse<F>(source: &Path, cache_file: &Path, parse: F) -> Result<HashMap<String, Profile>>
+where
+    F: FnOnce(&[u8]) -> HashMap<String, Profile>,
+{
+    let stamp = SourceStamp::of(source);
+    if let Some(stamp) = stamp {
+        if let Some(profiles) = read_cache(cache_file, stamp) {
+            return Ok(profiles);
+        }
+    }
+    let content = std::fs::read(source).map_err(|e| anyhow!("Failed to read {}: {}", source.display(), e))?;
+    let profiles = parse(&content);
+    let has_secrets = profiles
+        .values()
+        .any(|p| {
+            p.aws_access_key_id.is_some()
+                || p.aws_secret_access_key.is_some()
+                || p.aws_session_token.is_some()
+                || p.credential_process.is_some()
+        });
+    if let (Some(stamp), false) = (stamp, has_secrets) {
+        let _ = write_cache(cache_file, stamp, &profiles);
+    }
+    Ok(profiles)
+}
+
+/// Whether `cache_file` holds the profiles of `source` as it is now.
+pub fn is_current(source: &Path, cache_file: &Path) -> bool {
+    SourceStamp::of(source).is_some_and(|stamp| read_cache(cache_file, stamp).is_some())
+}
+
+fn read_cache(cache_file: &Path, stamp: SourceStamp) -> Option<HashMap<String, Profile>> {
+    let bytes = std::fs::read(cache_file).ok()?;
+    let (cached_stamp, profiles) = decode(&bytes)?;
+    if cached_stamp != stamp {
+        return None;
+    }
+    Some(profiles)
+}
+
+fn write_cache(cache_file: &Path, stamp: SourceStamp, profiles: &HashMap<String, Profile>) -> Result<()> {
+    crate::state::write(cache_file, &encode(stamp, profiles))
+}
+
+// Non-secret profile fields, in encoding order; duration_seconds follows them as a
+// decimal string.
+fn fields(p: &Profile) -> [&Option<String>; 16] {
+    [
+        &p.description,
+        &p.region,
+        &p.sso_start_url,
+        &p.sso_region,
+        &p.sso_session,
+        &p.sso_account_id,
+        &p.role_arn,
+        &p.source_profile,
+        &p.credential_source,
+        &p.web_identity_token_file,
+        &p.mfa_serial,
+        &p.role_session_name,
+        &p.output,
+        &p.cli_pager,
+        &p.cli_binary_format,
+        &p.cli_history,
+    ]
+}
+
+fn fields_mut(p: &mut Profile) -> [&mut Option<String>; 16] {
+    [
+        &mut p.description,
+        &mut p.region,
+        &mut p.sso_start_url,
+        &mut p.sso_region,
+        &mut p.sso_session,
+        &mut p.sso_account_id,
+        &mut p.role_arn,
+        &mut p.source_profile,
+        &mut p.credential_source,
+        &mut p.web_identity_token_file,
+        &mut p.mfa_serial,
+        &mut p.role_session_name,
+        &mut p.output,
+        &mut p.cli_pager,
+        &mut p.cli_binary_format,
+        &mut p.cli_history,
+    ]
+}
+
+pub fn encode(stamp: SourceStamp, profiles: &HashMap<String, Profile>) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.push(FORMAT_VERSION);
+    out.extend_from_slice(&stamp.secs.to_le_bytes());
+    out.extend_from_slice(&stamp.nanos.to_le_bytes());
+    out.extend_from_slice(&stamp.len.to_le_bytes());
+    out.extend_from_slice(&(profiles.len() as u32).to_le_bytes());
+    for (name, p) in profiles.iter() {
+        put_str(&mut out, name);
+        for field in fields(p) {
+            put_opt(&mut out, field.as_deref());
+        }
+        put_opt(&mut out, p.duration_seconds.map(|d| d.to_string()).as_deref());
+    }
+    out
+}
+
+pub fn decode(bytes: &[u8]) -> Option<(SourceStamp, HashMap<String, Profile>)> {
+    let mut r = Reader { bytes, pos: 0 };
+    if r.take(4)? != MAGIC || r.u8()? != FORMAT_VERSION {
+        return None;
+    }
+    let stamp = SourceStamp {
+        secs: r.u64()?,
+        nanos: r.u32()?,
+        len: r.u64()?,
+    };
+    let count = r.u32()? as usize;
+    let mut profiles = HashMap::with_capacity(count.min(4096));
+    for _ in 0..count {
+        let name = r.string()?;
+        let mut p = Profile {
+            name: name.clone(),
+            ..Default::default()
+        };
+        for field in fields_mut(&mut p) {
+            *field = r.opt()?;
+        }
+        p.duration_seconds = match r.opt()? {
+            Some(d) => Some(d.parse().ok()?),
+            None => None,
+        };
+        profiles.insert(name, p);
+    }
+    if r.pos != bytes.len() {
+        return None;
+    }
+    Some((stamp, profiles))
+}
+
+fn put_str(out: &mut Vec<u8>, s: &str) {
+    out.extend_from_slice(&(s.len() as u32).to_le_bytes());
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn put_opt(out: &mut Vec<u8>, value: Option<&str>) {
+    match value {
+        Some(v) => {
+            out.push(1);
+            put_str(out, v);
+        }
+        None => out.push(0),
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let end = self.pos.checked_add(n)?;
+        let slice = self.bytes.get(self.pos..end)?;
+        self.pos = end;
+        Some(slice)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).ok()
+    }
+
+    fn opt(&mut self) -> Option<Option<String>> {
+        match self.u8()? {
+            0 => Some(None),
+            1 => Some(Some(self.string()?)),
+            _ => None,
+        }
+    }
+}
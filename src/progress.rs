@@ -0,0 +1,157 @@
+// Step feedback while credentials are resolved.
+//
+// Before a single-profile run awx works out which network round-trips the resolution
+// needs. When that is more than `round_trip_warning` (default 2), the expected steps are
+// listed up front and each one is announced as it starts, so a multi-second chain of
+// STS calls does not look like a hang. Network calls report themselves through
+// `advance`, which shows a spinner for the running step when stderr is a terminal.
+// Spinners are cleared before prompts and before the child takes over the terminal.
+// `Renderer` follows the stages of the resolution pipeline to that end.
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use console::Term;
+use indicatif::{ProgressBar, ProgressStyle};
+
+use crate::arn::RoleSpec;
+use crate::pipeline::{Event, Observer};
+use crate::settings::IdentityCheck;
+use crate::theme::{self, Role};
+use crate::{mfa_account, Profile};
+
+pub const DEFAULT_ROUND_TRIP_WARNING: usize = 2;
+
+struct Active {
+    total: usize,
+    done: usize,
+}
+
+struct State {
+    plan: Option<Active>,
+    spinner: Option<ProgressBar>,
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+    plan: None,
+    spinner: None,
+});
+
+fn state() -> std::sync::MutexGuard<'static, State> {
+    STATE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn spinners_enabled() -> bool {
+    Term::stderr().is_term()
+}
+
+/// The network round-trips needed to resolve `profile`, in order.
+pub fn plan(profiles: &HashMap<String, Profile>, profile: &Profile, identity_check: IdentityCheck) -> Vec<String> {
+    let mut steps = Vec::new();
+    if profile.is_sso() && identity_check == IdentityCheck::Sts {
+        steps.push(format!("check SSO session of '{}'", profile.name));
+    }
+    let mfa_steps = |steps: &mut Vec<String>, p: &Profile| {
+        if p.mfa_serial.as_deref().and_then(mfa_account).is_some() {
+            steps.push(format!("verify account of '{}'", p.name));
+        }
+        steps.push(format!("GetSessionToken with MFA for '{}'", p.name));
+    };
+    if let Some(role) = &profile.role_arn {
+        if let Some(base) = profile.source_profile.as_ref().and_then(|s| profiles.get(s)) {
+            if base.requires_mfa() && base.is_static() {
+                mfa_steps(&mut steps, base);
+            }
+        }
+        if let (None, Some(source)) = (&profile.source_profile, &profile.credential_source) {
+            if source != "Environment" {
+                steps.push(format!("read {} credentials", source));
+            }
+        }
+        if let (Ok(RoleSpec::Name(_)), Some(source)) = (RoleSpec::parse(role), &profile.source_profile) {
+            steps.push(format!("look up account of '{}'", source));
+        }
+        if crate::iam::verifies_trust() && !crate::offline::is_offline() {
+            steps.push(format!("read trust policy of {}", role));
+        }
+        steps.push(format!("AssumeRole {}", role));
+    } else if profile.requires_mfa() && profile.is_static() {
+        mfa_steps(&mut steps, profile);
+    }
+    steps
+}
+
+/// Announce `steps` when there are more than `threshold` of them and start counting.
+pub fn begin(profile: &str, steps: &[String], threshold: usize) {
+    if steps.len() <= threshold {
+        return;
+    }
+    let yellow = theme::style(Role::Warning);
+    eprintln!(
+        "{}",
+        yellow.apply_to(format!("Resolving '{}' takes {} network round-trips:", profile, steps.len()))
+    );
+    for (i, step) in steps.iter().enumerate() {
+        eprintln!("  {}. {}", i + 1, step);
+    }
+    state().plan = Some(Active {
+        total: steps.len(),
+        done: 0,
+    });
+}
+
+/// Report that a network step is starting; it replaces the previous step's spinner.
+pub fn advance(label: &str) {
+    let mut st = state();
+    let text = match st.plan.as_mut() {
+        Some(a) => {
+            a.done += 1;
+            format!("[{}/{}] {}", a.done.min(a.total), a.total, label)
+        }
+        None => label.to_string(),
+    };
+    if let Some(spinner) = st.spinner.take() {
+        spinner.finish_and_clear();
+    }
+    if spinners_enabled() {
+        let spinner = ProgressBar::new_spinner();
+        if let Ok(style) = ProgressStyle::with_template(&crate::theme::spinner_template()) {
+            spinner.set_style(style);
+        }
+        spinner.set_message(format!("{}...", text));
+        spinner.enable_steady_tick(Duration::from_millis(100));
+        st.spinner = Some(spinner);
+    } else if st.plan.is_some() {
+        eprintln!("{}", theme::style(Role::Muted).apply_to(format!("{}...", text)));
+    }
+}
+
+/// Remove the running spinner, e.g. before prompting. The plan stays active.
+pub fn clear() {
+    if let Some(spinner) = state().spinner.take() {
+        spinner.finish_and_clear();
+    }
+}
+
+/// Stop reporting steps.
+pub fn finish() {
+    let mut st = state();
+    if let Some(spinner) = st.spinner.take() {
+        spinner.finish_and_clear();
+    }
+    st.plan = None;
+}
+
+/// The terminal's observer of the resolution pipeline: a stage that may prompt, or
+/// one that failed, leaves no spinner behind.
+pub struct Renderer;
+
+impl Observer for Renderer {
+    fn event(&self, event: &Event) {
+        match event {
+            Event::Started { stage, .. } if stage.interactive() => clear(),
+            Event::Failed { .. } => clear(),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,138 @@
+// Profiles picked by project (`[[projects]]` in ~/.awx/config.toml).
+//
+// Each entry maps a directory glob (`path`, `~` for the home directory) or a git remote
+// glob (`remote`, compared as `host/owner/repo` whatever the URL's form) to a profile.
+// When awx runs without a profile inside a matching directory or repository, the
+// picker is replaced by one confirmation line for the mapped profile; declining shows
+// the full list, and with --no-interactive the mapped profile is used after
+// AWS_PROFILE. The first matching entry wins. A directory matches a `path` when it or
+// one of its parents does. Remotes are read from the repository's .git/config without
+// running git.
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+
+use crate::exec::glob_match;
+
+static MAPPINGS: OnceLock<Vec<Mapping>> = OnceLock::new();
+
+/// Apply the configured mappings for this process.
+pub fn configure(mappings: &[Mapping]) {
+    if !mappings.is_empty() {
+        let _ = MAPPINGS.set(mappings.to_vec());
+    }
+}
+
+/// One `[[projects]]` entry.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Mapping {
+    pub profile: String,
+    /// Directory glob, e.g. "~/work/payments*".
+    pub path: Option<String>,
+    /// Remote glob, e.g. "github.com/acme/payments-*".
+    pub remote: Option<String>,
+}
+
+/// Why a mapping applies, for the confirmation line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reason {
+    Directory(PathBuf),
+    Remote(String),
+}
+
+impl std::fmt::Display for Reason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Reason::Directory(dir) => write!(f, "directory {}", dir.display()),
+            Reason::Remote(remote) => write!(f, "repository {}", remote),
+        }
+    }
+}
+
+/// `host/owner/repo` for a remote URL in any of git's forms.
+pub fn normalize_remote(url: &str) -> String {
+    let url = url.trim();
+    let rest = match url.split_once("://") {
+        Some((_, rest)) => rest.to_string(),
+        // scp-like `user@host:owner/repo`
+        None => url.replacen(':', "/", 1),
+    };
+    let rest = rest.rsplit_once('@').map(|(_, r)| r).unwrap_or(&rest);
+    let rest = rest.trim_end_matches('/');
+    rest.strip_suffix(".git").unwrap_or(rest).to_string()
+}
+
+/// The remote URLs of the repository containing `dir`.
+pub fn git_remotes(dir: &Path) -> Vec<String> {
+    let Some(config) = dir.ancestors().find_map(|d| git_config(&d.join(".git"))) else {
+        return Vec::new();
+    };
+    let mut in_remote = false;
+    let mut urls = Vec::new();
+    for line in config.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_remote = line.starts_with("[remote ");
+        } else if let Some((key, value)) = line.split_once('=') {
+            if in_remote && key.trim() == "url" {
+                urls.push(value.trim().trim_matches('"').to_string());
+            }
+        }
+    }
+    urls
+}
+
+// The config of the repository whose `.git` is `dot_git`: a directory, or a file
+// pointing at one (worktrees and submodules).
+fn git_config(dot_git: &Path) -> Option<String> {
+    let git_dir = if dot_git.is_dir() {
+        dot_git.to_path_buf()
+    } else {
+        let content = std::fs::read_to_string(dot_git).ok()?;
+        let target = PathBuf::from(content.trim().strip_prefix("gitdir:")?.trim());
+        let target = dot_git.parent()?.join(target);
+        // A worktree keeps its config in the main repository.
+        match std::fs::read_to_string(target.join("commondir")) {
+            Ok(common) => target.join(common.trim()),
+            Err(_) => target,
+        }
+    };
+    std::fs::read_to_string(git_dir.join("config")).ok()
+}
+
+fn expand_home(pattern: &str, home: Option<&Path>) -> String {
+    match (pattern.strip_prefix("~/"), home) {
+        (Some(rest), Some(home)) => format!("{}/{}", home.display(), rest),
+        _ => pattern.to_string(),
+    }
+}
+
+/// The first of `mappings` that applies in `cwd`, given the repository's `remotes`.
+pub fn matching<'a>(mappings: &'a [Mapping], cwd: &Path, remotes: &[String], home: Option<&Path>) -> Option<(&'a Mapping, Reason)> {
+    let remotes: Vec<String> = remotes.iter().map(|r| normalize_remote(r)).collect();
+    mappings.iter().find_map(|m| {
+        if let Some(pattern) = &m.path {
+            let pattern = expand_home(pattern, home);
+            let pattern = pattern.trim_end_matches('/');
+            // Outermost first: `*` also matches '/', so deeper directories match too.
+            let ancestors: Vec<&Path> = cwd.ancestors().collect();
+            if let Some(dir) = ancestors.into_iter().rev().find(|d| glob_match(pattern, &d.to_string_lossy())) {
+                return Some((m, Reason::Directory(dir.to_path_buf())));
+            }
+        }
+        if let Some(pattern) = &m.remote {
+            if let Some(remote) = remotes.iter().find(|r| glob_match(pattern, r)) {
+                return Some((m, Reason::Remote(remote.clone())));
+            }
+        }
+        None
+    })
+}
+
+/// The configured mapping for the current directory, if any.
+pub fn current() -> Option<(&'static Mapping, Reason)> {
+    let mappings = MAPPINGS.get()?;
+    let cwd = std::env::current_dir().ok()?;
+    matching(mappings, &cwd, &git_remotes(&cwd), dirs::home_dir().as_deref())
+}
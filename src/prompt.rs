@@ -0,0 +1,82 @@
+// Machine-readable input requests for non-interactive mode.
+//
+// When awx runs with `--no-interactive` it must never block on a prompt. Instead,
+// every place that would normally ask the user for something writes a single JSON
+// line to stderr describing the missing input, so wrapper UIs and automation can
+// collect it and re-invoke awx with the answer supplied.
+use serde::Serialize;
+
+/// Environment variable that supplies an MFA code without prompting.
+pub const MFA_CODE_ENV: &str = "AWX_MFA_CODE";
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "input", rename_all = "snake_case")]
+pub enum InputRequest {
+    /// No profile could be determined; one of `options` must be chosen.
+    Profile {
+        options: Vec<String>,
+        provide_via: Vec<String>,
+    },
+    /// A 6-digit MFA code is needed for `mfa_serial`.
+    MfaCode {
+        profile: String,
+        mfa_serial: String,
+        provide_via: Vec<String>,
+    },
+    /// The SSO session is missing or expired and a browser login is needed.
+    SsoLogin {
+        profile: String,
+        start_url: Option<String>,
+        sso_region: Option<String>,
+        command: String,
+    },
+}
+
+#[derive(Serialize)]
+struct Envelope<'a> {
+    awx: &'static str,
+    #[serde(flatten)]
+    request: &'a InputRequest,
+}
+
+impl InputRequest {
+    pub fn profile(mut options: Vec<String>) -> Self {
+        options.sort();
+        InputRequest::Profile {
+            options,
+            provide_via: vec!["--profile".to_string(), "AWS_PROFILE".to_string()],
+        }
+    }
+
+    pub fn mfa_code(profile: &str, mfa_serial: &str) -> Self {
+        InputRequest::MfaCode {
+            profile: profile.to_string(),
+            mfa_serial: mfa_serial.to_string(),
+            provide_via: vec![MFA_CODE_ENV.to_string()],
+        }
+    }
+
+    pub fn sso_login(profile: &str, start_url: Option<&str>, sso_region: Option<&str>) -> Self {
+        InputRequest::SsoLogin {
+            profile: profile.to_string(),
+            start_url: start_url.map(str::to_string),
+            sso_region: sso_region.map(str::to_string),
+            command: format!("aws sso login --profile {}", profile),
+        }
+    }
+
+    /// Serialize as a single JSON line tagged with `"awx": "input_required"`.
+    pub fn to_json_line(&self) -> String {
+        let envelope = Envelope {
+            awx: "input_required",
+            request: self,
+        };
+        serde_json::to_string(&envelope).unwrap_or_default()
+    }
+
+    /// Write the request to stderr.
+    pub fn emit(&self) {
+        crate::progress::clear();
+        eprintln!("{}", self.to_json_line());
+    }
+}
@@ -0,0 +1,263 @@
+// Finding and removing stale profiles (`awx config prune`).
+//
+// A profile is a candidate when the usage history (`metrics = true`) shows no use for
+// the given number of days, when its source_profile is gone, when its role can no
+// longer be assumed (the role was deleted or stopped trusting the source), or when SSO
+// no longer grants its account and role. The AWS checks are made only where they need
+// no prompt: profiles with mfa_serial, or whose SSO session is not signed in, are listed
+// as not checked instead. Nothing is changed until each candidate has been kept,
+// commented out or deleted, the resulting diff confirmed and ~/.aws backed up.
+use std::collections::HashMap;
+use std::path::Path;
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::backend::{self, CredentialSource};
+use crate::metrics::MetricsStore;
+use crate::troubleshoot::{classify, ErrorKind};
+use crate::Profile;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    pub profile: String,
+    pub reasons: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Keep,
+    CommentOut,
+    Delete,
+}
+
+impl Action {
+    pub const ALL: [Action; 3] = [Action::Keep, Action::CommentOut, Action::Delete];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::Keep => "Keep",
+            Action::CommentOut => "Comment out",
+            Action::Delete => "Delete",
+        }
+    }
+}
+
+/// Profiles `store` shows unused for `days` at `now`, with the reason.
+pub fn unused(
+    profiles: &HashMap<String, Profile>,
+    store: &MetricsStore,
+    days: i64,
+    now: DateTime<Utc>,
+) -> Vec<(String, String)> {
+    let mut found = Vec::new();
+    for name in profiles.keys().filter(|n| is_profile(n)) {
+        // A source_profile is used through the profiles naming it.
+        if profiles.values().any(|p| p.source_profile.as_ref() == Some(name)) {
+            continue;
+        }
+        let last_used = store.profiles.get(name).and_then(|s| s.last_used);
+        match last_used {
+            Some(at) if now - at >= Duration::days(days) => {
+                let reason = format!("not used in {} days (last {})", (now - at).num_days(), at.format("%Y-%m-%d"));
+                found.push((name.clone(), reason));
+            }
+            Some(_) => {}
+            None => found.push((name.clone(), "no recorded use".to_string())),
+        }
+    }
+    found.sort();
+    found
+}
+
+/// Ask AWS whether the roles and SSO accounts of `profiles` are still reachable. Returns
+/// the unreachable ones and those that could not be checked, each with the reason.
+pub async fn unreachable(
+    profiles: &HashMap<String, Profile>,
+    aws_dir: &Path,
+    now: DateTime<Utc>,
+) -> (Vec<(String, String)>, Vec<(String, String)>) {
+    let (mut stale, mut unchecked) = (Vec::new(), Vec::new());
+    let mut names: Vec<&String> = profiles.keys().filter(|n| is_profile(n)).collect();
+    names.sort();
+    for name in names {
+        let p = &profiles[name];
+        if let Some(role) = &p.role_arn {
+            let Some(source_name) = &p.source_profile else {
+                continue;
+            };
+            let Some(source) = profiles.get(source_name) else {
+                stale.push((name.clone(), format!("source_profile '{}' does not exist", source_name)));
+                continue;
+            };
+            if let Some(why) = needs_prompt(p, aws_dir, now).or_else(|| needs_prompt(source, aws_dir, now)) {
+                unchecked.push((name.clone(), why));
+                continue;
+            }
+            crate::progress::advance(&format!("Checking role of '{}'", name));
+            let result = backend::sts()
+                .assume_role(role, "awx-prune", 900, CredentialSource::Profile(source_name))
+                .await;
+            match result {
+                Ok(_) => {}
+                Err(e) if classify(&format!("{:#}", e)) == ErrorKind::AccessDenied => stale.push((
+                    name.clone(),
+                    format!("{} can no longer be assumed from '{}' (deleted, or no longer trusted)", role, source_name),
+                )),
+                Err(e) => unchecked.push((name.clone(), format!("{:#}", e))),
+            }
+        } else if uses_sso(p) {
+            if let Some(why) = needs_prompt(p, aws_dir, now) {
+                unchecked.push((name.clone(), why));
+                continue;
+            }
+            crate::progress::advance(&format!("Checking SSO access of '{}'", name));
+            match backend::sso().export_credentials(name).await {
+                Ok(_) => {}
+                Err(e) if revoked(&format!("{:#}", e)) => {
+                    stale.push((name.clone(), "SSO no longer grants this account and role".to_string()))
+                }
+                Err(e) => unchecked.push((name.clone(), format!("{:#}", e))),
+            }
+        }
+    }
+    (stale, unchecked)
+}
+
+/// Whether `name` is a profile rather than another section such as `sso-session corp`.
+fn is_profile(name: &str) -> bool {
+    !name.contains(char::is_whitespace)
+}
+
+/// Why using `p` would need someone at the terminal, if it would.
+fn needs_prompt(p: &Profile, aws_dir: &Path, now: DateTime<Utc>) -> Option<String> {
+    if p.requires_mfa() {
+        return Some("needs an MFA code".to_string());
+    }
+    if uses_sso(p) {
+        let key = p.sso_session.as_deref().or(p.sso_start_url.as_deref())?;
+        if !crate::sso::cached_token_valid(aws_dir, key, now) {
+            return Some(format!("SSO session of '{}' is not signed in", p.name));
+        }
+    }
+    None
+}
+
+fn uses_sso(p: &Profile) -> bool {
+    p.is_sso() || p.sso_session.is_some()
+}
+
+fn revoked(message: &str) -> bool {
+    ["ForbiddenException", "No access", "UnauthorizedException"].iter().any(|n| message.contains(n))
+}
+
+/// The reasons of both checks, grouped per profile and sorted by name.
+pub fn candidates(found: Vec<(String, String)>) -> Vec<Candidate> {
+    let mut by_profile: Vec<Candidate> = Vec::new();
+    for (profile, reason) in found {
+        match by_profile.iter_mut().find(|c| c.profile == profile) {
+            Some(c) => c.reasons.push(reason),
+            None => by_profile.push(Candidate { profile, reasons: vec![reason] }),
+        }
+    }
+    by_profile.sort_by(|a, b| a.profile.cmp(&b.profile));
+    by_profile
+}
+
+/// `content` with the sections named in `actions` commented out or deleted. A deleted
+/// section takes its `# awx:` description and trailing blank lines with it.
+pub fn apply(content: &str, actions: &[(String, Action)], today: &str) -> String {
+    let lines: Vec<&str> = content.lines().collect();
+    let header = |line: &str| {
+        let t = line.trim();
+        t.strip_prefix('[').and_then(|r| r.strip_suffix(']')).map(|n| n.trim().to_string())
+    };
+    let mut out: Vec<String> = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let action = header(lines[i]).and_then(|name| actions.iter().find(|(n, _)| *n == name).map(|(_, a)| *a));
+        let Some(action) = action.filter(|a| *a != Action::Keep) else {
+            out.push(lines[i].to_string());
+            i += 1;
+            continue;
+        };
+        let mut end = i + 1;
+        while end < lines.len() && header(lines[end]).is_none() {
+            end += 1;
+        }
+        let mut body_end = end;
+        while body_end > i + 1 && lines[body_end - 1].trim().is_empty() {
+            body_end -= 1;
+        }
+        match action {
+            Action::CommentOut => {
+                out.push(format!("# Pruned by awx on {}:", today));
+                for line in &lines[i..body_end] {
+                    out.push(if line.trim().is_empty() { String::new() } else { format!("# {}", line) });
+                }
+                out.extend(lines[body_end..end].iter().map(|l| l.to_string()));
+            }
+            Action::Delete => {
+                let description = |l: &String| l.trim_start().strip_prefix('#').is_some_and(|c| c.trim_start().starts_with("awx:"));
+                while out.last().is_some_and(description) {
+                    out.pop();
+                }
+            }
+            Action::Keep => {}
+        }
+        i = end;
+    }
+    let mut text = out.join("\n");
+    if content.ends_with('\n') && !text.is_empty() {
+        text.push('\n');
+    }
+    text
+}
+
+/// A line diff of `old` and `new`: changed lines with two lines of context, `-` for
+/// removed and `+` for added lines, hunks introduced by `@@ line N`.
+pub fn diff(old: &str, new: &str) -> String {
+    let (a, b): (Vec<&str>, Vec<&str>) = (old.lines().collect(), new.lines().collect());
+    // Longest common subsequence table, from the end.
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+        }
+    }
+    // (old line number, marker, text)
+    let mut ops: Vec<(usize, char, &str)> = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            ops.push((i + 1, ' ', a[i]));
+            i += 1;
+            j += 1;
+        } else if i < a.len() && (j == b.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            ops.push((i + 1, '-', a[i]));
+            i += 1;
+        } else {
+            ops.push((i + 1, '+', b[j]));
+            j += 1;
+        }
+    }
+    const CONTEXT: usize = 2;
+    let near_change = |k: usize| {
+        let lo = k.saturating_sub(CONTEXT);
+        let hi = (k + CONTEXT).min(ops.len() - 1);
+        ops[lo..=hi].iter().any(|(_, m, _)| *m != ' ')
+    };
+    let mut out = String::new();
+    let mut in_hunk = false;
+    for (k, (line, marker, text)) in ops.iter().enumerate() {
+        if !near_change(k) {
+            in_hunk = false;
+            continue;
+        }
+        if !in_hunk {
+            out.push_str(&format!("@@ line {}\n", line));
+            in_hunk = true;
+        }
+        out.push_str(&format!("{} {}\n", marker, text));
+    }
+    out
+}
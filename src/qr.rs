@@ -0,0 +1,462 @@
+// QR codes for `awx export --qr`.
+//
+// A session has to reach a phone or tablet app under test without anyone typing a
+// 600-character session token, so awx draws it as a QR code in the terminal. This is a
+// small encoder for exactly that job: byte mode, error correction level L (the payload
+// is shown on a screen, not printed, and level L fits the longest session tokens), the
+// smallest of the 40 versions that holds the data and the mask with the lowest penalty,
+// as laid out in ISO/IEC 18004. Two module rows share a line of half-block characters,
+// drawn black on white whatever the terminal's colors, inside the four-module quiet
+// zone scanners expect.
+use anyhow::{anyhow, Result};
+use console::Style;
+use serde_json::json;
+
+use crate::session::Descriptor;
+
+/// What `awx export --qr` encodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Payload {
+    /// The session as `credential_process` JSON (Version, AccessKeyId, SecretAccessKey,
+    /// SessionToken, Expiration), which SDK-based apps can parse as is.
+    Credentials,
+    /// The `--session-file` descriptor, with the profile and region.
+    Descriptor,
+}
+
+/// The text `payload` puts in the code for `descriptor`.
+pub fn payload(payload: Payload, descriptor: &Descriptor) -> Result<String> {
+    Ok(match payload {
+        Payload::Credentials => json!({
+            "Version": 1,
+            "AccessKeyId": descriptor.access_key_id,
+            "SecretAccessKey": descriptor.secret_access_key,
+            "SessionToken": descriptor.session_token,
+            "Expiration": descriptor.expiration.to_rfc3339(),
+        })
+        .to_string(),
+        Payload::Descriptor => serde_json::to_string(descriptor)?,
+    })
+}
+
+/// Error correction codewords per block for level L, by version.
+const ECC_PER_BLOCK: [usize; 41] = [
+    0, 7, 10, 15, 20, 26, 18, 20, 24, 30, 18, 20, 24, 26, 30, 22, 24, 28, 30, 28, 28, 28, 28, 30, 30, 26, 28, 30, 30, 30, 30, 30, 30,
+    30, 30, 30, 30, 30, 30, 30, 30,
+];
+/// Error correction blocks for level L, by version.
+const BLOCKS: [usize; 41] = [
+    0, 1, 1, 1, 1, 1, 2, 2, 2, 2, 4, 4, 4, 4, 4, 6, 6, 6, 6, 7, 8, 8, 9, 9, 10, 12, 12, 12, 13, 14, 15, 16, 17, 18, 19, 19, 20, 21, 22,
+    24, 25,
+];
+/// The two format bits of level L.
+const LEVEL_L: u32 = 0b01;
+const QUIET_ZONE: usize = 4;
+
+/// An encoded symbol; `modules[y][x]` is true for dark modules.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Code {
+    pub version: usize,
+    pub mask: u8,
+    pub modules: Vec<Vec<bool>>,
+}
+
+impl Code {
+    /// Encode `data` in the smallest version that holds it.
+    pub fn encode(data: &[u8]) -> Result<Code> {
+        let version = (1..=40)
+            .find(|&v| 4 + count_bits(v) + data.len() * 8 <= data_codewords(v) * 8)
+            .ok_or_else(|| anyhow!("{} bytes do not fit in a QR code (at most 2953)", data.len()))?;
+        let codewords = add_ecc_and_interleave(version, &data_bits(version, data));
+        let mut best: Option<(u32, Code)> = None;
+        for mask in 0..8 {
+            let mut symbol = Symbol::new(version);
+            symbol.draw_codewords(&codewords);
+            symbol.apply_mask(mask);
+            symbol.draw_format(mask);
+            let penalty = symbol.penalty();
+            if best.as_ref().is_none_or(|(p, _)| penalty < *p) {
+                best = Some((
+                    penalty,
+                    Code {
+                        version,
+                        mask,
+                        modules: symbol.modules,
+                    },
+                ));
+            }
+        }
+        Ok(best.expect("eight masks tried").1)
+    }
+
+    pub fn size(&self) -> usize {
+        self.modules.len()
+    }
+
+    fn dark(&self, x: isize, y: isize) -> bool {
+        let size = self.size() as isize;
+        (0..size).contains(&x) && (0..size).contains(&y) && self.modules[y as usize][x as usize]
+    }
+
+    /// The code as lines of half blocks, quiet zone included.
+    pub fn render(&self) -> String {
+        let style = Style::new().black().on_white();
+        let (from, to) = (-(QUIET_ZONE as isize), (self.size() + QUIET_ZONE) as isize);
+        let mut out = String::new();
+        for y in (from..to).step_by(2) {
+            let line: String = (from..to)
+                .map(|x| match (self.dark(x, y), y + 1 < to && self.dark(x, y + 1)) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                })
+                .collect();
+            out.push_str(&style.apply_to(line).to_string());
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// Bits of the byte-mode character count in `version`.
+fn count_bits(version: usize) -> usize {
+    if version <= 9 {
+        8
+    } else {
+        16
+    }
+}
+
+/// Modules left for codewords once the function patterns are drawn.
+fn raw_data_modules(version: usize) -> usize {
+    let mut result = (16 * version + 128) * version + 64;
+    if version >= 2 {
+        let align = version / 7 + 2;
+        result -= (25 * align - 10) * align - 55;
+        if version >= 7 {
+            result -= 36;
+        }
+    }
+    result
+}
+
+fn data_codewords(version: usize) -> usize {
+    raw_data_modules(version) / 8 - ECC_PER_BLOCK[version] * BLOCKS[version]
+}
+
+/// Mode indicator, count, data, terminator and padding, as codewords.
+fn data_bits(version: usize, data: &[u8]) -> Vec<u8> {
+    let capacity = data_codewords(version) * 8;
+    let mut bits: Vec<bool> = Vec::with_capacity(capacity);
+    let mut push = |value: usize, len: usize| bits.extend((0..len).rev().map(|i| (value >> i) & 1 == 1));
+    push(0b0100, 4);
+    push(data.len(), count_bits(version));
+    for &b in data {
+        push(b as usize, 8);
+    }
+    let terminator = (capacity - bits.len()).min(4);
+    bits.extend(std::iter::repeat_n(false, terminator));
+    bits.extend(std::iter::repeat_n(false, (8 - bits.len() % 8) % 8));
+    let mut bytes: Vec<u8> = bits.chunks(8).map(|c| c.iter().fold(0u8, |acc, &b| acc << 1 | b as u8)).collect();
+    for pad in [0xEC, 0x11].into_iter().cycle() {
+        if bytes.len() >= capacity / 8 {
+            break;
+        }
+        bytes.push(pad);
+    }
+    bytes
+}
+
+/// Split the data into blocks, append each block's error correction and interleave.
+fn add_ecc_and_interleave(version: usize, data: &[u8]) -> Vec<u8> {
+    let (blocks, ecc_len) = (BLOCKS[version], ECC_PER_BLOCK[version]);
+    let raw = raw_data_modules(version) / 8;
+    let short_blocks = blocks - raw % blocks;
+    let short_len = raw / blocks;
+    let divisor = rs_divisor(ecc_len);
+    let mut split = Vec::with_capacity(blocks);
+    let mut k = 0;
+    for i in 0..blocks {
+        let len = short_len - ecc_len + usize::from(i >= short_blocks);
+        let mut block = data[k..k + len].to_vec();
+        k += len;
+        let ecc = rs_remainder(&block, &divisor);
+        if i < short_blocks {
+            block.push(0);
+        }
+        block.extend(ecc);
+        split.push(block);
+    }
+    let mut result = Vec::with_capacity(raw);
+    for i in 0..split[0].len() {
+        for (j, block) in split.iter().enumerate() {
+            // Short blocks carry a placeholder where long blocks have one more data byte.
+            if i != short_len - ecc_len || j >= short_blocks {
+                result.push(block[i]);
+            }
+        }
+    }
+    result
+}
+
+/// Product in GF(2^8) modulo x^8 + x^4 + x^3 + x^2 + 1.
+fn gf_mul(x: u8, y: u8) -> u8 {
+    let mut z: u16 = 0;
+    for i in (0..8).rev() {
+        z = (z << 1) ^ ((z >> 7) * 0x11D);
+        z ^= ((y as u16 >> i) & 1) * x as u16;
+    }
+    z as u8
+}
+
+/// The Reed-Solomon generator of `degree`, leading coefficient dropped.
+pub fn rs_divisor(degree: usize) -> Vec<u8> {
+    let mut result = vec![0u8; degree];
+    result[degree - 1] = 1;
+    let mut root = 1u8;
+    for _ in 0..degree {
+        for j in 0..degree {
+            result[j] = gf_mul(result[j], root);
+            if j + 1 < degree {
+                result[j] ^= result[j + 1];
+            }
+        }
+        root = gf_mul(root, 0x02);
+    }
+    result
+}
+
+/// The error correction codewords of `data`.
+pub fn rs_remainder(data: &[u8], divisor: &[u8]) -> Vec<u8> {
+    let mut result = vec![0u8; divisor.len()];
+    for &b in data {
+        let factor = b ^ result.remove(0);
+        result.push(0);
+        for (r, &d) in result.iter_mut().zip(divisor) {
+            *r ^= gf_mul(d, factor);
+        }
+    }
+    result
+}
+
+/// The 15 format bits for level L and `mask`, masked as stored.
+pub fn format_bits(mask: u8) -> u32 {
+    let data = LEVEL_L << 3 | mask as u32;
+    let mut rem = data;
+    for _ in 0..10 {
+        rem = (rem << 1) ^ ((rem >> 9) * 0x537);
+    }
+    (data << 10 | rem) ^ 0x5412
+}
+
+/// The 18 version bits of versions 7 and up.
+pub fn version_bits(version: usize) -> u32 {
+    let mut rem = version as u32;
+    for _ in 0..12 {
+        rem = (rem << 1) ^ ((rem >> 11) * 0x1F25);
+    }
+    (version as u32) << 12 | rem
+}
+
+/// Centers of the alignment patterns along either axis.
+fn alignment_positions(version: usize) -> Vec<usize> {
+    if version == 1 {
+        return Vec::new();
+    }
+    let size = version * 4 + 17;
+    let align = version / 7 + 2;
+    let step = (version * 8 + align * 3 + 5) / (align * 4 - 4) * 2;
+    let mut result: Vec<usize> = (0..align - 1).map(|i| size - 7 - i * step).collect();
+    result.push(6);
+    result.reverse();
+    result
+}
+
+struct Symbol {
+    size: usize,
+    modules: Vec<Vec<bool>>,
+    function: Vec<Vec<bool>>,
+}
+
+impl Symbol {
+    /// A symbol of `version` with its function patterns drawn.
+    fn new(version: usize) -> Symbol {
+        let size = version * 4 + 17;
+        let mut symbol = Symbol {
+            size,
+            modules: vec![vec![false; size]; size],
+            function: vec![vec![false; size]; size],
+        };
+        for i in 0..size {
+            symbol.set(6, i, i % 2 == 0);
+            symbol.set(i, 6, i % 2 == 0);
+        }
+        for (x, y) in [(3, 3), (size - 4, 3), (3, size - 4)] {
+            symbol.draw_finder(x, y);
+        }
+        let positions = alignment_positions(version);
+        let last = positions.len().saturating_sub(1);
+        for (i, &x) in positions.iter().enumerate() {
+            for (j, &y) in positions.iter().enumerate() {
+                // The corners with a finder pattern get none.
+                if ![(0, 0), (0, last), (last, 0)].contains(&(i, j)) {
+                    symbol.draw_alignment(x, y);
+                }
+            }
+        }
+        // Reserve the format areas; the real bits depend on the mask.
+        symbol.draw_format(0);
+        if version >= 7 {
+            let bits = version_bits(version);
+            for i in 0..18 {
+                let dark = (bits >> i) & 1 == 1;
+                let (a, b) = (size - 11 + i % 3, i / 3);
+                symbol.set(a, b, dark);
+                symbol.set(b, a, dark);
+            }
+        }
+        symbol
+    }
+
+    fn set(&mut self, x: usize, y: usize, dark: bool) {
+        self.modules[y][x] = dark;
+        self.function[y][x] = true;
+    }
+
+    fn draw_finder(&mut self, x: usize, y: usize) {
+        for dy in -4isize..=4 {
+            for dx in -4isize..=4 {
+                let (xx, yy) = (x as isize + dx, y as isize + dy);
+                if (0..self.size as isize).contains(&xx) && (0..self.size as isize).contains(&yy) {
+                    let dist = dx.abs().max(dy.abs());
+                    self.set(xx as usize, yy as usize, dist != 2 && dist != 4);
+                }
+            }
+        }
+    }
+
+    fn draw_alignment(&mut self, x: usize, y: usize) {
+        for dy in -2isize..=2 {
+            for dx in -2isize..=2 {
+                self.set((x as isize + dx) as usize, (y as isize + dy) as usize, dx.abs().max(dy.abs()) != 1);
+            }
+        }
+    }
+
+    fn draw_format(&mut self, mask: u8) {
+        let bits = format_bits(mask);
+        let bit = |i: usize| (bits >> i) & 1 == 1;
+        let size = self.size;
+        for i in 0..6 {
+            self.set(8, i, bit(i));
+        }
+        self.set(8, 7, bit(6));
+        self.set(8, 8, bit(7));
+        self.set(7, 8, bit(8));
+        for i in 9..15 {
+            self.set(14 - i, 8, bit(i));
+        }
+        for i in 0..8 {
+            self.set(size - 1 - i, 8, bit(i));
+        }
+        for i in 8..15 {
+            self.set(8, size - 15 + i, bit(i));
+        }
+        self.set(8, size - 8, true);
+    }
+
+    /// Place the codewords in the zigzag of two-module columns, bottom right first.
+    fn draw_codewords(&mut self, data: &[u8]) {
+        let mut i = 0;
+        let mut right = self.size - 1;
+        loop {
+            if right == 6 {
+                right = 5;
+            }
+            for vert in 0..self.size {
+                for j in 0..2 {
+                    let x = right - j;
+                    let upward = (right + 1) & 2 == 0;
+                    let y = if upward { self.size - 1 - vert } else { vert };
+                    if !self.function[y][x] && i < data.len() * 8 {
+                        self.modules[y][x] = (data[i >> 3] >> (7 - (i & 7))) & 1 == 1;
+                        i += 1;
+                    }
+                }
+            }
+            if right < 2 {
+                break;
+            }
+            right -= 2;
+        }
+    }
+
+    fn apply_mask(&mut self, mask: u8) {
+        for y in 0..self.size {
+            for x in 0..self.size {
+                let invert = match mask {
+                    0 => (x + y) % 2 == 0,
+                    1 => y % 2 == 0,
+                    2 => x % 3 == 0,
+                    3 => (x + y) % 3 == 0,
+                    4 => (x / 3 + y / 2) % 2 == 0,
+                    5 => x * y % 2 + x * y % 3 == 0,
+                    6 => (x * y % 2 + x * y % 3) % 2 == 0,
+                    _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+                };
+                if invert && !self.function[y][x] {
+                    self.modules[y][x] = !self.modules[y][x];
+                }
+            }
+        }
+    }
+
+    /// The standard's penalty score: long runs, 2x2 blocks, finder look-alikes and an
+    /// unbalanced share of dark modules all make a symbol harder to scan.
+    fn penalty(&self) -> u32 {
+        let n = self.size;
+        let mut score = 0;
+        let lines = (0..n).flat_map(|i| {
+            [
+                (0..n).map(|j| self.modules[i][j]).collect::<Vec<_>>(),
+                (0..n).map(|j| self.modules[j][i]).collect::<Vec<_>>(),
+            ]
+        });
+        const FINDER: [bool; 7] = [true, false, true, true, true, false, true];
+        for line in lines {
+            let mut run = 1;
+            for j in 1..=n {
+                if j < n && line[j] == line[j - 1] {
+                    run += 1;
+                    continue;
+                }
+                if run >= 5 {
+                    score += run - 2;
+                }
+                run = 1;
+            }
+            for j in 0..n.saturating_sub(6) {
+                if line[j..j + 7] == FINDER {
+                    let light = |from: isize| (from..from + 4).all(|k| k < 0 || k >= n as isize || !line[k as usize]);
+                    if light(j as isize - 4) || light(j as isize + 7) {
+                        score += 40;
+                    }
+                }
+            }
+        }
+        for y in 0..n - 1 {
+            for x in 0..n - 1 {
+                let c = self.modules[y][x];
+                if c == self.modules[y][x + 1] && c == self.modules[y + 1][x] && c == self.modules[y + 1][x + 1] {
+                    score += 3;
+                }
+            }
+        }
+        let dark = self.modules.iter().flatten().filter(|&&m| m).count();
+        let total = n * n;
+        // Steps of 5% away from half dark, rounded down.
+        let k = ((dark * 20).abs_diff(total * 10) / total) as u32;
+        score as u32 + k * 10
+    }
+}
@@ -0,0 +1,170 @@
+// Client-side pacing of STS / SSO API calls.
+//
+// Every `aws` call that hits an AWS API goes through `output`, which takes a token from
+// a process-wide token bucket first and retries with exponential backoff when the
+// service answers with a throttling error. This keeps bulk runs such as
+// `awx exec --all-matching '*'` over hundreds of accounts under the API limits. The
+// time limit applies to each attempt, not to the call as a whole, so a service that is
+// slow to say "throttled" still gets every retry.
+use std::process::Output;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use tokio::process::Command;
+
+static POLICY: OnceLock<Policy> = OnceLock::new();
+
+/// How long one attempt of an API call may take, unless the caller says otherwise.
+pub const ATTEMPT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// `[rate_limit]` in ~/.awx/config.toml.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct RateLimitSettings {
+    /// Sustained API calls per second; 0 disables pacing.
+    pub rps: f64,
+    /// Calls allowed back to back before pacing starts.
+    pub burst: u32,
+    /// Retries of a throttled call before its error is returned.
+    pub max_retries: u32,
+}
+
+impl Default for RateLimitSettings {
+    fn default() -> Self {
+        RateLimitSettings {
+            rps: 10.0,
+            burst: 10,
+            max_retries: 5,
+        }
+    }
+}
+
+/// A token bucket that hands out reservations: callers that find it empty are told
+/// how long to wait for their token, so concurrent callers queue up fairly.
+#[derive(Debug)]
+pub struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    state: Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+    pub fn new(rate: f64, burst: u32, now: Instant) -> TokenBucket {
+        let burst = f64::from(burst.max(1));
+        TokenBucket {
+            rate,
+            burst,
+            state: Mutex::new((burst, now)),
+        }
+    }
+
+    /// Take one token at `now`, returning how long the caller has to wait for it.
+    pub fn reserve_at(&self, now: Instant) -> Duration {
+        if self.rate <= 0.0 {
+            return Duration::ZERO;
+        }
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let (tokens, last) = *state;
+        let elapsed = now.saturating_duration_since(last).as_secs_f64();
+        let tokens = (tokens + elapsed * self.rate).min(self.burst) - 1.0;
+        *state = (tokens, now.max(last));
+        if tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-tokens / self.rate)
+        }
+    }
+
+    pub async fn acquire(&self) {
+        let wait = self.reserve_at(Instant::now());
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+struct Policy {
+    bucket: TokenBucket,
+    max_retries: u32,
+}
+
+/// Set the process-wide limits; only the first call has an effect.
+pub fn configure(settings: &RateLimitSettings) {
+    let _ = POLICY.set(Policy {
+        bucket: TokenBucket::new(settings.rps, settings.burst, Instant::now()),
+        max_retries: settings.max_retries,
+    });
+}
+
+fn policy() -> &'static Policy {
+    POLICY.get_or_init(|| {
+        let defaults = RateLimitSettings::default();
+        Policy {
+            bucket: TokenBucket::new(defaults.rps, defaults.burst, Instant::now()),
+            max_retries: defaults.max_retries,
+        }
+    })
+}
+
+/// Whether an `aws` error output means the call was throttled.
+pub fn is_throttled(stderr: &str) -> bool {
+    ["Throttling", "TooManyRequests", "Rate exceeded", "RequestLimitExceeded"]
+        .iter()
+        .any(|marker| stderr.contains(marker))
+}
+
+/// Upper bound of the delay before retry `attempt` (0-based): 200ms doubling up to 10s.
+pub fn backoff_cap(attempt: u32) -> Duration {
+    Duration::from_millis(200u64.saturating_mul(1 << attempt.min(16))).min(Duration::from_secs(10))
+}
+
+// Half the cap plus up to half again, so concurrent retries spread out.
+fn backoff_delay(attempt: u32) -> Duration {
+    let cap = backoff_cap(attempt);
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    cap / 2 + cap.mul_f64(f64::from(nanos % 1000) / 2000.0)
+}
+
+/// Run an API-calling `aws` command under the rate limit, retrying throttled calls.
+pub async fn output(cmd: &mut Command) -> std::io::Result<Output> {
+    output_within(cmd, None, ATTEMPT_TIMEOUT).await
+}
+
+/// `output` with `input` on the command's stdin, e.g. parameters too secret for argv.
+pub async fn output_with_input(cmd: &mut Command, input: Option<&[u8]>) -> std::io::Result<Output> {
+    output_within(cmd, input, ATTEMPT_TIMEOUT).await
+}
+
+/// `output_with_input` giving each attempt `limit`; an attempt over it fails the call
+/// with `TimedOut`.
+pub async fn output_within(cmd: &mut Command, input: Option<&[u8]>, limit: Duration) -> std::io::Result<Output> {
+    if crate::offline::is_offline() {
+        let std = cmd.as_std();
+        let action = std::iter::once(std.get_program())
+            .chain(std.get_args().take(2))
+            .map(|a| a.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join(" ");
+        crate::offline::ensure_online(&action).map_err(|e| std::io::Error::other(e.to_string()))?;
+    }
+    let policy = policy();
+    let mut attempt = 0;
+    loop {
+        policy.bucket.acquire().await;
+        let out = tokio::time::timeout(limit, crate::backend::runner().output(cmd, input))
+            .await
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, format!("timed out after {}s", limit.as_secs())))??;
+        if out.status.success()
+            || attempt >= policy.max_retries
+            || !is_throttled(&String::from_utf8_lossy(&out.stderr))
+        {
+            return Ok(out);
+        }
+        tokio::time::sleep(backoff_delay(attempt)).await;
+        attempt += 1;
+    }
+}
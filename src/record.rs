@@ -0,0 +1,295 @@
+// Session recording (`--record`).
+//
+// The child runs on a pseudo-terminal of its own. What it prints is copied to our
+// terminal and appended to an asciicast v2 file in ~/.awx/recordings, which
+// `asciinema play` replays. Keys typed by the user reach the child through the same
+// terminal but are not recorded, as MFA codes and passwords are typed there; what the
+// child echoes back is. Our terminal is in raw mode while the child runs and is
+// restored afterwards, also when the run fails. Pseudo-terminals are a Unix facility;
+// elsewhere `--record` fails before running anything.
+#[cfg(unix)]
+use std::fs::File;
+#[cfg(unix)]
+use std::io::{BufWriter, Read, Write};
+#[cfg(unix)]
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+#[cfg(unix)]
+use std::process::Stdio;
+#[cfg(unix)]
+use std::sync::{Arc, Mutex};
+#[cfg(unix)]
+use std::time::Instant;
+
+#[cfg(unix)]
+use anyhow::Context;
+use anyhow::{anyhow, Result};
+#[cfg(unix)]
+use chrono::{DateTime, Utc};
+#[cfg(unix)]
+use serde_json::json;
+use tokio::process::Command;
+#[cfg(unix)]
+use tokio::time::{timeout, Duration};
+
+#[cfg(unix)]
+pub fn recordings_dir() -> Result<PathBuf> {
+    Ok(crate::settings::awx_dir()?.join("recordings"))
+}
+
+/// `<profile>-<UTC time>.cast`
+#[cfg(unix)]
+pub fn file_name(profile: &str, at: DateTime<Utc>) -> String {
+    format!("{}-{}.cast", profile, at.format("%Y%m%dT%H%M%SZ"))
+}
+
+/// An asciicast v2 writer: a JSON header line, then one JSON array per event with the
+/// seconds since the start.
+#[cfg(unix)]
+pub struct Cast<W: Write> {
+    out: W,
+    /// The start of a UTF-8 sequence split across reads.
+    pending: Vec<u8>,
+}
+
+#[cfg(unix)]
+impl<W: Write> Cast<W> {
+    pub fn new(mut out: W, width: u16, height: u16, at: DateTime<Utc>, title: &str, env: &[(&str, String)]) -> Result<Cast<W>> {
+        let env: serde_json::Map<String, serde_json::Value> =
+            env.iter().map(|(k, v)| (k.to_string(), json!(v))).collect();
+        let header = json!({
+            "version": 2,
+            "width": width,
+            "height": height,
+            "timestamp": at.timestamp(),
+            "title": title,
+            "env": env,
+        });
+        writeln!(out, "{}", header)?;
+        Ok(Cast { out, pending: Vec::new() })
+    }
+
+    /// Terminal output; an incomplete UTF-8 sequence at the end waits for the next read.
+    pub fn output(&mut self, elapsed: f64, bytes: &[u8]) -> Result<()> {
+        self.pending.extend_from_slice(bytes);
+        let complete = match std::str::from_utf8(&self.pending) {
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            _ => self.pending.len(),
+        };
+        if complete == 0 {
+            return Ok(());
+        }
+        let text = String::from_utf8_lossy(&self.pending[..complete]).into_owned();
+        self.pending.drain(..complete);
+        self.event(elapsed, "o", &text)
+    }
+
+    pub fn resize(&mut self, elapsed: f64, width: u16, height: u16) -> Result<()> {
+        self.event(elapsed, "r", &format!("{}x{}", width, height))
+    }
+
+    /// Write out what is left and flush.
+    pub fn finish(&mut self, elapsed: f64) -> Result<()> {
+        if !self.pending.is_empty() {
+            let text = String::from_utf8_lossy(&self.pending).into_owned();
+            self.pending.clear();
+            self.event(elapsed, "o", &text)?;
+        }
+        self.out.flush()?;
+        Ok(())
+    }
+
+    fn event(&mut self, elapsed: f64, kind: &str, data: &str) -> Result<()> {
+        writeln!(self.out, "[{:.6}, {}, {}]", elapsed, json!(kind), json!(data))?;
+        Ok(())
+    }
+}
+
+/// Run `cmd` (the AWS CLI with `args`) for `profile` on a recorded pseudo-terminal and
+/// return its exit code.
+#[cfg(unix)]
+pub async fn run(cmd: Command, profile: &str, args: &[String]) -> Result<i32> {
+    if unsafe { libc::isatty(libc::STDIN_FILENO) } != 1 {
+        return Err(anyhow!("--record needs a terminal on stdin"));
+    }
+    let dir = recordings_dir()?;
+    create_private_dir(&dir)?;
+    let now = Utc::now();
+    let path = dir.join(file_name(profile, now));
+    let code = record(cmd, &path, now, &format!("awx -p {} -- {}", profile, args.join(" "))).await?;
+    eprintln!("Session recorded to {}", path.display());
+    Ok(code)
+}
+
+#[cfg(not(unix))]
+pub async fn run(_cmd: Command, _profile: &str, _args: &[String]) -> Result<i32> {
+    Err(anyhow!("--record needs a Unix pseudo-terminal and is not supported on this platform"))
+}
+
+#[cfg(unix)]
+async fn record(mut cmd: Command, path: &Path, now: DateTime<Utc>, title: &str) -> Result<i32> {
+    let saved = terminal_mode(libc::STDIN_FILENO)?;
+    let mut size = window_size(libc::STDOUT_FILENO).unwrap_or(libc::winsize {
+        ws_row: 24,
+        ws_col: 80,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    });
+    let (master, slave) = open_pty(&saved, &mut size)?;
+
+    cmd.stdin(Stdio::from(slave.try_clone()?))
+        .stdout(Stdio::from(slave.try_clone()?))
+        .stderr(Stdio::from(slave));
+    // Make the pseudo-terminal the child's controlling terminal, so job control and
+    // Ctrl-C work inside it.
+    unsafe {
+        cmd.pre_exec(|| {
+            if libc::setsid() == -1 || libc::ioctl(libc::STDIN_FILENO, libc::TIOCSCTTY, 0) == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+
+    let file = open_private(path).with_context(|| format!("Cannot create {}", path.display()))?;
+    let env: Vec<(&str, String)> = ["SHELL", "TERM"]
+        .into_iter()
+        .filter_map(|k| crate::aws_env::current_lookup(k).map(|v| (k, v)))
+        .collect();
+    let cast = Arc::new(Mutex::new(Cast::new(BufWriter::new(file), size.ws_col, size.ws_row, now, title, &env)?));
+
+    let start = Instant::now();
+    let child = cmd.spawn().context("failed to spawn aws child command")?;
+    // The parent's copies of the slave must be closed for reads on the master to end.
+    drop(cmd);
+    let raw = RawMode::enable(libc::STDIN_FILENO, saved)?;
+
+    let mut input = File::from(master.try_clone()?);
+    std::thread::spawn(move || {
+        let mut buf = [0u8; 4096];
+        let mut stdin = std::io::stdin();
+        while let Ok(n) = stdin.read(&mut buf) {
+            if n == 0 || input.write_all(&buf[..n]).is_err() {
+                break;
+            }
+        }
+    });
+    let mut output = File::from(master.try_clone()?);
+    let output_cast = cast.clone();
+    let copier = std::thread::spawn(move || {
+        let mut buf = [0u8; 8192];
+        let mut stdout = std::io::stdout();
+        // Reading fails with EIO once the child and everything it started have exited.
+        while let Ok(n) = output.read(&mut buf) {
+            if n == 0 {
+                break;
+            }
+            let _ = stdout.write_all(&buf[..n]).and_then(|_| stdout.flush());
+            let _ = output_cast.lock().unwrap_or_else(|e| e.into_inner()).output(start.elapsed().as_secs_f64(), &buf[..n]);
+        }
+    });
+    let resize_cast = cast.clone();
+    let resizer = tokio::spawn(async move {
+        use tokio::signal::unix::{signal, SignalKind};
+        let Ok(mut winch) = signal(SignalKind::window_change()) else {
+            return;
+        };
+        while winch.recv().await.is_some() {
+            if let Some(size) = window_size(libc::STDOUT_FILENO) {
+                unsafe { libc::ioctl(master.as_raw_fd(), libc::TIOCSWINSZ, &size) };
+                let _ = resize_cast
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .resize(start.elapsed().as_secs_f64(), size.ws_col, size.ws_row);
+            }
+        }
+    });
+
+    let code = crate::child_span(crate::wait_forwarding_signals(child)).await;
+    resizer.abort();
+    // A background process still holding the terminal would keep the copier reading.
+    let _ = timeout(Duration::from_secs(1), tokio::task::spawn_blocking(move || copier.join())).await;
+    drop(raw);
+    cast.lock().unwrap_or_else(|e| e.into_inner()).finish(start.elapsed().as_secs_f64())?;
+    code
+}
+
+#[cfg(unix)]
+fn create_private_dir(dir: &Path) -> Result<()> {
+    use std::os::unix::fs::DirBuilderExt;
+    std::fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)
+        .with_context(|| format!("Cannot create {}", dir.display()))
+}
+
+// Create or truncate `path`, readable only by the user.
+#[cfg(unix)]
+fn open_private(path: &Path) -> std::io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)
+}
+
+#[cfg(unix)]
+fn terminal_mode(fd: i32) -> Result<libc::termios> {
+    let mut mode = std::mem::MaybeUninit::<libc::termios>::uninit();
+    if unsafe { libc::tcgetattr(fd, mode.as_mut_ptr()) } == -1 {
+        return Err(std::io::Error::last_os_error()).context("Cannot read terminal settings");
+    }
+    Ok(unsafe { mode.assume_init() })
+}
+
+#[cfg(unix)]
+fn window_size(fd: i32) -> Option<libc::winsize> {
+    let mut size = std::mem::MaybeUninit::<libc::winsize>::uninit();
+    if unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, size.as_mut_ptr()) } == -1 {
+        return None;
+    }
+    // Terminals that do not know their size report 0x0.
+    let size = unsafe { size.assume_init() };
+    (size.ws_col > 0 && size.ws_row > 0).then_some(size)
+}
+
+/// A pseudo-terminal with our terminal's settings and size: (master, slave).
+#[cfg(unix)]
+fn open_pty(mode: &libc::termios, size: &mut libc::winsize) -> Result<(OwnedFd, OwnedFd)> {
+    let mut mode = *mode;
+    let (mut master, mut slave) = (-1, -1);
+    if unsafe { libc::openpty(&mut master, &mut slave, std::ptr::null_mut(), &raw mut mode, size) } == -1 {
+        return Err(std::io::Error::last_os_error()).context("Cannot open a pseudo-terminal");
+    }
+    Ok(unsafe { (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave)) })
+}
+
+/// Raw mode on a terminal until dropped.
+#[cfg(unix)]
+struct RawMode {
+    fd: i32,
+    saved: libc::termios,
+}
+
+#[cfg(unix)]
+impl RawMode {
+    fn enable(fd: i32, saved: libc::termios) -> Result<RawMode> {
+        let mut raw = saved;
+        unsafe { libc::cfmakeraw(&mut raw) };
+        if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) } == -1 {
+            return Err(std::io::Error::last_os_error()).context("Cannot switch the terminal to raw mode");
+        }
+        Ok(RawMode { fd, saved })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(self.fd, libc::TCSANOW, &self.saved) };
+    }
+}
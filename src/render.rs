@@ -0,0 +1,827 @@
+// Human-readable output.
+//
+// Everything awx prints for people is rendered here into a `String` and printed by the
+// caller, so the exact text can be pinned by the golden tests in src/snapshots. Styling
+// goes through `console`, which drops the escapes when colors are off. Inputs are passed
+// in rather than looked up (time, files, environment) to keep rendering deterministic.
+// Columns holding names people chose (profiles, descriptions, paths) are padded with
+// `pad`, by the columns text takes in a terminal rather than its bytes: CJK characters
+// and most emoji take two, color escapes none.
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Local, TimeZone, Utc};
+use console::Style;
+
+use crate::i18n::{t, Msg};
+use crate::theme::{self, Role};
+use crate::{aws_env, backend, badge, broker, check, clear, exec, iam, janitor, metrics, prune, sdk, selftest, settings, spend, sso, trail, troubleshoot, warm, Profile};
+
+/// `awx -c`: the discovered profiles matching `filter`, sorted by name, and what
+/// their badges mean.
+pub fn config(profiles: &HashMap<String, Profile>, filter: &badge::Filter) -> Result<String> {
+    let bold = theme::style(Role::Heading);
+    let dim = theme::style(Role::Muted);
+    let names = filter.names(profiles);
+    if names.is_empty() && !filter.is_empty() {
+        return Err(anyhow!("No profiles match {}", filter.describe()));
+    }
+    let mut out = String::from("Discovered profiles:\n");
+    let mut shown = Vec::new();
+    let name_width = names.iter().map(|n| width(n)).max().unwrap_or(0);
+    let tags_width = names.iter().map(|n| width(&badge::tags(&badge::badges(&profiles[n])))).max().unwrap_or(0);
+    for name in &names {
+        let p = &profiles[name];
+        let badges = badge::badges(p);
+        let tags = theme::style(Role::Badge).apply_to(pad(&badge::tags(&badges), tags_width));
+        let mut line = format!("  {} {}", bold.apply_to(pad(name, name_width)), tags);
+        if let Some(account) = badge::account(p) {
+            line.push_str(&format!(" {}", dim.apply_to(account)));
+        }
+        if let Some(description) = &p.description {
+            line.push_str(&format!("  {}", description));
+        }
+        let _ = writeln!(out, "{}", line.trim_end());
+        if p.is_role() {
+            let _ = writeln!(out, "    {}", dim.apply_to(role_chain(profiles, p)));
+        }
+        shown.extend(badges);
+    }
+    let legend = badge::legend(&shown);
+    if !legend.is_empty() {
+        let _ = writeln!(out, "\n{}", bold.apply_to("Badges"));
+        for line in legend {
+            let _ = writeln!(out, "  {}", dim.apply_to(line));
+        }
+    }
+    Ok(out)
+}
+
+/// `--explain`: how a run would resolve, without performing any authentication.
+pub fn explain(
+    profiles: &HashMap<String, Profile>,
+    name: &str,
+    source: aws_env::Source,
+    files: &aws_env::AwsFiles,
+    aws_args: &[String],
+    lookup: &dyn Fn(&str) -> Option<String>,
+    now: i64,
+) -> Result<String> {
+    let bold = theme::style(Role::Heading);
+    let dim = theme::style(Role::Muted);
+    let profile = profiles
+        .get(name)
+        .ok_or_else(|| anyhow!("Profile '{}' not found", name))?;
+    let mut out = String::new();
+
+    let _ = writeln!(out, "{}", bold.apply_to("Resolution"));
+    let _ = writeln!(out, "  profile:           {} ({})", name, source);
+    if let Some(description) = &profile.description {
+        let _ = writeln!(out, "  description:       {}", description);
+    }
+    let _ = writeln!(out, "  config file:       {} ({})", files.config.display(), files.config_source);
+    let _ = writeln!(out, "  credentials file:  {} ({})", files.credentials.display(), files.credentials_source);
+    match aws_env::region(lookup, aws_args, profile) {
+        Some((r, src)) => {
+            let _ = writeln!(out, "  region:            {} ({})", r, src);
+        }
+        None => {
+            let _ = writeln!(out, "  region:            {}", dim.apply_to("(none)"));
+        }
+    }
+    let _ = writeln!(out, "  authentication:    {}", describe_auth(profiles, profile));
+    let backend = crate::auth::for_profile(profile).map_or("none (left to the AWS CLI)", |b| b.name());
+    let _ = writeln!(out, "  backend:           {}", backend);
+    if profile.is_role() {
+        let (session, src) = aws_env::role_session_name(lookup, profile, now);
+        let _ = writeln!(out, "  role session name: {} ({})", session, src);
+        let duration = crate::role_duration(profiles, profile);
+        let source = match (crate::duration::requested(), profile.duration_seconds) {
+            (Some(_), _) => "--duration",
+            (None, Some(_)) => "duration_seconds",
+            (None, None) => "default",
+        };
+        let _ = match duration.capped_from {
+            Some(requested) => writeln!(
+                out,
+                "  session duration:  {}s ({} {} capped: role chaining)",
+                duration.secs,
+                source,
+                requested
+            ),
+            None => writeln!(out, "  session duration:  {}s ({})", duration.secs, source),
+        };
+    }
+
+    let _ = writeln!(out);
+    let _ = writeln!(out, "{}", bold.apply_to("Precedence"));
+    let _ = writeln!(out, "  profile:     --profile > awx context > AWS_PROFILE > AWS_DEFAULT_PROFILE > default");
+    let _ = writeln!(out, "  files:       AWS_CONFIG_FILE / AWS_SHARED_CREDENTIALS_FILE > ~/.aws");
+    let _ = writeln!(out, "  region:      --region > AWS_REGION > AWS_DEFAULT_REGION > profile region");
+    let _ = writeln!(out, "  session:     AWS_ROLE_SESSION_NAME > role_session_name > awx-<timestamp>");
+    let _ = writeln!(out, "  credentials: awx STS session > AWS_ACCESS_KEY_ID in environment > profile static keys");
+
+    let _ = writeln!(out);
+    let _ = writeln!(out, "{}", bold.apply_to("Environment (respected, never overwritten)"));
+    for (knob, value) in aws_env::describe(lookup) {
+        let shown = match value {
+            Some(v) => v,
+            None => dim.apply_to("unset").to_string(),
+        };
+        let _ = writeln!(out, "  {:<28} {} {}", knob.name, pad(&shown, 20), dim.apply_to(knob.effect));
+    }
+
+    let settings: Vec<_> = aws_env::CLI_SETTINGS
+        .iter()
+        .filter_map(|&(key, var)| Some((key, var, aws_env::cli_setting(profile, key)?)))
+        .collect();
+    if !settings.is_empty() {
+        let _ = writeln!(out);
+        let _ = writeln!(out, "{}", bold.apply_to("CLI settings (profile)"));
+        for (key, var, value) in settings {
+            let shown = if value.is_empty() { dim.apply_to("(empty)").to_string() } else { value.to_string() };
+            let effect = match var {
+                Some(var) if lookup(var).is_some() => format!("{} in the environment wins", var),
+                Some(var) => format!("{} for the child", var),
+                None => "applied by the aws CLI through AWS_PROFILE".to_string(),
+            };
+            let _ = writeln!(out, "  {:<28} {} {}", key, pad(&shown, 20), dim.apply_to(effect));
+        }
+    }
+    Ok(out)
+}
+
+fn describe_auth(profiles: &HashMap<String, Profile>, profile: &Profile) -> String {
+    if let Some(role) = &profile.role_arn {
+        if let (None, Some(ambient)) = (&profile.source_profile, &profile.credential_source) {
+            return format!("assume-role {} via credential_source {}", role, ambient);
+        }
+        let source = profile.source_profile.as_deref().unwrap_or("?");
+        let base = profiles.get(source).map(profile_badges).unwrap_or_else(|| "[missing]".to_string());
+        return format!("assume-role {} via source_profile {} {}", role, source, base);
+    }
+    if profile.is_sso() {
+        return format!("SSO ({})", profile.sso_start_url.as_deref().unwrap_or("sso-session"));
+    }
+    if profile.is_static() && profile.requires_mfa() {
+        return "static keys + MFA (get-session-token)".to_string();
+    }
+    if profile.is_static() {
+        return "static keys".to_string();
+    }
+    "none (AWS CLI default chain)".to_string()
+}
+
+/// `role-prod ← mid [ROLE] ← base [STATIC+MFA]`: the profiles whose credentials assume
+/// `p`'s role, down to the one that holds its own. A missing or repeated source ends the
+/// chain with what is wrong with it.
+fn role_chain(profiles: &HashMap<String, Profile>, p: &Profile) -> String {
+    let mut out = p.name.clone();
+    let mut seen = vec![p.name.as_str()];
+    let mut current = p;
+    while current.is_role() {
+        let Some(source) = current.source_profile.as_deref() else {
+            match &current.credential_source {
+                Some(ambient) => out.push_str(&format!(" ← {} [credential_source]", ambient)),
+                None => out.push_str(" ← ? [no source_profile]"),
+            }
+            break;
+        };
+        if seen.contains(&source) {
+            out.push_str(&format!(" ← {} [cycle]", source));
+            break;
+        }
+        let Some(base) = profiles.get(source) else {
+            out.push_str(&format!(" ← {} [missing]", source));
+            break;
+        };
+        out.push_str(&format!(" ← {} {}", source, profile_badges(base)));
+        seen.push(source);
+        current = base;
+    }
+    out
+}
+
+/// The columns `text` takes in a terminal.
+pub fn width(text: &str) -> usize {
+    console::measure_text_width(text)
+}
+
+/// `text` followed by spaces up to `columns` terminal columns.
+pub fn pad(text: &str, columns: usize) -> String {
+    format!("{}{}", text, " ".repeat(columns.saturating_sub(width(text))))
+}
+
+fn profile_badges(p: &Profile) -> String {
+    let mut badges = Vec::new();
+    if p.is_sso() {
+        badges.push("SSO");
+    }
+    if p.is_role() {
+        badges.push("ROLE");
+    }
+    if p.is_static() {
+        badges.push("STATIC");
+    }
+    if p.requires_mfa() {
+        badges.push("MFA");
+    }
+    format!("[{}]", badges.join("+"))
+}
+
+/// `awx stats`: usage per profile, then the profiles never used.
+pub fn stats(store: &metrics::MetricsStore, profiles: &HashMap<String, Profile>, enabled: bool) -> String {
+    let bold = theme::style(Role::Heading);
+    let dim = theme::style(Role::Muted);
+    let mut out = String::new();
+    if !enabled {
+        let _ = writeln!(
+            out,
+            "{}",
+            dim.apply_to("Metrics are disabled. Set `metrics = true` in ~/.awx/config.toml or AWX_METRICS=1 to record usage.")
+        );
+    }
+    let mut rows: Vec<(&String, &metrics::ProfileStats)> = store.profiles.iter().collect();
+    rows.sort_by(|a, b| b.1.uses.cmp(&a.1.uses).then(a.0.cmp(b.0)));
+    let _ = writeln!(
+        out,
+        "{}",
+        bold.apply_to(format!(
+            "{:<30} {:>6} {:>9} {:>6} {:>5} {:>5}  {}",
+            "PROFILE", "USES", "FAILURES", "RATE", "MFA", "SSO", "LAST USED"
+        ))
+    );
+    for (name, st) in rows.iter() {
+        let last = st
+            .last_used
+            .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_else(|| "-".to_string());
+        let _ = writeln!(
+            out,
+            "{} {:>6} {:>9} {:>5.0}% {:>5} {:>5}  {}",
+            pad(name, 30),
+            st.uses,
+            st.auth_failures,
+            st.failure_rate() * 100.0,
+            st.mfa_prompts,
+            st.sso_logins,
+            last
+        );
+    }
+    let mut unused: Vec<&String> = profiles
+        .keys()
+        .filter(|name| store.profiles.get(*name).map(|s| s.uses == 0).unwrap_or(true))
+        .collect();
+    unused.sort();
+    if !unused.is_empty() {
+        let _ = writeln!(out);
+        let _ = writeln!(out, "{}", bold.apply_to("Never used since metrics were enabled:"));
+        for name in unused {
+            let _ = writeln!(out, "  {}", name);
+        }
+    }
+    out
+}
+
+/// `awx ctx`: the defined contexts, sorted by name, with the active one marked.
+pub fn contexts(contexts: &HashMap<String, settings::ContextSettings>, active: Option<&str>) -> String {
+    let bold = theme::style(Role::Heading);
+    let dim = theme::style(Role::Muted);
+    if contexts.is_empty() {
+        return format!(
+            "{}\n",
+            dim.apply_to("No contexts defined. Add [contexts.<name>] to ~/.awx/config.toml.")
+        );
+    }
+    let mut out = String::from("Contexts:\n");
+    let mut names: Vec<&String> = contexts.keys().collect();
+    names.sort();
+    for name in names {
+        let c = &contexts[name];
+        let mut details = vec![format!("profile {}", c.profile)];
+        if let Some(region) = &c.region {
+            details.push(format!("region {}", region));
+        }
+        if let Some(url) = &c.endpoint_url {
+            details.push(format!("endpoint {}", url));
+        }
+        if !c.command.is_empty() {
+            details.push(format!("runs `aws {}`", c.command.join(" ")));
+        }
+        let marker = if active == Some(name.as_str()) { "*" } else { " " };
+        let badge = if c.confirm { " [CONFIRM]" } else { "" };
+        let _ = writeln!(out, "{} {}{}  {}", marker, bold.apply_to(name), badge, dim.apply_to(details.join(", ")));
+    }
+    if let Some(active) = active.filter(|a| !contexts.contains_key(*a)) {
+        let _ = writeln!(out, "Active context '{}' is not defined.", active);
+    }
+    out
+}
+
+/// `awx cache gc`: what was removed, or with `dry_run` what would be.
+pub fn cache_gc(awx_dir: &std::path::Path, entries: &[janitor::Entry], dry_run: bool) -> String {
+    if entries.is_empty() {
+        return "Nothing to clean up.\n".to_string();
+    }
+    let total: u64 = entries.iter().map(|e| e.bytes).sum();
+    let verb = if dry_run { "Would remove" } else { "Removed" };
+    let noun = if entries.len() == 1 { "entry" } else { "entries" };
+    let mut out = format!("{} {} expired {} ({}):\n", verb, entries.len(), noun, bytes(total));
+    let shown: Vec<String> = entries
+        .iter()
+        .map(|e| match e.path.strip_prefix(awx_dir) {
+            Ok(rel) => format!("~/.awx/{}", rel.display()),
+            Err(_) => e.path.display().to_string(),
+        })
+        .collect();
+    let width = shown.iter().map(|p| self::width(p)).max().unwrap_or(0);
+    for (path, entry) in shown.iter().zip(entries) {
+        let _ = writeln!(out, "  {}  {}", pad(path, width), theme::style(Role::Muted).apply_to(entry.reason));
+    }
+    out
+}
+
+/// `awx check`: one line saying whether `profile` is ready and why.
+pub fn check(profile: &str, verdict: &check::Verdict, now: DateTime<Utc>) -> String {
+    match verdict {
+        check::Verdict::Ready { source, expires: Some(at) } => {
+            format!("{}: ready ({}, {})\n", profile, source, expiration(*at, now))
+        }
+        check::Verdict::Ready { source, expires: None } => format!("{}: ready ({})\n", profile, source),
+        check::Verdict::NeedsAuth { reason } => format!("{}: authentication needed ({})\n", profile, reason),
+    }
+}
+
+/// `--clear-cache`: the files deleted, and how many profiles the broker forgot when it
+/// was reached.
+pub fn cache_clear(
+    awx_dir: &std::path::Path,
+    aws_dir: &std::path::Path,
+    selector: &clear::Selector,
+    removed: &[janitor::Entry],
+    forgotten: Option<usize>,
+) -> String {
+    if removed.is_empty() && forgotten.is_none_or(|n| n == 0) {
+        return format!("Nothing cached for {}.\n", selector);
+    }
+    let mut out = String::new();
+    if !removed.is_empty() {
+        let noun = if removed.len() == 1 { "file" } else { "files" };
+        let _ = writeln!(out, "Cleared {}: removed {} {}", selector, removed.len(), noun);
+        let shown: Vec<String> = removed
+            .iter()
+            .map(|e| {
+                if let Ok(rel) = e.path.strip_prefix(awx_dir) {
+                    format!("~/.awx/{}", rel.display())
+                } else if let Ok(rel) = e.path.strip_prefix(aws_dir) {
+                    format!("~/.aws/{}", rel.display())
+                } else {
+                    e.path.display().to_string()
+                }
+            })
+            .collect();
+        let width = shown.iter().map(|p| self::width(p)).max().unwrap_or(0);
+        for (path, entry) in shown.iter().zip(removed) {
+            let _ = writeln!(out, "  {}  {}", pad(path, width), theme::style(Role::Muted).apply_to(entry.reason));
+        }
+    } else {
+        let _ = writeln!(out, "Cleared {}", selector);
+    }
+    if let Some(n) = forgotten.filter(|n| *n > 0) {
+        let noun = if n == 1 { "profile" } else { "profiles" };
+        let _ = writeln!(out, "The broker forgot any sessions of {} {}.", n, noun);
+    }
+    out
+}
+
+/// `awx cache ls`: the session files and the sessions the broker holds, one line per
+/// profile and `--duration`.
+pub fn cache_ls(awx_dir: &std::path::Path, files: &[warm::Cached], held: &[broker::Held], now: DateTime<Utc>) -> String {
+    if files.is_empty() && held.is_empty() {
+        return "No cached sessions.\n".to_string();
+    }
+    let bold = theme::style(Role::Heading);
+    let keys: Vec<String> = held.iter().map(broker::Held::key).collect();
+    let width = files.iter().map(|f| self::width(&f.key)).chain(keys.iter().map(|k| self::width(k))).max().unwrap_or(0);
+    let mut out = String::new();
+    if !files.is_empty() {
+        let _ = writeln!(out, "{}", bold.apply_to("Session files"));
+    }
+    for file in files {
+        let path = match file.path.strip_prefix(awx_dir) {
+            Ok(rel) => format!("~/.awx/{}", rel.display()),
+            Err(_) => file.path.display().to_string(),
+        };
+        let _ = writeln!(
+            out,
+            "  {}  {}  {}",
+            pad(&file.key, width),
+            expiration_colored(file.expiration, now),
+            theme::style(Role::Muted).apply_to(path)
+        );
+    }
+    if !held.is_empty() {
+        let _ = writeln!(out, "{}", bold.apply_to("Broker"));
+    }
+    for (key, session) in keys.iter().zip(held) {
+        let _ = writeln!(out, "  {}  {}", pad(key, width), expiration_colored(session.expiration, now));
+    }
+    out
+}
+
+/// `awx login --all-sso`: every sign-in the config needs and whether it is current.
+/// `awx config prune`: the candidates and why, then the profiles the AWS checks skipped.
+pub fn prune_report(candidates: &[prune::Candidate], unchecked: &[(String, String)], history: bool) -> String {
+    let bold = theme::style(Role::Heading);
+    let dim = theme::style(Role::Muted);
+    let mut out = String::new();
+    if !history {
+        let _ = writeln!(
+            out,
+            "{}",
+            dim.apply_to("Usage history is off (metrics = true in ~/.awx/config.toml), so unused profiles are not detected.")
+        );
+    }
+    if candidates.is_empty() {
+        let _ = writeln!(out, "No stale profiles found.");
+    } else {
+        let _ = writeln!(out, "{}", bold.apply_to(format!("Stale profiles ({})", candidates.len())));
+        for c in candidates {
+            let _ = writeln!(out, "  {}", c.profile);
+            for reason in &c.reasons {
+                let _ = writeln!(out, "    - {}", reason);
+            }
+        }
+    }
+    if !unchecked.is_empty() {
+        let _ = writeln!(out, "{}", bold.apply_to("Not checked with AWS"));
+        for (profile, why) in unchecked {
+            let _ = writeln!(out, "  {}  {}", profile, dim.apply_to(why));
+        }
+    }
+    out
+}
+
+/// A diff from `prune::diff` for `path`, colored.
+pub fn file_diff(path: &std::path::Path, diff: &str) -> String {
+    let mut out = format!("{}\n", theme::style(Role::Heading).apply_to(path.display()));
+    for line in diff.lines() {
+        let style = match line.chars().next() {
+            Some('-') => theme::style(Role::Error),
+            Some('+') => theme::style(Role::Success),
+            Some('@') => theme::style(Role::Accent),
+            _ => theme::style(Role::Muted),
+        };
+        let _ = writeln!(out, "{}", style.apply_to(line));
+    }
+    out
+}
+
+pub fn sso_identities(identities: &[(sso::Identity, bool)]) -> String {
+    if identities.is_empty() {
+        return "No SSO start URLs or sso-session blocks in ~/.aws/config.\n".to_string();
+    }
+    let pending = identities.iter().filter(|(_, signed_in)| !signed_in).count();
+    let mut out = format!("SSO sign-ins ({} of {} need a login):\n", pending, identities.len());
+    let width = identities.iter().map(|(i, _)| i.start_url.len()).max().unwrap_or(0);
+    for (identity, signed_in) in identities {
+        let state = if *signed_in {
+            theme::style(Role::Success).apply_to("signed in")
+        } else {
+            theme::style(Role::Warning).apply_to("login    ")
+        };
+        let mut details = Vec::new();
+        if let Some(session) = &identity.session {
+            details.push(format!("sso-session {}", session));
+        }
+        let count = identity.profiles.len();
+        details.push(format!("{} profile{}", count, if count == 1 { "" } else { "s" }));
+        let _ = writeln!(
+            out,
+            "  {}  {:<width$}  {}",
+            state,
+            identity.start_url,
+            theme::style(Role::Muted).apply_to(details.join(", ")),
+            width = width
+        );
+    }
+    out
+}
+
+fn bytes(n: u64) -> String {
+    match n {
+        0..1024 => format!("{} B", n),
+        1024..1_048_576 => format!("{:.1} KiB", n as f64 / 1024.0),
+        _ => format!("{:.1} MiB", n as f64 / 1_048_576.0),
+    }
+}
+
+/// `awx selftest`: one line per check.
+pub fn selftest_report(checks: &[selftest::Check]) -> String {
+    check_list("awx selftest", checks)
+}
+
+/// The troubleshooting checks and what they point to.
+pub fn diagnosis(d: &troubleshoot::Diagnosis) -> String {
+    let bold = theme::style(Role::Heading);
+    let mut out = check_list(&t(Msg::Diagnostics, &[]), &d.checks);
+    let _ = writeln!(out);
+    let _ = writeln!(out, "{} {}", bold.apply_to(t(Msg::MostLikelyCause, &[])), d.cause);
+    let _ = writeln!(out, "{} {}", bold.apply_to(t(Msg::SuggestedFix, &[])), d.fix);
+    out
+}
+
+fn check_list(title: &str, checks: &[selftest::Check]) -> String {
+    let bold = theme::style(Role::Heading);
+    let width = checks.iter().map(|c| self::width(&c.name)).max().unwrap_or(0);
+    let mut out = String::new();
+    let _ = writeln!(out, "{}", bold.apply_to(title));
+    for check in checks {
+        let mark = match check.status {
+            selftest::Status::Ok => theme::style(Role::Success).apply_to("ok  "),
+            selftest::Status::Warn => theme::style(Role::Warning).apply_to("warn"),
+            selftest::Status::Fail => theme::style(Role::Error).apply_to("FAIL"),
+            selftest::Status::Skip => theme::style(Role::Muted).apply_to("skip"),
+        };
+        let _ = writeln!(out, "  {} {}  {}", mark, pad(&check.name, width), check.detail);
+    }
+    out
+}
+
+/// `awx whoami`: the caller identity and, with `--deep`, what is attached to it.
+pub fn whoami(profile: &str, identity: &backend::CallerIdentity, details: Option<&iam::Details>) -> String {
+    let dim = theme::style(Role::Muted);
+    let mut out = String::new();
+    let _ = writeln!(out, "{}", theme::style(Role::Heading).apply_to("Identity"));
+    let _ = writeln!(out, "  profile:              {}", profile);
+    let _ = writeln!(out, "  account:              {}", identity.account);
+    let _ = writeln!(out, "  arn:                  {}", identity.arn);
+    let _ = writeln!(out, "  user id:              {}", identity.user_id);
+    let Some(d) = details else {
+        return out;
+    };
+    let list = |lookup: &iam::Lookup<Vec<String>>| match lookup {
+        Ok(names) if names.is_empty() => dim.apply_to("(none)").to_string(),
+        Ok(names) => names.join(", "),
+        Err(reason) => theme::style(Role::Warning).apply_to(format!("not readable ({})", reason)).to_string(),
+    };
+    let _ = writeln!(out);
+    let _ = writeln!(out, "{}", theme::style(Role::Heading).apply_to("IAM"));
+    let _ = writeln!(out, "  principal:            {}", d.principal);
+    if let Some(set) = &d.permission_set {
+        let _ = writeln!(out, "  permission set:       {}", set);
+    }
+    let _ = writeln!(out, "  attached policies:    {}", list(&d.attached_policies));
+    let _ = writeln!(out, "  inline policies:      {}", list(&d.inline_policies));
+    if let Some(groups) = &d.groups {
+        let _ = writeln!(out, "  groups:               {}", list(groups));
+    }
+    let boundary = d.permissions_boundary.clone().map(|b| b.into_iter().collect::<Vec<_>>());
+    let _ = writeln!(out, "  permissions boundary: {}", list(&boundary));
+    out
+}
+
+/// `awx credential-chain`: the default credential chain over the prepared environment.
+pub fn credential_chain(profile: &str, report: &sdk::Report) -> String {
+    let dim = theme::style(Role::Muted);
+    let mut out = String::new();
+    let _ = writeln!(out, "{}", theme::style(Role::Heading).apply_to(format!("Default credential chain for '{}'", profile)));
+    let width = report.steps.iter().map(|s| s.provider.label().len()).max().unwrap_or(0);
+    for (i, step) in report.steps.iter().enumerate() {
+        let (outcome, detail) = match step.outcome {
+            sdk::Outcome::Picked => (theme::style(Role::Success).apply_to("picked "), step.detail.clone()),
+            sdk::Outcome::Skipped => (dim.apply_to("skipped"), dim.apply_to(&step.detail).to_string()),
+            sdk::Outcome::NotReached => (dim.apply_to("-      "), String::new()),
+        };
+        let line = format!("  {}. {:<width$}  {}  {}", i + 1, step.provider.label(), outcome, detail, width = width);
+        let _ = writeln!(out, "{}", line.trim_end());
+    }
+    let expected = match &report.expected {
+        sdk::Expected::Keys(id) => format!("keys {} in the environment", sdk::mask(id)),
+        sdk::Expected::Profile(name) => format!("profile '{}'", name),
+    };
+    let _ = writeln!(out);
+    let _ = writeln!(out, "  awx injected:  {}", expected);
+    match &report.shadowed {
+        None => {
+            let _ = writeln!(out, "  {}", theme::style(Role::Success).apply_to("The chain would pick the credentials awx injected."));
+        }
+        Some(why) => {
+            let _ = writeln!(out, "  {} {}", theme::style(Role::Error).apply_to("Shadowed:"), why);
+        }
+    }
+    out
+}
+
+/// `awx can-i`: the decision for each action and resource.
+pub fn can_i(principal: &str, evaluations: &[iam::Evaluation]) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "{}", theme::style(Role::Heading).apply_to(format!("Policy simulation for {}", principal)));
+    let width = evaluations.iter().map(|e| e.eval_decision.len()).max().unwrap_or(0);
+    for e in evaluations {
+        let style = if e.allowed() { theme::style(Role::Success) } else { theme::style(Role::Error) };
+        let decision = style.apply_to(format!("{:<width$}", e.eval_decision, width = width));
+        let _ = writeln!(out, "  {}  {} on {}", decision, e.eval_action_name, e.eval_resource_name);
+    }
+    let _ = writeln!(
+        out,
+        "{}",
+        theme::style(Role::Muted)
+            .apply_to("Resource-based policies such as bucket policies are not evaluated.")
+    );
+    out
+}
+
+/// `awx ls-roles`: the roles whose trust policy lets the caller in.
+pub fn assumable_roles(caller: &str, account: &str, roles: &[iam::Assumable]) -> String {
+    let dim = theme::style(Role::Muted);
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "{}",
+        theme::style(Role::Heading).apply_to(format!("Roles in {} that trust {}", account, caller))
+    );
+    if roles.is_empty() {
+        let _ = writeln!(out, "  {}", dim.apply_to("(none)"));
+    }
+    let width = roles.iter().map(|r| r.arn.len()).max().unwrap_or(0);
+    for role in roles {
+        let how = match role.trust {
+            iam::Trust::Principal => "trusts you",
+            iam::Trust::Account => "trusts the account; your policies must allow sts:AssumeRole",
+            iam::Trust::Anyone => "trusts any AWS principal",
+        };
+        let conditions = if role.conditional { " (with conditions)" } else { "" };
+        let _ = writeln!(out, "  {:<width$}  {}{}", role.arn, how, conditions, width = width);
+    }
+    let _ = writeln!(out);
+    let _ = writeln!(
+        out,
+        "{}",
+        dim.apply_to("Trust policy conditions are not evaluated. Roles in other accounts are not listed.")
+    );
+    out
+}
+
+/// `awx trail`: the calls CloudTrail recorded for `username`, oldest first, with times
+/// in `tz`.
+pub fn trail<Tz: TimeZone>(username: &str, since: DateTime<Utc>, events: &[trail::Event], tz: &Tz) -> String
+where
+    Tz::Offset: std::fmt::Display,
+{
+    let dim = theme::style(Role::Muted);
+    let time = |at: DateTime<Utc>| at.with_timezone(tz).format("%Y-%m-%d %H:%M:%S").to_string();
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "{}",
+        theme::style(Role::Heading).apply_to(format!("API calls by '{}' since {}", username, time(since)))
+    );
+    if events.is_empty() {
+        let _ = writeln!(out, "  {}", dim.apply_to("(none recorded yet)"));
+    }
+    let service_width = events.iter().map(|e| e.service.len()).max().unwrap_or(0);
+    let name_width = events.iter().map(|e| e.name.len()).max().unwrap_or(0);
+    for e in events {
+        let mut line = format!(
+            "  {}  {:<sw$}  {:<nw$}",
+            time(e.time),
+            e.service,
+            e.name,
+            sw = service_width,
+            nw = name_width
+        );
+        if !e.resources.is_empty() {
+            let _ = write!(line, "  {}", e.resources.join(", "));
+        }
+        match &e.error {
+            Some(code) => {
+                let _ = writeln!(out, "{}  {}", line.trim_end(), theme::style(Role::Error).apply_to(code));
+            }
+            None => {
+                let _ = writeln!(out, "{}", line.trim_end());
+            }
+        }
+    }
+    let _ = writeln!(
+        out,
+        "{}",
+        dim.apply_to("CloudTrail takes up to 15 minutes to record a call; data events are not listed.")
+    );
+    out
+}
+
+/// The spend line before a command; a failed lookup only when it was just made.
+pub fn spend(profile: &str, entry: &spend::Entry, looked_up: bool, now: DateTime<Utc>) -> String {
+    let dim = theme::style(Role::Muted);
+    match &entry.spend {
+        spend::Spend::Amount { amount, unit } => format!(
+            "Month-to-date spend of '{}': {:.2} {} {}\n",
+            profile,
+            amount,
+            unit,
+            dim.apply_to(format!("(fetched {} ago)", duration(now - entry.fetched_at)))
+        ),
+        spend::Spend::Failed { reason } if looked_up => format!(
+            "{}\n",
+            theme::style(Role::Warning).apply_to(format!("Warning: no month-to-date spend for '{}': {}", profile, reason))
+        ),
+        spend::Spend::Failed { .. } => String::new(),
+    }
+}
+
+/// `awx exec`: the per-target summary table, for stderr so stdout stays the children's.
+pub fn exec_summary(outcomes: &[exec::Outcome]) -> String {
+    let bold = theme::style(Role::Heading);
+    let red = theme::style(Role::Error);
+    let mut out = String::from("\n");
+    let _ = writeln!(
+        out,
+        "{}",
+        bold.apply_to(format!("{:<30} {:<16} {:>5} {:>8}  {}", "PROFILE", "REGION", "EXIT", "TIME", "STATUS"))
+    );
+    for o in outcomes {
+        let exit = o.exit_code.map(|c| c.to_string()).unwrap_or_else(|| "-".to_string());
+        let line = format!(
+            "{} {:<16} {:>5} {:>7.1}s  {}",
+            pad(&o.target.profile, 30),
+            o.target.region.as_deref().unwrap_or("-"),
+            exit,
+            o.duration.as_secs_f64(),
+            o.status()
+        );
+        if o.status() == "ok" {
+            let _ = writeln!(out, "{}", line);
+        } else {
+            let _ = writeln!(out, "{}", red.apply_to(line));
+        }
+    }
+    out
+}
+
+/// Sessions expiring sooner than this are shown as urgent.
+const EXPIRY_URGENT_MINUTES: i64 = 15;
+
+/// A credential expiry in local time with the time left, e.g.
+/// `2024-05-01 18:30:00 +09:00 (in 47 minutes)`.
+pub fn expiration(at: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    expiration_in(at, now, &Local)
+}
+
+pub fn expiration_in<Tz: TimeZone>(at: DateTime<Utc>, now: DateTime<Utc>, tz: &Tz) -> String
+where
+    Tz::Offset: std::fmt::Display,
+{
+    format!("{} ({})", at.with_timezone(tz).format("%Y-%m-%d %H:%M:%S %:z"), relative(at - now))
+}
+
+/// `expiration`, colored by urgency.
+pub fn expiration_colored(at: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    expiry_style(at - now).apply_to(expiration(at, now)).to_string()
+}
+
+/// Red once expired, yellow when close, green otherwise.
+pub fn expiry_style(left: Duration) -> Style {
+    if left <= Duration::zero() {
+        theme::style(Role::Error)
+    } else if left < Duration::minutes(EXPIRY_URGENT_MINUTES) {
+        theme::style(Role::Warning)
+    } else {
+        theme::style(Role::Success)
+    }
+}
+
+/// An RFC 3339 `Expiration` as returned by STS, shown as is when it does not parse.
+pub fn expiration_rfc3339(value: &str, now: DateTime<Utc>) -> String {
+    match DateTime::parse_from_rfc3339(value) {
+        Ok(at) => expiration_colored(at.with_timezone(&Utc), now),
+        Err(_) => value.to_string(),
+    }
+}
+
+/// `in 47 minutes`, `3 hours ago`, rounded down to the largest fitting unit.
+pub fn relative(delta: Duration) -> String {
+    if delta >= Duration::zero() {
+        format!("in {}", duration(delta))
+    } else {
+        format!("{} ago", duration(-delta))
+    }
+}
+
+/// `47 minutes`, `2 hours`, rounded down to the largest fitting unit.
+pub fn duration(d: Duration) -> String {
+    let plural = |n: i64, unit: &str| format!("{} {}{}", n, unit, if n == 1 { "" } else { "s" });
+    let secs = d.num_seconds().abs();
+    match secs {
+        0..=59 => "less than a minute".to_string(),
+        60..=3599 => plural(secs / 60, "minute"),
+        3600..=172_799 => plural(secs / 3600, "hour"),
+        _ => plural(secs / 86400, "day"),
+    }
+}
+
+/// A fatal error.
+pub fn error(err: &anyhow::Error) -> String {
+    format!("{} {}\n", t(Msg::Error, &[]), err)
+}
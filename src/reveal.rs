@@ -0,0 +1,71 @@
+// Keeping secrets off the screen (`--reveal`).
+//
+// `awx export` and `awx login` print `export` lines carrying a session's secret key and
+// token, meant for `eval` or the shell function from `awx init`, both of which capture
+// stdout. When stdout is an interactive terminal instead, nothing captures the lines:
+// they end up on a screen that may be shared or recorded. Unless `--reveal` is given,
+// awx then writes them to ~/.awx/exports/<profile>.sh, readable by the owner only, and
+// prints just the command that loads them (`awx export --clipboard` remains the other
+// way). The file starts with the session's expiration, and `awx cache gc` deletes it
+// once that has passed, or after a day for credentials that do not expire.
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+
+/// First line of every exports file, followed by the profile and the expiration.
+const HEADER: &str = "# Written by awx for";
+
+/// Whether secrets about to go to stdout must be written to a file instead.
+pub fn guarded() -> bool {
+    must_divert(std::io::stdout().is_terminal(), crate::backend::flags().reveal)
+}
+
+pub fn must_divert(stdout_is_terminal: bool, reveal: bool) -> bool {
+    stdout_is_terminal && !reveal
+}
+
+/// Where the exports of `profile` are written instead of the terminal.
+pub fn path(awx_dir: &Path, profile: &str) -> PathBuf {
+    awx_dir.join("exports").join(format!("{}.sh", profile))
+}
+
+/// The exports file of `profile`: `lines` after a header naming the expiration.
+pub fn contents(profile: &str, lines: &str, expiration: Option<DateTime<Utc>>) -> String {
+    let expires = expiration.map(|e| e.to_rfc3339()).unwrap_or_else(|| "never".to_string());
+    format!("{} '{}'; expires {}\n{}", HEADER, profile, expires, lines)
+}
+
+/// Write `lines` to the exports file of `profile` and return its path.
+pub fn divert(awx_dir: &Path, profile: &str, lines: &str, expiration: Option<DateTime<Utc>>) -> Result<PathBuf> {
+    let path = path(awx_dir, profile);
+    crate::state::write(&path, contents(profile, lines, expiration).as_bytes())?;
+    Ok(path)
+}
+
+/// Exports files under `awx_dir` that have expired at `now`.
+pub fn expired(awx_dir: &Path, now: DateTime<Utc>) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(awx_dir.join("exports"))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|e| e.path())
+        .filter(|path| {
+            let Ok(content) = std::fs::read_to_string(path) else {
+                return false;
+            };
+            let Some(header) = content.lines().next().filter(|l| l.starts_with(HEADER)) else {
+                return false;
+            };
+            match header.rsplit_once("; expires ").map(|(_, at)| DateTime::parse_from_rfc3339(at)) {
+                Some(Ok(at)) => at <= now,
+                _ => std::fs::metadata(path)
+                    .and_then(|m| m.modified())
+                    .is_ok_and(|t| DateTime::<Utc>::from(t) + Duration::days(1) <= now),
+            }
+        })
+        .collect();
+    files.sort();
+    files
+}
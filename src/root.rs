@@ -0,0 +1,141 @@
+// Centralized root access for member accounts (`awx root`).
+//
+// In an organization with centralized root access enabled, the management account or a
+// delegated administrator for IAM may call sts:AssumeRoot for the root user of a member
+// account. The session is limited to one task by an AWS-managed task policy under
+// `policy/root-task/` and lasts at most 15 minutes. `awx root` resolves the management
+// profile as usual and calls AssumeRoot with its credentials, but only after the target
+// account was typed back (`--confirm <account>` for scripts). Every attempt, declined,
+// failed or granted, is appended to ~/.awx/root-audit.jsonl before the session is used,
+// and a granted one is announced to the `[notify]` webhook whatever its profile and role
+// lists say. The session goes to the command after `--`, or is printed as `export`
+// lines the way `awx export` prints them.
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+use crate::arn::{self, Arn};
+use crate::{ratelimit, StsCredentials, StsCredsWrapper};
+
+/// The task policies AWS offers for root sessions.
+pub const TASKS: [&str; 5] = [
+    "IAMAuditRootUserCredentials",
+    "IAMCreateRootUserPassword",
+    "IAMDeleteRootUserCredentials",
+    "S3UnlockBucketPolicy",
+    "SQSUnlockQueuePolicy",
+];
+
+/// Root sessions last at most this long.
+pub const DURATION_SECS: i64 = 900;
+
+/// Parse `--target`: a 12-digit member account id.
+pub fn parse_account(s: &str) -> Result<String> {
+    if !arn::is_account_id(s) {
+        return Err(anyhow!("'{}' is not a 12-digit account id", s));
+    }
+    Ok(s.to_string())
+}
+
+/// Parse `--task`: one of `TASKS` (ignoring case) or its ARN; returns the ARN.
+pub fn task_policy_arn(task: &str) -> Result<String> {
+    let (partition, name) = if task.starts_with("arn:") {
+        let arn: Arn = task.parse()?;
+        match arn.resource.strip_prefix("policy/root-task/") {
+            Some(name) if arn.service == "iam" && arn.account == "aws" => (arn.partition.clone(), name.to_string()),
+            _ => return Err(anyhow!("'{}' is not an AWS-managed root task policy", task)),
+        }
+    } else {
+        ("aws".to_string(), task.to_string())
+    };
+    let name = TASKS
+        .iter()
+        .find(|t| t.eq_ignore_ascii_case(&name))
+        .ok_or_else(|| anyhow!("unknown root task '{}': expected one of {}", name, TASKS.join(", ")))?;
+    Ok(format!("arn:{}:iam::aws:policy/root-task/{}", partition, name))
+}
+
+/// The task a task policy ARN names.
+pub fn task_name(task_policy_arn: &str) -> &str {
+    task_policy_arn.rsplit('/').next().unwrap_or(task_policy_arn)
+}
+
+/// Call AssumeRoot with `aws`, a command carrying the management profile's credentials.
+pub async fn assume(mut aws: Command, target: &str, task_policy_arn: &str) -> Result<StsCredentials> {
+    let cmd = &mut aws;
+    let policy = format!("arn={}", task_policy_arn);
+    let duration = DURATION_SECS.to_string();
+    cmd.args(["sts", "assume-root", "--target-principal", target, "--task-policy-arn", policy.as_str()])
+        .args(["--duration-seconds", duration.as_str(), "--output", "json"]);
+    let output = ratelimit::output(cmd)
+        .await
+        .context("failed to run aws sts assume-root")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("assume-root failed: {}", stderr.trim()));
+    }
+    let wrap: StsCredsWrapper = serde_json::from_slice(&output.stdout).context("Parsing assume-root JSON response failed")?;
+    Ok(wrap.credentials)
+}
+
+/// The `export` lines of a root session. AWS_PROFILE is unset so that nothing falls
+/// back to the management profile.
+pub fn exports(credentials: &StsCredentials, region: Option<&str>) -> String {
+    let mut lines = String::from("unset AWS_PROFILE\n");
+    lines.push_str(&format!("export AWS_ACCESS_KEY_ID={}\n", credentials.access_key_id));
+    lines.push_str(&format!("export AWS_SECRET_ACCESS_KEY={}\n", credentials.secret_access_key));
+    lines.push_str(&format!("export AWS_SESSION_TOKEN={}\n", credentials.session_token));
+    if let Some(region) = region {
+        lines.push_str(&format!("export AWS_DEFAULT_REGION={}\n", region));
+    }
+    lines
+}
+
+pub fn audit_path(awx_dir: &Path) -> PathBuf {
+    awx_dir.join("root-audit.jsonl")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// The target account was not typed back.
+    Declined,
+    Failed,
+    Granted,
+}
+
+/// One `awx root` attempt.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub time: DateTime<Utc>,
+    pub user: String,
+    pub profile: String,
+    pub target: String,
+    pub task_policy_arn: String,
+    pub outcome: Outcome,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiration: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Append `entry` to the audit log at `log`. A root session is not handed out when this
+/// fails.
+pub fn record(log: &Path, entry: &AuditEntry) -> Result<()> {
+    let line = serde_json::to_string(entry)?;
+    if let Some(dir) = log.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options
+        .open(log)
+        .with_context(|| format!("Cannot write the root audit log {}", log.display()))?;
+    writeln!(file, "{}", line).with_context(|| format!("Cannot write the root audit log {}", log.display()))
+}
@@ -0,0 +1,154 @@
+// S3 Express One Zone session credentials (`awx s3express session`).
+//
+// Directory buckets (`<name>--<az-id>--x-s3`) authorize requests with credentials from
+// CreateSession rather than the caller's own: they are scoped to one bucket, last five
+// minutes, and go with each request in the `x-amz-s3session-token` header. The SDKs
+// handle this themselves; `awx s3express session` is for tools that do not, or for
+// scripts that want to see what a session allows. The profile's session makes the call,
+// and the result is kept in ~/.awx/s3express, readable by the owner only, and reused
+// while it lasts at least another minute, so a loop does not call CreateSession for
+// every object. `awx cache gc` deletes expired ones.
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+use crate::{ratelimit, state, StsCredentials, StsCredsWrapper};
+
+/// Kept sessions are reused while they last this long.
+const MIN_REMAINING_SECS: i64 = 60;
+
+/// What a session may do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+pub enum Mode {
+    ReadWrite,
+    ReadOnly,
+}
+
+impl Mode {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Mode::ReadWrite => "ReadWrite",
+            Mode::ReadOnly => "ReadOnly",
+        }
+    }
+}
+
+/// Parse `--bucket`: a directory bucket name, `<name>--<az-id>--x-s3`.
+pub fn parse_bucket(s: &str) -> Result<String> {
+    let valid = s
+        .strip_suffix("--x-s3")
+        .and_then(|rest| rest.rsplit_once("--"))
+        .is_some_and(|(name, zone)| !name.is_empty() && zone.contains("-az"));
+    if !valid || !s.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-') {
+        return Err(anyhow!("'{}' is not a directory bucket name (<name>--<az-id>--x-s3)", s));
+    }
+    Ok(s.to_string())
+}
+
+/// A session kept for one profile, bucket and mode.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    pub profile: String,
+    pub bucket: String,
+    pub mode: Mode,
+    pub expiration: DateTime<Utc>,
+    pub credentials: StsCredentials,
+}
+
+pub fn dir(awx_dir: &Path) -> PathBuf {
+    awx_dir.join("s3express")
+}
+
+pub fn path(awx_dir: &Path, profile: &str, bucket: &str, mode: Mode) -> PathBuf {
+    dir(awx_dir).join(format!("{}@{}.{}.json", profile, bucket, mode.as_str()))
+}
+
+fn read(path: &Path) -> Option<Session> {
+    serde_json::from_str(&std::fs::read_to_string(path).ok()?).ok()
+}
+
+/// The kept session at `path` when it lasts long enough at `now`.
+pub fn reusable(path: &Path, now: DateTime<Utc>) -> Option<Session> {
+    read(path).filter(|s| s.expiration - now >= Duration::seconds(MIN_REMAINING_SECS))
+}
+
+/// Call CreateSession with `aws`, a command carrying the profile's credentials.
+pub async fn create(mut aws: Command, bucket: &str, mode: Mode, region: Option<&str>) -> Result<StsCredentials> {
+    let cmd = &mut aws;
+    cmd.args(["s3api", "create-session", "--bucket", bucket, "--session-mode", mode.as_str()]);
+    if let Some(region) = region {
+        cmd.args(["--region", region]);
+    }
+    cmd.args(["--output", "json"]);
+    let output = ratelimit::output(cmd)
+        .await
+        .context("failed to run aws s3api create-session")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("create-session failed: {}", stderr.trim()));
+    }
+    let wrap: StsCredsWrapper = serde_json::from_slice(&output.stdout).context("Parsing create-session JSON response failed")?;
+    Ok(wrap.credentials)
+}
+
+/// A session for `bucket`: the kept one when it lasts, else a new one from `aws`,
+/// kept for later runs.
+pub async fn session(
+    awx_dir: &Path,
+    profile: &str,
+    bucket: &str,
+    mode: Mode,
+    aws: impl FnOnce() -> Command,
+    region: Option<&str>,
+    now: DateTime<Utc>,
+) -> Result<Session> {
+    let path = path(awx_dir, profile, bucket, mode);
+    if let Some(kept) = reusable(&path, now) {
+        return Ok(kept);
+    }
+    let credentials = create(aws(), bucket, mode, region).await?;
+    let expiration = DateTime::parse_from_rfc3339(&credentials.expiration)
+        .map(|e| e.with_timezone(&Utc))
+        .with_context(|| format!("create-session returned an unreadable Expiration '{}'", credentials.expiration))?;
+    let session = Session {
+        profile: profile.to_string(),
+        bucket: bucket.to_string(),
+        mode,
+        expiration,
+        credentials,
+    };
+    state::write(&path, &serde_json::to_vec_pretty(&session)?)?;
+    Ok(session)
+}
+
+/// The `export` lines of `session`; the names are awx's own, as no tool reads standard
+/// ones for directory bucket sessions.
+pub fn exports(session: &Session) -> String {
+    let c = &session.credentials;
+    [
+        ("AWX_S3EXPRESS_BUCKET", session.bucket.as_str()),
+        ("AWX_S3EXPRESS_ACCESS_KEY_ID", c.access_key_id.as_str()),
+        ("AWX_S3EXPRESS_SECRET_ACCESS_KEY", c.secret_access_key.as_str()),
+        ("AWX_S3EXPRESS_SESSION_TOKEN", c.session_token.as_str()),
+        ("AWX_S3EXPRESS_EXPIRATION", c.expiration.as_str()),
+    ]
+    .iter()
+    .map(|(name, value)| format!("export {}={}\n", name, value))
+    .collect()
+}
+
+/// Kept sessions under `awx_dir` that have expired at `now`.
+pub fn expired(awx_dir: &Path, now: DateTime<Utc>) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir(awx_dir))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| read(p).is_some_and(|s| s.expiration <= now))
+        .collect();
+    files.sort();
+    files
+}
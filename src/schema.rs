@@ -0,0 +1,256 @@
+// JSON Schemas for editors (`awx config schema`).
+//
+// Taplo and other TOML language servers validate and complete a file against a JSON
+// Schema, e.g. with `#:schema ./awx.schema.json` at the top of ~/.awx/config.toml. The
+// schema is written out here rather than derived, so every setting carries the
+// description a user sees on hover; the tests check that the values it allows are the
+// ones `Settings` accepts. `--aws` prints the profile keys awx recognizes in ~/.aws
+// instead, for tools that check INI files against a schema of their sections.
+use serde_json::{json, Map, Value};
+
+const DRAFT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+fn string(description: &str) -> Value {
+    json!({ "type": "string", "description": description })
+}
+
+fn boolean(description: &str) -> Value {
+    json!({ "type": "boolean", "description": description })
+}
+
+fn one_of(description: &str, values: &[&str]) -> Value {
+    json!({ "type": "string", "enum": values, "description": description })
+}
+
+fn strings(description: &str) -> Value {
+    json!({ "type": "array", "items": { "type": "string" }, "description": description })
+}
+
+fn string_map(description: &str) -> Value {
+    json!({ "type": "object", "additionalProperties": { "type": "string" }, "description": description })
+}
+
+fn duration(description: &str) -> Value {
+    json!({
+        "type": "string",
+        "pattern": "^([0-9]+[smhd])+$",
+        "description": format!("{} (e.g. 90s, 45m, 2h, 1h30m)", description),
+    })
+}
+
+fn reference(description: &str) -> Value {
+    json!({
+        "type": "string",
+        "pattern": "^(op|bw)://.+/.+$",
+        "description": format!("{} (op://<vault>/<item>/<field> or bw://<item>/<field>)", description),
+    })
+}
+
+fn table(description: &str, properties: Value) -> Value {
+    json!({
+        "type": "object",
+        "description": description,
+        "properties": properties,
+        "additionalProperties": false,
+    })
+}
+
+fn tables(description: &str, entry: Value) -> Value {
+    json!({ "type": "object", "description": description, "additionalProperties": entry })
+}
+
+/// Values of `identity_check`.
+pub const IDENTITY_CHECKS: [&str; 3] = ["none", "token-file", "sts"];
+/// Values of `conflict_resolution`.
+pub const CONFLICT_RESOLUTIONS: [&str; 3] = ["prefer-credentials", "prefer-config", "merge"];
+/// Values of `sso_login`.
+pub const SSO_LOGIN_MODES: [&str; 2] = ["cli", "native"];
+/// Values of `orphans`.
+pub const ORPHAN_POLICIES: [&str; 3] = ["terminate", "kill", "keep"];
+/// Values of `session_pinning`.
+pub const PINNINGS: [&str; 3] = ["never", "daily", "always"];
+/// Values of `profiles.<name>.cli_auto_prompt`.
+pub const AUTO_PROMPTS: [&str; 2] = ["on", "on-partial"];
+/// Values of `language`.
+pub const LANGUAGES: [&str; 2] = ["en", "ja"];
+/// Values of `theme.preset`.
+pub const THEME_PRESETS: [&str; 3] = ["default", "high-contrast", "monochrome"];
+/// The console roles `[theme]` styles.
+pub const THEME_ROLES: [&str; 8] = ["heading", "muted", "success", "warning", "error", "badge", "banner", "accent"];
+
+fn sso_login_options(description: &str) -> Value {
+    table(
+        description,
+        json!({
+            "no_browser": boolean("Print the verification URL instead of opening a browser"),
+            "use_device_code": boolean("Use the device-code flow instead of the PKCE authorization-code flow"),
+        }),
+    )
+}
+
+/// The schema of ~/.awx/config.toml.
+pub fn settings() -> Value {
+    let mut theme = Map::new();
+    theme.insert("preset".into(), one_of("Base palette", &THEME_PRESETS));
+    for role in THEME_ROLES {
+        theme.insert(role.into(), string("Dotted style, e.g. \"magenta.bold\"; empty for none"));
+    }
+    let properties = json!({
+        "conflict_resolution": one_of(
+            "How a profile defined in both ~/.aws/config and ~/.aws/credentials is combined; unset asks",
+            &CONFLICT_RESOLUTIONS,
+        ),
+        "metrics": boolean("Keep local per-profile usage counters for `awx stats`"),
+        "broker": boolean("Ask the in-memory `awx broker` for sessions and hand it the ones resolved"),
+        "broker_acl": json!({
+            "type": "object",
+            "description": "Executables (absolute paths or path globs) allowed to receive the broker's sessions of profiles matching each glob",
+            "additionalProperties": { "type": "array", "items": { "type": "string", "pattern": "^/" } },
+        }),
+        "sso_login": one_of("Who performs SSO logins: `aws sso login` or awx itself", &SSO_LOGIN_MODES),
+        "rate_limit": table("Pacing of STS and SSO API calls", json!({
+            "rps": { "type": "number", "minimum": 0, "description": "Sustained API calls per second; 0 disables pacing" },
+            "burst": { "type": "integer", "minimum": 0, "description": "Calls allowed back to back before pacing starts" },
+            "max_retries": { "type": "integer", "minimum": 0, "description": "Retries of a throttled call before its error is returned" },
+        })),
+        "targets": tables("Saved target sets for `awx exec --targets`", table("A target set", json!({
+            "profiles": strings("Profile names or globs such as `prod-*`"),
+            "regions": strings("Regions to run each profile in; empty means the profile's own region"),
+        }))),
+        "contexts": tables("Named contexts for `awx ctx use`", json!({
+            "type": "object",
+            "required": ["profile"],
+            "additionalProperties": false,
+            "properties": {
+                "profile": string("Profile used when a command names none"),
+                "region": string("Region for the profile's commands"),
+                "endpoint_url": string("Endpoint for every service (AWS_ENDPOINT_URL), e.g. a LocalStack URL"),
+                "env": string_map("Extra environment for the profile's commands"),
+                "command": strings("AWS CLI arguments run when `awx` is given none"),
+                "confirm": boolean("Ask before running a command with the profile; refuse with --no-interactive"),
+            },
+        })),
+        "services": tables("Presets for passthrough AWS CLI commands by service", table("A service preset", json!({
+            "endpoint_url": string("Endpoint for this service only (AWS_ENDPOINT_URL_<SERVICE>)"),
+            "pager": string("AWS_PAGER for the service's commands; empty turns the pager off"),
+            "output": string("AWS_DEFAULT_OUTPUT for the service's commands, e.g. \"table\""),
+            "env": string_map("Extra environment for the service's commands"),
+        }))),
+        "orphans": one_of("What happens to a running command when awx dies before it (Linux)", &ORPHAN_POLICIES),
+        "projects": json!({
+            "type": "array",
+            "description": "Profiles by directory or git remote",
+            "items": {
+                "type": "object",
+                "required": ["profile"],
+                "additionalProperties": false,
+                "properties": {
+                    "profile": string("Profile used in the project"),
+                    "path": string("Directory glob, e.g. \"~/work/payments*\""),
+                    "remote": string("Remote glob, e.g. \"github.com/acme/payments-*\""),
+                },
+            },
+        }),
+        "round_trip_warning": {
+            "type": "integer",
+            "minimum": 0,
+            "description": "List the expected steps when resolving a profile needs more network round-trips than this",
+        },
+        "mfa_attempts": {
+            "type": "integer",
+            "minimum": 1,
+            "description": "MFA codes asked for before giving up (default 3)",
+        },
+        "sso_login_options": sso_login_options("Default `aws sso login` options for every profile"),
+        "identity_check": one_of("How SSO sessions are verified unless a profile says otherwise", &IDENTITY_CHECKS),
+        "profiles": tables("Per-profile overrides", table("Settings of one profile", json!({
+            "sso_login": sso_login_options("`aws sso login` options for this profile"),
+            "identity_check": one_of("How this profile's SSO session is verified", &IDENTITY_CHECKS),
+            "needs": duration("How long commands run with this profile usually take"),
+            "cli_auto_prompt": one_of("AWS_CLI_AUTO_PROMPT for the AWS CLI commands run with this profile", &AUTO_PROMPTS),
+            "cli_alias_file": string("CLI aliases awx expands for this profile, in the format of ~/.aws/cli/alias"),
+            "aws_access_key_id": reference("Where the access key is kept"),
+            "aws_secret_access_key": reference("Where the secret key is kept"),
+            "vault_path": string("Vault AWS secrets engine path the session is read from, e.g. \"aws/sts/deploy\" (awx built with --features vault)"),
+        }))),
+        "notify": table("Webhook notifications for sensitive profiles and roles", json!({
+            "webhook": string("Incoming webhook URL (Slack, or anything taking a JSON POST)"),
+            "profiles": strings("Announce runs and logins with these profiles (names or globs)"),
+            "roles": strings("Announce assuming these roles (ARNs or globs)"),
+        })),
+        "spend": table("Month-to-date spend (Cost Explorer) shown before commands", json!({
+            "profiles": strings("Show the spend of these profiles' accounts (names or globs)"),
+            "refresh": duration("How long a fetched figure is shown before Cost Explorer is asked again"),
+        })),
+        "telemetry": table("OpenTelemetry trace export of awx's phases", json!({
+            "otlp_endpoint": string("OTLP/HTTP base URL, e.g. http://collector:4318"),
+            "headers": string_map("Headers sent with each export, e.g. an API key"),
+        })),
+        "language": one_of("Language of messages and guidance; defaults to the locale", &LANGUAGES),
+        "theme": table("Colors and emphasis of console output", Value::Object(theme)),
+        "clipboard_clear_after": duration("How long `awx export --clipboard` leaves the exports in the clipboard"),
+        "session_pinning": one_of(
+            "When saved session files are pinned to their caller identity and checked again",
+            &PINNINGS,
+        ),
+        "default_region": string("Region of profiles that set none, e.g. ones only in ~/.aws/credentials"),
+        "default_profile": table("What the `default` profile is allowed to do", json!({
+            "hide": boolean("Leave `default` out of the profile picker and profile lists"),
+            "explicit": boolean("Never use `default` unless it is named; --no-interactive fails instead"),
+            "production_accounts": strings("Warn when `default` holds static keys of these accounts"),
+        })),
+    });
+    json!({
+        "$schema": DRAFT,
+        "title": "awx configuration (~/.awx/config.toml)",
+        "type": "object",
+        "properties": properties,
+        "additionalProperties": false,
+    })
+}
+
+/// The profile keys awx reads from ~/.aws/config and ~/.aws/credentials.
+pub const AWS_PROFILE_KEYS: [(&str, &str); 21] = [
+    ("region", "Default region of the profile"),
+    ("sso_start_url", "AWS access portal URL (legacy SSO configuration)"),
+    ("sso_region", "Region of the IAM Identity Center instance"),
+    ("sso_session", "Name of an [sso-session] section"),
+    ("sso_account_id", "Account of the SSO permission set"),
+    ("role_arn", "Role to assume; `<account>/<name>` and a bare name are expanded"),
+    ("source_profile", "Profile whose credentials assume the role"),
+    ("credential_source", "Ambient credentials that assume the role"),
+    ("web_identity_token_file", "OIDC token the AWS CLI assumes the role with"),
+    ("credential_process", "Program the AWS CLI runs for credentials"),
+    ("mfa_serial", "MFA device asked for a code"),
+    ("role_session_name", "Session name of the assumed role"),
+    ("duration_seconds", "Length of the assumed-role session, in seconds"),
+    ("aws_access_key_id", "Static access key"),
+    ("aws_secret_access_key", "Static secret key"),
+    ("aws_session_token", "Session token of temporary keys"),
+    ("output", "Default output format of the AWS CLI; given to children as AWS_DEFAULT_OUTPUT"),
+    ("cli_pager", "Pager of the AWS CLI, empty for none; given to children as AWS_PAGER"),
+    ("cli_binary_format", "How the AWS CLI reads blob parameters"),
+    ("cli_history", "Whether the AWS CLI records command history"),
+    (crate::config_parser::DESCRIPTION_KEY, "Description shown in the picker, -c and --explain"),
+];
+
+/// The schema of one profile section in ~/.aws, as awx reads it.
+pub fn aws_profile() -> Value {
+    let mut properties = Map::new();
+    for (key, description) in AWS_PROFILE_KEYS {
+        let property = match key {
+            "duration_seconds" => json!({ "type": "integer", "minimum": 900, "maximum": 43_200, "description": description }),
+            "credential_source" => one_of(description, &["Environment", "Ec2InstanceMetadata", "EcsContainer"]),
+            _ => string(description),
+        };
+        properties.insert(key.into(), property);
+    }
+    json!({
+        "$schema": DRAFT,
+        "title": "AWS profile keys awx recognizes (~/.aws/config, ~/.aws/credentials)",
+        "type": "object",
+        "properties": Value::Object(properties),
+        // The AWS CLI and SDKs read many more keys.
+        "additionalProperties": true,
+    })
+}
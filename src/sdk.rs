@@ -0,0 +1,243 @@
+// Which credential provider the default chain would pick (`awx credential-chain`).
+//
+// The SDKs look for credentials through a fixed chain and stop at the first provider
+// that has them: environment variables, the shared profile named by AWS_PROFILE (or
+// `default`), a web identity token, the container credentials endpoint and finally the
+// instance metadata service. This is the order of the Rust SDK's default chain
+// (aws-config); the other SDKs and the AWS CLI agree on the first two, which are the
+// ones awx writes. awx walks the chain over the environment it prepares for a command
+// and the shared files that environment points at, without calling AWS, and reports the
+// provider that would answer. When that is not what awx injected, something else
+// shadows it: keys already exported in the shell, a context overriding AWS_PROFILE, or
+// files moved with AWS_CONFIG_FILE / AWS_SHARED_CREDENTIALS_FILE.
+//
+// This is a model of the chain, not a run of it: no SDK is loaded and no provider is
+// asked for credentials. It stops at the first provider whose variables or profile
+// keys are present, so it can differ from what an SDK actually ends up with: a
+// credential_process that fails, an SSO profile without a valid token, or a container
+// endpoint or metadata service that does not answer would make an SDK move on where
+// this walk reports a pick, and SDKs other than the Rust one differ in the details.
+use std::collections::HashMap;
+use std::path::Path;
+
+use tokio::process::Command;
+
+use crate::aws_env::{self, AwsFiles};
+use crate::config_parser::{self, Sections};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provider {
+    Environment,
+    Profile,
+    WebIdentity,
+    Container,
+    Imds,
+}
+
+impl Provider {
+    pub fn label(self) -> &'static str {
+        match self {
+            Provider::Environment => "environment",
+            Provider::Profile => "shared profile",
+            Provider::WebIdentity => "web identity token",
+            Provider::Container => "container endpoint",
+            Provider::Imds => "instance metadata",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// The provider has credentials and ends the chain.
+    Picked,
+    /// The provider has nothing and the chain moves on.
+    Skipped,
+    /// An earlier provider already answered.
+    NotReached,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub provider: Provider,
+    pub outcome: Outcome,
+    pub detail: String,
+}
+
+/// The credentials awx meant the command to use.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expected {
+    /// Keys in the environment with this access key id.
+    Keys(String),
+    /// The named shared profile.
+    Profile(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub steps: Vec<Step>,
+    pub expected: Expected,
+    /// Why the picked provider is not what awx injected, if it is not.
+    pub shadowed: Option<String>,
+}
+
+/// The environment `cmd` starts with: the variables it sets or removes over the
+/// environment of this run.
+pub fn environment(cmd: &Command) -> HashMap<String, String> {
+    let mut vars: HashMap<String, String> = HashMap::new();
+    let names = aws_env::KNOBS.iter().map(|k| k.name).chain(CHAIN_VARS.iter().copied());
+    for name in names {
+        if let Some(value) = aws_env::current_lookup(name) {
+            vars.insert(name.to_string(), value);
+        }
+    }
+    for (name, value) in cmd.as_std().get_envs() {
+        let name = name.to_string_lossy().into_owned();
+        match value {
+            Some(value) => vars.insert(name, value.to_string_lossy().into_owned()),
+            None => vars.remove(&name),
+        };
+    }
+    vars
+}
+
+/// Variables the chain reads beyond those awx itself honors.
+const CHAIN_VARS: &[&str] = &[
+    "AWS_WEB_IDENTITY_TOKEN_FILE",
+    "AWS_ROLE_ARN",
+    "AWS_CONTAINER_CREDENTIALS_RELATIVE_URI",
+    "AWS_CONTAINER_CREDENTIALS_FULL_URI",
+    "AWS_EC2_METADATA_DISABLED",
+];
+
+/// The two shared files as the environment `lookup` locates them.
+pub fn read_files(lookup: &dyn Fn(&str) -> Option<String>, aws_dir: &Path) -> (AwsFiles, Sections, Sections) {
+    let files = AwsFiles::resolve(lookup, aws_dir);
+    let read = |path: &Path| std::fs::read(path).map(|b| config_parser::parse_bytes(&b).sections).unwrap_or_default();
+    let (config, credentials) = (read(&files.config), read(&files.credentials));
+    (files, config, credentials)
+}
+
+/// Walk the default chain over `lookup` and the parsed shared files.
+pub fn walk(
+    lookup: &dyn Fn(&str) -> Option<String>,
+    files: &AwsFiles,
+    config: &Sections,
+    credentials: &Sections,
+    expected: Expected,
+) -> Report {
+    let var = |name: &str| aws_env::get(lookup, name);
+    let mut found: Vec<(Provider, Result<String, String>)> = Vec::new();
+
+    found.push((
+        Provider::Environment,
+        match (var("AWS_ACCESS_KEY_ID"), var("AWS_SECRET_ACCESS_KEY")) {
+            (Some(id), Some(_)) => Ok(format!("AWS_ACCESS_KEY_ID={}", mask(&id))),
+            (Some(_), None) => Err("AWS_ACCESS_KEY_ID is set without AWS_SECRET_ACCESS_KEY".to_string()),
+            _ => Err("AWS_ACCESS_KEY_ID is not set".to_string()),
+        },
+    ));
+
+    // The Rust SDK reads AWS_PROFILE only; AWS_DEFAULT_PROFILE is a CLI spelling.
+    let name = var("AWS_PROFILE").unwrap_or_else(|| "default".to_string());
+    let section = if name == "default" { name.clone() } else { format!("profile {}", name) };
+    let conf = config.get(&section);
+    let creds = credentials.get(&name);
+    found.push((
+        Provider::Profile,
+        match (conf, creds) {
+            (None, None) => Err(format!(
+                "profile '{}' is in neither {} nor {}",
+                name,
+                files.config.display(),
+                files.credentials.display()
+            )),
+            _ => profile_kind(conf, creds)
+                .map(|kind| format!("profile '{}' ({})", name, kind))
+                .ok_or_else(|| format!("profile '{}' has no credentials", name)),
+        },
+    ));
+
+    found.push((
+        Provider::WebIdentity,
+        match (var("AWS_WEB_IDENTITY_TOKEN_FILE"), var("AWS_ROLE_ARN")) {
+            (Some(file), Some(role)) => Ok(format!("{} with {}", role, file)),
+            _ => Err("AWS_WEB_IDENTITY_TOKEN_FILE and AWS_ROLE_ARN are not both set".to_string()),
+        },
+    ));
+
+    found.push((
+        Provider::Container,
+        match var("AWS_CONTAINER_CREDENTIALS_RELATIVE_URI").or_else(|| var("AWS_CONTAINER_CREDENTIALS_FULL_URI")) {
+            Some(uri) => Ok(uri),
+            None => Err("AWS_CONTAINER_CREDENTIALS_RELATIVE_URI / _FULL_URI are not set".to_string()),
+        },
+    ));
+
+    found.push((
+        Provider::Imds,
+        match var("AWS_EC2_METADATA_DISABLED") {
+            Some(v) if v.eq_ignore_ascii_case("true") => Err("AWS_EC2_METADATA_DISABLED=true".to_string()),
+            _ => Ok("answers only on EC2 and other hosts with a metadata service".to_string()),
+        },
+    ));
+
+    let mut steps = Vec::new();
+    let mut done = false;
+    for (provider, result) in found {
+        let (outcome, detail) = match result {
+            _ if done => (Outcome::NotReached, String::new()),
+            Ok(detail) => (Outcome::Picked, detail),
+            Err(detail) => (Outcome::Skipped, detail),
+        };
+        done |= outcome == Outcome::Picked;
+        steps.push(Step { provider, outcome, detail });
+    }
+
+    let picked = steps.iter().find(|s| s.outcome == Outcome::Picked).map(|s| s.provider);
+    let shadowed = match (&expected, picked) {
+        (Expected::Keys(id), Some(Provider::Environment)) if var("AWS_ACCESS_KEY_ID").as_ref() == Some(id) => None,
+        (Expected::Keys(_), Some(Provider::Environment)) => Some(format!(
+            "AWS_ACCESS_KEY_ID in the environment ({}) is not the key awx resolved; keys exported in the shell win over the profile",
+            var("AWS_ACCESS_KEY_ID").map(|id| mask(&id)).unwrap_or_default()
+        )),
+        (Expected::Profile(want), Some(Provider::Profile)) if *want == name => None,
+        (Expected::Profile(want), Some(Provider::Profile)) => {
+            Some(format!("AWS_PROFILE is '{}' instead of '{}'", name, want))
+        }
+        (Expected::Profile(want), Some(Provider::Environment)) => Some(format!(
+            "keys in the environment ({}) win over profile '{}'; unset AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY",
+            var("AWS_ACCESS_KEY_ID").map(|id| mask(&id)).unwrap_or_default(),
+            want
+        )),
+        (_, Some(other)) => Some(format!("the chain would fall through to the {} provider", other.label())),
+        (_, None) => Some("no provider has credentials".to_string()),
+    };
+    Report { steps, expected, shadowed }
+}
+
+/// How a profile provides credentials, in the order the SDK checks its keys.
+fn profile_kind(conf: Option<&HashMap<String, String>>, creds: Option<&HashMap<String, String>>) -> Option<&'static str> {
+    let has = |key: &str| conf.is_some_and(|s| s.contains_key(key)) || creds.is_some_and(|s| s.contains_key(key));
+    if has("role_arn") {
+        Some("assume role")
+    } else if has("sso_session") || has("sso_start_url") {
+        Some("SSO")
+    } else if has("credential_process") {
+        Some("credential_process")
+    } else if has("aws_access_key_id") && has("aws_secret_access_key") {
+        Some("static keys")
+    } else {
+        None
+    }
+}
+
+/// `AKIA…WXYZ`: enough to tell keys apart without printing them.
+pub fn mask(access_key_id: &str) -> String {
+    let chars: Vec<char> = access_key_id.chars().collect();
+    if chars.len() <= 8 {
+        return "…".to_string();
+    }
+    let head: String = chars[..4].iter().collect();
+    let tail: String = chars[chars.len() - 4..].iter().collect();
+    format!("{}…{}", head, tail)
+}
@@ -0,0 +1,190 @@
+// Static keys kept in a password manager (`op://`, `bw://`).
+//
+// Long-lived access keys need not sit in ~/.aws/credentials in plain text.
+// `aws_access_key_id` and `aws_secret_access_key` under `[profiles.<name>]` in
+// ~/.awx/config.toml may name where they are kept instead: `op://<vault>/<item>/<field>`
+// in 1Password, or `bw://<item>/<field>` in Bitwarden, where the field is `username`,
+// `password`, `notes` or a custom field of the item. The profile stays in ~/.aws/config
+// (without keys) and counts as a static one. The keys are read with `op read` or
+// `bw get` the first time the profile, or a role using it as source_profile, is
+// authenticated in a run, and are kept in memory only. The AWS CLI gets them through
+// the environment, for awx's own calls (`backend::select_profile`) as well as for the
+// command awx runs. Either CLI must already be signed in (`op signin`, BW_SESSION).
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::{Mutex, OnceLock};
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+
+use crate::settings::ProfileSettings;
+use crate::theme::{self, Role};
+use crate::{backend, Profile};
+
+static RESOLVED: OnceLock<Mutex<HashMap<String, Keys>>> = OnceLock::new();
+
+fn resolved_keys() -> &'static Mutex<HashMap<String, Keys>> {
+    RESOLVED.get_or_init(Default::default)
+}
+
+/// Where a secret is kept.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Reference {
+    /// `op://<vault>/<item>[/<section>]/<field>`, read with `op read`.
+    OnePassword(String),
+    /// `bw://<item>/<field>`, read with `bw get`.
+    Bitwarden { item: String, field: String },
+}
+
+impl Reference {
+    pub fn parse(s: &str) -> Result<Reference> {
+        if let Some(path) = s.strip_prefix("op://") {
+            if path.split('/').filter(|p| !p.is_empty()).count() < 3 {
+                return Err(anyhow!("'{}' names no field: use op://<vault>/<item>/<field>", s));
+            }
+            return Ok(Reference::OnePassword(s.to_string()));
+        }
+        if let Some(path) = s.strip_prefix("bw://") {
+            return match path.rsplit_once('/') {
+                Some((item, field)) if !item.is_empty() && !field.is_empty() => Ok(Reference::Bitwarden {
+                    item: item.to_string(),
+                    field: field.to_string(),
+                }),
+                _ => Err(anyhow!("'{}' names no field: use bw://<item>/<field>", s)),
+            };
+        }
+        Err(anyhow!("'{}' is not a secret reference (op://<vault>/<item>/<field> or bw://<item>/<field>)", s))
+    }
+}
+
+impl TryFrom<String> for Reference {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Reference> {
+        Reference::parse(&s)
+    }
+}
+
+impl fmt::Display for Reference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reference::OnePassword(uri) => f.write_str(uri),
+            Reference::Bitwarden { item, field } => write!(f, "bw://{}/{}", item, field),
+        }
+    }
+}
+
+/// Whether a profile's key is a reference rather than the key.
+pub fn is_reference(value: &str) -> bool {
+    Reference::parse(value).is_ok()
+}
+
+/// Keys read from a password manager.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keys {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+}
+
+/// Put the references of `[profiles.<name>]` in place of the profiles' keys.
+pub fn apply(profiles: &mut HashMap<String, Profile>, settings: &HashMap<String, ProfileSettings>) {
+    for (name, s) in settings {
+        if s.aws_access_key_id.is_none() && s.aws_secret_access_key.is_none() {
+            continue;
+        }
+        let Some(profile) = profiles.get_mut(name) else {
+            eprintln!(
+                "{}",
+                theme::style(Role::Warning).apply_to(format!(
+                    "Warning: ~/.awx/config.toml sets keys for profile '{}', which is not in ~/.aws/config; add a [profile {}] section.",
+                    name, name
+                ))
+            );
+            continue;
+        };
+        if let Some(r) = &s.aws_access_key_id {
+            profile.aws_access_key_id = Some(r.to_string());
+        }
+        if let Some(r) = &s.aws_secret_access_key {
+            profile.aws_secret_access_key = Some(r.to_string());
+        }
+    }
+}
+
+/// The keys read for `profile` in this run.
+pub fn resolved(profile: &str) -> Option<Keys> {
+    resolved_keys().lock().expect("secret map poisoned").get(profile).cloned()
+}
+
+/// Read the referenced keys of `profile` and of the source profiles it stands on.
+pub async fn prepare(profiles: &HashMap<String, Profile>, profile: &Profile) -> Result<()> {
+    let mut seen = HashSet::new();
+    let mut current = Some(profile);
+    while let Some(p) = current.filter(|p| seen.insert(p.name.as_str())) {
+        if resolved(&p.name).is_none() {
+            if let (Some(id), Some(secret)) = (&p.aws_access_key_id, &p.aws_secret_access_key) {
+                if is_reference(id) || is_reference(secret) {
+                    let keys = Keys {
+                        access_key_id: read_value(id).await?,
+                        secret_access_key: read_value(secret).await?,
+                    };
+                    resolved_keys().lock().expect("secret map poisoned").insert(p.name.clone(), keys);
+                }
+            }
+        }
+        current = p.source_profile.as_deref().and_then(|s| profiles.get(s));
+    }
+    Ok(())
+}
+
+// `value` itself, or what it refers to.
+async fn read_value(value: &str) -> Result<String> {
+    match Reference::parse(value) {
+        Ok(reference) => read(&reference).await,
+        Err(_) => Ok(value.to_string()),
+    }
+}
+
+/// The secret `reference` points to.
+pub async fn read(reference: &Reference) -> Result<String> {
+    crate::progress::advance(&format!("read {}", reference));
+    let value = match reference {
+        Reference::OnePassword(uri) => run("op", &["read", "--no-newline", uri]).await?,
+        Reference::Bitwarden { item, field } if ["username", "password", "notes", "totp"].contains(&field.as_str()) => {
+            run("bw", &["get", field, item]).await?
+        }
+        Reference::Bitwarden { item, field } => custom_field(&run("bw", &["get", "item", item]).await?, field)
+            .ok_or_else(|| anyhow!("Bitwarden item '{}' has no field '{}'", item, field))?,
+    };
+    let value = value.trim().to_string();
+    if value.is_empty() {
+        return Err(anyhow!("{} is empty", reference));
+    }
+    Ok(value)
+}
+
+/// The custom field `name` of a Bitwarden item (`bw get item` JSON).
+pub fn custom_field(item_json: &str, name: &str) -> Option<String> {
+    let item: serde_json::Value = serde_json::from_str(item_json).ok()?;
+    item["fields"]
+        .as_array()?
+        .iter()
+        .find(|f| f["name"] == name)
+        .and_then(|f| f["value"].as_str())
+        .map(str::to_string)
+}
+
+async fn run(program: &str, args: &[&str]) -> Result<String> {
+    let mut cmd = backend::command(program);
+    cmd.args(args);
+    let output = tokio::time::timeout(std::time::Duration::from_secs(60), backend::runner().output(&mut cmd, None))
+        .await
+        .with_context(|| format!("{} timeout", program))?
+        .with_context(|| format!("failed to run {} (is it installed?)", program))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("{} {} failed: {}", program, args[0], stderr.trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
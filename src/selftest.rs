@@ -0,0 +1,318 @@
+// `awx selftest`: check the local installation and run the resolution pipeline
+// against a built-in mock of the AWS CLI.
+//
+// The environment checks look at what awx depends on: the `aws` binary, openssl (for
+// backups and pairing) and the permissions of the shared AWS files and ~/.awx. The
+// pipeline checks then run in an environment whose PATH starts with a temporary
+// directory where `aws` is a link to the awx binary itself; started under that name with AWX_SELFTEST_MOCK=1, awx
+// answers STS calls with canned responses instead of running normally. Profiles, role
+// assumption and credential injection into the child therefore go through the real
+// code without touching the network or the user's ~/.aws files.
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde_json::json;
+
+use crate::{aws_env, settings, Profile, StsCredentials};
+
+pub const MOCK_ENV: &str = "AWX_SELFTEST_MOCK";
+const MOCK_ROLE_KEY: &str = "ASIASELFTESTROLE";
+const MOCK_ACCOUNT: &str = "000000000000";
+
+const MOCK_CONFIG: &str = "[profile selftest-static]\nregion = us-east-1\n\n\
+[profile selftest-role]\nrole_arn = arn:aws:iam::000000000000:role/selftest\nsource_profile = selftest-static\n\n\
+[profile selftest-sso]\nsso_start_url = https://selftest.awsapps.com/start\nsso_region = us-east-1\n";
+const MOCK_CREDENTIALS: &str =
+    "[selftest-static]\naws_access_key_id = AKIASELFTESTSTATIC\naws_secret_access_key = selftest-secret\n";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    Warn,
+    Fail,
+    Skip,
+}
+
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub name: String,
+    pub status: Status,
+    pub detail: String,
+}
+
+impl Check {
+    pub fn new(name: &str, status: Status, detail: impl Into<String>) -> Check {
+        Check {
+            name: name.to_string(),
+            status,
+            detail: detail.into(),
+        }
+    }
+
+    fn from_result(name: &str, result: Result<String>) -> Check {
+        match result {
+            Ok(detail) => Check::new(name, Status::Ok, detail),
+            Err(e) => Check::new(name, Status::Fail, format!("{:#}", e)),
+        }
+    }
+}
+
+/// Whether this process was started as the mock `aws` of a self-test.
+pub fn is_mock_invocation() -> bool {
+    let invoked_as_aws = std::env::args_os()
+        .next()
+        .and_then(|a| Path::new(&a).file_stem().map(|s| s == "aws"))
+        .unwrap_or(false);
+    invoked_as_aws && aws_env::get(&aws_env::current_lookup, MOCK_ENV).as_deref() == Some("1")
+}
+
+/// Answer an `aws` invocation the way the mock backend does: exit code and stdout.
+pub fn mock_response(args: &[String], access_key: Option<&str>, now: DateTime<Utc>) -> (i32, String) {
+    let credentials = |key: &str| {
+        json!({
+            "AccessKeyId": key,
+            "SecretAccessKey": "selftest-secret",
+            "SessionToken": "selftest-token",
+            "Expiration": (now + Duration::hours(1)).to_rfc3339(),
+        })
+    };
+    let command: Vec<&str> = args.iter().map(String::as_str).take(2).collect();
+    match command.as_slice() {
+        ["--version", ..] => (0, "aws-cli/2 awx-selftest-mock".to_string()),
+        ["sts", "get-caller-identity"] => {
+            let arn = if access_key == Some(MOCK_ROLE_KEY) {
+                format!("arn:aws:sts::{}:assumed-role/selftest/awx", MOCK_ACCOUNT)
+            } else {
+                format!("arn:aws:iam::{}:user/selftest", MOCK_ACCOUNT)
+            };
+            let identity = json!({"Account": MOCK_ACCOUNT, "Arn": arn, "UserId": "AIDASELFTEST"});
+            (0, identity.to_string())
+        }
+        ["sts", "assume-role"] => (0, json!({"Credentials": credentials(MOCK_ROLE_KEY)}).to_string()),
+        ["sts", "get-session-token"] => (0, json!({"Credentials": credentials("ASIASELFTESTSESSION")}).to_string()),
+        ["configure", "export-credentials"] => {
+            let mut exported = credentials("ASIASELFTESTSSO");
+            exported["Version"] = json!(1);
+            (0, exported.to_string())
+        }
+        _ => (255, String::new()),
+    }
+}
+
+/// Entry point of the mock `aws`.
+pub fn run_mock() -> i32 {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let key = aws_env::get(&aws_env::current_lookup, "AWS_ACCESS_KEY_ID");
+    let (code, stdout) = mock_response(&args, key.as_deref(), Utc::now());
+    if code == 0 {
+        println!("{}", stdout);
+    } else {
+        eprintln!("awx selftest mock: unsupported command 'aws {}'", args.join(" "));
+    }
+    code
+}
+
+/// Checks of the local installation.
+pub async fn environment_checks(files: &aws_env::AwsFiles, awx_dir: &Path) -> Vec<Check> {
+    let mut checks = Vec::new();
+    checks.push(match version_of("aws", &["--version"]).await {
+        Some(version) => Check::new("aws binary", Status::Ok, version),
+        None => Check::new(
+            "aws binary",
+            Status::Fail,
+            "not found on PATH; install AWS CLI v2 and make sure `aws` is on PATH",
+        ),
+    });
+    checks.push(match version_of("openssl", &["version"]).await {
+        Some(version) => Check::new("openssl", Status::Ok, version),
+        None => Check::new(
+            "openssl",
+            Status::Warn,
+            "not found; needed for `awx config backup --encrypt` and `awx pair`",
+        ),
+    });
+    checks.push(Check::new(
+        "keyring",
+        Status::Skip,
+        "awx keeps no secrets in an OS keyring; credentials stay in ~/.aws",
+    ));
+    checks.push(file_permissions(&files.credentials));
+    checks.push(file_permissions(&files.config));
+    checks.push(Check::from_result("~/.awx writable", writable(awx_dir)));
+    checks.push(state_files(&crate::state::incidents(&awx_dir.join("incidents.jsonl"))));
+    checks
+}
+
+/// Corrupted state files found and quarantined by earlier runs.
+pub fn state_files(incidents: &[crate::state::Incident]) -> Check {
+    let Some(last) = incidents.last() else {
+        return Check::new("state files", Status::Ok, "no corrupted files found");
+    };
+    let moved = match &last.moved_to {
+        Some(dest) => format!("moved to {}", dest.display()),
+        None => "could not be moved".to_string(),
+    };
+    Check::new(
+        "state files",
+        Status::Warn,
+        format!(
+            "{} corrupted file(s) quarantined, last {} at {} ({}; {}); see ~/.awx/incidents.jsonl",
+            incidents.len(),
+            last.path.display(),
+            last.time.format("%Y-%m-%d %H:%M UTC"),
+            last.problem,
+            moved
+        ),
+    )
+}
+
+pub async fn version_of(program: &str, args: &[&str]) -> Option<String> {
+    let output = crate::backend::runner()
+        .output(crate::backend::command(program).args(args), None)
+        .await
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let text = if output.stdout.is_empty() { output.stderr } else { output.stdout };
+    Some(String::from_utf8_lossy(&text).trim().to_string())
+}
+
+fn file_permissions(path: &Path) -> Check {
+    let name = format!("{} permissions", path.display());
+    let Ok(meta) = std::fs::metadata(path) else {
+        return Check::new(&name, Status::Skip, "not present");
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = meta.permissions().mode() & 0o777;
+        if mode & 0o077 != 0 {
+            return Check::new(
+                &name,
+                Status::Warn,
+                format!("mode {:o} lets other users read it; run chmod 600 {}", mode, path.display()),
+            );
+        }
+        Check::new(&name, Status::Ok, format!("mode {:o}", mode))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = meta;
+        Check::new(&name, Status::Ok, "present")
+    }
+}
+
+fn writable(dir: &Path) -> Result<String> {
+    std::fs::create_dir_all(dir).with_context(|| format!("cannot create {}", dir.display()))?;
+    let probe = tempfile::NamedTempFile::new_in(dir).with_context(|| format!("cannot write to {}", dir.display()))?;
+    drop(probe);
+    Ok(dir.display().to_string())
+}
+
+/// Run the resolution pipeline against the mock backend, in an environment whose PATH
+/// and AWS file variables point at a temporary directory.
+pub async fn pipeline_checks() -> Result<Vec<Check>> {
+    let td = crate::tmp::tempdir()?;
+    let bin = td.path().join("bin");
+    std::fs::create_dir_all(&bin)?;
+    let exe = std::env::current_exe().context("cannot locate the awx binary")?;
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(&exe, bin.join("aws"))?;
+    #[cfg(not(unix))]
+    std::fs::copy(&exe, bin.join("aws.exe"))?;
+
+    let files = aws_env::AwsFiles::in_dir(td.path());
+    std::fs::write(&files.config, MOCK_CONFIG)?;
+    std::fs::write(&files.credentials, MOCK_CREDENTIALS)?;
+    let outer = crate::backend::env();
+    let path = std::env::join_paths(
+        std::iter::once(bin.clone()).chain(std::env::split_paths(&outer.var("PATH").unwrap_or_default())),
+    )?;
+    let mut env = (*outer)
+        .clone()
+        .with("PATH", path.to_string_lossy())
+        .with(MOCK_ENV, "1")
+        .with("AWS_CONFIG_FILE", files.config.to_string_lossy())
+        .with("AWS_SHARED_CREDENTIALS_FILE", files.credentials.to_string_lossy());
+    for var in ["AWS_PROFILE", "AWS_ACCESS_KEY_ID", "AWS_SECRET_ACCESS_KEY", "AWS_SESSION_TOKEN", crate::session::SESSION_ENV] {
+        env = env.without(var);
+    }
+    let backends = crate::backend::Backends {
+        env: std::sync::Arc::new(env),
+        ..crate::backend::current()
+    };
+    let checks = crate::backend::scope(backends, mock_pipeline(&files)).await;
+    crate::progress::finish();
+    Ok(checks)
+}
+
+async fn mock_pipeline(files: &aws_env::AwsFiles) -> Vec<Check> {
+    let mut checks = Vec::new();
+    let profiles = match crate::load_profile_sources(files, None)
+        .and_then(|s| s.merge(|_| Ok(settings::ConflictResolution::Merge)))
+    {
+        Ok(profiles) => profiles,
+        Err(e) => return vec![Check::new("load profiles", Status::Fail, format!("{:#}", e))],
+    };
+    checks.push(Check::new(
+        "load profiles",
+        Status::Ok,
+        format!("{} profiles from config and credentials", profiles.len()),
+    ));
+
+    let settings = settings::Settings::default();
+    checks.push(Check::from_result(
+        "SSO session check",
+        crate::check_sso_session(&profiles["selftest-sso"], &settings)
+            .await
+            .and_then(|ok| if ok { Ok("mock STS accepted the session".to_string()) } else { Err(anyhow!("session reported as expired")) }),
+    ));
+    checks.push(Check::from_result(
+        "static profile",
+        resolve(&profiles, "selftest-static", &settings).await.and_then(|creds| match creds {
+            None => Ok("keys are left to the AWS CLI".to_string()),
+            Some(_) => Err(anyhow!("unexpected temporary credentials")),
+        }),
+    ));
+    let role = resolve(&profiles, "selftest-role", &settings).await;
+    let role_creds = role.as_ref().ok().cloned().flatten();
+    checks.push(Check::from_result(
+        "AssumeRole via source_profile",
+        role.and_then(|creds| match creds {
+            Some(c) if c.access_key_id == MOCK_ROLE_KEY => Ok(format!("session expires {}", crate::render::expiration_rfc3339(&c.expiration, Utc::now()))),
+            _ => Err(anyhow!("role credentials were not returned")),
+        }),
+    ));
+    checks.push(Check::from_result(
+        "credential injection",
+        injected_identity(&profiles["selftest-role"], role_creds).await,
+    ));
+    checks
+}
+
+async fn resolve(
+    profiles: &HashMap<String, Profile>,
+    name: &str,
+    settings: &settings::Settings,
+) -> Result<Option<StsCredentials>> {
+    crate::perform_authentication(profiles, name, true, settings).await
+}
+
+// The child sees the assumed role's keys rather than the source profile's.
+async fn injected_identity(profile: &Profile, creds: Option<StsCredentials>) -> Result<String> {
+    let creds = creds.ok_or_else(|| anyhow!("no role credentials to inject"))?;
+    let args = ["sts", "get-caller-identity"].map(String::from);
+    let output = crate::child_command("aws", &args, Some(creds), profile)
+        .output()
+        .await
+        .context("failed to start the child command")?;
+    let identity: serde_json::Value = serde_json::from_slice(&output.stdout).context("child printed no identity")?;
+    let arn = identity["Arn"].as_str().unwrap_or_default();
+    if !arn.contains(":assumed-role/") {
+        return Err(anyhow!("child ran as {} instead of the assumed role", arn));
+    }
+    Ok(format!("child ran as {}", arn))
+}
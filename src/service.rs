@@ -0,0 +1,92 @@
+// Per-service presets for commands passed through to the AWS CLI.
+//
+// `[services.<name>]` in ~/.awx/config.toml gives the commands of one service their own
+// environment: an endpoint (AWS_ENDPOINT_URL_<SERVICE>, e.g. a local DynamoDB), the
+// pager (AWS_PAGER, empty to turn it off), the default output (AWS_DEFAULT_OUTPUT) and
+// any other variables. The service is the first token of the passthrough arguments
+// after the CLI's global options, so `awx -p dev dynamodb scan ...` gets the dynamodb
+// preset and `awx -p dev s3 ls` does not. Presets only reach that child: the shell,
+// other services and awx's own calls are unaffected. They win over the active context,
+// while variables already set in the environment and the CLI's own flags (`--output`,
+// `--endpoint-url`, `--no-cli-pager`) win over them.
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use crate::settings::ServiceSettings;
+
+static PRESETS: OnceLock<HashMap<String, ServiceSettings>> = OnceLock::new();
+
+/// Apply the configured presets for this process.
+pub fn configure(presets: &HashMap<String, ServiceSettings>) {
+    if !presets.is_empty() {
+        let _ = PRESETS.set(presets.clone());
+    }
+}
+
+/// Global options of the AWS CLI that take a value.
+const VALUE_OPTIONS: &[&str] = &[
+    "--region",
+    "--output",
+    "--profile",
+    "--endpoint-url",
+    "--query",
+    "--color",
+    "--ca-bundle",
+    "--cli-read-timeout",
+    "--cli-connect-timeout",
+    "--cli-binary-format",
+];
+
+/// The service an AWS CLI invocation with `args` addresses, if any.
+pub fn name(args: &[String]) -> Option<&str> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with('-') {
+            return Some(arg);
+        }
+        if VALUE_OPTIONS.contains(&arg.as_str()) {
+            args.next();
+        }
+    }
+    None
+}
+
+/// The suffix of the service's AWS_ENDPOINT_URL_<SERVICE> variable. It follows the
+/// SDK's service id, which a few CLI commands do not spell the same way.
+pub fn endpoint_id(service: &str) -> String {
+    let id = match service {
+        "s3api" => "s3",
+        "logs" => "cloudwatch-logs",
+        "events" => "eventbridge",
+        "elb" => "elastic-load-balancing",
+        "elbv2" => "elastic-load-balancing-v2",
+        "es" => "elasticsearch-service",
+        other => other,
+    };
+    id.to_uppercase().replace('-', "_")
+}
+
+/// Environment of `preset` for `service`, leaving out variables `lookup` already has.
+pub fn env(service: &str, preset: &ServiceSettings, lookup: &impl Fn(&str) -> Option<String>) -> Vec<(String, String)> {
+    let mut env: Vec<(String, String)> = preset.env.clone().into_iter().collect();
+    if let Some(url) = &preset.endpoint_url {
+        env.push((format!("AWS_ENDPOINT_URL_{}", endpoint_id(service)), url.clone()));
+    }
+    if let Some(pager) = &preset.pager {
+        env.push(("AWS_PAGER".to_string(), pager.clone()));
+    }
+    if let Some(output) = &preset.output {
+        env.push(("AWS_DEFAULT_OUTPUT".to_string(), output.clone()));
+    }
+    env.retain(|(k, _)| lookup(k).map(|v| v.is_empty()).unwrap_or(true));
+    env.sort();
+    env
+}
+
+/// Environment of the configured preset for an AWS CLI run with `args`.
+pub fn env_for(args: &[String], lookup: &impl Fn(&str) -> Option<String>) -> Vec<(String, String)> {
+    let (Some(presets), Some(service)) = (PRESETS.get(), name(args)) else {
+        return Vec::new();
+    };
+    presets.get(service).map(|preset| env(service, preset, lookup)).unwrap_or_default()
+}
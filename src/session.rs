@@ -0,0 +1,202 @@
+// Context markers for nested awx invocations.
+//
+// Every child started by awx gets AWX_SESSION describing the profile whose credentials
+// it was given. When a script run under awx calls awx again for the same profile (or
+// without naming one), the inner awx reuses the inherited environment instead of
+// prompting or assuming the role a second time. `--fresh` ignores the marker.
+//
+// The same module defines the session descriptor file used to hand a session to
+// another process or host. With `session_pinning` in ~/.awx/config.toml, a descriptor
+// also records the ARN GetCallerIdentity returned for its credentials when it was
+// written, and before a saved session is reused ("daily", or "always") awx asks again
+// and refuses a session that now resolves to another principal or no longer resolves
+// at all, e.g. after the role was renamed upstream.
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{aws_env, StsCredentials};
+
+pub const SESSION_ENV: &str = "AWX_SESSION";
+/// Inherited sessions this close to expiry are resolved again instead.
+const REUSE_MARGIN_SECS: i64 = 300;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Marker {
+    pub profile: String,
+    /// Expiry of the injected STS credentials; None when the child resolves the
+    /// profile itself (SSO or static keys).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiration: Option<DateTime<Utc>>,
+}
+
+impl Marker {
+    pub fn new(profile: &str, expiration: Option<&str>) -> Marker {
+        Marker {
+            profile: profile.to_string(),
+            expiration: expiration
+                .and_then(|e| DateTime::parse_from_rfc3339(e).ok())
+                .map(|e| e.with_timezone(&Utc)),
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    pub fn decode(value: &str) -> Option<Marker> {
+        serde_json::from_str(value).ok()
+    }
+}
+
+/// The marker left by an outer awx, if it can still be used at `now`: injected
+/// credentials must be present in the environment and not about to expire.
+pub fn inherited(lookup: &dyn Fn(&str) -> Option<String>, now: DateTime<Utc>) -> Option<Marker> {
+    let marker = Marker::decode(&aws_env::get(lookup, SESSION_ENV)?)?;
+    if let Some(expiration) = marker.expiration {
+        if expiration - Duration::seconds(REUSE_MARGIN_SECS) <= now {
+            return None;
+        }
+        aws_env::get(lookup, "AWS_ACCESS_KEY_ID")?;
+        aws_env::get(lookup, "AWS_SESSION_TOKEN")?;
+    }
+    Some(marker)
+}
+
+/// How much longer than the session lasts a command that `needs` this long would run.
+pub fn shortfall(expiration: DateTime<Utc>, needs: Duration, now: DateTime<Utc>) -> Option<Duration> {
+    let left = expiration - now;
+    (left < needs).then(|| needs - left)
+}
+
+/// Whether a run for `requested` (None = no profile named) may reuse `marker`.
+pub fn reusable_for(marker: Option<&Marker>, requested: Option<&str>) -> Option<String> {
+    let marker = marker?;
+    match requested {
+        Some(name) if name != marker.profile => None,
+        _ => Some(marker.profile.clone()),
+    }
+}
+
+/// Version tag of the session descriptor written by `--session-file`.
+pub const DESCRIPTOR_FORMAT: &str = "awx-session/1";
+
+/// A portable, self-contained session: temporary credentials plus the profile and
+/// region they belong to. Written with `--session-file`, consumed with
+/// `--from-session-file` by another process or host until `expiration`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Descriptor {
+    pub format: String,
+    pub profile: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: String,
+    pub expiration: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    /// The ARN the credentials resolved to when pinned.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<String>,
+    /// When the pinned identity was last confirmed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verified_at: Option<DateTime<Utc>>,
+}
+
+/// When the identity pinned in a session file is checked again before reuse.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Pinning {
+    /// Sessions are neither pinned nor checked.
+    #[default]
+    Never,
+    /// Checked when the last check is a day old.
+    Daily,
+    /// Checked on every reuse.
+    Always,
+}
+
+impl Descriptor {
+    pub fn new(
+        profile: &str,
+        region: Option<String>,
+        creds: &StsCredentials,
+        now: DateTime<Utc>,
+    ) -> Result<Descriptor> {
+        let expiration = DateTime::parse_from_rfc3339(&creds.expiration)
+            .with_context(|| format!("Unexpected credential expiration '{}'", creds.expiration))?
+            .with_timezone(&Utc);
+        Ok(Descriptor {
+            format: DESCRIPTOR_FORMAT.to_string(),
+            profile: profile.to_string(),
+            region,
+            access_key_id: creds.access_key_id.clone(),
+            secret_access_key: creds.secret_access_key.clone(),
+            session_token: creds.session_token.clone(),
+            expiration,
+            created_at: now,
+            identity: None,
+            verified_at: None,
+        })
+    }
+
+    /// Whether `pinning` wants the identity checked before the session is reused at `now`.
+    pub fn check_due(&self, pinning: Pinning, now: DateTime<Utc>) -> bool {
+        match pinning {
+            Pinning::Never => false,
+            Pinning::Always => true,
+            Pinning::Daily => self.verified_at.is_none_or(|at| at + Duration::days(1) <= now),
+        }
+    }
+
+    /// Compare `arn`, what the credentials resolve to now, with the pinned identity, and
+    /// pin it when there is none yet.
+    pub fn confirm_identity(&mut self, arn: &str, now: DateTime<Utc>) -> Result<()> {
+        if let Some(pinned) = self.identity.as_deref().filter(|pinned| *pinned != arn) {
+            return Err(anyhow!(
+                "The session for '{}' now resolves to {} instead of {}; the principal changed upstream, so resolve the profile again",
+                self.profile,
+                arn,
+                pinned
+            ));
+        }
+        self.identity = Some(arn.to_string());
+        self.verified_at = Some(now);
+        Ok(())
+    }
+
+    pub fn credentials(&self) -> StsCredentials {
+        StsCredentials {
+            access_key_id: self.access_key_id.clone(),
+            secret_access_key: self.secret_access_key.clone(),
+            session_token: self.session_token.clone(),
+            expiration: self.expiration.to_rfc3339(),
+        }
+    }
+
+    /// Write the descriptor readable by the owner only.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        crate::state::write(path, serde_json::to_string_pretty(self)?.as_bytes())
+    }
+
+    /// Read a descriptor, rejecting unknown formats and expired sessions.
+    pub fn read(path: &Path, now: DateTime<Utc>) -> Result<Descriptor> {
+        let content = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let descriptor: Descriptor =
+            serde_json::from_str(&content).with_context(|| format!("{} is not an awx session file", path.display()))?;
+        if descriptor.format != DESCRIPTOR_FORMAT {
+            return Err(anyhow!("Unsupported session file format '{}'", descriptor.format));
+        }
+        if descriptor.expiration <= now {
+            return Err(anyhow!(
+                "Session for '{}' in {} expired at {}",
+                descriptor.profile,
+                path.display(),
+                crate::render::expiration(descriptor.expiration, now)
+            ));
+        }
+        Ok(descriptor)
+    }
+}
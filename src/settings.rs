@@ -0,0 +1,383 @@
+// awx-specific configuration and state locations.
+//
+// ~/.aws stays owned by the AWS CLI; awx keeps its own settings in
+// ~/.awx/config.toml (override with AWX_CONFIG) and machine-written state next to it.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::Duration;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::ratelimit::RateLimitSettings;
+
+/// How to treat a profile that is defined in both ~/.aws/config and
+/// ~/.aws/credentials with conflicting keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictResolution {
+    /// Keep the credentials-file side and drop conflicting config keys.
+    PreferCredentials,
+    /// Keep the config-file side and drop conflicting credentials keys.
+    PreferConfig,
+    /// Combine both sides (the AWS CLI's behavior).
+    Merge,
+}
+
+impl ConflictResolution {
+    pub const ALL: [ConflictResolution; 3] = [
+        ConflictResolution::PreferCredentials,
+        ConflictResolution::PreferConfig,
+        ConflictResolution::Merge,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ConflictResolution::PreferCredentials => "prefer-credentials",
+            ConflictResolution::PreferConfig => "prefer-config",
+            ConflictResolution::Merge => "merge",
+        }
+    }
+}
+
+/// Which implementation performs SSO logins.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SsoLoginMode {
+    /// Run `aws sso login`.
+    #[default]
+    Cli,
+    /// Drive the device-authorization flow through `aws sso-oidc`, reusing a
+    /// persisted client registration.
+    Native,
+}
+
+/// How awx decides whether an SSO profile still has an active session.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IdentityCheck {
+    /// Assume the session is active; the AWS command fails if it is not.
+    None,
+    /// Check the expiry of the cached token in ~/.aws/sso/cache without a network call.
+    TokenFile,
+    /// Call `sts get-caller-identity`.
+    #[default]
+    Sts,
+}
+
+/// Options forwarded to `aws sso login`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct SsoLoginOptions {
+    /// Print the verification URL instead of opening a browser.
+    pub no_browser: bool,
+    /// Use the device-code flow instead of the PKCE authorization-code flow.
+    pub use_device_code: bool,
+}
+
+impl SsoLoginOptions {
+    /// Options enabled here or in `other`.
+    pub fn or(self, other: SsoLoginOptions) -> SsoLoginOptions {
+        SsoLoginOptions {
+            no_browser: self.no_browser || other.no_browser,
+            use_device_code: self.use_device_code || other.use_device_code,
+        }
+    }
+
+    pub fn args(self) -> Vec<&'static str> {
+        let mut args = Vec::new();
+        if self.no_browser {
+            args.push("--no-browser");
+        }
+        if self.use_device_code {
+            args.push("--use-device-code");
+        }
+        args
+    }
+}
+
+/// Per-profile settings (`[profiles.<name>]`).
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ProfileSettings {
+    pub sso_login: SsoLoginOptions,
+    pub identity_check: Option<IdentityCheck>,
+    /// How long commands run with this profile usually take, e.g. `2h` for a large sync.
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub needs: Option<Duration>,
+    /// AWS_CLI_AUTO_PROMPT for the AWS CLI commands run with this profile.
+    pub cli_auto_prompt: Option<crate::cli::AutoPrompt>,
+    /// CLI aliases awx expands for this profile (format of ~/.aws/cli/alias).
+    pub cli_alias_file: Option<String>,
+    /// Vault AWS secrets engine path the session is read from (`--features vault`).
+    pub vault_path: Option<String>,
+    /// Where the profile's access key is kept (`op://`, `bw://`).
+    pub aws_access_key_id: Option<crate::secrets::Reference>,
+    /// Where the profile's secret key is kept (`op://`, `bw://`).
+    pub aws_secret_access_key: Option<crate::secrets::Reference>,
+}
+
+/// Parse a duration such as `90s`, `45m`, `2h` or `1h30m`.
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    let invalid = || anyhow!("invalid duration '{}': use e.g. 90s, 45m, 2h or 1h30m", s);
+    let mut total = Duration::zero();
+    let mut digits = String::new();
+    for c in s.trim().chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let n: i64 = digits.parse().map_err(|_| invalid())?;
+        let part = match c {
+            's' => Duration::try_seconds(n),
+            'm' => Duration::try_minutes(n),
+            'h' => Duration::try_hours(n),
+            'd' => Duration::try_days(n),
+            _ => None,
+        };
+        total = part.and_then(|p| total.checked_add(&p)).ok_or_else(invalid)?;
+        digits.clear();
+    }
+    if !digits.is_empty() || total <= Duration::zero() {
+        return Err(invalid());
+    }
+    Ok(total)
+}
+
+fn deserialize_duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    parse_duration(&value).map(Some).map_err(serde::de::Error::custom)
+}
+
+/// A named fleet for `awx exec --targets`: every matching profile in every region.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct TargetSet {
+    /// Profile names or globs such as `prod-*`.
+    pub profiles: Vec<String>,
+    /// Regions to run each profile in; empty means the profile's own region.
+    pub regions: Vec<String>,
+}
+
+/// Webhook notifications (`[notify]`).
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct NotifySettings {
+    /// Incoming webhook URL (Slack, or anything taking a JSON POST).
+    pub webhook: Option<String>,
+    /// Announce runs and logins with these profiles (names or globs such as `prod-*`).
+    pub profiles: Vec<String>,
+    /// Announce assuming these roles, e.g. break-glass roles (ARNs or globs).
+    pub roles: Vec<String>,
+}
+
+/// Month-to-date spend before commands (`[spend]`).
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct SpendSettings {
+    /// Show the spend of these profiles' accounts (names or globs such as `sandbox-*`).
+    pub profiles: Vec<String>,
+    /// How long a fetched figure is shown before Cost Explorer is asked again.
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub refresh: Option<Duration>,
+}
+
+/// OpenTelemetry trace export (`[telemetry]`). The standard `OTEL_EXPORTER_OTLP_*`
+/// variables take precedence.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct TelemetrySettings {
+    /// OTLP/HTTP base URL, e.g. `http://collector:4318`; traces go to `/v1/traces`.
+    pub otlp_endpoint: Option<String>,
+    /// Headers sent with each export, e.g. an API key.
+    pub headers: HashMap<String, String>,
+}
+
+/// Console styling (`[theme]`): a preset, and dotted styles for single roles, e.g.
+/// `warning = "magenta.bold"`.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ThemeSettings {
+    pub preset: crate::theme::Preset,
+    #[serde(flatten)]
+    pub styles: HashMap<crate::theme::Role, String>,
+}
+
+/// A named context for `awx ctx use` (`[contexts.<name>]`).
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct ContextSettings {
+    /// Profile used when a command names none.
+    pub profile: String,
+    /// Region for the profile's commands.
+    #[serde(default)]
+    pub region: Option<String>,
+    /// Endpoint for every service (AWS_ENDPOINT_URL), e.g. a LocalStack URL.
+    #[serde(default)]
+    pub endpoint_url: Option<String>,
+    /// Extra environment for the profile's commands.
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// AWS CLI arguments run when `awx` is given none.
+    #[serde(default)]
+    pub command: Vec<String>,
+    /// Ask before running a command with the profile; refuse with --no-interactive.
+    #[serde(default)]
+    pub confirm: bool,
+}
+
+/// Environment for AWS CLI commands of one service (`[services.<name>]`).
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ServiceSettings {
+    /// Endpoint for this service only (AWS_ENDPOINT_URL_<SERVICE>).
+    pub endpoint_url: Option<String>,
+    /// AWS_PAGER for the service's commands; empty turns the pager off.
+    pub pager: Option<String>,
+    /// AWS_DEFAULT_OUTPUT for the service's commands, e.g. "table".
+    pub output: Option<String>,
+    /// Extra environment for the service's commands.
+    pub env: HashMap<String, String>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Resolution applied to every conflicting profile. When unset, awx asks
+    /// interactively and merges in non-interactive mode.
+    pub conflict_resolution: Option<ConflictResolution>,
+    /// Keep local per-profile usage counters for `awx stats`.
+    pub metrics: bool,
+    /// Ask the in-memory `awx broker` for sessions and hand it the ones resolved.
+    pub broker: bool,
+    /// Executables allowed to receive the broker's sessions of matching profiles
+    /// (`[broker_acl]`, profile glob = absolute paths or path globs).
+    pub broker_acl: crate::broker::Acl,
+    pub sso_login: SsoLoginMode,
+    /// Pacing of STS and SSO API calls.
+    pub rate_limit: RateLimitSettings,
+    /// Saved target sets, keyed by name (`[targets.<name>]`).
+    pub targets: HashMap<String, TargetSet>,
+    /// Named contexts (`[contexts.<name>]`).
+    pub contexts: HashMap<String, ContextSettings>,
+    /// Presets for passthrough AWS CLI commands by service (`[services.<name>]`).
+    pub services: HashMap<String, ServiceSettings>,
+    /// What happens to a running command when awx dies before it (Linux).
+    pub orphans: crate::orphan::Policy,
+    /// Profiles by directory or git remote (`[[projects]]`).
+    pub projects: Vec<crate::projects::Mapping>,
+    /// List the expected steps when resolving a profile needs more network
+    /// round-trips than this.
+    pub round_trip_warning: Option<usize>,
+    /// MFA codes asked for before giving up.
+    pub mfa_attempts: Option<u32>,
+    /// Default `aws sso login` options for every profile.
+    #[serde(rename = "sso_login_options")]
+    pub sso_login_defaults: SsoLoginOptions,
+    /// How SSO sessions are verified unless a profile says otherwise.
+    pub identity_check: IdentityCheck,
+    /// Per-profile overrides.
+    pub profiles: HashMap<String, ProfileSettings>,
+    /// Webhook notifications for sensitive profiles and roles.
+    pub notify: NotifySettings,
+    /// Month-to-date spend shown before commands with some profiles.
+    pub spend: SpendSettings,
+    /// Trace export of awx's phases.
+    pub telemetry: TelemetrySettings,
+    /// Language of messages and guidance ("en" or "ja"); defaults to the locale.
+    pub language: Option<crate::i18n::Lang>,
+    /// Colors and emphasis of console output.
+    pub theme: ThemeSettings,
+    /// How long `awx export --clipboard` leaves the exports in the clipboard.
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub clipboard_clear_after: Option<Duration>,
+    /// When saved session files are pinned to their caller identity and checked again.
+    pub session_pinning: crate::session::Pinning,
+    /// Region of profiles that set none, e.g. ones only in ~/.aws/credentials.
+    pub default_region: Option<String>,
+    /// What the `default` profile is allowed to do (`[default_profile]`).
+    pub default_profile: crate::default_profile::DefaultProfileSettings,
+    /// Options given on the command line for this run.
+    #[serde(skip)]
+    pub sso_login_flags: SsoLoginOptions,
+    /// `--needs` for this run.
+    #[serde(skip)]
+    pub needs_flag: Option<Duration>,
+}
+
+impl Settings {
+    pub fn round_trip_warning(&self) -> usize {
+        self.round_trip_warning.unwrap_or(crate::progress::DEFAULT_ROUND_TRIP_WARNING)
+    }
+
+    pub fn mfa_attempts(&self) -> u32 {
+        self.mfa_attempts.unwrap_or(crate::pipeline::DEFAULT_MFA_ATTEMPTS).max(1)
+    }
+
+    /// `aws sso login` options for `profile`: command-line flags, the profile's
+    /// settings and the defaults combined.
+    pub fn sso_login_options(&self, profile: &str) -> SsoLoginOptions {
+        let per_profile = self.profiles.get(profile).map(|p| p.sso_login).unwrap_or_default();
+        self.sso_login_flags.or(per_profile).or(self.sso_login_defaults)
+    }
+
+    /// The session lifetime a command run with `profile` needs: `--needs`, else the
+    /// profile's hint.
+    pub fn needs(&self, profile: &str) -> Option<Duration> {
+        self.needs_flag.or_else(|| self.profiles.get(profile).and_then(|p| p.needs))
+    }
+
+    pub fn spend_refresh(&self) -> Duration {
+        self.spend.refresh.unwrap_or_else(|| Duration::hours(crate::spend::DEFAULT_REFRESH_HOURS))
+    }
+
+    pub fn clipboard_clear_after(&self) -> Duration {
+        self.clipboard_clear_after.unwrap_or_else(|| Duration::seconds(crate::clipboard::DEFAULT_CLEAR_SECS))
+    }
+
+    pub fn identity_check(&self, profile: &str) -> IdentityCheck {
+        self.profiles
+            .get(profile)
+            .and_then(|p| p.identity_check)
+            .unwrap_or(self.identity_check)
+    }
+}
+
+pub fn awx_dir() -> Result<PathBuf> {
+    let home = dirs::home_dir().ok_or_else(|| anyhow!("Could not determine home directory"))?;
+    Ok(home.join(".awx"))
+}
+
+fn config_path() -> Result<PathBuf> {
+    match crate::aws_env::current_lookup("AWX_CONFIG") {
+        Some(p) if !p.is_empty() => Ok(PathBuf::from(p)),
+        _ => Ok(awx_dir()?.join("config.toml")),
+    }
+}
+
+pub fn load_settings() -> Result<Settings> {
+    load_settings_from(&config_path()?)
+}
+
+pub fn load_settings_from(path: &Path) -> Result<Settings> {
+    if !path.exists() {
+        return Ok(Settings::default());
+    }
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    toml::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// Per-profile conflict resolutions remembered from interactive choices.
+pub fn load_remembered_conflicts() -> HashMap<String, ConflictResolution> {
+    let path = match awx_dir() {
+        Ok(d) => d.join("conflicts.json"),
+        Err(_) => return HashMap::new(),
+    };
+    crate::state::read_json(&path).unwrap_or_default()
+}
+
+pub fn remember_conflict(profile: &str, resolution: ConflictResolution) -> Result<()> {
+    let mut remembered = load_remembered_conflicts();
+    remembered.insert(profile.to_string(), resolution);
+    crate::state::write_json(&awx_dir()?.join("conflicts.json"), &remembered)
+}
@@ -0,0 +1,56 @@
+// Single-flight STS and SSO calls within one process.
+//
+// `awx exec` resolves many targets concurrently, and several of them often ask for the
+// very same session: two profiles naming one role from one source, or the same SSO
+// profile exported for two groups. An identical request made while another is still
+// in flight waits for that one and shares its outcome instead of calling AWS again.
+// Nothing is kept once the call completes, so this is not a cache: a request made
+// after the first one finished calls AWS as usual. Requests are keyed by the client
+// that serves them too, so separately scoped backends never share a call.
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use anyhow::{anyhow, Result};
+use tokio::sync::OnceCell;
+
+use crate::StsCredentials;
+
+type Flight = Arc<OnceCell<std::result::Result<StsCredentials, String>>>;
+
+static IN_FLIGHT: OnceLock<Mutex<HashMap<String, Flight>>> = OnceLock::new();
+
+fn in_flight() -> &'static Mutex<HashMap<String, Flight>> {
+    IN_FLIGHT.get_or_init(Default::default)
+}
+
+/// A key for one request served by `client`; `parts` tell requests apart.
+pub fn key<T: ?Sized>(client: &Arc<T>, parts: &[&str]) -> String {
+    format!("{:p}|{}", Arc::as_ptr(client) as *const (), parts.join("|"))
+}
+
+/// Run `call` unless an identical request (`key`) is in flight, in which case its
+/// outcome is shared. The caller that made the call gets its error as is; the others
+/// get it as text.
+pub async fn share<F>(key: String, call: F) -> Result<StsCredentials>
+where
+    F: Future<Output = Result<StsCredentials>>,
+{
+    let flight = in_flight().lock().expect("in-flight map poisoned").entry(key.clone()).or_default().clone();
+    let mut own = None;
+    let shared = flight
+        .get_or_init(|| async {
+            let result = call.await;
+            let shared = result.as_ref().map(Clone::clone).map_err(|e| format!("{:#}", e));
+            own = Some(result);
+            shared
+        })
+        .await
+        .clone();
+    let mut map = in_flight().lock().expect("in-flight map poisoned");
+    if map.get(&key).is_some_and(|f| Arc::ptr_eq(f, &flight)) {
+        map.remove(&key);
+    }
+    drop(map);
+    own.unwrap_or_else(|| shared.map_err(|e| anyhow!(e)))
+}
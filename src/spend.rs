@@ -0,0 +1,115 @@
+// Month-to-date spend before a command (`[spend]`).
+//
+// For the profiles listed under `[spend]` in ~/.awx/config.toml, typically sandbox
+// accounts someone owns and pays attention to, awx prints the account's unblended cost
+// so far this month (Cost Explorer GetCostAndUsage) on stderr before it runs the
+// command. Cost Explorer bills every request, so the figure is kept per profile in
+// ~/.awx/spend.json and fetched again after `refresh` (default 6h) or when the month
+// changes. A failed lookup, e.g. for lack of ce:GetCostAndUsage, is kept the same way
+// and reported once per refresh; it never stops the command.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::process::Command;
+
+use crate::exec::glob_match;
+use crate::settings::SpendSettings;
+use crate::{ratelimit, state};
+
+pub const DEFAULT_REFRESH_HOURS: i64 = 6;
+
+/// The spend is not worth delaying the command for longer than this.
+const TIMEOUT_SECS: u64 = 10;
+
+/// What the last lookup for a profile found.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub fetched_at: DateTime<Utc>,
+    /// The month the figure is for, e.g. `2024-05`.
+    pub month: String,
+    #[serde(flatten)]
+    pub spend: Spend,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Spend {
+    Amount { amount: f64, unit: String },
+    Failed { reason: String },
+}
+
+pub fn path(awx_dir: &Path) -> PathBuf {
+    awx_dir.join("spend.json")
+}
+
+/// Whether `settings` ask for the spend of `profile`.
+pub fn wanted(settings: &SpendSettings, profile: &str) -> bool {
+    settings.profiles.iter().any(|p| glob_match(p, profile))
+}
+
+fn month(now: DateTime<Utc>) -> String {
+    now.format("%Y-%m").to_string()
+}
+
+/// The kept entry for `profile` when it is from this month and younger than `refresh`.
+pub fn fresh<'a>(store: &'a HashMap<String, Entry>, profile: &str, refresh: Duration, now: DateTime<Utc>) -> Option<&'a Entry> {
+    store
+        .get(profile)
+        .filter(|e| e.month == month(now) && now - e.fetched_at < refresh)
+}
+
+/// The first of this month, and tomorrow since Cost Explorer's end date is exclusive.
+pub fn period(now: DateTime<Utc>) -> (NaiveDate, NaiveDate) {
+    let today = now.date_naive();
+    (today.with_day(1).unwrap_or(today), today + Duration::days(1))
+}
+
+/// Ask Cost Explorer with `aws`, a command carrying the profile's credentials.
+pub async fn fetch(mut aws: Command, now: DateTime<Utc>) -> Result<Spend> {
+    let cmd = &mut aws;
+    let (start, end) = period(now);
+    let period = format!("Start={},End={}", start, end);
+    // Cost Explorer has a single endpoint.
+    cmd.args(["ce", "get-cost-and-usage", "--time-period", period.as_str(), "--granularity", "MONTHLY"])
+        .args(["--metrics", "UnblendedCost", "--region", "us-east-1", "--output", "json"]);
+    let output = ratelimit::output_within(cmd, None, std::time::Duration::from_secs(TIMEOUT_SECS))
+        .await
+        .context("failed to run aws ce get-cost-and-usage")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("get-cost-and-usage failed: {}", stderr.trim()));
+    }
+    let response: Value = serde_json::from_slice(&output.stdout).context("Parsing get-cost-and-usage JSON response failed")?;
+    let total = &response["ResultsByTime"][0]["Total"]["UnblendedCost"];
+    let amount = total["Amount"].as_str().and_then(|a| a.parse::<f64>().ok());
+    match (amount, total["Unit"].as_str()) {
+        (Some(amount), Some(unit)) => Ok(Spend::Amount { amount, unit: unit.to_string() }),
+        _ => Err(anyhow!("get-cost-and-usage returned no UnblendedCost total")),
+    }
+}
+
+/// The spend of `profile`: the kept entry when it is fresh, else a new lookup with the
+/// command `aws` makes, kept for later runs. The flag says whether it was looked up now.
+pub async fn current(
+    awx_dir: &Path,
+    profile: &str,
+    aws: impl FnOnce() -> Command,
+    refresh: Duration,
+    now: DateTime<Utc>,
+) -> (Entry, bool) {
+    let path = path(awx_dir);
+    let mut store: HashMap<String, Entry> = state::read_json(&path).unwrap_or_default();
+    if let Some(entry) = fresh(&store, profile, refresh, now) {
+        return (entry.clone(), false);
+    }
+    let spend = fetch(aws(), now).await.unwrap_or_else(|e| Spend::Failed { reason: format!("{:#}", e) });
+    let entry = Entry { fetched_at: now, month: month(now), spend };
+    store.insert(profile.to_string(), entry.clone());
+    // Without the file the next run only asks again.
+    let _ = state::write_json(&path, &store);
+    (entry, true)
+}
@@ -0,0 +1,290 @@
+// Native SSO device-authorization login driven through `aws sso-oidc`.
+//
+// `aws sso login` registers a fresh OIDC client on many setups; the native flow keeps
+// one registered client per start URL in ~/.awx/sso/clients/ and only registers again
+// when the stored client secret is about to expire, the same way the CLI's own cache
+// behaves. The resulting access token is written to the AWS CLI SSO cache so every
+// later `aws` invocation recognizes the session. The client secret and the device code
+// go to `aws sso-oidc` as `--cli-input-json` on stdin, never in its argv, where `ps`
+// would show them.
+//
+// `awx login --all-sso` signs in to every identity the config knows, one after
+// another: each distinct legacy start URL and each `[sso-session]` block used by a
+// profile, with one of its profiles standing in for the `aws sso login`.
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+
+/// Registrations this close to expiry are rotated before use.
+const ROTATION_MARGIN_SECS: i64 = 24 * 60 * 60;
+/// Cached tokens this close to expiry count as expired.
+const TOKEN_MARGIN_SECS: i64 = 60;
+const CLIENT_NAME: &str = "awx";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientRegistration {
+    pub client_id: String,
+    pub client_secret: String,
+    pub client_id_issued_at: i64,
+    pub client_secret_expires_at: i64,
+    #[serde(default)]
+    pub start_url: String,
+    #[serde(default)]
+    pub region: String,
+}
+
+impl ClientRegistration {
+    pub fn needs_rotation(&self, now: DateTime<Utc>) -> bool {
+        self.client_secret_expires_at - ROTATION_MARGIN_SECS <= now.timestamp()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DeviceAuthorization {
+    device_code: String,
+    user_code: String,
+    verification_uri: String,
+    verification_uri_complete: Option<String>,
+    expires_in: i64,
+    #[serde(default = "default_interval")]
+    interval: u64,
+}
+
+fn default_interval() -> u64 {
+    5
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TokenResponse {
+    access_token: String,
+    expires_in: i64,
+}
+
+/// Token file in the format the AWS CLI reads from ~/.aws/sso/cache.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CachedToken {
+    pub start_url: String,
+    pub region: String,
+    pub access_token: String,
+    pub expires_at: String,
+}
+
+fn sha1_hex(input: &str) -> String {
+    let digest = Sha1::digest(input.as_bytes());
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Where the registered client for `start_url` is stored.
+pub fn client_path(awx_dir: &Path, start_url: &str, region: &str) -> PathBuf {
+    awx_dir
+        .join("sso")
+        .join("clients")
+        .join(format!("{}.json", sha1_hex(&format!("{}|{}", start_url, region))))
+}
+
+/// The AWS CLI's cache file for a legacy start URL, or for an sso-session by its name.
+pub fn token_cache_path(aws_dir: &Path, start_url: &str) -> PathBuf {
+    aws_dir.join("sso").join("cache").join(format!("{}.json", sha1_hex(start_url)))
+}
+
+/// Whether the AWS CLI cache holds a token for `start_url` (or sso-session name) that
+/// is still valid at `now`.
+pub fn cached_token_valid(aws_dir: &Path, start_url: &str, now: DateTime<Utc>) -> bool {
+    cached_token_expiry(aws_dir, start_url).is_some_and(|expiry| expiry > now)
+}
+
+/// When the token the AWS CLI cached for `start_url` (or sso-session name) stops being
+/// usable, a little before it expires.
+pub fn cached_token_expiry(aws_dir: &Path, start_url: &str) -> Option<DateTime<Utc>> {
+    let content = std::fs::read_to_string(token_cache_path(aws_dir, start_url)).ok()?;
+    let token = serde_json::from_str::<CachedToken>(&content).ok()?;
+    let expires_at = DateTime::parse_from_rfc3339(&token.expires_at).ok()?;
+    Some(expires_at.with_timezone(&Utc) - chrono::Duration::seconds(TOKEN_MARGIN_SECS))
+}
+
+/// One SSO sign-in: a legacy start URL or an sso-session block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identity {
+    pub start_url: String,
+    pub region: Option<String>,
+    pub session: Option<String>,
+    /// The profiles signed in with it, sorted; the first one performs the login.
+    pub profiles: Vec<String>,
+}
+
+impl Identity {
+    /// Whether the AWS CLI cache holds a valid token for this sign-in.
+    pub fn signed_in(&self, aws_dir: &Path, now: DateTime<Utc>) -> bool {
+        cached_token_valid(aws_dir, self.session.as_deref().unwrap_or(&self.start_url), now)
+    }
+}
+
+/// Every sign-in some profile needs, ordered by start URL. sso-session blocks no
+/// profile uses are left out.
+pub fn identities(profiles: &HashMap<String, crate::Profile>) -> Vec<Identity> {
+    let mut found: BTreeMap<(String, Option<String>), Identity> = BTreeMap::new();
+    for profile in profiles.values() {
+        let (start_url, region, session) = match &profile.sso_session {
+            Some(session) => {
+                let Some(block) = profiles.get(&format!("sso-session {}", session)) else {
+                    continue;
+                };
+                let Some(url) = &block.sso_start_url else {
+                    continue;
+                };
+                (url, &block.sso_region, Some(session.clone()))
+            }
+            None if profile.name.starts_with("sso-session ") => continue,
+            None => match &profile.sso_start_url {
+                Some(url) => (url, &profile.sso_region, None),
+                None => continue,
+            },
+        };
+        found
+            .entry((start_url.clone(), session.clone()))
+            .or_insert_with(|| Identity {
+                start_url: start_url.clone(),
+                region: region.clone(),
+                session,
+                profiles: Vec::new(),
+            })
+            .profiles
+            .push(profile.name.clone());
+    }
+    let mut identities: Vec<Identity> = found.into_values().collect();
+    for identity in identities.iter_mut() {
+        identity.profiles.sort();
+    }
+    identities
+}
+
+pub fn load_client(path: &Path) -> Option<ClientRegistration> {
+    crate::state::read_json(path)
+}
+
+// Run `aws <args>`, with `secret` parameters read from stdin when given.
+async fn aws_json(args: &[&str], secret: Option<serde_json::Value>) -> Result<std::result::Result<serde_json::Value, String>> {
+    let mut cmd = crate::backend::command("aws");
+    cmd.args(args).arg("--output").arg("json");
+    let input = secret.map(|v| v.to_string().into_bytes());
+    if input.is_some() {
+        cmd.arg("--cli-input-json").arg("file:///dev/stdin");
+    }
+    let output = crate::ratelimit::output_with_input(&mut cmd, input.as_deref())
+        .await
+        .with_context(|| format!("failed to run aws {}", args[..2.min(args.len())].join(" ")))?;
+    if !output.status.success() {
+        return Ok(Err(String::from_utf8_lossy(&output.stderr).to_string()));
+    }
+    let v = serde_json::from_slice(&output.stdout).context("Parsing sso-oidc JSON response failed")?;
+    Ok(Ok(v))
+}
+
+/// Return the stored client for the start URL, registering a new one when there is
+/// none or the stored secret is (nearly) expired.
+pub async fn ensure_client(awx_dir: &Path, start_url: &str, region: &str) -> Result<ClientRegistration> {
+    let path = client_path(awx_dir, start_url, region);
+    if let Some(existing) = load_client(&path) {
+        if !existing.needs_rotation(Utc::now()) {
+            return Ok(existing);
+        }
+        eprintln!("Stored SSO client registration expired; registering a new one.");
+    }
+    let v = aws_json(
+        &["sso-oidc", "register-client", "--client-name", CLIENT_NAME, "--client-type", "public", "--region", region],
+        None,
+    )
+    .await?
+    .map_err(|e| anyhow!("register-client failed: {}", e))?;
+    let mut reg: ClientRegistration = serde_json::from_value(v).context("Unexpected register-client response")?;
+    reg.start_url = start_url.to_string();
+    reg.region = region.to_string();
+    crate::state::write_json(&path, &reg)?;
+    Ok(reg)
+}
+
+/// Run the device-authorization flow and store the token for the AWS CLI, under the
+/// sso-session name `session` when the sign-in is one, as the CLI looks it up. With
+/// `open_url` false the verification URL is only printed.
+pub async fn native_login(
+    awx_dir: &Path,
+    aws_dir: &Path,
+    start_url: &str,
+    session: Option<&str>,
+    region: &str,
+    open_url: bool,
+) -> Result<()> {
+    let client = ensure_client(awx_dir, start_url, region).await?;
+    let v = aws_json(
+        &["sso-oidc", "start-device-authorization", "--start-url", start_url, "--region", region],
+        Some(serde_json::json!({ "clientId": client.client_id, "clientSecret": client.client_secret })),
+    )
+    .await?
+    .map_err(|e| anyhow!("start-device-authorization failed: {}", e))?;
+    let auth: DeviceAuthorization = serde_json::from_value(v).context("Unexpected start-device-authorization response")?;
+
+    let url = auth.verification_uri_complete.clone().unwrap_or_else(|| auth.verification_uri.clone());
+    eprintln!("Open the following URL to approve the login:");
+    eprintln!("  {}", url);
+    eprintln!("Code: {}", auth.user_code);
+    if open_url {
+        open_browser(&url);
+    }
+
+    let deadline = Utc::now() + chrono::Duration::seconds(auth.expires_in);
+    let mut interval = auth.interval.max(1);
+    loop {
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+        let res = aws_json(
+            &["sso-oidc", "create-token", "--region", region],
+            Some(serde_json::json!({
+                "clientId": client.client_id,
+                "clientSecret": client.client_secret,
+                "grantType": "urn:ietf:params:oauth:grant-type:device_code",
+                "deviceCode": auth.device_code,
+            })),
+        )
+        .await?;
+        match res {
+            Ok(v) => {
+                let token: TokenResponse = serde_json::from_value(v).context("Unexpected create-token response")?;
+                let expires_at = Utc::now() + chrono::Duration::seconds(token.expires_in);
+                let cached = CachedToken {
+                    start_url: start_url.to_string(),
+                    region: region.to_string(),
+                    access_token: token.access_token,
+                    expires_at: expires_at.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+                };
+                // The AWS CLI reads this file, so it gets no checksum line.
+                let path = token_cache_path(aws_dir, session.unwrap_or(start_url));
+                crate::state::write(&path, serde_json::to_string(&cached)?.as_bytes())?;
+                return Ok(());
+            }
+            Err(e) if e.contains("AuthorizationPendingException") => {}
+            Err(e) if e.contains("SlowDownException") => interval += 5,
+            Err(e) => return Err(anyhow!("create-token failed: {}", e.trim())),
+        }
+        if Utc::now() >= deadline {
+            return Err(anyhow!("SSO device authorization expired before it was approved"));
+        }
+    }
+}
+
+fn open_browser(url: &str) {
+    let opener = if cfg!(target_os = "macos") { "open" } else { "xdg-open" };
+    let _ = std::process::Command::new(opener)
+        .arg(url)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn();
+}
+
@@ -0,0 +1,164 @@
+// Atomic, checksummed state files under ~/.awx.
+//
+// Files awx keeps for itself are written to a uniquely named temporary file next to the
+// target and renamed over it, so an interrupted write leaves the old or the new file,
+// never half of one, and two awx processes writing the same file cannot clobber each
+// other's temporary file. JSON state (metrics, remembered conflict choices, SSO client registrations)
+// also ends with a `//awx-sha1:<hex>` line over the bytes before it. A state file that
+// fails its checksum or no longer parses is moved aside to `<name>.corrupt-<time>` and
+// treated as missing, so the command goes on with defaults; the incident is appended
+// to ~/.awx/incidents.jsonl for `awx selftest` (alias `awx doctor`) to report. Files
+// written before checksums existed are accepted without one. The ~/.aws files awx
+// edits (a discovered mfa_serial, `awx config prune`) are replaced the same way and
+// keep their permissions.
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+
+const CHECKSUM_PREFIX: &str = "//awx-sha1:";
+
+/// A state file found corrupted and moved aside.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Incident {
+    pub time: DateTime<Utc>,
+    pub path: PathBuf,
+    pub moved_to: Option<PathBuf>,
+    pub problem: String,
+}
+
+pub fn incidents_path() -> Result<PathBuf> {
+    Ok(crate::settings::awx_dir()?.join("incidents.jsonl"))
+}
+
+/// Replace `path` with `bytes` atomically, readable by the owner only.
+pub fn write(path: &Path, bytes: &[u8]) -> Result<()> {
+    write_with_mode(path, bytes, 0o600)
+}
+
+/// Replace a file awx does not own (~/.aws/config) atomically, keeping its permissions.
+pub fn replace(path: &Path, bytes: &[u8]) -> Result<()> {
+    write_with_mode(path, bytes, mode_of(path).unwrap_or(0o600))
+}
+
+/// Replace `path` with `bytes` atomically; the file has `mode` from the start (Unix).
+pub fn write_with_mode(path: &Path, bytes: &[u8], mode: u32) -> Result<()> {
+    let result = (|| -> std::io::Result<()> {
+        let dir = path.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        std::fs::create_dir_all(dir)?;
+        let name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+        let prefix = format!(".{}.", name);
+        let mut builder = tempfile::Builder::new();
+        builder.prefix(&prefix).suffix(".tmp");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            builder.permissions(std::fs::Permissions::from_mode(mode));
+        }
+        let mut file = builder.tempfile_in(dir)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            // The umask may have narrowed `mode`; never widened it.
+            file.as_file().set_permissions(std::fs::Permissions::from_mode(mode))?;
+        }
+        #[cfg(not(unix))]
+        let _ = mode;
+        file.write_all(bytes)?;
+        file.as_file().sync_all()?;
+        // An error drops (and so removes) the temporary file.
+        file.persist(path).map(|_| ()).map_err(|e| e.error)
+    })();
+    result.with_context(|| format!("Failed to write {}", path.display()))
+}
+
+#[cfg(unix)]
+fn mode_of(path: &Path) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(std::fs::metadata(path).ok()?.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn mode_of(_path: &Path) -> Option<u32> {
+    None
+}
+
+/// Write `value` as pretty JSON followed by its checksum line.
+pub fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    let mut body = serde_json::to_string_pretty(value)?;
+    body.push('\n');
+    let line = format!("{}{}\n", CHECKSUM_PREFIX, sha1_hex(body.as_bytes()));
+    write(path, (body + &line).as_bytes())
+}
+
+/// Read JSON written by `write_json`; `None` when missing or corrupted (then quarantined
+/// and logged to ~/.awx/incidents.jsonl).
+pub fn read_json<T: DeserializeOwned>(path: &Path) -> Option<T> {
+    read_json_logged(path, &incidents_path().ok()?)
+}
+
+/// `read_json` with the incident log at `log`.
+pub fn read_json_logged<T: DeserializeOwned>(path: &Path, log: &Path) -> Option<T> {
+    let content = std::fs::read(path).ok()?;
+    let parsed = verify(&content).and_then(|body| serde_json::from_slice(body).map_err(|e| format!("invalid JSON: {}", e)));
+    match parsed {
+        Ok(value) => Some(value),
+        Err(problem) => {
+            quarantine(path, &problem, log, Utc::now());
+            None
+        }
+    }
+}
+
+/// The content without its checksum line, if the checksum matches or there is none.
+fn verify(content: &[u8]) -> std::result::Result<&[u8], String> {
+    let trimmed = content.strip_suffix(b"\n").unwrap_or(content);
+    let start = trimmed.iter().rposition(|b| *b == b'\n').map(|i| i + 1).unwrap_or(0);
+    let Some(sum) = trimmed[start..].strip_prefix(CHECKSUM_PREFIX.as_bytes()) else {
+        return Ok(content);
+    };
+    let body = &content[..start];
+    if sum != sha1_hex(body).as_bytes() {
+        return Err("checksum mismatch (partial or outside write)".to_string());
+    }
+    Ok(body)
+}
+
+/// Move a corrupted `path` aside and record why.
+pub fn quarantine(path: &Path, problem: &str, log: &Path, now: DateTime<Utc>) {
+    let name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+    let dest = path.with_file_name(format!("{}.corrupt-{}", name, now.format("%Y%m%dT%H%M%S%.6fZ")));
+    let moved_to = std::fs::rename(path, &dest).ok().map(|_| dest);
+    let incident = Incident {
+        time: now,
+        path: path.to_path_buf(),
+        moved_to,
+        problem: problem.to_string(),
+    };
+    let Ok(line) = serde_json::to_string(&incident) else {
+        return;
+    };
+    if let Some(dir) = log.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    if let Ok(mut file) = std::fs::OpenOptions::new().create(true).append(true).open(log) {
+        let _ = writeln!(file, "{}", line);
+    }
+}
+
+/// The incidents recorded in `log`, oldest first.
+pub fn incidents(log: &Path) -> Vec<Incident> {
+    std::fs::read_to_string(log)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|l| serde_json::from_str(l).ok())
+        .collect()
+}
+
+fn sha1_hex(bytes: &[u8]) -> String {
+    Sha1::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect()
+}
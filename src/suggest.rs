@@ -0,0 +1,71 @@
+// The likely next profile, for the top of the profile picker.
+//
+// Purely local: the use history kept with `metrics = true` is scored against the
+// current moment and directory, and the profile with the most weight is offered first.
+// Every past use counts, more when it is recent (weight halves every two weeks), when
+// it happened around the same time of day (within two hours) and when it was in the
+// same project, i.e. the current directory or one above or below it. Nothing is
+// suggested without a few uses to go on or when no profile stands out.
+use std::collections::HashMap;
+use std::path::Path;
+
+use chrono::{DateTime, Local, Timelike, Utc};
+
+use crate::metrics::UseRecord;
+
+/// Uses needed before anything is suggested.
+const MIN_USES: usize = 3;
+const HALF_LIFE_DAYS: f64 = 14.0;
+const HOUR_WINDOW: u32 = 2;
+
+/// How much `record` says about a pick at `now` (local `hour`) in `cwd`.
+fn weight(record: &UseRecord, now: DateTime<Utc>, hour: u32, cwd: Option<&Path>) -> f64 {
+    let age_days = (now - record.at).num_minutes().max(0) as f64 / (24.0 * 60.0);
+    let mut weight = 0.5f64.powf(age_days / HALF_LIFE_DAYS);
+    let then = record.at.with_timezone(&Local).hour();
+    let apart = then.abs_diff(hour).min(24 - then.abs_diff(hour));
+    if apart <= HOUR_WINDOW {
+        weight *= 2.0;
+    }
+    if let (Some(dir), Some(cwd)) = (&record.dir, cwd) {
+        if cwd.starts_with(dir) || dir.starts_with(cwd) {
+            weight *= 4.0;
+        }
+    }
+    weight
+}
+
+/// The profile among `candidates` that `history` points to at `now` (local `hour`) in
+/// `cwd`, if one clearly leads.
+pub fn likely_next(
+    history: &[UseRecord],
+    candidates: &[String],
+    now: DateTime<Utc>,
+    hour: u32,
+    cwd: Option<&Path>,
+) -> Option<String> {
+    let relevant: Vec<&UseRecord> = history.iter().filter(|r| candidates.contains(&r.profile)).collect();
+    if relevant.len() < MIN_USES {
+        return None;
+    }
+    let mut scores: HashMap<&str, f64> = HashMap::new();
+    for record in relevant {
+        *scores.entry(&record.profile).or_default() += weight(record, now, hour, cwd);
+    }
+    let mut ranked: Vec<(&str, f64)> = scores.into_iter().collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(b.0)));
+    let (top, score) = ranked.first()?;
+    // A near tie is no suggestion.
+    if ranked.get(1).is_some_and(|(_, second)| *score < second * 1.2) {
+        return None;
+    }
+    Some(top.to_string())
+}
+
+/// The suggestion for the picker right now, when metrics are enabled.
+pub fn for_picker(candidates: &[String]) -> Option<String> {
+    let store = crate::metrics::load_enabled()?;
+    let now = Utc::now();
+    let cwd = std::env::current_dir().ok();
+    likely_next(&store.history, candidates, now, Local::now().hour(), cwd.as_deref())
+}
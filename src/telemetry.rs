@@ -0,0 +1,239 @@
+// Optional OpenTelemetry trace export.
+//
+// When an OTLP endpoint is configured (OTEL_EXPORTER_OTLP_TRACES_ENDPOINT,
+// OTEL_EXPORTER_OTLP_ENDPOINT or `[telemetry]` in ~/.awx/config.toml), each run becomes
+// one trace: an `awx` root span with a span per phase (profile load, SSO check and
+// login, MFA wait, each AWS API call, the child command). The trace is posted once, as
+// OTLP/HTTP JSON, when awx exits. Phase spans are direct children of the root since
+// `awx exec` resolves profiles concurrently. Nothing is recorded when no endpoint is
+// set, and nothing is sent with --offline.
+use std::fmt::Display;
+use std::io::Read;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+
+use crate::settings::TelemetrySettings;
+use crate::theme::{self, Role};
+use crate::{backend, offline};
+
+static TRACE: Mutex<Option<Trace>> = Mutex::new(None);
+
+/// Where and how traces are sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exporter {
+    /// The full traces URL.
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub service: String,
+}
+
+/// A finished span.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpanRecord {
+    pub name: String,
+    pub id: String,
+    pub start: SystemTime,
+    pub end: SystemTime,
+    pub attributes: Vec<(String, String)>,
+    pub error: Option<String>,
+}
+
+struct Trace {
+    exporter: Exporter,
+    trace_id: String,
+    root: SpanRecord,
+    spans: Vec<SpanRecord>,
+}
+
+/// The exporter configured by the environment (`lookup`) and `settings`, if any.
+pub fn exporter(settings: &TelemetrySettings, lookup: &dyn Fn(&str) -> Option<String>) -> Option<Exporter> {
+    let var = |name: &str| lookup(name).filter(|v| !v.is_empty());
+    if var("OTEL_SDK_DISABLED").is_some_and(|v| v.eq_ignore_ascii_case("true")) {
+        return None;
+    }
+    let url = match var("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT") {
+        Some(url) => url,
+        None => {
+            let base = var("OTEL_EXPORTER_OTLP_ENDPOINT").or_else(|| settings.otlp_endpoint.clone())?;
+            format!("{}/v1/traces", base.trim_end_matches('/'))
+        }
+    };
+    let mut headers: Vec<(String, String)> = settings.headers.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+    headers.sort();
+    let env_headers = var("OTEL_EXPORTER_OTLP_TRACES_HEADERS").or_else(|| var("OTEL_EXPORTER_OTLP_HEADERS"));
+    for pair in env_headers.iter().flat_map(|h| h.split(',')) {
+        if let Some((name, value)) = pair.split_once('=') {
+            let name = name.trim().to_string();
+            headers.retain(|(n, _)| !n.eq_ignore_ascii_case(&name));
+            headers.push((name, value.trim().to_string()));
+        }
+    }
+    Some(Exporter {
+        url,
+        headers,
+        service: var("OTEL_SERVICE_NAME").unwrap_or_else(|| "awx".to_string()),
+    })
+}
+
+/// Start recording this run; the root span starts now.
+pub fn enable(exporter: Exporter, command: &str) {
+    let mut trace = TRACE.lock().unwrap();
+    *trace = Some(Trace {
+        exporter,
+        trace_id: random_hex(16),
+        root: SpanRecord {
+            name: "awx".to_string(),
+            id: random_hex(8),
+            start: SystemTime::now(),
+            end: SystemTime::now(),
+            attributes: vec![("awx.command".to_string(), command.to_string())],
+            error: None,
+        },
+        spans: Vec::new(),
+    });
+}
+
+pub fn enabled() -> bool {
+    TRACE.lock().unwrap().is_some()
+}
+
+/// Set an attribute on the root span, e.g. the profile the run resolved.
+pub fn root_attr(key: &str, value: impl Display) {
+    if let Some(trace) = TRACE.lock().unwrap().as_mut() {
+        set(&mut trace.root.attributes, key, value.to_string());
+    }
+}
+
+/// A phase, recorded when dropped.
+pub struct Span {
+    record: Option<SpanRecord>,
+}
+
+pub fn span(name: impl Into<String>) -> Span {
+    let record = enabled().then(|| SpanRecord {
+        name: name.into(),
+        id: random_hex(8),
+        start: SystemTime::now(),
+        end: SystemTime::now(),
+        attributes: Vec::new(),
+        error: None,
+    });
+    Span { record }
+}
+
+impl Span {
+    pub fn attr(&mut self, key: &str, value: impl Display) {
+        if let Some(record) = self.record.as_mut() {
+            set(&mut record.attributes, key, value.to_string());
+        }
+    }
+
+    /// Mark the phase as failed.
+    pub fn fail(&mut self, error: impl Display) {
+        if let Some(record) = self.record.as_mut() {
+            record.error = Some(error.to_string());
+        }
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        let Some(mut record) = self.record.take() else {
+            return;
+        };
+        record.end = SystemTime::now();
+        if let Some(trace) = TRACE.lock().unwrap().as_mut() {
+            trace.spans.push(record);
+        }
+    }
+}
+
+fn set(attributes: &mut Vec<(String, String)>, key: &str, value: String) {
+    attributes.retain(|(k, _)| k != key);
+    attributes.push((key.to_string(), value));
+}
+
+/// The OTLP/HTTP JSON body for one trace.
+pub fn payload(service: &str, trace_id: &str, root: &SpanRecord, spans: &[SpanRecord]) -> Value {
+    let attributes = |attrs: &[(String, String)]| -> Vec<Value> {
+        attrs
+            .iter()
+            .map(|(k, v)| json!({ "key": k, "value": { "stringValue": v } }))
+            .collect()
+    };
+    let nanos = |t: SystemTime| t.duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0).to_string();
+    let span = |s: &SpanRecord, parent: Option<&str>| {
+        let mut span = json!({
+            "traceId": trace_id,
+            "spanId": s.id,
+            "name": s.name,
+            // SPAN_KIND_INTERNAL
+            "kind": 1,
+            "startTimeUnixNano": nanos(s.start),
+            "endTimeUnixNano": nanos(s.end),
+            "attributes": attributes(&s.attributes),
+        });
+        if let Some(parent) = parent {
+            span["parentSpanId"] = json!(parent);
+        }
+        if let Some(error) = &s.error {
+            // STATUS_CODE_ERROR
+            span["status"] = json!({ "code": 2, "message": error });
+        }
+        span
+    };
+    let all: Vec<Value> = std::iter::once(span(root, None))
+        .chain(spans.iter().map(|s| span(s, Some(&root.id))))
+        .collect();
+    let resource = [
+        ("service.name".to_string(), service.to_string()),
+        ("service.version".to_string(), env!("CARGO_PKG_VERSION").to_string()),
+        ("host.name".to_string(), crate::notify::hostname()),
+    ];
+    json!({
+        "resourceSpans": [{
+            "resource": { "attributes": attributes(&resource) },
+            "scopeSpans": [{
+                "scope": { "name": "awx", "version": env!("CARGO_PKG_VERSION") },
+                "spans": all,
+            }],
+        }],
+    })
+}
+
+/// End the root span with `error`, if the run failed, and send the trace.
+pub async fn export(error: Option<String>) {
+    let Some(mut trace) = TRACE.lock().unwrap().take() else {
+        return;
+    };
+    if offline::is_offline() {
+        return;
+    }
+    trace.root.end = SystemTime::now();
+    trace.root.error = error;
+    let body = payload(&trace.exporter.service, &trace.trace_id, &trace.root, &trace.spans);
+    if let Err(e) = backend::post_json(&trace.exporter.url, &trace.exporter.headers, &body).await {
+        eprintln!(
+            "{}",
+            theme::style(Role::Warning)
+                .apply_to(format!("Warning: trace not exported to {}: {:#}", trace.exporter.url, e))
+        );
+    }
+}
+
+/// `bytes` random bytes as lowercase hex.
+fn random_hex(bytes: usize) -> String {
+    let mut buf = vec![0u8; bytes];
+    let read = std::fs::File::open("/dev/urandom").and_then(|mut f| f.read_exact(&mut buf));
+    if read.is_err() {
+        // Unique enough to tell runs apart.
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
+        let seed = nanos ^ u128::from(std::process::id()) << 64;
+        for (i, b) in buf.iter_mut().enumerate() {
+            *b = (seed >> ((i % 16) * 8)) as u8;
+        }
+    }
+    buf.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,182 @@
+// Console styling.
+//
+// Every colored or emphasized piece of output has a role (heading, warning, badge,
+// ...) and gets its style from here, so one setting restyles all of awx: `[theme]` in
+// ~/.awx/config.toml picks a preset (`default`, `high-contrast` without dim text and
+// with bright, bold colors, or `monochrome` using only bold, dim and reverse video) and
+// may restyle single roles with dotted style strings such as "magenta.bold" or
+// "white.on_red" ("" for plain text). The interactive prompts and the progress spinner
+// follow the same roles. Whether color is shown at all is still decided per stream
+// (not a terminal, NO_COLOR, TERM=dumb).
+use std::sync::OnceLock;
+
+use anyhow::{anyhow, Result};
+use console::Style;
+use dialoguer::theme::ColorfulTheme;
+use serde::Deserialize;
+
+use crate::settings::ThemeSettings;
+
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Titles, step counters and codes to compare.
+    Heading,
+    /// Secondary details and progress notes.
+    Muted,
+    Success,
+    Warning,
+    Error,
+    /// Profile badges such as [SSO] and [MFA].
+    Badge,
+    /// The line naming a context before its confirmation prompt.
+    Banner,
+    /// Prompt markers, the selected item and the spinner.
+    Accent,
+}
+
+impl Role {
+    pub const ALL: [Role; 8] = [
+        Role::Heading,
+        Role::Muted,
+        Role::Success,
+        Role::Warning,
+        Role::Error,
+        Role::Badge,
+        Role::Banner,
+        Role::Accent,
+    ];
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Preset {
+    #[default]
+    Default,
+    HighContrast,
+    Monochrome,
+}
+
+impl Preset {
+    /// The dotted style of `role` in this preset.
+    pub fn style(self, role: Role) -> &'static str {
+        let (default, high_contrast, monochrome) = match role {
+            Role::Heading => ("bold", "bold.underlined", "bold"),
+            Role::Muted => ("dim", "", "dim"),
+            Role::Success => ("green", "green.bright.bold", ""),
+            Role::Warning => ("yellow", "yellow.bright.bold", "bold"),
+            Role::Error => ("red", "red.bright.bold", "bold.underlined"),
+            Role::Badge => ("cyan", "cyan.bright.bold", "bold"),
+            Role::Banner => ("white.on_red.bold", "white.bright.on_red.bold", "reverse.bold"),
+            Role::Accent => ("cyan", "cyan.bright.bold", "bold"),
+        };
+        match self {
+            Preset::Default => default,
+            Preset::HighContrast => high_contrast,
+            Preset::Monochrome => monochrome,
+        }
+    }
+}
+
+/// The dotted style of every role.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Theme {
+    styles: Vec<(Role, String)>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::preset(Preset::default())
+    }
+}
+
+impl Theme {
+    pub fn preset(preset: Preset) -> Theme {
+        Theme { styles: Role::ALL.iter().map(|&r| (r, preset.style(r).to_string())).collect() }
+    }
+
+    /// The preset of `settings` with its per-role styles applied.
+    pub fn from_settings(settings: &ThemeSettings) -> Result<Theme> {
+        let mut theme = Theme::preset(settings.preset);
+        for (&role, dotted) in &settings.styles {
+            check(dotted).map_err(|e| anyhow!("Invalid [theme] style for {}: {}", format!("{:?}", role).to_lowercase(), e))?;
+            if let Some(entry) = theme.styles.iter_mut().find(|(r, _)| *r == role) {
+                entry.1 = dotted.trim().to_string();
+            }
+        }
+        Ok(theme)
+    }
+
+    pub fn dotted(&self, role: Role) -> &str {
+        self.styles.iter().find(|(r, _)| *r == role).map(|(_, s)| s.as_str()).unwrap_or("")
+    }
+
+    pub fn style(&self, role: Role) -> Style {
+        Style::from_dotted_str(self.dotted(role))
+    }
+}
+
+/// Reject words console would silently ignore, so a typo does not pass for plain text.
+fn check(dotted: &str) -> Result<()> {
+    const WORDS: &[&str] = &[
+        "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white", "bright", "on_black", "on_red",
+        "on_green", "on_yellow", "on_blue", "on_magenta", "on_cyan", "on_white", "on_bright", "bold", "dim",
+        "underlined", "blink", "blink_fast", "reverse", "hidden", "strikethrough",
+    ];
+    let dotted = dotted.trim();
+    if dotted.is_empty() {
+        return Ok(());
+    }
+    for part in dotted.split('.') {
+        let color256 = part.strip_prefix("on_").unwrap_or(part).parse::<u8>().is_ok();
+        if !color256 && !WORDS.contains(&part) {
+            return Err(anyhow!("unknown style '{}' in \"{}\"", part, dotted));
+        }
+    }
+    Ok(())
+}
+
+/// Use `theme` for this process.
+pub fn configure(theme: Theme) {
+    let _ = THEME.set(theme);
+}
+
+pub fn current() -> &'static Theme {
+    THEME.get_or_init(Theme::default)
+}
+
+/// The style for `role` in the current theme.
+pub fn style(role: Role) -> Style {
+    current().style(role)
+}
+
+/// dialoguer's prompt theme in the current roles.
+pub fn prompts() -> ColorfulTheme {
+    let accent = style(Role::Accent).for_stderr();
+    let success = style(Role::Success).for_stderr();
+    let error = style(Role::Error).for_stderr();
+    let muted = style(Role::Muted).for_stderr();
+    ColorfulTheme {
+        defaults_style: muted.clone(),
+        prompt_style: style(Role::Heading).for_stderr(),
+        prompt_prefix: accent.apply_to("?".to_string()),
+        success_prefix: success.apply_to("✔".to_string()),
+        error_prefix: error.apply_to("✘".to_string()),
+        error_style: error,
+        hint_style: muted,
+        values_style: success,
+        active_item_style: accent.clone(),
+        active_item_prefix: accent.apply_to("❯".to_string()),
+        ..ColorfulTheme::default()
+    }
+}
+
+/// indicatif's template for the spinner in the accent style.
+pub fn spinner_template() -> String {
+    match current().dotted(Role::Accent) {
+        "" => "{spinner} {msg}".to_string(),
+        dotted => format!("{{spinner:.{}}} {{msg}}", dotted),
+    }
+}
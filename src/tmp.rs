@@ -0,0 +1,171 @@
+// Temporary files and directories (`--tmpdir`).
+//
+// Whatever awx writes only for the duration of a run, such as the public key sealed
+// into a pairing response or the mock AWS files `selftest` runs against, goes below
+// one private directory instead of the shared system one: `--tmpdir`, else AWX_TMPDIR,
+// else `$XDG_RUNTIME_DIR/awx` or `<system temp>/awx-<uid>`. The directory must belong
+// to the user and be closed to everyone else; awx creates it 0700 and refuses one that
+// is group- or world-accessible or a symlink. Files and directories are created with
+// the usual private modes, narrowed further by the umask, never widened.
+//
+// Each run works in its own `run-<pid>` subdirectory. Every artifact is a scoped guard
+// deleted when dropped, the run directory is removed on every exit awx takes itself
+// (including Ctrl-C and `exit` with a child's code), and run directories of processes
+// that no longer exist, left by a crash or SIGKILL, are swept by `awx cache gc`.
+//
+// Owners, modes and process IDs are checked on Unix. Elsewhere the directory is named
+// after USERNAME, only symlinks and non-directories are refused, and run directories
+// are never swept, as there is no cheap way to tell whether their process lives.
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use anyhow::{anyhow, Context, Result};
+
+pub const TMPDIR_ENV: &str = "AWX_TMPDIR";
+
+static RUN: OnceLock<PathBuf> = OnceLock::new();
+
+/// The temporary directory `lookup` and `--tmpdir` select; not created.
+pub fn dir_for(lookup: &impl Fn(&str) -> Option<String>) -> PathBuf {
+    if let Some(dir) = &crate::backend::flags().tmpdir {
+        return dir.clone();
+    }
+    if let Some(dir) = crate::aws_env::get(lookup, TMPDIR_ENV) {
+        return PathBuf::from(dir);
+    }
+    match crate::aws_env::get(lookup, "XDG_RUNTIME_DIR") {
+        Some(runtime) => Path::new(&runtime).join("awx"),
+        None => std::env::temp_dir().join(format!("awx-{}", user())),
+    }
+}
+
+#[cfg(unix)]
+fn user() -> String {
+    unsafe { libc::geteuid() }.to_string()
+}
+
+#[cfg(not(unix))]
+fn user() -> String {
+    crate::aws_env::current_lookup("USERNAME").unwrap_or_else(|| "user".to_string())
+}
+
+pub fn dir() -> PathBuf {
+    dir_for(&crate::aws_env::current_lookup)
+}
+
+/// Create `dir` private to the user, or check that an existing one is.
+pub fn ensure_private(dir: &Path) -> Result<()> {
+    match std::fs::symlink_metadata(dir) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => create_private(dir)
+            .with_context(|| format!("Cannot create the temporary directory {}", dir.display())),
+        Err(e) => Err(e).with_context(|| format!("Cannot use the temporary directory {}", dir.display())),
+        Ok(meta) => {
+            let refuse = |why: &str| {
+                Err(anyhow!(
+                    "Refusing to use {} for temporary files: {} (choose another with --tmpdir or {})",
+                    dir.display(),
+                    why,
+                    TMPDIR_ENV
+                ))
+            };
+            if !meta.is_dir() {
+                return refuse("not a directory");
+            }
+            match not_private(&meta) {
+                Some(why) => refuse(why),
+                None => Ok(()),
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn create_private(dir: &Path) -> std::io::Result<()> {
+    std::fs::DirBuilder::new().recursive(true).mode(0o700).create(dir)
+}
+
+#[cfg(not(unix))]
+fn create_private(dir: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)
+}
+
+/// Why a directory others may use is not private.
+#[cfg(unix)]
+fn not_private(meta: &std::fs::Metadata) -> Option<&'static str> {
+    if meta.uid() != unsafe { libc::geteuid() } {
+        return Some("owned by another user");
+    }
+    (meta.permissions().mode() & 0o077 != 0).then_some("accessible to other users")
+}
+
+#[cfg(not(unix))]
+fn not_private(_meta: &std::fs::Metadata) -> Option<&'static str> {
+    None
+}
+
+/// This run's directory below `dir()`, created on first use.
+fn run_dir() -> Result<&'static Path> {
+    if let Some(run) = RUN.get() {
+        return Ok(run);
+    }
+    let dir = dir();
+    ensure_private(&dir)?;
+    let run = dir.join(format!("run-{}", std::process::id()));
+    ensure_private(&run)?;
+    Ok(RUN.get_or_init(|| run))
+}
+
+/// A temporary directory, deleted when dropped.
+pub fn tempdir() -> Result<tempfile::TempDir> {
+    tempfile::Builder::new()
+        .prefix("awx-")
+        .tempdir_in(run_dir()?)
+        .context("Cannot create a temporary directory")
+}
+
+/// A temporary file, deleted when dropped.
+pub fn file() -> Result<tempfile::NamedTempFile> {
+    tempfile::Builder::new()
+        .prefix("awx-")
+        .tempfile_in(run_dir()?)
+        .context("Cannot create a temporary file")
+}
+
+/// Remove this run's directory with whatever is left in it; for exits that skip drops.
+pub fn cleanup() {
+    if let Some(run) = RUN.get() {
+        let _ = std::fs::remove_dir_all(run);
+    }
+}
+
+/// Run directories in `dir` whose process is gone.
+pub fn abandoned(dir: &Path) -> Vec<PathBuf> {
+    let mut found: Vec<PathBuf> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|e| {
+            let name = e.file_name();
+            let pid = name.to_str().and_then(|n| n.strip_prefix("run-")).and_then(|p| p.parse::<u32>().ok());
+            pid.is_some_and(|pid| !alive(pid)) && e.file_type().is_ok_and(|t| t.is_dir())
+        })
+        .map(|e| e.path())
+        .collect();
+    found.sort();
+    found
+}
+
+#[cfg(unix)]
+fn alive(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return true;
+    };
+    unsafe { libc::kill(pid, 0) == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM) }
+}
+
+#[cfg(not(unix))]
+fn alive(_pid: u32) -> bool {
+    true
+}
@@ -0,0 +1,119 @@
+// Presets for tools other than the AWS CLI (`awx tool <name> -- ...`).
+//
+// Every tool gets the resolved credentials the way `awx exec` passes them. On top of
+// that a preset applies the tool's own conventions: rclone is given an `awx:` remote
+// and defaults for on-the-fly `:s3:` remotes through its RCLONE_* variables, Go tools
+// built on the v1 SDK are told to read ~/.aws/config for the region, and aws-nuke is
+// given `--default-region`. Anything the user already set, in the environment or on
+// the command line, is left as it is.
+//
+// For object-storage tools started outside awx, `awx login --rclone <remote>` and
+// `--mc <alias>` also print the session in their configuration-free forms: rclone's
+// RCLONE_CONFIG_<REMOTE>_* variables and the MinIO client's MC_HOST_<alias> URL.
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+
+use crate::StsCredentials;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Tool {
+    Steampipe,
+    AwsNuke,
+    CloudNuke,
+    Rclone,
+    Terragrunt,
+}
+
+impl Tool {
+    pub fn program(self) -> &'static str {
+        match self {
+            Tool::Steampipe => "steampipe",
+            Tool::AwsNuke => "aws-nuke",
+            Tool::CloudNuke => "cloud-nuke",
+            Tool::Rclone => "rclone",
+            Tool::Terragrunt => "terragrunt",
+        }
+    }
+
+    /// The preset's variables for `region`, leaving out those `lookup` already has.
+    pub fn env(self, region: Option<&str>, lookup: &impl Fn(&str) -> Option<String>) -> Vec<(String, String)> {
+        let mut env: Vec<(String, Option<&str>)> = Vec::new();
+        match self {
+            Tool::Steampipe => {}
+            Tool::AwsNuke | Tool::CloudNuke | Tool::Terragrunt => {
+                env.push(("AWS_SDK_LOAD_CONFIG".to_string(), Some("1")));
+            }
+            Tool::Rclone => {
+                // A named remote (`rclone ls awx:bucket`) and defaults for `:s3:bucket`;
+                // env_auth reads the injected AWS_* variables.
+                env.push(("RCLONE_CONFIG_AWX_TYPE".to_string(), Some("s3")));
+                for prefix in ["RCLONE_CONFIG_AWX", "RCLONE_S3"] {
+                    env.push((format!("{}_PROVIDER", prefix), Some("AWS")));
+                    env.push((format!("{}_ENV_AUTH", prefix), Some("true")));
+                    env.push((format!("{}_REGION", prefix), region));
+                }
+            }
+        }
+        env.into_iter()
+            .filter_map(|(k, v)| Some((k, v?.to_string())))
+            .filter(|(k, _)| lookup(k).map(|v| v.is_empty()).unwrap_or(true))
+            .collect()
+    }
+
+    /// `args` with the preset's flags for `region` added.
+    pub fn args(self, args: &[String], region: Option<&str>) -> Vec<String> {
+        let mut out = args.to_vec();
+        if let (Tool::AwsNuke, Some(region)) = (self, region) {
+            if !has_flag(args, "--default-region") {
+                out.push("--default-region".to_string());
+                out.push(region.to_string());
+            }
+        }
+        out
+    }
+}
+
+fn has_flag(args: &[String], flag: &str) -> bool {
+    args.iter()
+        .any(|a| a == flag || a.strip_prefix(flag).is_some_and(|rest| rest.starts_with('=')))
+}
+
+/// A remote or alias name usable inside an environment variable name.
+pub fn parse_name(name: &str) -> Result<String> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(anyhow!("'{}' must consist of letters, digits and '_'", name));
+    }
+    Ok(name.to_string())
+}
+
+/// Variables defining an rclone S3 remote `remote` that uses `creds`.
+pub fn rclone_remote(remote: &str, creds: &StsCredentials, region: Option<&str>) -> Vec<(String, String)> {
+    let prefix = format!("RCLONE_CONFIG_{}", remote.to_uppercase());
+    let mut vars = vec![
+        ("TYPE", "s3"),
+        ("PROVIDER", "AWS"),
+        ("ACCESS_KEY_ID", creds.access_key_id.as_str()),
+        ("SECRET_ACCESS_KEY", creds.secret_access_key.as_str()),
+        ("SESSION_TOKEN", creds.session_token.as_str()),
+    ];
+    if let Some(region) = region {
+        vars.push(("REGION", region));
+    }
+    vars.into_iter()
+        .map(|(k, v)| (format!("{}_{}", prefix, k), v.to_string()))
+        .collect()
+}
+
+/// The MinIO client's MC_HOST_<alias> for S3 in `region` with `creds`. mc splits the
+/// URL at the colons and the last '@', so the keys go in unescaped.
+pub fn mc_host(alias: &str, creds: &StsCredentials, region: Option<&str>) -> (String, String) {
+    let endpoint = match region {
+        Some(region) => format!("s3.{}.amazonaws.com", region),
+        None => "s3.amazonaws.com".to_string(),
+    };
+    let url = format!(
+        "https://{}:{}:{}@{}",
+        creds.access_key_id, creds.secret_access_key, creds.session_token, endpoint
+    );
+    (format!("MC_HOST_{}", alias), url)
+}
@@ -0,0 +1,129 @@
+// What a session did (`awx trail`).
+//
+// CloudTrail's LookupEvents returns the management events of the last 90 days in one
+// region, filtered by a single attribute. `awx trail` resolves the profile, asks STS who
+// the session is and looks up the events recorded under that name since `--last`: the
+// role session name for an assumed role, the user name for an IAM user, so calls of
+// earlier sessions with the same name show up too. CloudTrail takes up to about 15
+// minutes to make an event available, and data events such as S3 object reads are not
+// kept for lookup. IAM and STS calls are recorded in us-east-1. Needs
+// cloudtrail:LookupEvents.
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::process::Command;
+use tokio::time::Duration;
+
+use crate::arn::Arn;
+use crate::ratelimit;
+
+/// At most this many events are read.
+const MAX_EVENTS: usize = 500;
+
+/// The CloudTrail `Username` of a caller: the session name of an assumed role, else the
+/// name of the user.
+pub fn username(caller: &Arn) -> Option<String> {
+    let parts: Vec<&str> = caller.resource.split('/').collect();
+    match parts[..] {
+        ["assumed-role", _, session] => Some(session.to_string()),
+        ["user", .., name] | ["federated-user", name] => Some(name.to_string()),
+        ["root"] => Some("root".to_string()),
+        _ => None,
+    }
+}
+
+/// One API call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub time: DateTime<Utc>,
+    /// `s3` for `s3.amazonaws.com`.
+    pub service: String,
+    pub name: String,
+    pub resources: Vec<String>,
+    /// The error code of a failed call, e.g. `AccessDenied`.
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct LookupResponse {
+    #[serde(default)]
+    events: Vec<RawEvent>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RawEvent {
+    event_time: Value,
+    #[serde(default)]
+    event_source: String,
+    #[serde(default)]
+    event_name: String,
+    #[serde(default)]
+    resources: Vec<RawResource>,
+    #[serde(default)]
+    cloud_trail_event: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RawResource {
+    #[serde(default)]
+    resource_name: String,
+}
+
+// The CLI prints timestamps as ISO 8601, or as epoch seconds with
+// `cli_timestamp_format = none`.
+fn event_time(value: &Value) -> Option<DateTime<Utc>> {
+    match value {
+        Value::String(s) => DateTime::parse_from_rfc3339(s).ok().map(|t| t.with_timezone(&Utc)),
+        Value::Number(n) => DateTime::from_timestamp(n.as_f64()? as i64, 0),
+        _ => None,
+    }
+}
+
+/// The events in a LookupEvents response, oldest first.
+pub fn parse(json: &[u8]) -> Result<Vec<Event>> {
+    let response: LookupResponse = serde_json::from_slice(json).context("Parsing lookup-events JSON response failed")?;
+    let mut events = Vec::new();
+    for raw in response.events {
+        let time = event_time(&raw.event_time).ok_or_else(|| anyhow!("lookup-events returned an unreadable EventTime {}", raw.event_time))?;
+        let error = raw
+            .cloud_trail_event
+            .and_then(|e| serde_json::from_str::<Value>(&e).ok())
+            .and_then(|e| e.get("errorCode")?.as_str().map(str::to_string));
+        events.push(Event {
+            time,
+            service: raw.event_source.trim_end_matches(".amazonaws.com").to_string(),
+            name: raw.event_name,
+            resources: raw.resources.into_iter().map(|r| r.resource_name).filter(|r| !r.is_empty()).collect(),
+            error,
+        });
+    }
+    events.sort_by_key(|e| e.time);
+    Ok(events)
+}
+
+/// The events CloudTrail holds for `username` since `since`, looked up with `aws`, a
+/// command carrying the session's credentials.
+pub async fn lookup(mut aws: Command, username: &str, since: DateTime<Utc>, region: Option<&str>) -> Result<Vec<Event>> {
+    let cmd = &mut aws;
+    let attribute = format!("AttributeKey=Username,AttributeValue={}", username);
+    let start = since.to_rfc3339();
+    let max = MAX_EVENTS.to_string();
+    cmd.args(["cloudtrail", "lookup-events", "--lookup-attributes", attribute.as_str()])
+        .args(["--start-time", start.as_str(), "--max-items", max.as_str()]);
+    if let Some(region) = region {
+        cmd.args(["--region", region]);
+    }
+    cmd.args(["--output", "json"]);
+    let output = ratelimit::output_within(cmd, None, Duration::from_secs(60))
+        .await
+        .context("failed to run aws cloudtrail lookup-events")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("lookup-events failed: {}", stderr.trim()));
+    }
+    parse(&output.stdout)
+}
@@ -0,0 +1,209 @@
+// Guided diagnosis of a failed credential resolution.
+//
+// When resolving a profile fails in an interactive session, awx offers to look into it
+// instead of leaving the user with the raw error: the error is classified, the identity
+// behind the source profile is checked, the cached SSO token is inspected and, for
+// roles, IAM is asked whether that identity may call sts:AssumeRole on the role (when
+// it is allowed to run the simulation). The results point to one most likely cause and
+// a suggested fix. Network checks are skipped with --offline.
+use std::collections::HashMap;
+use std::path::Path;
+
+use chrono::Utc;
+use tokio::time::{timeout, Duration};
+
+use crate::i18n::{t, Msg};
+use crate::selftest::{Check, Status};
+use crate::{arn, backend, iam, offline, sso, Profile};
+
+/// What the error text says went wrong.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    ExpiredToken,
+    InvalidKeys,
+    ClockSkew,
+    MfaRejected,
+    RoleChaining,
+    AccessDenied,
+    Throttled,
+    Offline,
+    Network,
+    Unknown,
+}
+
+pub fn classify(message: &str) -> ErrorKind {
+    let has = |needles: &[&str]| needles.iter().any(|n| message.contains(n));
+    if has(&["running with --offline"]) {
+        ErrorKind::Offline
+    } else if has(&["RequestExpired", "Signature expired", "RequestTimeTooSkewed"]) {
+        ErrorKind::ClockSkew
+    } else if has(&["ExpiredToken", "Token has expired", "token is expired"]) {
+        ErrorKind::ExpiredToken
+    } else if has(&["InvalidClientTokenId", "SignatureDoesNotMatch", "security token included in the request is invalid"]) {
+        ErrorKind::InvalidKeys
+    } else if has(&["MultiFactorAuthentication failed", "invalid MFA one time pass code"]) {
+        ErrorKind::MfaRejected
+    } else if has(&["role chaining"]) {
+        ErrorKind::RoleChaining
+    } else if has(&["AccessDenied", "not authorized to perform"]) {
+        ErrorKind::AccessDenied
+    } else if has(&["Throttling", "Rate exceeded"]) {
+        ErrorKind::Throttled
+    } else if has(&["Could not connect", "timeout", "Connection", "Name or service not known"]) {
+        ErrorKind::Network
+    } else {
+        ErrorKind::Unknown
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnosis {
+    pub checks: Vec<Check>,
+    pub cause: String,
+    pub fix: String,
+}
+
+/// Look into why resolving `profile` failed with `error`.
+pub async fn diagnose(
+    profiles: &HashMap<String, Profile>,
+    profile: &Profile,
+    error: &anyhow::Error,
+    aws_dir: &Path,
+) -> Diagnosis {
+    let kind = classify(&format!("{:#}", error));
+    let mut checks = vec![Check::new("error", Status::Fail, format!("{:#}", error))];
+    // Credentials are checked where they come from: the source profile of a role.
+    let base = profile
+        .source_profile
+        .as_ref()
+        .and_then(|s| profiles.get(s))
+        .unwrap_or(profile);
+
+    let token = token_check(base, aws_dir);
+    if let Some(check) = &token {
+        checks.push(check.clone());
+    }
+
+    let identity = if offline::is_offline() {
+        checks.push(Check::new(
+            &format!("identity of '{}'", base.name),
+            Status::Skip,
+            "skipped with --offline",
+        ));
+        None
+    } else {
+        let name = format!("identity of '{}'", base.name);
+        match timeout(Duration::from_secs(10), backend::sts().caller_identity(&base.name)).await {
+            Ok(Ok(identity)) => {
+                checks.push(Check::new(&name, Status::Ok, identity.arn.clone()));
+                Some(identity)
+            }
+            Ok(Err(e)) => {
+                checks.push(Check::new(&name, Status::Fail, format!("{:#}", e)));
+                None
+            }
+            Err(_) => {
+                checks.push(Check::new(&name, Status::Fail, "timed out"));
+                None
+            }
+        }
+    };
+
+    checks.push(match kind {
+        ErrorKind::ClockSkew => Check::new("clock", Status::Fail, "requests were rejected as expired or skewed"),
+        _ => Check::new("clock", Status::Ok, format!("no time errors (local time {})", Utc::now().format("%H:%M:%S UTC"))),
+    });
+
+    let mut simulated = None;
+    if let (Some(role), Some(identity)) = (profile.role_arn.as_deref(), identity.as_ref()) {
+        let principal = identity.arn.parse().ok().as_ref().and_then(iam::principal_arn);
+        let role = arn::RoleSpec::parse(role).ok().and_then(|spec| match spec {
+            arn::RoleSpec::Arn(role) => Some(role.to_string()),
+            _ => None,
+        });
+        let check = match (principal, role) {
+            (Some(principal), Some(role)) => {
+                let actions = ["sts:AssumeRole".to_string()];
+                match iam::simulate(iam::as_profile(&base.name), &principal, &actions, std::slice::from_ref(&role)).await {
+                    Ok(results) if results.iter().all(|r| r.allowed()) => {
+                        simulated = Some(true);
+                        Check::new("sts:AssumeRole permission", Status::Ok, format!("{} may assume {}", principal, role))
+                    }
+                    Ok(results) => {
+                        simulated = Some(false);
+                        let decision = results.first().map(|r| r.eval_decision.as_str()).unwrap_or("no result");
+                        Check::new("sts:AssumeRole permission", Status::Fail, format!("{} for {}", decision, principal))
+                    }
+                    Err(e) => Check::new("sts:AssumeRole permission", Status::Skip, format!("cannot simulate: {:#}", e)),
+                }
+            }
+            _ => Check::new("sts:AssumeRole permission", Status::Skip, "principal or role cannot be simulated"),
+        };
+        checks.push(check);
+    }
+
+    let token_expired = token.as_ref().map(|c| c.status == Status::Fail).unwrap_or(false);
+    let identity_ok = identity.is_some();
+    let (cause, fix) = likely_cause(kind, profile, base, token_expired, identity_ok, simulated);
+    Diagnosis { checks, cause, fix }
+}
+
+fn token_check(profile: &Profile, aws_dir: &Path) -> Option<Check> {
+    if !profile.is_sso() {
+        return None;
+    }
+    let name = format!("SSO token of '{}'", profile.name);
+    let Some(start_url) = profile.sso_start_url.as_deref() else {
+        return Some(Check::new(&name, Status::Skip, "profile uses an sso-session block"));
+    };
+    Some(if sso::cached_token_valid(aws_dir, start_url, Utc::now()) {
+        Check::new(&name, Status::Ok, "cached token is valid")
+    } else {
+        Check::new(&name, Status::Fail, "no valid cached token")
+    })
+}
+
+/// The most likely cause and its fix, from the error and the checks that ran.
+pub fn likely_cause(
+    kind: ErrorKind,
+    profile: &Profile,
+    base: &Profile,
+    token_expired: bool,
+    identity_ok: bool,
+    may_assume: Option<bool>,
+) -> (String, String) {
+    let role = profile.role_arn.as_deref().unwrap_or("the role");
+    let base_args = [("profile", base.name.as_str())];
+    let profile_args = [("profile", profile.name.as_str())];
+    let role_args = [("profile", base.name.as_str()), ("role", role)];
+    let pick = |cause: Msg, cause_args: &[(&str, &str)], fix: Msg, fix_args: &[(&str, &str)]| {
+        (t(cause, cause_args), t(fix, fix_args))
+    };
+    if kind == ErrorKind::Offline {
+        return pick(Msg::OfflineCause, &[], Msg::OfflineFix, &[]);
+    }
+    if token_expired {
+        return pick(Msg::SsoExpiredCause, &base_args, Msg::SsoLoginFix, &base_args);
+    }
+    if kind == ErrorKind::ClockSkew {
+        return pick(Msg::ClockSkewCause, &[], Msg::ClockSkewFix, &[]);
+    }
+    if !identity_ok && matches!(kind, ErrorKind::ExpiredToken | ErrorKind::InvalidKeys) {
+        return pick(Msg::InvalidKeysCause, &base_args, Msg::InvalidKeysFix, &base_args);
+    }
+    match kind {
+        ErrorKind::MfaRejected => pick(Msg::MfaRejectedCause, &[], Msg::MfaRejectedFix, &base_args),
+        ErrorKind::RoleChaining => pick(Msg::RoleChainingCause, &[], Msg::RoleChainingFix, &profile_args),
+        ErrorKind::AccessDenied if may_assume == Some(false) => {
+            pick(Msg::AssumeRoleDeniedCause, &role_args, Msg::AssumeRoleDeniedFix, &[])
+        }
+        ErrorKind::AccessDenied if profile.is_role() && identity_ok => {
+            pick(Msg::TrustPolicyCause, &role_args, Msg::TrustPolicyFix, &[])
+        }
+        ErrorKind::AccessDenied => pick(Msg::NotPermittedCause, &base_args, Msg::NotPermittedFix, &[]),
+        ErrorKind::Throttled => pick(Msg::ThrottledCause, &[], Msg::ThrottledFix, &[]),
+        ErrorKind::Network => pick(Msg::NetworkCause, &[], Msg::NetworkFix, &[]),
+        _ if !identity_ok => pick(Msg::CredentialsBrokenCause, &base_args, Msg::CredentialsBrokenFix, &base_args),
+        _ => pick(Msg::UnknownCause, &[], Msg::UnknownFix, &profile_args),
+    }
+}
@@ -0,0 +1,76 @@
+// Credentials from the HashiCorp Vault AWS secrets engine (built with `--features vault`).
+//
+// A profile with `vault_path = "aws/sts/deploy"` under `[profiles.<name>]` gets its
+// session from `vault read -format=json <path>` instead of from ~/.aws, with whatever
+// VAULT_ADDR and token the vault CLI is set up with. The path must give temporary
+// credentials (an `assumed_role` or `federation_token` role, read through `aws/sts/` or
+// `aws/creds/`): IAM user keys carry no session token and are refused. The lease's
+// duration becomes the session's expiration.
+use anyhow::{anyhow, Context, Result};
+use chrono::{Duration, SecondsFormat, Utc};
+use serde::Deserialize;
+
+use crate::auth::{self, AuthBackend, Request};
+use crate::backend::{self, BoxFuture};
+use crate::pipeline::Stage;
+use crate::{Profile, StsCredentials};
+
+#[derive(Debug, Deserialize)]
+struct Secret {
+    lease_duration: i64,
+    data: Data,
+}
+
+#[derive(Debug, Deserialize)]
+struct Data {
+    access_key: String,
+    secret_key: String,
+    security_token: Option<String>,
+}
+
+/// Parse the output of `vault read -format=json` read at `now`.
+pub fn parse(stdout: &[u8], path: &str, now: chrono::DateTime<Utc>) -> Result<StsCredentials> {
+    let secret: Secret = serde_json::from_slice(stdout).with_context(|| format!("Parsing the Vault secret at {} failed", path))?;
+    let session_token = secret.data.security_token.ok_or_else(|| {
+        anyhow!("{} gives IAM user keys; use a Vault role of type assumed_role or federation_token", path)
+    })?;
+    Ok(StsCredentials {
+        access_key_id: secret.data.access_key,
+        secret_access_key: secret.data.secret_key,
+        session_token,
+        expiration: (now + Duration::seconds(secret.lease_duration)).to_rfc3339_opts(SecondsFormat::Secs, true),
+    })
+}
+
+async fn read(path: &str) -> Result<StsCredentials> {
+    let mut cmd = backend::command("vault");
+    cmd.args(["read", "-format=json", path]);
+    let output = backend::runner().output(&mut cmd, None).await.context("failed to run vault read")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("vault read {} failed: {}", path, stderr.trim()));
+    }
+    parse(&output.stdout, path, Utc::now())
+}
+
+/// Profiles with `vault_path`.
+pub struct Vault;
+
+impl AuthBackend for Vault {
+    fn name(&self) -> &'static str {
+        "vault"
+    }
+
+    fn handles(&self, profile: &Profile) -> bool {
+        auth::vault_path(&profile.name).is_some()
+    }
+
+    fn resolve<'a>(&'a self, r: Request<'a>) -> BoxFuture<'a, Result<Option<StsCredentials>>> {
+        Box::pin(async move {
+            let path = auth::vault_path(&r.profile.name).unwrap_or_default();
+            crate::offline::ensure_online(&format!("reading {} from Vault", path))?;
+            let name = r.profile.name.as_str();
+            r.run.stage(name, Stage::BaseCredentials, r.no_interactive, read(path)).await.map(Some)
+        })
+    }
+}
@@ -0,0 +1,173 @@
+// Credential warming for pipelines (`awx warm`).
+//
+// A CI pre-step resolves a profile once, without prompting, and leaves a session file
+// (~/.awx/sessions/<profile>.json by default) that later steps run with through
+// `awx --from-session-file`, so they pay no authentication latency. Whatever works
+// unattended is used: a valid session file already there, a cached SSO token, static
+// keys (turned into a GetSessionToken session), AssumeRole from those, and anything the
+// AWS CLI resolves on its own for profiles awx does not handle, such as
+// web_identity_token_file. Profiles that need an MFA code or an
+// SSO login are reported as not ready. The report is one JSON object on stdout, and
+// the exit code is 1 when the profile is not ready.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+use crate::session::Descriptor;
+use crate::Profile;
+
+/// How a profile's session is obtained without anyone at the terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Method {
+    /// A session file from an earlier run that lasts long enough.
+    Cached,
+    /// Static keys, turned into a GetSessionToken session.
+    Static,
+    /// The cached SSO token.
+    Sso,
+    /// AssumeRole with the source profile's or the host's credentials.
+    AssumeRole,
+    /// The AWS CLI's own providers (web identity, credential_source, credential_process).
+    AwsCli,
+    /// An MFA code is needed; not possible unattended.
+    Mfa,
+}
+
+/// The machine-readable outcome, printed as one JSON line.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Report {
+    pub profile: String,
+    pub ready: bool,
+    pub method: Method,
+    pub session_file: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expiration: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_in_secs: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// How later steps use the session.
+    pub use_with: String,
+    /// The resolution stages run, with their timings.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stages: Vec<crate::pipeline::Event>,
+}
+
+impl Report {
+    pub fn ready(profile: &str, method: Method, session_file: &Path, expiration: DateTime<Utc>, now: DateTime<Utc>) -> Report {
+        Report {
+            profile: profile.to_string(),
+            ready: true,
+            method,
+            session_file: session_file.to_path_buf(),
+            expiration: Some(expiration),
+            expires_in_secs: Some((expiration - now).num_seconds()),
+            reason: None,
+            use_with: use_with(session_file),
+            stages: Vec::new(),
+        }
+    }
+
+    pub fn not_ready(profile: &str, method: Method, session_file: &Path, reason: String) -> Report {
+        Report {
+            profile: profile.to_string(),
+            ready: false,
+            method,
+            session_file: session_file.to_path_buf(),
+            expiration: None,
+            expires_in_secs: None,
+            reason: Some(reason),
+            use_with: use_with(session_file),
+            stages: Vec::new(),
+        }
+    }
+}
+
+fn use_with(session_file: &Path) -> String {
+    format!("awx --from-session-file {} -- <aws args>", session_file.display())
+}
+
+/// Where the session of `profile` for a `--duration` of `duration` is kept unless
+/// `--output` says otherwise.
+pub fn default_path(awx_dir: &Path, profile: &str, duration: Option<i64>) -> PathBuf {
+    let key = crate::duration::cache_key(profile, duration);
+    awx_dir.join("sessions").join(format!("{}.json", key))
+}
+
+/// The session files kept for `profile` at their default paths, whatever their duration.
+pub fn session_files(awx_dir: &Path, profile: &str) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(awx_dir.join("sessions"))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| {
+            let key = p.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+            p.extension().is_some_and(|e| e == "json") && crate::duration::split_key(key).0 == profile
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+/// A session file at a default path, for `awx cache ls`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cached {
+    /// `<profile>` or `<profile>@<duration>`.
+    pub key: String,
+    pub path: PathBuf,
+    pub expiration: DateTime<Utc>,
+}
+
+/// Every readable session file under `awx_dir`, expired or not.
+pub fn cached(awx_dir: &Path) -> Vec<Cached> {
+    let mut cached: Vec<Cached> = std::fs::read_dir(awx_dir.join("sessions"))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|e| {
+            let path = e.path();
+            let key = path.file_stem()?.to_str()?.to_string();
+            let content = std::fs::read_to_string(&path).ok()?;
+            let descriptor: Descriptor = serde_json::from_str(&content).ok()?;
+            Some(Cached { key, path, expiration: descriptor.expiration })
+        })
+        .collect();
+    cached.sort_by(|a, b| a.key.cmp(&b.key));
+    cached
+}
+
+/// How `profile` would be resolved unattended.
+pub fn method(profiles: &HashMap<String, Profile>, profile: &Profile) -> Method {
+    if profile.is_role() {
+        let Some(source) = &profile.source_profile else {
+            return match profile.credential_source {
+                Some(_) => Method::AssumeRole,
+                // web_identity_token_file
+                None => Method::AwsCli,
+            };
+        };
+        return match profiles.get(source) {
+            Some(base) if base.requires_mfa() => Method::Mfa,
+            _ => Method::AssumeRole,
+        };
+    }
+    if profile.requires_mfa() {
+        Method::Mfa
+    } else if profile.is_sso() || profile.sso_session.is_some() {
+        Method::Sso
+    } else if profile.is_static() {
+        Method::Static
+    } else {
+        Method::AwsCli
+    }
+}
+
+/// The session file at `path` when it belongs to `profile` and lasts `min_remaining`.
+pub fn reusable(path: &Path, profile: &str, min_remaining: Duration, now: DateTime<Utc>) -> Option<Descriptor> {
+    let descriptor = Descriptor::read(path, now).ok()?;
+    (descriptor.profile == profile && descriptor.expiration - now >= min_remaining).then_some(descriptor)
+}